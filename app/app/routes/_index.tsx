@@ -1,6 +1,6 @@
 import type { MetaFunction } from "@remix-run/node";
 import { useEffect, useRef } from "react";
-import init from "walk-the-dog-core";
+import init, { start, type GameHandle } from "walk-the-dog-core";
 
 export const meta: MetaFunction = () => {
   return [
@@ -11,10 +11,13 @@ export const meta: MetaFunction = () => {
 
 export default function Index() {
   const initialized = useRef<boolean>(false);
+  const handle = useRef<GameHandle | null>(null);
 
   useEffect(() => {
     if (!initialized.current) {
-      init();
+      init().then(() => {
+        handle.current = start();
+      });
       initialized.current = true;
     }
   }, [initialized]);
@@ -0,0 +1,53 @@
+//! Times `RedHatBoyStateMachine::simulate` over a long run of events.
+//! Run with `cargo bench -p walk-the-dog-sim`.
+
+use std::{hint::black_box, time::Instant};
+use walk_the_dog_sim::{
+    red_hat_boy::{
+        red_hat_boy_states::{Idle, PhysicsConfig, RedHatBoyState, Surface},
+        Event, RedHatBoyStateMachine,
+    },
+    GROUND,
+};
+
+const LAPS: usize = 2_000;
+const ROUNDS: usize = 20;
+
+/// Running on ground and ice, jumping, sliding and stopping, the way a
+/// player's updates reach the machine.
+fn lap() -> Vec<Event> {
+    let ground = Event::Land(GROUND, Surface::Ground);
+    let ice = Event::Land(GROUND, Surface::Ice);
+    let mut events = vec![Event::Run];
+    events.extend([ground, Event::Update].repeat(20));
+    events.push(Event::Jump);
+    events.extend([Event::Update; 60]);
+    events.extend([ice, Event::Update].repeat(20));
+    events.push(Event::Slide);
+    events.extend([Event::Update; 20]);
+    events.extend([ground, Event::Stop, Event::Update].repeat(5));
+    events
+}
+
+fn main() {
+    let events = lap().repeat(LAPS);
+    let start: RedHatBoyStateMachine =
+        RedHatBoyState::<Idle>::new(0, PhysicsConfig::default()).into();
+
+    let fastest = (0..ROUNDS)
+        .map(|_| {
+            let started = Instant::now();
+            black_box(black_box(start).simulate(black_box(&events)));
+            started.elapsed()
+        })
+        .min()
+        .unwrap_or_default();
+
+    println!(
+        "simulate: {} events in {:.2?}, {:.1} ns per event (fastest of {} rounds)",
+        events.len(),
+        fastest,
+        fastest.as_nanos() as f64 / events.len() as f64,
+        ROUNDS
+    );
+}
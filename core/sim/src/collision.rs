@@ -0,0 +1,134 @@
+//! Contact tests between the boy and the world, measured on boxes and
+//! lines alone.
+
+use crate::{Point, Rect, Segment};
+
+/// How close a box came to a hazard, ordered from most to least severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Proximity {
+    Hit,
+    NearMiss,
+    Clear,
+}
+
+impl Proximity {
+    /// Whether `bounding_box` overlaps `hazard`, passes within `margin` of
+    /// it or stays clear. Boxes whose centers are too far apart to touch
+    /// are ruled out without the rect tests.
+    pub fn between(bounding_box: &Rect, hazard: &Rect, margin: i16) -> Proximity {
+        let reach_x = i32::from(bounding_box.width + hazard.width) / 2 + i32::from(margin);
+        let reach_y = i32::from(bounding_box.height + hazard.height) / 2 + i32::from(margin);
+        if bounding_box.center().distance_squared(&hazard.center())
+            > reach_x * reach_x + reach_y * reach_y
+        {
+            return Proximity::Clear;
+        }
+
+        if bounding_box.intersects(hazard) {
+            Proximity::Hit
+        } else if bounding_box.inflated(margin).intersects(hazard) {
+            Proximity::NearMiss
+        } else {
+            Proximity::Clear
+        }
+    }
+}
+
+/// The highest of `slopes` under `feet` that they are at most `snap`
+/// above, the surface to stand them on.
+pub fn slope_top(slopes: &[Segment], feet: Point, snap: i16) -> Option<i16> {
+    slopes
+        .iter()
+        .filter_map(|slope| slope.y_at(feet.x))
+        .filter(|top| feet.y >= top - snap)
+        .min()
+}
+
+/// What a box of feet stands on: how much of it the tops under it cover,
+/// from 0 to 1, and how far those tops reach to either side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footing {
+    pub covered: f32,
+    pub left: i16,
+    pub right: i16,
+}
+
+/// The footing `feet` finds on the tops of `boxes` that lie within its
+/// depth, or `None` when it stands on none of them.
+pub fn footing(feet: &Rect, boxes: impl IntoIterator<Item = Rect>) -> Option<Footing> {
+    boxes
+        .into_iter()
+        .filter(|rect| feet.y() <= rect.y() && rect.y() < feet.bottom())
+        .map(|rect| Rect::new_from_x_y(rect.x(), feet.y(), rect.width, feet.height))
+        .filter(|top| top.intersects(feet))
+        .fold(None, |found: Option<Footing>, top| {
+            let covered = feet.overlap_fraction(&top);
+            Some(match found {
+                None => Footing {
+                    covered,
+                    left: top.x(),
+                    right: top.right(),
+                },
+                Some(found) => Footing {
+                    covered: found.covered + covered,
+                    left: found.left.min(top.x()),
+                    right: found.right.max(top.right()),
+                },
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proximity_tells_hits_from_near_misses() {
+        let boy = Rect::new_from_x_y(100, 100, 40, 80);
+
+        let overlapping = Rect::new_from_x_y(130, 150, 20, 20);
+        let just_past = Rect::new_from_x_y(150, 150, 20, 20);
+        let far = Rect::new_from_x_y(400, 150, 20, 20);
+
+        assert_eq!(Proximity::between(&boy, &overlapping, 16), Proximity::Hit);
+        assert_eq!(
+            Proximity::between(&boy, &just_past, 16),
+            Proximity::NearMiss
+        );
+        assert_eq!(Proximity::between(&boy, &just_past, 5), Proximity::Clear);
+        assert_eq!(Proximity::between(&boy, &far, 16), Proximity::Clear);
+    }
+
+    #[test]
+    fn slope_top_snaps_to_the_highest_slope_within_reach() {
+        let ramp = Segment::new(Point { x: 0, y: 500 }, Point { x: 100, y: 400 });
+        let flat = Segment::new(Point { x: 0, y: 520 }, Point { x: 100, y: 520 });
+        let slopes = [ramp, flat];
+
+        assert_eq!(slope_top(&slopes, Point { x: 50, y: 460 }, 8), Some(450));
+        assert_eq!(slope_top(&slopes, Point { x: 50, y: 440 }, 8), None);
+        assert_eq!(slope_top(&slopes, Point { x: 50, y: 440 }, 10), Some(450));
+        assert_eq!(slope_top(&slopes, Point { x: 150, y: 600 }, 8), None);
+    }
+
+    #[test]
+    fn footing_sums_the_tops_within_reach_of_the_feet() {
+        let feet = Rect::new_from_x_y(100, 490, 40, 20);
+        let boxes = [
+            Rect::new_from_x_y(80, 500, 30, 50),
+            Rect::new_from_x_y(130, 500, 70, 50),
+            Rect::new_from_x_y(100, 520, 40, 50),
+            Rect::new_from_x_y(300, 500, 40, 50),
+        ];
+
+        assert_eq!(
+            footing(&feet, boxes),
+            Some(Footing {
+                covered: 0.5,
+                left: 80,
+                right: 200,
+            })
+        );
+        assert_eq!(footing(&feet, boxes[2..].iter().copied()), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! Scripted scenes: timed commands the game acts out while the player
+//! watches.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Point;
+
+/// Something a script can move or speak for.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Actor {
+    Boy,
+    Dog,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Command {
+    /// Moves `actor` to `to` over `frames`, starting from `from` when given.
+    /// The boy walks there at his own pace instead, so only the x of
+    /// `from` and `to` counts for him.
+    #[serde(rename_all = "camelCase")]
+    MoveEntity {
+        actor: Actor,
+        #[serde(default)]
+        from: Option<Point>,
+        to: Point,
+        frames: u32,
+    },
+    /// Shows `text`, a locale key or plain text, above `actor`.
+    ShowText {
+        actor: Actor,
+        text: String,
+        frames: u32,
+    },
+    /// Does nothing for `frames`, holding the scene open.
+    Wait {
+        frames: u32,
+    },
+    PlaySound {
+        sound: String,
+    },
+}
+
+impl Command {
+    pub fn frames(&self) -> u32 {
+        match self {
+            Command::MoveEntity { frames, .. }
+            | Command::ShowText { frames, .. }
+            | Command::Wait { frames } => *frames,
+            Command::PlaySound { .. } => 0,
+        }
+    }
+}
+
+/// A command and the frame it starts on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Cue {
+    pub at: u32,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// Cues in any order; several may run at once.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct Script {
+    pub cues: Vec<Cue>,
+}
+
+impl Script {
+    /// The frame the last command finishes on.
+    pub fn length(&self) -> u32 {
+        self.cues
+            .iter()
+            .map(|cue| cue.at + cue.command.frames())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Where the last move to finish leaves `actor`, if it moves at all.
+    pub fn final_position(&self, actor: Actor) -> Option<Point> {
+        self.cues
+            .iter()
+            .filter_map(|cue| match cue.command {
+                Command::MoveEntity {
+                    actor: moved, to, ..
+                } if moved == actor => Some((cue.at + cue.command.frames(), to)),
+                _ => None,
+            })
+            .max_by_key(|(end, _)| *end)
+            .map(|(_, to)| to)
+    }
+}
+
+/// Steps through a script one frame at a time, handing back each command
+/// as it starts. Carrying the commands out is up to the caller.
+#[derive(Clone, Debug)]
+pub struct CutscenePlayer {
+    script: Script,
+    frame: u32,
+}
+
+impl CutscenePlayer {
+    pub fn new(script: Script) -> Self {
+        CutscenePlayer { script, frame: 0 }
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Advances one frame and returns the commands starting on it.
+    pub fn update(&mut self) -> Vec<Command> {
+        let started = self
+            .script
+            .cues
+            .iter()
+            .filter(|cue| cue.at == self.frame)
+            .map(|cue| cue.command.clone())
+            .collect();
+        self.frame += 1;
+        started
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame > self.script.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(at: u32, command: Command) -> Cue {
+        Cue { at, command }
+    }
+
+    fn move_to(actor: Actor, x: i16, frames: u32) -> Command {
+        Command::MoveEntity {
+            actor,
+            from: None,
+            to: Point { x, y: 400 },
+            frames,
+        }
+    }
+
+    /// An intro with its cues out of order: the boy walks in, the dog runs
+    /// ahead of him and then on again while the boy speaks.
+    fn intro() -> Script {
+        Script {
+            cues: vec![
+                cue(60, move_to(Actor::Dog, 900, 30)),
+                cue(0, move_to(Actor::Boy, 200, 90)),
+                cue(0, move_to(Actor::Dog, 500, 60)),
+                cue(
+                    90,
+                    Command::ShowText {
+                        actor: Actor::Boy,
+                        text: "intro.hello".to_string(),
+                        frames: 60,
+                    },
+                ),
+                cue(150, Command::Wait { frames: 30 }),
+            ],
+        }
+    }
+
+    #[test]
+    fn a_script_runs_to_its_last_cue_and_leaves_actors_where_they_moved() {
+        let mut player = CutscenePlayer::new(intro());
+        let mut positions = Vec::new();
+        let mut started = Vec::new();
+
+        while !player.is_finished() {
+            let frame = player.frame();
+            for command in player.update() {
+                if let Command::MoveEntity { actor, to, .. } = command {
+                    positions.retain(|(moved, _)| *moved != actor);
+                    positions.push((actor, to));
+                }
+                started.push(frame);
+            }
+        }
+
+        assert_eq!(player.frame(), 181);
+        assert_eq!(started, [0, 0, 60, 90, 150]);
+        assert!(positions.contains(&(Actor::Boy, Point { x: 200, y: 400 })));
+        assert!(positions.contains(&(Actor::Dog, Point { x: 900, y: 400 })));
+    }
+
+    #[test]
+    fn the_final_position_is_where_the_last_move_to_finish_ends() {
+        let script = intro();
+
+        assert_eq!(script.length(), 180);
+        assert_eq!(
+            script.final_position(Actor::Boy),
+            Some(Point { x: 200, y: 400 })
+        );
+        assert_eq!(
+            script.final_position(Actor::Dog),
+            Some(Point { x: 900, y: 400 })
+        );
+        assert_eq!(Script::default().final_position(Actor::Dog), None);
+    }
+}
@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Point {
+    pub x: i16,
+    pub y: i16,
+}
+
+impl Point {
+    pub fn distance_to(&self, other: &Point) -> f32 {
+        (self.distance_squared(other) as f32).sqrt()
+    }
+
+    /// Squared distance, for comparisons in hot loops without the `sqrt`.
+    pub fn distance_squared(&self, other: &Point) -> i32 {
+        let dx = i32::from(other.x) - i32::from(self.x);
+        let dy = i32::from(other.y) - i32::from(self.y);
+        dx * dx + dy * dy
+    }
+}
+
+/// A straight stretch of ground between two points, such as a ramp.
+#[derive(Clone, Copy, Default, Debug, Deserialize, Serialize)]
+pub struct Segment {
+    pub start: Point,
+    pub end: Point,
+}
+
+impl Segment {
+    pub const fn new(start: Point, end: Point) -> Self {
+        Segment { start, end }
+    }
+
+    /// The height of the segment at `x`, or `None` outside its span.
+    pub fn y_at(&self, x: i16) -> Option<i16> {
+        let (left, right) = if self.start.x <= self.end.x {
+            (self.start, self.end)
+        } else {
+            (self.end, self.start)
+        };
+        if x < left.x || x > right.x || left.x == right.x {
+            return None;
+        }
+        let run = i32::from(right.x) - i32::from(left.x);
+        let rise = i32::from(right.y) - i32::from(left.y);
+        let along = i32::from(x) - i32::from(left.x);
+        Some(left.y + (rise * along / run) as i16)
+    }
+}
+
+/// Which point of a sprite its position refers to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Center,
+    /// Between the feet, for things standing on the ground.
+    BottomCenter,
+}
+
+impl Anchor {
+    /// The `width` x `height` rect whose anchor point is at `point`.
+    pub fn rect(self, point: Point, width: i16, height: i16) -> Rect {
+        let offset = match self {
+            Anchor::TopLeft => Point { x: 0, y: 0 },
+            Anchor::Center => Point {
+                x: width / 2,
+                y: height / 2,
+            },
+            Anchor::BottomCenter => Point {
+                x: width / 2,
+                y: height,
+            },
+        };
+        Rect::new_from_x_y(point.x - offset.x, point.y - offset.y, width, height)
+    }
+
+    /// Where this anchor sits on `rect`, the reverse of `rect`.
+    pub fn point(self, rect: &Rect) -> Point {
+        match self {
+            Anchor::TopLeft => rect.position,
+            Anchor::Center => Point {
+                x: rect.x() + rect.width / 2,
+                y: rect.y() + rect.height / 2,
+            },
+            Anchor::BottomCenter => Point {
+                x: rect.x() + rect.width / 2,
+                y: rect.bottom(),
+            },
+        }
+    }
+}
+
+/// One of the eight ways held direction keys can point. `y` grows down, as
+/// on the canvas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heading {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Heading {
+    /// The heading for the sign of each axis; opposite keys cancel out, so
+    /// `(0, 0)` has none.
+    pub fn from_axes(x: i8, y: i8) -> Option<Heading> {
+        match (x.signum(), y.signum()) {
+            (0, -1) => Some(Heading::Up),
+            (1, -1) => Some(Heading::UpRight),
+            (1, 0) => Some(Heading::Right),
+            (1, 1) => Some(Heading::DownRight),
+            (0, 1) => Some(Heading::Down),
+            (-1, 1) => Some(Heading::DownLeft),
+            (-1, 0) => Some(Heading::Left),
+            (-1, -1) => Some(Heading::UpLeft),
+            _ => None,
+        }
+    }
+
+    /// The sign of each axis, the reverse of `from_axes`.
+    pub fn axes(self) -> (i8, i8) {
+        match self {
+            Heading::Up => (0, -1),
+            Heading::UpRight => (1, -1),
+            Heading::Right => (1, 0),
+            Heading::DownRight => (1, 1),
+            Heading::Down => (0, 1),
+            Heading::DownLeft => (-1, 1),
+            Heading::Left => (-1, 0),
+            Heading::UpLeft => (-1, -1),
+        }
+    }
+
+    /// A unit vector along the heading, so diagonals move no faster than
+    /// straight lines.
+    pub fn vector(self) -> (f32, f32) {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        match self {
+            Heading::Up => (0.0, -1.0),
+            Heading::UpRight => (diagonal, -diagonal),
+            Heading::Right => (1.0, 0.0),
+            Heading::DownRight => (diagonal, diagonal),
+            Heading::Down => (0.0, 1.0),
+            Heading::DownLeft => (-diagonal, diagonal),
+            Heading::Left => (-1.0, 0.0),
+            Heading::UpLeft => (-diagonal, -diagonal),
+        }
+    }
+
+    /// `vector` scaled to `speed` and rounded to whole pixels.
+    pub fn step(self, speed: i16) -> Point {
+        let (x, y) = self.vector();
+        Point {
+            x: (x * f32::from(speed)).round() as i16,
+            y: (y * f32::from(speed)).round() as i16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rect {
+    pub position: Point,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl Rect {
+    pub const fn new(position: Point, width: i16, height: i16) -> Self {
+        Rect {
+            position,
+            width,
+            height,
+        }
+    }
+
+    pub const fn new_from_x_y(x: i16, y: i16, width: i16, height: i16) -> Self {
+        Rect::new(Point { x, y }, width, height)
+    }
+
+    /// The rect spanning two opposite corners, given in any order.
+    pub fn from_corners(a: Point, b: Point) -> Self {
+        Rect::new_from_x_y(
+            a.x.min(b.x),
+            a.y.min(b.y),
+            (a.x - b.x).abs(),
+            (a.y - b.y).abs(),
+        )
+    }
+
+    pub fn intersects(&self, rect: &Rect) -> bool {
+        self.x() < rect.right()
+            && self.right() > rect.x()
+            && self.y() < rect.bottom()
+            && self.bottom() > rect.y()
+    }
+
+    /// The area the two rects share, if any.
+    pub fn intersection(&self, rect: &Rect) -> Option<Rect> {
+        if !self.intersects(rect) {
+            return None;
+        }
+        Some(Rect::from_corners(
+            Point {
+                x: self.x().max(rect.x()),
+                y: self.y().max(rect.y()),
+            },
+            Point {
+                x: self.right().min(rect.right()),
+                y: self.bottom().min(rect.bottom()),
+            },
+        ))
+    }
+
+    pub fn area(&self) -> i32 {
+        i32::from(self.width) * i32::from(self.height)
+    }
+
+    /// How much of this rect `rect` covers, from 0 to 1.
+    pub fn overlap_fraction(&self, rect: &Rect) -> f32 {
+        let area = self.area();
+        if area <= 0 {
+            return 0.0;
+        }
+        self.intersection(rect)
+            .map_or(0.0, |shared| shared.area() as f32 / area as f32)
+    }
+
+    /// The smallest rect covering both.
+    pub fn union(&self, rect: &Rect) -> Rect {
+        Rect::from_corners(
+            Point {
+                x: self.x().min(rect.x()),
+                y: self.y().min(rect.y()),
+            },
+            Point {
+                x: self.right().max(rect.right()),
+                y: self.bottom().max(rect.bottom()),
+            },
+        )
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        point.x >= self.x()
+            && point.x < self.right()
+            && point.y >= self.y()
+            && point.y < self.bottom()
+    }
+
+    pub fn intersects_circle(&self, center: &Point, radius: i16) -> bool {
+        let closest = Point {
+            x: center.x.clamp(self.x(), self.right()),
+            y: center.y.clamp(self.y(), self.bottom()),
+        };
+        closest.distance_squared(center) <= i32::from(radius) * i32::from(radius)
+    }
+
+    pub fn center(&self) -> Point {
+        Point {
+            x: self.x() + self.width / 2,
+            y: self.y() + self.height / 2,
+        }
+    }
+
+    pub fn inflated(&self, margin: i16) -> Rect {
+        Rect::new_from_x_y(
+            self.x() - margin,
+            self.y() - margin,
+            self.width + margin * 2,
+            self.height + margin * 2,
+        )
+    }
+
+    pub fn right(&self) -> i16 {
+        self.x() + self.width
+    }
+
+    pub fn bottom(&self) -> i16 {
+        self.y() + self.height
+    }
+
+    pub fn x(&self) -> i16 {
+        self.position.x
+    }
+
+    pub fn y(&self) -> i16 {
+        self.position.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_matches_known_distances() {
+        let origin = Point { x: 0, y: 0 };
+        let three_four = Point { x: 3, y: 4 };
+
+        assert_eq!(origin.distance_to(&three_four), 5.0);
+        assert_eq!(three_four.distance_to(&origin), 5.0);
+        assert_eq!(origin.distance_squared(&three_four), 25);
+        assert_eq!(
+            Point { x: -10, y: 7 }.distance_squared(&Point { x: 2, y: -9 }),
+            144 + 256
+        );
+    }
+
+    #[test]
+    fn distance_to_itself_is_zero() {
+        let point = Point { x: 12, y: -40 };
+
+        assert_eq!(point.distance_to(&point), 0.0);
+        assert_eq!(point.distance_squared(&point), 0);
+    }
+
+    #[test]
+    fn a_center_anchor_puts_the_point_in_the_middle() {
+        let point = Point { x: 100, y: 50 };
+
+        let rect = Anchor::Center.rect(point, 40, 20);
+
+        assert_eq!(rect, Rect::new_from_x_y(80, 40, 40, 20));
+        assert_eq!(Anchor::Center.point(&rect), point);
+    }
+
+    #[test]
+    fn every_anchor_finds_the_point_it_was_placed_by() {
+        let point = Point { x: -30, y: 200 };
+
+        for anchor in [Anchor::TopLeft, Anchor::Center, Anchor::BottomCenter] {
+            assert_eq!(anchor.point(&anchor.rect(point, 160, 136)), point);
+        }
+        assert_eq!(
+            Anchor::BottomCenter.rect(point, 160, 136),
+            Rect::new_from_x_y(-110, 64, 160, 136)
+        );
+    }
+
+    #[test]
+    fn every_pair_of_held_arrows_has_its_heading() {
+        let expected = [
+            ((0, -1), Heading::Up),
+            ((1, -1), Heading::UpRight),
+            ((1, 0), Heading::Right),
+            ((1, 1), Heading::DownRight),
+            ((0, 1), Heading::Down),
+            ((-1, 1), Heading::DownLeft),
+            ((-1, 0), Heading::Left),
+            ((-1, -1), Heading::UpLeft),
+        ];
+
+        for ((x, y), heading) in expected {
+            assert_eq!(Heading::from_axes(x, y), Some(heading));
+            assert_eq!(heading.axes(), (x, y));
+            let (dx, dy) = heading.vector();
+            assert!(
+                ((dx * dx + dy * dy).sqrt() - 1.0).abs() < 1e-6,
+                "{:?}",
+                heading
+            );
+            assert_eq!(dx.signum() as i8 * i8::from(dx != 0.0), x);
+            assert_eq!(dy.signum() as i8 * i8::from(dy != 0.0), y);
+        }
+        assert_eq!(Heading::from_axes(0, 0), None);
+    }
+
+    #[test]
+    fn from_corners_is_the_same_rect_for_every_corner_order() {
+        let top_left = Point { x: 10, y: 20 };
+        let top_right = Point { x: 50, y: 20 };
+        let bottom_left = Point { x: 10, y: 80 };
+        let bottom_right = Point { x: 50, y: 80 };
+        let expected = Rect::new_from_x_y(10, 20, 40, 60);
+
+        assert_eq!(Rect::from_corners(top_left, bottom_right), expected);
+        assert_eq!(Rect::from_corners(bottom_right, top_left), expected);
+        assert_eq!(Rect::from_corners(top_right, bottom_left), expected);
+        assert_eq!(Rect::from_corners(bottom_left, top_right), expected);
+    }
+}
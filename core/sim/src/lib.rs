@@ -0,0 +1,20 @@
+//! Game logic with no browser dependencies: geometry, collision, timers,
+//! projectile physics and the red hat boy's state machine. It builds and tests natively, while the
+//! `walk-the-dog-core` crate adds rendering, input and audio for wasm.
+
+pub mod collision;
+pub mod cutscene;
+mod geometry;
+mod projectile;
+pub mod red_hat_boy;
+mod stamina;
+mod timer;
+pub mod tween;
+
+pub use geometry::{Anchor, Heading, Point, Rect, Segment};
+pub use projectile::Projectile;
+pub use stamina::{Stamina, StaminaConfig};
+pub use timer::{Cooldown, Timer, FRAME_SIZE};
+
+/// Where the ground is on screen, along the bottom of the play field.
+pub const GROUND: i16 = 600;
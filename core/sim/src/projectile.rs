@@ -0,0 +1,83 @@
+use crate::{Point, Rect, GROUND};
+
+/// A thrown object on a parabolic arc, landing on the ground after
+/// `flight` frames.
+#[derive(Clone, Copy, Debug)]
+pub struct Projectile {
+    x: f32,
+    y: f32,
+    velocity: (f32, f32),
+    flight: f32,
+}
+
+impl Projectile {
+    pub const RADIUS: i16 = 14;
+    const GRAVITY: f32 = 0.5;
+
+    pub fn new(from: Point, flight: f32) -> Self {
+        Projectile {
+            x: f32::from(from.x),
+            y: f32::from(from.y),
+            velocity: (0.0, 0.0),
+            flight,
+        }
+    }
+
+    /// Picks the launch velocity that brings it down on the ground at `x`.
+    pub fn aimed_at(mut self, x: i16) -> Self {
+        let ground = f32::from(GROUND - Projectile::RADIUS);
+        let flight = self.flight;
+        self.velocity = (
+            (f32::from(x) - self.x) / flight,
+            (ground - self.y - Projectile::GRAVITY * flight * flight / 2.0) / flight,
+        );
+        self
+    }
+
+    pub fn update(&mut self) {
+        self.x += self.velocity.0;
+        self.y += self.velocity.1 + Projectile::GRAVITY / 2.0;
+        self.velocity.1 += Projectile::GRAVITY;
+    }
+
+    pub fn center(&self) -> Point {
+        Point {
+            x: self.x.round() as i16,
+            y: self.y.round() as i16,
+        }
+    }
+
+    /// Measured on the drawn position, so rounding in the arc can't keep
+    /// it in the air a frame longer than its flight.
+    pub fn landed(&self) -> bool {
+        self.center().y >= GROUND - Projectile::RADIUS
+    }
+
+    pub fn hits(&self, target: &Rect) -> bool {
+        target.intersects_circle(&self.center(), Projectile::RADIUS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_aimed_projectile_lands_on_target_after_its_flight() {
+        let mut projectile = Projectile::new(Point { x: 1080, y: 450 }, 30.0).aimed_at(200);
+        for _ in 0..29 {
+            projectile.update();
+            assert!(!projectile.landed());
+        }
+        projectile.update();
+
+        assert!(projectile.landed());
+        assert_eq!(
+            projectile.center(),
+            Point {
+                x: 200,
+                y: GROUND - Projectile::RADIUS
+            }
+        );
+    }
+}
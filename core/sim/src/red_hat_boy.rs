@@ -0,0 +1,1943 @@
+//! The red hat boy's state machine. Each state is its own type, and
+//! events move the boy between them.
+
+use serde::{Deserialize, Serialize};
+
+use self::red_hat_boy_states::*;
+use crate::Anchor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateKind {
+    Idle,
+    Running,
+    Sliding,
+    Jumping,
+    Landing,
+    WallSliding,
+    Climbing,
+    Jetpack,
+    Falling,
+    KnockedOut,
+}
+
+impl StateKind {
+    /// The point of his frame the boy is placed and turned by: his feet
+    /// while he is on them, his middle otherwise.
+    pub fn anchor(self) -> Anchor {
+        match self {
+            StateKind::Idle
+            | StateKind::Running
+            | StateKind::Sliding
+            | StateKind::Landing
+            | StateKind::KnockedOut => Anchor::BottomCenter,
+            StateKind::Jumping
+            | StateKind::WallSliding
+            | StateKind::Climbing
+            | StateKind::Jetpack
+            | StateKind::Falling => Anchor::Center,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Run,
+    /// Brings a running boy to a standstill.
+    Stop,
+    Slide,
+    Jump,
+    SuperJump,
+    Update,
+    KnockOut,
+    Land(i16, Surface),
+    /// The boy is touching a wall on this side of him.
+    WallContact(Direction),
+    /// The boy hit the side of a ledge with this top; he pulls himself up
+    /// if his feet only just missed it.
+    LedgeContact(Direction, i16),
+    /// Straps on a jetpack with this many frames of fuel.
+    Jetpack(u16),
+    /// Jump is held; a jetpack pushes the boy up this tick.
+    Thrust,
+    /// A standing boy goes over the edge he was teetering on.
+    TipOff,
+}
+
+/// An `Event` without its data, for listing which events a state handles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Run,
+    Stop,
+    Slide,
+    Jump,
+    SuperJump,
+    Update,
+    KnockOut,
+    Land,
+    WallContact,
+    LedgeContact,
+    Jetpack,
+    Thrust,
+    TipOff,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Run => EventKind::Run,
+            Event::Stop => EventKind::Stop,
+            Event::Slide => EventKind::Slide,
+            Event::Jump => EventKind::Jump,
+            Event::SuperJump => EventKind::SuperJump,
+            Event::Update => EventKind::Update,
+            Event::KnockOut => EventKind::KnockOut,
+            Event::Land(..) => EventKind::Land,
+            Event::WallContact(_) => EventKind::WallContact,
+            Event::LedgeContact(..) => EventKind::LedgeContact,
+            Event::Jetpack(_) => EventKind::Jetpack,
+            Event::Thrust => EventKind::Thrust,
+            Event::TipOff => EventKind::TipOff,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum RedHatBoyStateMachine {
+    Idle(RedHatBoyState<Idle>),
+    Running(RedHatBoyState<Running>),
+    Sliding(RedHatBoyState<Sliding>),
+    Jumping(RedHatBoyState<Jumping>),
+    Landing(RedHatBoyState<Landing>),
+    WallSliding(RedHatBoyState<WallSliding>),
+    Climbing(RedHatBoyState<Climbing>),
+    Jetpack(RedHatBoyState<Jetpack>),
+    Falling(RedHatBoyState<Falling>),
+    KnockedOut(RedHatBoyState<KnockedOut>),
+}
+
+impl RedHatBoyStateMachine {
+    pub fn transition(self, event: Event) -> Self {
+        if !self.handled_events().contains(&event.kind()) {
+            return self;
+        }
+        match (self, event) {
+            (RedHatBoyStateMachine::Idle(state), Event::Run) => state.run().into(),
+            (RedHatBoyStateMachine::Idle(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Idle(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::Idle(state), Event::TipOff) => state.tip_off().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Stop) => state.stop().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Slide) => state.slide().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Jump) => state.jump().into(),
+            (RedHatBoyStateMachine::Running(state), Event::SuperJump) => state.super_jump().into(),
+            (RedHatBoyStateMachine::Sliding(state), Event::SuperJump) => state.super_jump().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Sliding(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Landing(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Running(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Jumping(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Sliding(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Landing(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Falling(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Running(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::Jumping(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::Sliding(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::Landing(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::Jumping(state), Event::WallContact(side))
+                if state.context().moving_toward(side) =>
+            {
+                state.cling(side).into()
+            }
+            (RedHatBoyStateMachine::WallSliding(state), Event::WallContact(side)) => {
+                state.touch(side).into()
+            }
+            (RedHatBoyStateMachine::Jumping(state), Event::LedgeContact(side, top))
+                if state.context().can_grab(side, top) =>
+            {
+                state.grab(top).into()
+            }
+            (RedHatBoyStateMachine::WallSliding(state), Event::LedgeContact(side, top))
+                if state.context().can_grab(side, top) =>
+            {
+                state.grab(top).into()
+            }
+            (RedHatBoyStateMachine::Climbing(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Climbing(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Idle(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Running(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Sliding(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Jumping(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Landing(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::WallSliding(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Jetpack(state), Event::Jetpack(fuel)) => {
+                state.strap_on(fuel).into()
+            }
+            (RedHatBoyStateMachine::Jetpack(state), Event::Thrust) => state.thrust().into(),
+            (RedHatBoyStateMachine::Jetpack(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::Jetpack(state), Event::KnockOut) => state.knock_out().into(),
+            (RedHatBoyStateMachine::Jetpack(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            (RedHatBoyStateMachine::WallSliding(state), Event::Update) => state.update().into(),
+            (RedHatBoyStateMachine::WallSliding(state), Event::Jump) => state.wall_jump().into(),
+            (RedHatBoyStateMachine::WallSliding(state), Event::KnockOut) => {
+                state.knock_out().into()
+            }
+            (RedHatBoyStateMachine::WallSliding(state), Event::Land(position, surface)) => {
+                state.land_on(position, surface).into()
+            }
+            _ => self,
+        }
+    }
+
+    /// The events the current state reacts to: its outgoing edges. Some
+    /// are guarded, such as a wall contact only starting a cling when the
+    /// boy is moving toward the wall; every other event leaves the machine
+    /// as it is.
+    pub fn handled_events(&self) -> &'static [EventKind] {
+        use EventKind::*;
+        match self {
+            RedHatBoyStateMachine::Idle(_) => &[Run, Update, Land, TipOff, Jetpack],
+            RedHatBoyStateMachine::Running(_) => &[
+                Stop, Slide, Jump, SuperJump, Update, KnockOut, Land, Jetpack,
+            ],
+            RedHatBoyStateMachine::Sliding(_) => &[SuperJump, Update, KnockOut, Land, Jetpack],
+            RedHatBoyStateMachine::Jumping(_) => {
+                &[Update, KnockOut, Land, WallContact, LedgeContact, Jetpack]
+            }
+            RedHatBoyStateMachine::Landing(_) => &[Update, KnockOut, Land, Jetpack],
+            RedHatBoyStateMachine::WallSliding(_) => &[
+                WallContact,
+                Update,
+                Jump,
+                KnockOut,
+                Land,
+                LedgeContact,
+                Jetpack,
+            ],
+            RedHatBoyStateMachine::Climbing(_) => &[Update, KnockOut],
+            RedHatBoyStateMachine::Jetpack(_) => &[Jetpack, Thrust, Update, KnockOut, Land],
+            RedHatBoyStateMachine::Falling(_) => &[Update],
+            RedHatBoyStateMachine::KnockedOut(_) => &[],
+        }
+    }
+
+    /// Applies an `Update`, also returning the boy's new facing when the
+    /// update turned him around.
+    pub fn update(self) -> (Self, Option<Direction>) {
+        let facing = self.context().facing;
+        let updated = self.transition(Event::Update);
+        let turned = Some(updated.context().facing).filter(|turned| *turned != facing);
+        (updated, turned)
+    }
+
+    /// Applies `events` in order and returns the resulting machine. Nothing
+    /// outside the machine is touched, so this is safe for look-ahead.
+    pub fn simulate(self, events: &[Event]) -> Self {
+        events
+            .iter()
+            .fold(self, |machine, event| machine.transition(*event))
+    }
+
+    pub fn frame_name(&self) -> &str {
+        match self {
+            RedHatBoyStateMachine::Idle(state) => state.frame_name(),
+            RedHatBoyStateMachine::Running(state) => state.frame_name(),
+            RedHatBoyStateMachine::Jumping(state) => state.frame_name(),
+            RedHatBoyStateMachine::Sliding(state) => state.frame_name(),
+            RedHatBoyStateMachine::Landing(state) => state.frame_name(),
+            RedHatBoyStateMachine::WallSliding(state) => state.frame_name(),
+            RedHatBoyStateMachine::Climbing(state) => state.frame_name(),
+            RedHatBoyStateMachine::Jetpack(state) => state.frame_name(),
+            RedHatBoyStateMachine::Falling(state) => state.frame_name(),
+            RedHatBoyStateMachine::KnockedOut(state) => state.frame_name(),
+        }
+    }
+
+    pub fn restore(kind: StateKind, context: RedHatBoyContext) -> Self {
+        match kind {
+            StateKind::Idle => RedHatBoyState::with_context(context, Idle).into(),
+            StateKind::Running => RedHatBoyState::with_context(context, Running).into(),
+            StateKind::Sliding => RedHatBoyState::with_context(context, Sliding).into(),
+            StateKind::Jumping => RedHatBoyState::with_context(context, Jumping).into(),
+            StateKind::Landing => RedHatBoyState::with_context(context, Landing).into(),
+            // Contact is re-established by the next collision check, or the
+            // boy lets go.
+            StateKind::WallSliding => {
+                RedHatBoyState::with_context(context, WallSliding::new(context.facing)).into()
+            }
+            // Restored mid-climb, he finishes where he is.
+            StateKind::Climbing => RedHatBoyState::with_context(
+                context,
+                Climbing::new(context.position.y, context.position.y + PLAYER_HEIGHT),
+            )
+            .into(),
+            // Fuel isn't saved, so a restored jetpack sputters out.
+            StateKind::Jetpack => RedHatBoyState::with_context(context, Jetpack::new(0)).into(),
+            StateKind::Falling => RedHatBoyState::with_context(context, Falling).into(),
+            StateKind::KnockedOut => RedHatBoyState::with_context(context, KnockedOut).into(),
+        }
+    }
+
+    pub fn state_kind(&self) -> StateKind {
+        match self {
+            RedHatBoyStateMachine::Idle(_) => StateKind::Idle,
+            RedHatBoyStateMachine::Running(_) => StateKind::Running,
+            RedHatBoyStateMachine::Jumping(_) => StateKind::Jumping,
+            RedHatBoyStateMachine::Sliding(_) => StateKind::Sliding,
+            RedHatBoyStateMachine::Landing(_) => StateKind::Landing,
+            RedHatBoyStateMachine::WallSliding(_) => StateKind::WallSliding,
+            RedHatBoyStateMachine::Climbing(_) => StateKind::Climbing,
+            RedHatBoyStateMachine::Jetpack(_) => StateKind::Jetpack,
+            RedHatBoyStateMachine::Falling(_) => StateKind::Falling,
+            RedHatBoyStateMachine::KnockedOut(_) => StateKind::KnockedOut,
+        }
+    }
+
+    pub fn context(&self) -> &RedHatBoyContext {
+        match self {
+            RedHatBoyStateMachine::Idle(state) => state.context(),
+            RedHatBoyStateMachine::Running(state) => state.context(),
+            RedHatBoyStateMachine::Jumping(state) => state.context(),
+            RedHatBoyStateMachine::Sliding(state) => state.context(),
+            RedHatBoyStateMachine::Landing(state) => state.context(),
+            RedHatBoyStateMachine::WallSliding(state) => state.context(),
+            RedHatBoyStateMachine::Climbing(state) => state.context(),
+            RedHatBoyStateMachine::Jetpack(state) => state.context(),
+            RedHatBoyStateMachine::Falling(state) => state.context(),
+            RedHatBoyStateMachine::KnockedOut(state) => state.context(),
+        }
+    }
+
+    /// How much jetpack fuel is left, from 0 to 1, while he wears one.
+    pub fn jetpack_fuel(&self) -> Option<f32> {
+        match self {
+            RedHatBoyStateMachine::Jetpack(state) => Some(state.fuel_fraction()),
+            _ => None,
+        }
+    }
+
+    pub fn context_mut(&mut self) -> &mut RedHatBoyContext {
+        match self {
+            RedHatBoyStateMachine::Idle(state) => state.context_mut(),
+            RedHatBoyStateMachine::Running(state) => state.context_mut(),
+            RedHatBoyStateMachine::Jumping(state) => state.context_mut(),
+            RedHatBoyStateMachine::Sliding(state) => state.context_mut(),
+            RedHatBoyStateMachine::Landing(state) => state.context_mut(),
+            RedHatBoyStateMachine::WallSliding(state) => state.context_mut(),
+            RedHatBoyStateMachine::Climbing(state) => state.context_mut(),
+            RedHatBoyStateMachine::Jetpack(state) => state.context_mut(),
+            RedHatBoyStateMachine::Falling(state) => state.context_mut(),
+            RedHatBoyStateMachine::KnockedOut(state) => state.context_mut(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<Idle>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Idle>) -> Self {
+        RedHatBoyStateMachine::Idle(state)
+    }
+}
+
+impl From<RedHatBoyState<Running>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Running>) -> Self {
+        RedHatBoyStateMachine::Running(state)
+    }
+}
+
+impl From<RedHatBoyState<Sliding>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Sliding>) -> Self {
+        RedHatBoyStateMachine::Sliding(state)
+    }
+}
+
+impl From<RedHatBoyState<Jumping>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Jumping>) -> Self {
+        RedHatBoyStateMachine::Jumping(state)
+    }
+}
+
+impl From<SlidingEndState> for RedHatBoyStateMachine {
+    fn from(state: SlidingEndState) -> Self {
+        match state {
+            SlidingEndState::Sliding(sliding) => sliding.into(),
+            SlidingEndState::Running(running) => running.into(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<Landing>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Landing>) -> Self {
+        RedHatBoyStateMachine::Landing(state)
+    }
+}
+
+impl From<LandingEndState> for RedHatBoyStateMachine {
+    fn from(state: LandingEndState) -> Self {
+        match state {
+            LandingEndState::Landing(landing) => landing.into(),
+            LandingEndState::Running(running) => running.into(),
+            LandingEndState::Idle(idle) => idle.into(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<WallSliding>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<WallSliding>) -> Self {
+        RedHatBoyStateMachine::WallSliding(state)
+    }
+}
+
+impl From<WallSlidingEndState> for RedHatBoyStateMachine {
+    fn from(state: WallSlidingEndState) -> Self {
+        match state {
+            WallSlidingEndState::WallSliding(sliding) => sliding.into(),
+            WallSlidingEndState::Jumping(jumping) => jumping.into(),
+            WallSlidingEndState::Landing(landing) => landing.into(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<Climbing>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Climbing>) -> Self {
+        RedHatBoyStateMachine::Climbing(state)
+    }
+}
+
+impl From<ClimbingEndState> for RedHatBoyStateMachine {
+    fn from(state: ClimbingEndState) -> Self {
+        match state {
+            ClimbingEndState::Climbing(climbing) => climbing.into(),
+            ClimbingEndState::Running(running) => running.into(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<Jetpack>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Jetpack>) -> Self {
+        RedHatBoyStateMachine::Jetpack(state)
+    }
+}
+
+impl From<JetpackEndState> for RedHatBoyStateMachine {
+    fn from(state: JetpackEndState) -> Self {
+        match state {
+            JetpackEndState::Jetpack(jetpack) => jetpack.into(),
+            JetpackEndState::Jumping(jumping) => jumping.into(),
+            JetpackEndState::Running(running) => running.into(),
+        }
+    }
+}
+
+impl From<JumpingEndState> for RedHatBoyStateMachine {
+    fn from(state: JumpingEndState) -> Self {
+        match state {
+            JumpingEndState::Jumping(jumping) => jumping.into(),
+            JumpingEndState::Landing(landing) => landing.into(),
+        }
+    }
+}
+
+impl From<RedHatBoyState<Falling>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<Falling>) -> Self {
+        RedHatBoyStateMachine::Falling(state)
+    }
+}
+
+impl From<RedHatBoyState<KnockedOut>> for RedHatBoyStateMachine {
+    fn from(state: RedHatBoyState<KnockedOut>) -> Self {
+        RedHatBoyStateMachine::KnockedOut(state)
+    }
+}
+
+impl From<FallingEndState> for RedHatBoyStateMachine {
+    fn from(state: FallingEndState) -> Self {
+        match state {
+            FallingEndState::Falling(falling) => falling.into(),
+            FallingEndState::KnockedOut(knocked_out) => knocked_out.into(),
+        }
+    }
+}
+
+pub mod red_hat_boy_states {
+    use crate::{Heading, Point, GROUND};
+    use serde::{Deserialize, Serialize};
+
+    const FLOOR: i16 = 479;
+    /// From the boy's position down to his feet.
+    pub const PLAYER_HEIGHT: i16 = GROUND - FLOOR;
+    const STARTING_POINT: i16 = -20;
+    const IDLE_FRAMES: u8 = 29;
+    const RUNNING_FRAMES: u8 = 23;
+    const JUMPING_FRAMES: u8 = 35;
+    const SLIDING_FRAMES: u8 = 14;
+    /// How many updates the crouch after a jump lasts.
+    pub const LANDING_FRAMES: u8 = 8;
+    const WALL_CLING_FRAMES: u8 = 30;
+    const FALLING_FRAMES: u8 = 29;
+    const CLIMBING_FRAMES: u8 = 10;
+    const RUNNING_SPEED: i16 = 4;
+    const IDLE_FRAME_NAME: &str = "Idle";
+    const RUN_FRAME_NAME: &str = "Run";
+    const SLIDING_FRAME_NAME: &str = "Slide";
+    const JUMPING_FRAME_NAME: &str = "Jump";
+    const LANDING_FRAME_NAME: &str = "Slide";
+    const FALLING_FRAME_NAME: &str = "Dead";
+    pub const JUMP_SPEED: i16 = -25;
+    const SUPER_JUMP_SPEED: i16 = -32;
+    const GRAVITY: i16 = 1;
+    const TERMINAL_VELOCITY: i16 = 20;
+    /// The furthest all the forces on him together push him in one tick.
+    pub const MAX_EXTERNAL_FORCE: i16 = 4;
+    const ICE_ACCELERATION: i16 = 1;
+    const ICE_SLIDE_BOOST: i16 = 3;
+    const WALL_SLIDE_SPEED: i16 = 3;
+    const WALL_JUMP_SPEED_X: i16 = 6;
+    const WALL_JUMP_SPEED_Y: i16 = -20;
+    const JETPACK_THRUST: i16 = 2;
+    const JETPACK_MAX_ASCENT: i16 = -8;
+    /// The highest his position can fly to, keeping him on screen.
+    const JETPACK_CEILING: i16 = 0;
+    const LEDGE_WINDOW: i16 = 12;
+    const LEDGE_MAX_FALL_SPEED: i16 = 8;
+
+    /// What the boy is standing on. On ice his speed eases toward the target
+    /// instead of changing instantly.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum Surface {
+        #[default]
+        Ground,
+        Ice,
+    }
+
+    /// Which way the boy last moved. Standing still keeps the old facing.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub enum Direction {
+        Left,
+        #[default]
+        Right,
+    }
+
+    #[derive(Copy, Clone, Debug)]
+    pub struct PhysicsConfig {
+        pub running_speed: i16,
+        /// Fastest the boy slides down a wall he is clinging to.
+        pub wall_slide_speed: i16,
+        /// Horizontal speed away from the wall on a wall jump.
+        pub wall_jump_speed_x: i16,
+        pub wall_jump_speed_y: i16,
+        /// Velocity a knock-out throws the boy with, backward along `x` and
+        /// up along `y`. `None` stops him where he was hit.
+        pub knock_back: Option<Point>,
+        /// Pulling up onto ledges he only just missed; `None` turns it off.
+        pub ledge_grab: Option<LedgeGrab>,
+    }
+
+    /// How forgiving a ledge grab is.
+    #[derive(Copy, Clone, Debug)]
+    pub struct LedgeGrab {
+        /// How far below a ledge's top his feet may be.
+        pub window: i16,
+        /// Falling faster than this carries him past the ledge.
+        pub max_fall_speed: i16,
+    }
+
+    impl Default for LedgeGrab {
+        fn default() -> Self {
+            LedgeGrab {
+                window: LEDGE_WINDOW,
+                max_fall_speed: LEDGE_MAX_FALL_SPEED,
+            }
+        }
+    }
+
+    impl Default for PhysicsConfig {
+        fn default() -> Self {
+            PhysicsConfig {
+                running_speed: RUNNING_SPEED,
+                wall_slide_speed: WALL_SLIDE_SPEED,
+                wall_jump_speed_x: WALL_JUMP_SPEED_X,
+                wall_jump_speed_y: WALL_JUMP_SPEED_Y,
+                knock_back: None,
+                ledge_grab: Some(LedgeGrab::default()),
+            }
+        }
+    }
+
+    impl Direction {
+        /// The horizontal part of `heading`, if it has one.
+        pub fn along(heading: Heading) -> Option<Direction> {
+            match heading.axes().0 {
+                -1 => Some(Direction::Left),
+                1 => Some(Direction::Right),
+                _ => None,
+            }
+        }
+
+        fn opposite(self) -> Direction {
+            match self {
+                Direction::Left => Direction::Right,
+                Direction::Right => Direction::Left,
+            }
+        }
+
+        fn sign(self) -> i16 {
+            match self {
+                Direction::Left => -1,
+                Direction::Right => 1,
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct RedHatBoyState<S> {
+        context: RedHatBoyContext,
+        _state: S,
+    }
+
+    impl<S> RedHatBoyState<S> {
+        pub fn with_context(context: RedHatBoyContext, state: S) -> Self {
+            RedHatBoyState {
+                context,
+                _state: state,
+            }
+        }
+
+        pub fn context(&self) -> &RedHatBoyContext {
+            &self.context
+        }
+
+        pub fn context_mut(&mut self) -> &mut RedHatBoyContext {
+            &mut self.context
+        }
+
+        fn update_context(&mut self, frames: u8) {
+            self.context = self.context.update(frames);
+        }
+
+        pub fn strap_on(self, fuel: u16) -> RedHatBoyState<Jetpack> {
+            RedHatBoyState {
+                context: self.context.reset_frame().resume_running(),
+                _state: Jetpack::new(fuel),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Idle;
+
+    impl RedHatBoyState<Idle> {
+        pub fn new(start_offset: i16, physics: PhysicsConfig) -> Self {
+            RedHatBoyState {
+                context: RedHatBoyContext {
+                    frame: 0,
+                    position: Point {
+                        x: STARTING_POINT + start_offset,
+                        y: FLOOR,
+                    },
+                    velocity: Point { x: 0, y: 0 },
+                    physics,
+                    external_force: 0,
+                    target_velocity_x: 0,
+                    surface: Surface::Ground,
+                    facing: Direction::Right,
+                    run_velocity_x: None,
+                    floor: Some(FLOOR),
+                    gravity_scale: 1.0,
+                    gravity_carry: 0.0,
+                },
+                _state: Idle {},
+            }
+        }
+
+        pub fn frame_name(&self) -> &str {
+            IDLE_FRAME_NAME
+        }
+
+        pub fn run(self) -> RedHatBoyState<Running> {
+            RedHatBoyState {
+                context: self.context.reset_frame().run_right(),
+                _state: Running {},
+            }
+        }
+
+        pub fn update(mut self) -> RedHatBoyState<Idle> {
+            self.update_context(IDLE_FRAMES);
+            self
+        }
+
+        /// Keeps him standing on whatever he was stopped on.
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Idle> {
+            RedHatBoyState {
+                context: self.context.set_on(position, surface),
+                _state: Idle,
+            }
+        }
+
+        pub fn tip_off(self) -> RedHatBoyState<Jumping> {
+            RedHatBoyState {
+                context: self.context.reset_frame(),
+                _state: Jumping {},
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Running;
+
+    impl RedHatBoyState<Running> {
+        pub fn frame_name(&self) -> &str {
+            RUN_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> RedHatBoyState<Running> {
+            self.update_context(RUNNING_FRAMES);
+            self
+        }
+
+        pub fn stop(self) -> RedHatBoyState<Idle> {
+            RedHatBoyState {
+                context: self.context.reset_frame().stop(),
+                _state: Idle {},
+            }
+        }
+
+        pub fn slide(&self) -> RedHatBoyState<Sliding> {
+            RedHatBoyState {
+                context: self.context.reset_frame().slide_boost(),
+                _state: Sliding {},
+            }
+        }
+
+        pub fn jump(&self) -> RedHatBoyState<Jumping> {
+            RedHatBoyState {
+                context: self.context.reset_frame().set_vertical_velocity(JUMP_SPEED),
+                _state: Jumping {},
+            }
+        }
+
+        pub fn super_jump(&self) -> RedHatBoyState<Jumping> {
+            RedHatBoyState {
+                context: self
+                    .context
+                    .reset_frame()
+                    .set_vertical_velocity(SUPER_JUMP_SPEED),
+                _state: Jumping {},
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Running> {
+            RedHatBoyState {
+                context: self.context.set_on(position, surface),
+                _state: Running,
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Sliding;
+
+    pub enum SlidingEndState {
+        Sliding(RedHatBoyState<Sliding>),
+        Running(RedHatBoyState<Running>),
+    }
+
+    impl RedHatBoyState<Sliding> {
+        pub fn frame_name(&self) -> &str {
+            SLIDING_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> SlidingEndState {
+            self.update_context(SLIDING_FRAMES);
+
+            if self.context.frame >= SLIDING_FRAMES {
+                SlidingEndState::Running(self.stand())
+            } else {
+                SlidingEndState::Sliding(self)
+            }
+        }
+
+        pub fn super_jump(&self) -> RedHatBoyState<Jumping> {
+            RedHatBoyState {
+                context: self
+                    .context
+                    .reset_frame()
+                    .set_vertical_velocity(SUPER_JUMP_SPEED),
+                _state: Jumping {},
+            }
+        }
+
+        pub fn stand(self) -> RedHatBoyState<Running> {
+            RedHatBoyState {
+                context: self.context.reset_frame(),
+                _state: Running {},
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Running> {
+            RedHatBoyState {
+                context: self.context.set_on(position, surface),
+                _state: Running,
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Jumping;
+
+    pub enum JumpingEndState {
+        Jumping(RedHatBoyState<Jumping>),
+        Landing(RedHatBoyState<Landing>),
+    }
+
+    impl RedHatBoyState<Jumping> {
+        pub fn frame_name(&self) -> &str {
+            JUMPING_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> JumpingEndState {
+            self.update_context(JUMPING_FRAMES);
+
+            if let Some(ground) = self.context.ground_reached() {
+                JumpingEndState::Landing(self.land_on(ground, Surface::Ground))
+            } else {
+                JumpingEndState::Jumping(self)
+            }
+        }
+
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Landing> {
+            RedHatBoyState {
+                context: self.context.reset_frame().set_on(position, surface),
+                _state: Landing {},
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+
+        pub fn grab(self, top: i16) -> RedHatBoyState<Climbing> {
+            RedHatBoyState {
+                context: self.context.reset_frame().hold_still(),
+                _state: Climbing::new(self.context.position.y, top),
+            }
+        }
+
+        pub fn cling(self, side: Direction) -> RedHatBoyState<WallSliding> {
+            RedHatBoyState {
+                context: self.context.reset_frame().hold_still(),
+                _state: WallSliding {
+                    side,
+                    contact: true,
+                },
+            }
+        }
+    }
+
+    /// Clinging to a wall mid-air, sliding slowly down it. The boy lets go
+    /// after a short while or once he is no longer touching the wall.
+    #[derive(Copy, Clone)]
+    pub struct WallSliding {
+        side: Direction,
+        contact: bool,
+    }
+
+    impl WallSliding {
+        /// Clinging to a wall on `side`, not yet touched this update.
+        pub fn new(side: Direction) -> Self {
+            WallSliding {
+                side,
+                contact: false,
+            }
+        }
+    }
+
+    pub enum WallSlidingEndState {
+        WallSliding(RedHatBoyState<WallSliding>),
+        Jumping(RedHatBoyState<Jumping>),
+        Landing(RedHatBoyState<Landing>),
+    }
+
+    impl RedHatBoyState<WallSliding> {
+        pub fn frame_name(&self) -> &str {
+            JUMPING_FRAME_NAME
+        }
+
+        pub fn touch(mut self, side: Direction) -> RedHatBoyState<WallSliding> {
+            self._state = WallSliding {
+                side,
+                contact: true,
+            };
+            self
+        }
+
+        pub fn update(mut self) -> WallSlidingEndState {
+            self.context = self.context.slow_wall_slide();
+            self.update_context(WALL_CLING_FRAMES);
+
+            if let Some(ground) = self.context.ground_reached() {
+                WallSlidingEndState::Landing(self.land_on(ground, Surface::Ground))
+            } else if !self._state.contact || self.context.frame >= WALL_CLING_FRAMES {
+                WallSlidingEndState::Jumping(self.let_go())
+            } else {
+                self._state.contact = false;
+                WallSlidingEndState::WallSliding(self)
+            }
+        }
+
+        /// Leaps up and away from the wall.
+        pub fn wall_jump(self) -> RedHatBoyState<Jumping> {
+            let away = self._state.side.opposite();
+            RedHatBoyState {
+                context: self.context.reset_frame().push_off(away),
+                _state: Jumping {},
+            }
+        }
+
+        pub fn grab(self, top: i16) -> RedHatBoyState<Climbing> {
+            RedHatBoyState {
+                context: self.context.reset_frame(),
+                _state: Climbing::new(self.context.position.y, top),
+            }
+        }
+
+        fn let_go(self) -> RedHatBoyState<Jumping> {
+            RedHatBoyState {
+                context: self.context.reset_frame().resume_running(),
+                _state: Jumping {},
+            }
+        }
+
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Landing> {
+            RedHatBoyState {
+                context: self.context.reset_frame().set_on(position, surface),
+                _state: Landing {},
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().resume_running().knock_back(),
+                _state: Falling {},
+            }
+        }
+    }
+
+    /// A short crouch after a jump, during which the boy can't jump or slide.
+    #[derive(Copy, Clone)]
+    pub struct Landing;
+
+    pub enum LandingEndState {
+        Landing(RedHatBoyState<Landing>),
+        Running(RedHatBoyState<Running>),
+        Idle(RedHatBoyState<Idle>),
+    }
+
+    impl RedHatBoyState<Landing> {
+        pub fn frame_name(&self) -> &str {
+            LANDING_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> LandingEndState {
+            self.update_context(LANDING_FRAMES);
+
+            if self.context.frame < LANDING_FRAMES {
+                LandingEndState::Landing(self)
+            } else if self.context.velocity.x == 0 {
+                LandingEndState::Idle(RedHatBoyState {
+                    context: self.context.reset_frame(),
+                    _state: Idle {},
+                })
+            } else {
+                LandingEndState::Running(RedHatBoyState {
+                    context: self.context.reset_frame(),
+                    _state: Running {},
+                })
+            }
+        }
+
+        pub fn land_on(self, position: i16, surface: Surface) -> RedHatBoyState<Landing> {
+            RedHatBoyState {
+                context: self.context.set_on(position, surface),
+                _state: Landing,
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Falling;
+
+    impl RedHatBoyState<Falling> {
+        pub fn frame_name(&self) -> &str {
+            FALLING_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> FallingEndState {
+            // A knock-back carries him backwards; he shouldn't turn round.
+            let facing = self.context.facing;
+            self.update_context(FALLING_FRAMES);
+            self.context.facing = facing;
+
+            if self.context.frame >= FALLING_FRAMES {
+                FallingEndState::KnockedOut(self.knock_out())
+            } else {
+                FallingEndState::Falling(self)
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<KnockedOut> {
+            RedHatBoyState {
+                context: self.context,
+                _state: KnockedOut {},
+            }
+        }
+    }
+
+    pub enum FallingEndState {
+        Falling(RedHatBoyState<Falling>),
+        KnockedOut(RedHatBoyState<KnockedOut>),
+    }
+
+    /// Pulling himself up onto a ledge, eased from where he grabbed it to
+    /// standing on its top.
+    #[derive(Copy, Clone)]
+    pub struct Climbing {
+        from: i16,
+        top: i16,
+    }
+
+    impl Climbing {
+        /// Climbing from position `from` to stand on `top`.
+        pub fn new(from: i16, top: i16) -> Self {
+            Climbing { from, top }
+        }
+    }
+
+    pub enum ClimbingEndState {
+        Climbing(RedHatBoyState<Climbing>),
+        Running(RedHatBoyState<Running>),
+    }
+
+    impl RedHatBoyState<Climbing> {
+        pub fn frame_name(&self) -> &str {
+            JUMPING_FRAME_NAME
+        }
+
+        pub fn update(mut self) -> ClimbingEndState {
+            self.context.frame += 1;
+            let to = self._state.top - PLAYER_HEIGHT;
+            let rise = i32::from(to - self._state.from) * i32::from(self.context.frame)
+                / i32::from(CLIMBING_FRAMES);
+            self.context.position.y = self._state.from + rise as i16;
+
+            if self.context.frame >= CLIMBING_FRAMES {
+                ClimbingEndState::Running(RedHatBoyState {
+                    context: self
+                        .context
+                        .reset_frame()
+                        .set_vertical_velocity(0)
+                        .set_on(self._state.top, Surface::Ground),
+                    _state: Running {},
+                })
+            } else {
+                ClimbingEndState::Climbing(self)
+            }
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+    }
+
+    /// Wearing a jetpack until its fuel runs out. Thrust pushes him up
+    /// while jump is held; otherwise gravity pulls as usual.
+    #[derive(Copy, Clone)]
+    pub struct Jetpack {
+        fuel: u16,
+        capacity: u16,
+        thrusting: bool,
+    }
+
+    impl Jetpack {
+        pub fn new(fuel: u16) -> Self {
+            Jetpack {
+                fuel,
+                capacity: fuel,
+                thrusting: false,
+            }
+        }
+    }
+
+    pub enum JetpackEndState {
+        Jetpack(RedHatBoyState<Jetpack>),
+        Jumping(RedHatBoyState<Jumping>),
+        Running(RedHatBoyState<Running>),
+    }
+
+    impl RedHatBoyState<Jetpack> {
+        pub fn frame_name(&self) -> &str {
+            JUMPING_FRAME_NAME
+        }
+
+        pub fn fuel_fraction(&self) -> f32 {
+            if self._state.capacity == 0 {
+                0.0
+            } else {
+                f32::from(self._state.fuel) / f32::from(self._state.capacity)
+            }
+        }
+
+        pub fn thrust(mut self) -> Self {
+            self._state.thrusting = true;
+            self
+        }
+
+        pub fn update(mut self) -> JetpackEndState {
+            // Standing on the ground shouldn't build up speed to fall with.
+            if self.context.ground_reached().is_some() {
+                self.context.velocity.y = self.context.velocity.y.min(0);
+            }
+            if self._state.thrusting {
+                self.context.velocity.y =
+                    (self.context.velocity.y - JETPACK_THRUST).max(JETPACK_MAX_ASCENT);
+                self._state.thrusting = false;
+            }
+            self.update_context(JUMPING_FRAMES);
+            if self.context.position.y < JETPACK_CEILING {
+                self.context.position.y = JETPACK_CEILING;
+                self.context.velocity.y = self.context.velocity.y.max(0);
+            }
+
+            self._state.fuel = self._state.fuel.saturating_sub(1);
+            if self._state.fuel > 0 {
+                JetpackEndState::Jetpack(self)
+            } else if let Some(ground) = self.context.ground_reached() {
+                JetpackEndState::Running(RedHatBoyState {
+                    context: self.context.reset_frame().set_on(ground, Surface::Ground),
+                    _state: Running {},
+                })
+            } else {
+                JetpackEndState::Jumping(RedHatBoyState {
+                    context: self.context.reset_frame(),
+                    _state: Jumping {},
+                })
+            }
+        }
+
+        pub fn land_on(mut self, position: i16, surface: Surface) -> RedHatBoyState<Jetpack> {
+            self.context = self
+                .context
+                .set_vertical_velocity(0)
+                .set_on(position, surface);
+            self
+        }
+
+        pub fn knock_out(self) -> RedHatBoyState<Falling> {
+            RedHatBoyState {
+                context: self.context.reset_frame().knock_back(),
+                _state: Falling {},
+            }
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct KnockedOut;
+
+    impl RedHatBoyState<KnockedOut> {
+        pub fn frame_name(&self) -> &str {
+            FALLING_FRAME_NAME
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct RedHatBoyContext {
+        pub frame: u8,
+        pub position: Point,
+        pub velocity: Point,
+        pub physics: PhysicsConfig,
+        pub external_force: i16,
+        pub target_velocity_x: i16,
+        pub surface: Surface,
+        pub facing: Direction,
+        /// The running speed to go back to after a wall cling or wall jump
+        /// took over horizontal movement.
+        pub run_velocity_x: Option<i16>,
+        /// The lowest his position can fall to this tick, or `None` over a
+        /// pit. Set by the game from the ground under him.
+        pub floor: Option<i16>,
+        /// How strongly gravity pulls this tick; zones set it and each
+        /// update puts it back to 1.
+        pub gravity_scale: f32,
+        /// Gravity a scaled pull left over, added on the next tick.
+        pub gravity_carry: f32,
+    }
+
+    /// One tick of gravity and motion, stopping at `floor` if there is one.
+    /// Shared by `RedHatBoyContext::update` and anything predicting the
+    /// boy's path.
+    pub fn integrate_step(position: Point, velocity: Point, floor: Option<i16>) -> (Point, Point) {
+        integrate_step_with(position, velocity, floor, GRAVITY)
+    }
+
+    /// `integrate_step` with `gravity` in place of the usual pull.
+    fn integrate_step_with(
+        mut position: Point,
+        mut velocity: Point,
+        floor: Option<i16>,
+        gravity: i16,
+    ) -> (Point, Point) {
+        if velocity.y < TERMINAL_VELOCITY {
+            velocity.y += gravity;
+        }
+        position.x += velocity.x;
+        position.y += velocity.y;
+        if let Some(floor) = floor {
+            position.y = position.y.min(floor);
+        }
+        (position, velocity)
+    }
+
+    /// How far above the floor a plain jump lifts the boy.
+    pub fn max_jump_height() -> i16 {
+        let mut position = Point { x: 0, y: FLOOR };
+        let mut velocity = Point {
+            x: 0,
+            y: JUMP_SPEED,
+        };
+        while velocity.y < 0 {
+            (position, velocity) = integrate_step(position, velocity, Some(FLOOR));
+        }
+        FLOOR - position.y
+    }
+
+    /// How far a plain jump from the floor carries the boy at `speed`.
+    pub fn max_jump_distance(speed: i16) -> i16 {
+        let mut position = Point { x: 0, y: FLOOR };
+        let mut velocity = Point {
+            x: speed,
+            y: JUMP_SPEED,
+        };
+        loop {
+            (position, velocity) = integrate_step(position, velocity, Some(FLOOR));
+            if position.y == FLOOR {
+                return position.x;
+            }
+        }
+    }
+
+    impl RedHatBoyContext {
+        /// Adds a horizontal push, such as wind, for the next update only.
+        /// Pushes applied in the same tick sum and are clamped.
+        /// Sets the ground under his feet for the next update, as the y of
+        /// its top; `None` is a pit. Levels without ground data never call
+        /// this, leaving the single floor every level used to have.
+        pub fn set_ground(&mut self, ground: Option<i16>) {
+            self.floor = ground.map(|ground| ground - PLAYER_HEIGHT);
+        }
+
+        /// Scales gravity for the next update, as a low-gravity zone does.
+        pub fn scale_gravity(&mut self, scale: f32) {
+            self.gravity_scale *= scale;
+        }
+
+        /// This tick's whole pixels of gravity. What a scaled pull leaves
+        /// over carries into the next tick, so half gravity pulls every
+        /// other tick.
+        fn scaled_gravity(&mut self) -> i16 {
+            self.gravity_carry += f32::from(GRAVITY) * self.gravity_scale;
+            let gravity = self.gravity_carry.floor();
+            self.gravity_carry -= gravity;
+            self.gravity_scale = 1.0;
+            gravity as i16
+        }
+
+        /// The top of the floor he reached this tick, if he is standing on it.
+        fn ground_reached(&self) -> Option<i16> {
+            self.floor
+                .filter(|floor| self.position.y >= *floor)
+                .map(|floor| floor + PLAYER_HEIGHT)
+        }
+
+        pub fn apply_external_force(&mut self, x: i16) {
+            self.external_force =
+                (self.external_force + x).clamp(-MAX_EXTERNAL_FORCE, MAX_EXTERNAL_FORCE);
+        }
+
+        pub fn update(mut self, frame_count: u8) -> Self {
+            if self.frame < frame_count {
+                self.frame += 1;
+            } else {
+                self.frame = 0;
+            }
+
+            self.velocity.x = match self.surface {
+                Surface::Ground => self.target_velocity_x,
+                Surface::Ice => {
+                    self.velocity.x
+                        + (self.target_velocity_x - self.velocity.x)
+                            .clamp(-ICE_ACCELERATION, ICE_ACCELERATION)
+                }
+            };
+            if self.velocity.x > 0 {
+                self.facing = Direction::Right;
+            } else if self.velocity.x < 0 {
+                self.facing = Direction::Left;
+            }
+
+            let gravity = self.scaled_gravity();
+            let (position, velocity) =
+                integrate_step_with(self.position, self.velocity, self.floor, gravity);
+            self.position = Point {
+                x: position.x + self.external_force,
+                ..position
+            };
+            self.velocity = velocity;
+            self.external_force = 0;
+            // Landing on a surface sets it again each tick the boy stays on it.
+            self.surface = Surface::Ground;
+
+            self
+        }
+
+        fn reset_frame(mut self) -> Self {
+            self.frame = 0;
+            self
+        }
+
+        fn set_vertical_velocity(mut self, y: i16) -> Self {
+            self.velocity.y = y;
+            self
+        }
+
+        fn run_right(mut self) -> Self {
+            self.target_velocity_x += self.physics.running_speed;
+            if self.surface == Surface::Ground {
+                self.velocity.x = self.target_velocity_x;
+            }
+            self
+        }
+
+        fn stop(mut self) -> Self {
+            self.target_velocity_x = 0;
+            if self.surface == Surface::Ground {
+                self.velocity.x = 0;
+            }
+            self.velocity.y = 0;
+            self
+        }
+
+        /// Stops the boy for a knock-out, then throws him back and up if
+        /// the physics has a knock-back.
+        fn knock_back(self) -> Self {
+            let mut context = self.stop();
+            if let Some(impulse) = context.physics.knock_back {
+                let back = -context.facing.sign() * impulse.x;
+                context.target_velocity_x = back;
+                context.velocity = Point {
+                    x: back,
+                    y: -impulse.y,
+                };
+            }
+            context
+        }
+
+        fn slide_boost(mut self) -> Self {
+            if self.surface == Surface::Ice {
+                self.velocity.x += ICE_SLIDE_BOOST;
+            }
+            self
+        }
+
+        pub fn moving_toward(&self, side: Direction) -> bool {
+            self.target_velocity_x * side.sign() > 0
+        }
+
+        /// Whether he can pull up onto a ledge with this `top` on `side`:
+        /// grabbing is on, he is heading for it, his feet are just below
+        /// its top and he isn't falling too fast to catch it.
+        pub fn can_grab(&self, side: Direction, top: i16) -> bool {
+            let Some(grab) = self.physics.ledge_grab else {
+                return false;
+            };
+            let below = self.position.y + PLAYER_HEIGHT - top;
+            (self.moving_toward(side) || self.run_velocity_x.is_some_and(|x| x * side.sign() > 0))
+                && (0..=grab.window).contains(&below)
+                && self.velocity.y <= grab.max_fall_speed
+        }
+
+        fn hold_still(mut self) -> Self {
+            self.run_velocity_x.get_or_insert(self.target_velocity_x);
+            self.target_velocity_x = 0;
+            self.velocity.x = 0;
+            self
+        }
+
+        /// Caps the fall so that, with gravity added, it stays at the wall
+        /// slide speed.
+        fn slow_wall_slide(mut self) -> Self {
+            self.velocity.y = self.velocity.y.min(self.physics.wall_slide_speed - GRAVITY);
+            self
+        }
+
+        fn push_off(mut self, away: Direction) -> Self {
+            self.target_velocity_x = away.sign() * self.physics.wall_jump_speed_x;
+            self.velocity.x = self.target_velocity_x;
+            self.velocity.y = self.physics.wall_jump_speed_y;
+            self
+        }
+
+        fn resume_running(mut self) -> Self {
+            if let Some(velocity_x) = self.run_velocity_x.take() {
+                self.target_velocity_x = velocity_x;
+            }
+            self
+        }
+
+        fn set_on(mut self, position: i16, surface: Surface) -> Self {
+            self = self.resume_running();
+            self.position.y = position - PLAYER_HEIGHT;
+            self.surface = surface;
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collision::slope_top, Heading, Point, Segment};
+
+    fn idle(physics: PhysicsConfig) -> RedHatBoyStateMachine {
+        RedHatBoyState::<Idle>::new(0, physics).into()
+    }
+
+    fn jumping(physics: PhysicsConfig) -> RedHatBoyStateMachine {
+        idle(physics).simulate(&[Event::Run, Event::Jump, Event::Update, Event::Update])
+    }
+
+    #[test]
+    fn simulating_a_jump_matches_stepping_it_live() {
+        let mut events = vec![Event::Run, Event::Jump];
+        events.extend([Event::Update; 60]);
+        let start = idle(PhysicsConfig::default());
+
+        let mut live = start;
+        let mut kinds = Vec::new();
+        for (stepped, event) in events.iter().enumerate() {
+            live = live.transition(*event);
+            let simulated = start.simulate(&events[..=stepped]);
+            assert_eq!(simulated.state_kind(), live.state_kind());
+            assert_eq!(simulated.context().position, live.context().position);
+            assert_eq!(simulated.context().velocity, live.context().velocity);
+            assert_eq!(simulated.context().frame, live.context().frame);
+            if kinds.last() != Some(&live.state_kind()) {
+                kinds.push(live.state_kind());
+            }
+        }
+
+        assert_eq!(
+            kinds,
+            [
+                StateKind::Running,
+                StateKind::Jumping,
+                StateKind::Landing,
+                StateKind::Running
+            ]
+        );
+    }
+
+    /// Where a jump from a run lands with `force` applied every tick, and
+    /// how many ticks it was in the air.
+    fn jump_landing(force: i16) -> (i16, i16) {
+        let mut machine = idle(PhysicsConfig::default()).simulate(&[Event::Run, Event::Jump]);
+        let mut airtime = 0;
+        while machine.state_kind() == StateKind::Jumping {
+            machine.context_mut().apply_external_force(force);
+            machine = machine.transition(Event::Update);
+            airtime += 1;
+        }
+        (machine.context().position.x, airtime)
+    }
+
+    #[test]
+    fn a_tailwind_carries_a_jump_its_force_further_each_tick() {
+        let (plain, airtime) = jump_landing(0);
+        let (windy, windy_airtime) = jump_landing(2);
+
+        assert_eq!(windy_airtime, airtime);
+        assert_eq!(windy - plain, 2 * airtime);
+    }
+
+    #[test]
+    fn overlapping_forces_sum_up_to_the_cap_and_clear_each_tick() {
+        let mut context = *idle(PhysicsConfig::default()).context();
+
+        context.apply_external_force(1);
+        context.apply_external_force(2);
+        assert_eq!(context.external_force, 3);
+        context.apply_external_force(3);
+        assert_eq!(context.external_force, MAX_EXTERNAL_FORCE);
+        context.apply_external_force(-3 * MAX_EXTERNAL_FORCE);
+        assert_eq!(context.external_force, -MAX_EXTERNAL_FORCE);
+
+        let x = context.position.x;
+        let context = context.update(0);
+        assert_eq!(context.position.x, x - MAX_EXTERNAL_FORCE);
+        assert_eq!(context.external_force, 0);
+    }
+
+    /// A boy up to speed on `surface`, which he lands on again each tick
+    /// like the game does while he stands on it.
+    fn running_on(surface: Surface) -> RedHatBoyStateMachine {
+        let on = Event::Land(crate::GROUND, surface);
+        idle(PhysicsConfig::default())
+            .transition(Event::Run)
+            .simulate(&[on, Event::Update].repeat(10))
+    }
+
+    /// How far a boy running on `surface` travels once told to stop.
+    fn stop_distance(surface: Surface) -> i16 {
+        let on = Event::Land(crate::GROUND, surface);
+        let mut machine = running_on(surface).simulate(&[on, Event::Stop]);
+        let from = machine.context().position.x;
+        for _ in 0..100 {
+            machine = machine.simulate(&[on, Event::Update]);
+        }
+        assert_eq!(machine.context().velocity.x, 0);
+        machine.context().position.x - from
+    }
+
+    #[test]
+    fn a_boy_on_ice_slides_to_a_stop_and_on_ground_stops_dead() {
+        assert_eq!(stop_distance(Surface::Ground), 0);
+        // Easing off by one a tick from the running speed of 4.
+        assert_eq!(stop_distance(Surface::Ice), 3 + 2 + 1);
+    }
+
+    #[test]
+    fn off_ice_speed_changes_are_instant() {
+        let physics = PhysicsConfig::default();
+
+        let running = idle(physics).transition(Event::Run);
+        assert_eq!(running.context().velocity.x, physics.running_speed);
+        assert_eq!(running.transition(Event::Stop).context().velocity.x, 0);
+
+        // One update without landing on the ice again leaves it behind.
+        let stepped_off = running_on(Surface::Ice).transition(Event::Update);
+        assert_eq!(stepped_off.context().surface, Surface::Ground);
+        assert_eq!(stepped_off.transition(Event::Stop).context().velocity.x, 0);
+    }
+
+    #[test]
+    fn rapid_reversals_turn_the_boy_once_per_change_of_sign() {
+        let mut machine = idle(PhysicsConfig::default()).transition(Event::Run);
+        let mut turns = Vec::new();
+
+        for velocity in [4, -4, -4, 0, 0, -4, 4, 0, 4, -4, 4] {
+            machine.context_mut().target_velocity_x = velocity;
+            let (updated, turned) = machine.update();
+            machine = updated;
+            turns.push(turned);
+        }
+
+        use Direction::*;
+        assert_eq!(
+            turns,
+            [
+                None,
+                Some(Left),
+                None,
+                None,
+                None,
+                None,
+                Some(Right),
+                None,
+                None,
+                Some(Left),
+                Some(Right)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_wall_jump_pushes_off_the_wall_and_landing_resumes_the_run() {
+        let physics = PhysicsConfig::default();
+
+        let clinging = jumping(physics).transition(Event::WallContact(Direction::Right));
+        assert_eq!(clinging.state_kind(), StateKind::WallSliding);
+        assert_eq!(clinging.context().velocity.x, 0);
+
+        let wall_jump = clinging.transition(Event::Jump);
+        assert_eq!(wall_jump.state_kind(), StateKind::Jumping);
+        assert_eq!(
+            wall_jump.context().velocity,
+            Point {
+                x: -physics.wall_jump_speed_x,
+                y: physics.wall_jump_speed_y,
+            }
+        );
+
+        let landed = wall_jump.transition(Event::Land(600, Surface::Ground));
+        assert_eq!(landed.state_kind(), StateKind::Landing);
+        assert_eq!(landed.context().target_velocity_x, physics.running_speed);
+        assert_eq!(landed.context().run_velocity_x, None);
+    }
+
+    #[test]
+    fn a_wall_behind_the_boy_does_not_stop_him() {
+        let machine =
+            jumping(PhysicsConfig::default()).transition(Event::WallContact(Direction::Left));
+
+        assert_eq!(machine.state_kind(), StateKind::Jumping);
+    }
+
+    #[test]
+    fn a_heading_faces_the_boy_along_its_horizontal() {
+        assert_eq!(Direction::along(Heading::UpLeft), Some(Direction::Left));
+        assert_eq!(Direction::along(Heading::Right), Some(Direction::Right));
+        assert_eq!(Direction::along(Heading::DownRight), Some(Direction::Right));
+        assert_eq!(Direction::along(Heading::Down), None);
+    }
+
+    #[test]
+    fn a_knock_out_stops_the_boy_where_he_was_hit_by_default() {
+        let machine = idle(PhysicsConfig::default())
+            .simulate(&[Event::Run, Event::Update])
+            .transition(Event::KnockOut);
+
+        assert_eq!(machine.state_kind(), StateKind::Falling);
+        assert_eq!(machine.context().velocity, Point { x: 0, y: 0 });
+        assert_eq!(machine.context().target_velocity_x, 0);
+    }
+
+    #[test]
+    fn a_knock_back_throws_the_boy_back_and_up() {
+        let physics = PhysicsConfig {
+            knock_back: Some(Point { x: 4, y: 8 }),
+            ..PhysicsConfig::default()
+        };
+
+        let machine = idle(physics)
+            .simulate(&[Event::Run, Event::Update])
+            .transition(Event::KnockOut);
+
+        assert_eq!(machine.state_kind(), StateKind::Falling);
+        assert_eq!(machine.context().velocity, Point { x: -4, y: -8 });
+        assert_eq!(machine.context().target_velocity_x, -4);
+    }
+
+    #[test]
+    fn a_plain_jump_peaks_at_the_sum_of_its_rising_speeds() {
+        // Gravity takes one off the speed before each step, so the boy
+        // rises 24 + 23 + ... + 1 before he stops.
+        assert_eq!(max_jump_height(), (1..-JUMP_SPEED).sum::<i16>());
+    }
+
+    #[test]
+    fn a_plain_jump_covers_its_airtime_times_the_speed() {
+        // 24 steps up and one at the peak. Falling tops out at terminal
+        // velocity, so the 300 back down take 20 steps to reach it and 5
+        // more at it.
+        let airtime = 24 + 1 + 20 + 5;
+
+        assert_eq!(max_jump_distance(1), airtime);
+        assert_eq!(max_jump_distance(0), 0);
+        assert_eq!(max_jump_distance(4), 4 * airtime);
+        assert_eq!(max_jump_distance(10), 10 * airtime);
+    }
+
+    #[test]
+    fn grounded_states_turn_on_his_feet_and_airborne_ones_on_his_middle() {
+        use crate::Anchor;
+
+        assert_eq!(StateKind::Running.anchor(), Anchor::BottomCenter);
+        assert_eq!(StateKind::Idle.anchor(), Anchor::BottomCenter);
+        assert_eq!(StateKind::Jumping.anchor(), Anchor::Center);
+        assert_eq!(StateKind::Falling.anchor(), Anchor::Center);
+    }
+
+    #[test]
+    fn a_running_boy_steps_up_onto_higher_ground_and_falls_into_a_gap() {
+        use crate::GROUND;
+        let floor = GROUND - PLAYER_HEIGHT;
+        let running = |ground| {
+            let mut machine = idle(PhysicsConfig::default()).transition(Event::Run);
+            machine.context_mut().set_ground(ground);
+            machine.transition(Event::Update)
+        };
+
+        let stepped = running(Some(GROUND - 6));
+        assert_eq!(stepped.context().position.y, floor - 6);
+        assert_eq!(stepped.state_kind(), StateKind::Running);
+
+        let mut falling = running(None);
+        for _ in 0..10 {
+            falling.context_mut().set_ground(None);
+            falling = falling.transition(Event::Update);
+        }
+        assert!(falling.context().position.y > floor);
+    }
+
+    /// The top of a ledge his feet are `below` the edge of.
+    fn ledge_top(machine: &RedHatBoyStateMachine, below: i16) -> i16 {
+        machine.context().position.y + PLAYER_HEIGHT - below
+    }
+
+    #[test]
+    fn a_grabbed_ledge_is_climbed_and_the_run_resumes_on_top() {
+        let physics = PhysicsConfig::default();
+        let machine = jumping(physics);
+        let top = ledge_top(&machine, 6);
+
+        let climbing = machine.transition(Event::LedgeContact(Direction::Right, top));
+        assert_eq!(climbing.state_kind(), StateKind::Climbing);
+
+        let climbed = climbing.simulate(&[Event::Update; 10]);
+        assert_eq!(climbed.state_kind(), StateKind::Running);
+        assert_eq!(climbed.context().position.y, top - PLAYER_HEIGHT);
+        assert_eq!(climbed.context().velocity.y, 0);
+        assert_eq!(climbed.context().target_velocity_x, physics.running_speed);
+    }
+
+    #[test]
+    fn a_fast_fall_carries_the_boy_past_the_ledge() {
+        let grab = LedgeGrab::default();
+        let mut machine = jumping(PhysicsConfig::default());
+        machine.context_mut().velocity.y = grab.max_fall_speed + 1;
+        let top = ledge_top(&machine, 6);
+
+        let machine = machine.transition(Event::LedgeContact(Direction::Right, top));
+
+        assert_eq!(machine.state_kind(), StateKind::Jumping);
+    }
+
+    #[test]
+    fn hardcore_physics_never_grabs_a_ledge() {
+        let machine = jumping(PhysicsConfig {
+            ledge_grab: None,
+            ..PhysicsConfig::default()
+        });
+        let top = ledge_top(&machine, 6);
+
+        let machine = machine.transition(Event::LedgeContact(Direction::Right, top));
+
+        assert_eq!(machine.state_kind(), StateKind::Jumping);
+    }
+
+    #[test]
+    fn a_jetpack_climbs_no_faster_and_no_higher_than_its_caps() {
+        let mut machine =
+            idle(PhysicsConfig::default()).simulate(&[Event::Run, Event::Jetpack(u16::MAX)]);
+
+        for _ in 0..200 {
+            machine = machine.simulate(&[Event::Thrust, Event::Update]);
+            // Thrust never climbs faster than the jetpack's top ascent.
+            assert!(machine.context().velocity.y >= -8);
+        }
+
+        assert_eq!(machine.state_kind(), StateKind::Jetpack);
+        // Held at the top of the screen rather than flown off it.
+        assert_eq!(machine.context().position.y, 0);
+    }
+
+    #[test]
+    fn running_out_of_fuel_mid_air_drops_the_boy_into_a_fall() {
+        let machine = idle(PhysicsConfig::default())
+            .simulate(&[Event::Run, Event::Jetpack(10)])
+            .simulate(&[Event::Thrust, Event::Update].repeat(9));
+        assert_eq!(machine.state_kind(), StateKind::Jetpack);
+
+        let machine = machine.simulate(&[Event::Thrust, Event::Update]);
+
+        assert_eq!(machine.state_kind(), StateKind::Jumping);
+        assert!(machine.context().position.y + PLAYER_HEIGHT < crate::GROUND);
+    }
+
+    #[test]
+    fn idle_handles_running_updates_landing_tipping_off_and_jetpacks() {
+        use EventKind::*;
+
+        assert_eq!(
+            idle(PhysicsConfig::default()).handled_events(),
+            &[Run, Update, Land, TipOff, Jetpack]
+        );
+    }
+
+    /// An event of each kind, with arbitrary data.
+    fn event_of(kind: EventKind) -> Event {
+        match kind {
+            EventKind::Run => Event::Run,
+            EventKind::Stop => Event::Stop,
+            EventKind::Slide => Event::Slide,
+            EventKind::Jump => Event::Jump,
+            EventKind::SuperJump => Event::SuperJump,
+            EventKind::Update => Event::Update,
+            EventKind::KnockOut => Event::KnockOut,
+            EventKind::Land => Event::Land(400, Surface::Ground),
+            EventKind::WallContact => Event::WallContact(Direction::Right),
+            EventKind::LedgeContact => Event::LedgeContact(Direction::Right, 400),
+            EventKind::Jetpack => Event::Jetpack(10),
+            EventKind::Thrust => Event::Thrust,
+            EventKind::TipOff => Event::TipOff,
+        }
+    }
+
+    #[test]
+    fn every_event_a_state_does_not_list_leaves_the_machine_alone() {
+        use EventKind::*;
+        let states = [
+            StateKind::Idle,
+            StateKind::Running,
+            StateKind::Sliding,
+            StateKind::Jumping,
+            StateKind::Landing,
+            StateKind::WallSliding,
+            StateKind::Climbing,
+            StateKind::Jetpack,
+            StateKind::Falling,
+            StateKind::KnockedOut,
+        ];
+        let kinds = [
+            Run,
+            Stop,
+            Slide,
+            Jump,
+            SuperJump,
+            Update,
+            KnockOut,
+            Land,
+            WallContact,
+            LedgeContact,
+            Jetpack,
+            Thrust,
+            TipOff,
+        ];
+        let context = *idle(PhysicsConfig::default()).context();
+
+        for state in states {
+            let machine = RedHatBoyStateMachine::restore(state, context);
+            for kind in kinds {
+                if machine.handled_events().contains(&kind) {
+                    continue;
+                }
+                let after = machine.transition(event_of(kind));
+                assert_eq!(after.state_kind(), state, "{:?} on {:?}", kind, state);
+                assert_eq!(after.context().frame, context.frame);
+                assert_eq!(after.context().position, context.position);
+                assert_eq!(after.context().velocity, context.velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn a_jump_lands_crouches_for_its_frames_and_runs_on() {
+        let mut machine = jumping(PhysicsConfig::default());
+        while machine.state_kind() == StateKind::Jumping {
+            machine = machine.transition(Event::Update);
+        }
+        assert_eq!(machine.state_kind(), StateKind::Landing);
+        let speed = machine.context().velocity.x;
+
+        for _ in 1..LANDING_FRAMES {
+            machine = machine.transition(Event::Update);
+            assert_eq!(machine.state_kind(), StateKind::Landing);
+        }
+        machine = machine.transition(Event::Update);
+
+        assert_eq!(machine.state_kind(), StateKind::Running);
+        assert_eq!(machine.context().frame, 0);
+        assert_eq!(machine.context().velocity.x, speed);
+        assert_eq!(machine.context().position.y, crate::GROUND - PLAYER_HEIGHT);
+    }
+
+    #[test]
+    fn running_over_a_ramp_follows_its_incline_without_falling_through() {
+        const SNAP: i16 = 8;
+        let (low, high) = (550, 500);
+        let slopes = [
+            Segment::new(Point { x: -100, y: low }, Point { x: 40, y: low }),
+            Segment::new(Point { x: 40, y: low }, Point { x: 140, y: high }),
+            Segment::new(Point { x: 140, y: high }, Point { x: 300, y: high }),
+        ];
+        let surface_at = |x| {
+            slopes
+                .iter()
+                .filter_map(|slope| slope.y_at(x))
+                .min()
+                .unwrap()
+        };
+        let mut machine = idle(PhysicsConfig::default())
+            .simulate(&[Event::Land(low, Surface::Ground), Event::Run]);
+
+        let mut heights = Vec::new();
+        while machine.context().position.x < 280 {
+            machine = machine.transition(Event::Update);
+            let position = machine.context().position;
+            let feet = Point {
+                x: position.x,
+                y: position.y + PLAYER_HEIGHT,
+            };
+            let top = slope_top(&slopes, feet, SNAP);
+            assert_eq!(top, Some(surface_at(feet.x)), "fell through at {feet:?}");
+            machine = machine.transition(Event::Land(top.unwrap(), Surface::Ground));
+            assert_eq!(machine.state_kind(), StateKind::Running);
+            heights.push(machine.context().position.y + PLAYER_HEIGHT);
+        }
+
+        assert_eq!(heights.first(), Some(&low));
+        assert_eq!(heights.last(), Some(&high));
+        assert!(heights.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(heights.iter().any(|y| high < *y && *y < low));
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Timer;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StaminaConfig {
+    pub max: f32,
+    pub jump_cost: f32,
+    /// Stamina regained each update tick once the delay has passed.
+    pub regen_per_tick: f32,
+    /// Ticks after spending before regeneration starts.
+    pub regen_delay: u32,
+}
+
+impl Default for StaminaConfig {
+    fn default() -> Self {
+        StaminaConfig {
+            max: 100.0,
+            jump_cost: 40.0,
+            regen_per_tick: 0.5,
+            regen_delay: 30,
+        }
+    }
+}
+
+/// Energy spent on jumps that refills over time.
+#[derive(Clone, Copy, Debug)]
+pub struct Stamina {
+    config: StaminaConfig,
+    value: f32,
+    regen_delay: Timer,
+}
+
+impl Stamina {
+    pub fn new(config: StaminaConfig) -> Self {
+        Stamina {
+            config,
+            value: config.max,
+            regen_delay: Timer::default(),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.regen_delay.is_active() {
+            self.regen_delay.tick();
+        } else {
+            self.value = (self.value + self.config.regen_per_tick).min(self.config.max);
+        }
+    }
+
+    pub fn can_jump(&self) -> bool {
+        self.value >= self.config.jump_cost
+    }
+
+    pub fn spend_jump(&mut self) {
+        self.value = (self.value - self.config.jump_cost).max(0.0);
+        self.regen_delay = Timer::new(self.config.regen_delay);
+    }
+
+    pub fn refill(&mut self) {
+        self.value = self.config.max;
+        self.regen_delay = Timer::default();
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.config.max > 0.0 {
+            self.value / self.config.max
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: StaminaConfig = StaminaConfig {
+        max: 100.0,
+        jump_cost: 40.0,
+        regen_per_tick: 10.0,
+        regen_delay: 3,
+    };
+
+    #[test]
+    fn jumps_drain_stamina_until_they_are_denied() {
+        let mut stamina = Stamina::new(CONFIG);
+        stamina.spend_jump();
+        stamina.spend_jump();
+
+        assert!(!stamina.can_jump());
+        assert_eq!(stamina.fraction(), 0.2);
+    }
+
+    #[test]
+    fn stamina_refills_after_the_delay() {
+        let mut stamina = Stamina::new(CONFIG);
+        stamina.spend_jump();
+        stamina.spend_jump();
+        stamina.spend_jump();
+        assert_eq!(stamina.fraction(), 0.0);
+
+        for _ in 0..CONFIG.regen_delay {
+            stamina.tick();
+        }
+        assert_eq!(stamina.fraction(), 0.0);
+
+        for _ in 0..4 {
+            stamina.tick();
+        }
+        assert!(stamina.can_jump());
+
+        for _ in 0..20 {
+            stamina.tick();
+        }
+        assert_eq!(stamina.fraction(), 1.0);
+    }
+}
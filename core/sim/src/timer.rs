@@ -0,0 +1,144 @@
+pub const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
+
+/// Counts down a timed effect in fixed update ticks.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Timer {
+    remaining: u32,
+    duration: u32,
+}
+
+impl Timer {
+    pub fn new(ticks: u32) -> Self {
+        Timer {
+            remaining: ticks,
+            duration: ticks,
+        }
+    }
+
+    /// Rounds up to whole updates, so a short duration still lasts one.
+    pub fn from_millis(millis: f32) -> Self {
+        Timer::new((millis / FRAME_SIZE).ceil().max(0.0) as u32)
+    }
+
+    pub fn tick(&mut self) {
+        self.remaining = self.remaining.saturating_sub(1);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.is_active()
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// 1.0 when just started, falling to 0.0 when finished.
+    pub fn remaining_fraction(&self) -> f32 {
+        if self.duration == 0 {
+            return 0.0;
+        }
+        self.remaining as f32 / self.duration as f32
+    }
+
+    /// Starts the same duration again.
+    pub fn reset(&mut self) {
+        self.remaining = self.duration;
+    }
+}
+
+/// An action that can be used once, then has to wait out its timer.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Cooldown {
+    timer: Timer,
+}
+
+impl Cooldown {
+    /// Starts out ready.
+    pub fn new(ticks: u32) -> Self {
+        Cooldown {
+            timer: Timer {
+                remaining: 0,
+                duration: ticks,
+            },
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.timer.tick();
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.timer.is_finished()
+    }
+
+    /// Returns whether the action may happen now, restarting the wait if so.
+    pub fn try_use(&mut self) -> bool {
+        if !self.is_ready() {
+            return false;
+        }
+        self.timer.reset();
+        true
+    }
+
+    /// Makes the action available straight away.
+    pub fn clear(&mut self) {
+        self.timer.remaining = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_timer_finishes_after_its_ticks() {
+        let mut timer = Timer::new(3);
+        assert!(timer.is_active());
+        assert_eq!(timer.remaining_fraction(), 1.0);
+
+        timer.tick();
+        timer.tick();
+        assert_eq!(timer.remaining(), 1);
+        timer.tick();
+        assert!(timer.is_finished());
+        assert_eq!(timer.remaining_fraction(), 0.0);
+
+        timer.tick();
+        assert_eq!(timer.remaining(), 0);
+        timer.reset();
+        assert_eq!(timer.remaining(), timer.duration());
+    }
+
+    #[test]
+    fn from_millis_rounds_up_to_whole_ticks() {
+        assert_eq!(Timer::from_millis(1000.0).duration(), 60);
+        assert_eq!(Timer::from_millis(1.0).duration(), 1);
+        assert_eq!(Timer::from_millis(0.0).duration(), 0);
+        assert!(Timer::default().is_finished());
+        assert_eq!(Timer::default().remaining_fraction(), 0.0);
+    }
+
+    #[test]
+    fn a_cooldown_starts_ready_and_waits_after_use() {
+        let mut cooldown = Cooldown::new(2);
+        assert!(cooldown.try_use());
+        assert!(!cooldown.is_ready());
+        assert!(!cooldown.try_use());
+
+        cooldown.tick();
+        assert!(!cooldown.try_use());
+        cooldown.tick();
+        assert!(cooldown.try_use());
+
+        cooldown.clear();
+        assert!(cooldown.try_use());
+    }
+}
@@ -0,0 +1,213 @@
+//! Eased interpolation for menus, popups and anything else that should
+//! glide rather than snap.
+
+use crate::Point;
+
+/// Maps progress from `0.0..=1.0` onto eased progress. Every curve starts
+/// at 0.0 and ends at 1.0.
+pub type Easing = fn(f32) -> f32;
+
+pub fn linear(t: f32) -> f32 {
+    t
+}
+
+pub fn ease_in_quad(t: f32) -> f32 {
+    t * t
+}
+
+pub fn ease_out_quad(t: f32) -> f32 {
+    t * (2.0 - t)
+}
+
+pub fn ease_in_out_quad(t: f32) -> f32 {
+    if t < 0.5 {
+        2.0 * t * t
+    } else {
+        -1.0 + (4.0 - 2.0 * t) * t
+    }
+}
+
+pub fn ease_in_out_cubic(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        let u = 2.0 * t - 2.0;
+        1.0 + u * u * u / 2.0
+    }
+}
+
+pub fn ease_in_out_sine(t: f32) -> f32 {
+    (1.0 - (t * std::f32::consts::PI).cos()) / 2.0
+}
+
+/// Reaches the end early, then drops back and returns to it in a few
+/// shrinking bounces, never going past it.
+pub fn ease_out_bounce(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+pub trait Lerp: Copy {
+    /// The value `t` of the way from `self` to `to`.
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for Point {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        Point {
+            x: f32::from(self.x).lerp(f32::from(to.x), t).round() as i16,
+            y: f32::from(self.y).lerp(f32::from(to.y), t).round() as i16,
+        }
+    }
+}
+
+/// An opacity, kept within `0.0..=1.0` even when an easing overshoots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alpha(pub f32);
+
+impl Lerp for Alpha {
+    fn lerp(self, to: Self, t: f32) -> Self {
+        Alpha(self.0.lerp(to.0, t).clamp(0.0, 1.0))
+    }
+}
+
+/// Drives a value from `from` to `to` over a number of update ticks.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<T: Lerp> {
+    from: T,
+    to: T,
+    ticks: u32,
+    elapsed: u32,
+    easing: Easing,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, ticks: u32, easing: Easing) -> Self {
+        Tween {
+            from,
+            to,
+            ticks,
+            elapsed: 0,
+            easing,
+        }
+    }
+
+    /// A tween that has already arrived at `value`.
+    pub fn at(value: T) -> Self {
+        Tween::new(value, value, 0, linear)
+    }
+
+    pub fn tick(&mut self) {
+        self.elapsed = (self.elapsed + 1).min(self.ticks);
+    }
+
+    pub fn value(&self) -> T {
+        if self.ticks == 0 {
+            return self.to;
+        }
+        let progress = self.elapsed as f32 / self.ticks as f32;
+        self.from.lerp(self.to, (self.easing)(progress))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.ticks
+    }
+
+    /// Heads for `to` from wherever the tween is now, over its full length.
+    pub fn retarget(&mut self, to: T) {
+        self.from = self.value();
+        self.to = to;
+        self.elapsed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONOTONIC: [(&str, Easing); 6] = [
+        ("linear", linear),
+        ("ease_in_quad", ease_in_quad),
+        ("ease_out_quad", ease_out_quad),
+        ("ease_in_out_quad", ease_in_out_quad),
+        ("ease_in_out_cubic", ease_in_out_cubic),
+        ("ease_in_out_sine", ease_in_out_sine),
+    ];
+
+    fn samples() -> impl Iterator<Item = f32> {
+        (0..=100).map(|step| step as f32 / 100.0)
+    }
+
+    #[test]
+    fn every_easing_starts_at_zero_and_ends_at_one() {
+        for (name, easing) in MONOTONIC
+            .into_iter()
+            .chain([("ease_out_bounce", ease_out_bounce as Easing)])
+        {
+            assert!(
+                easing(0.0).abs() < 1e-6,
+                "{} starts at {}",
+                name,
+                easing(0.0)
+            );
+            assert!(
+                (easing(1.0) - 1.0).abs() < 1e-6,
+                "{} ends at {}",
+                name,
+                easing(1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_easings_never_move_backwards() {
+        for (name, easing) in MONOTONIC {
+            let values: Vec<f32> = samples().map(easing).collect();
+            assert!(
+                values.windows(2).all(|pair| pair[1] >= pair[0]),
+                "{} moves backwards",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn ease_out_bounce_bounces_without_overshooting() {
+        let values: Vec<f32> = samples().map(ease_out_bounce).collect();
+
+        assert!(values.iter().all(|value| (0.0..=1.0).contains(value)));
+        assert!(values.windows(2).any(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn a_tween_arrives_after_its_ticks() {
+        let mut tween = Tween::new(0.0, 10.0, 4, linear);
+        tween.tick();
+        tween.tick();
+        assert_eq!(tween.value(), 5.0);
+
+        tween.tick();
+        tween.tick();
+        tween.tick();
+        assert!(tween.is_finished());
+        assert_eq!(tween.value(), 10.0);
+    }
+}
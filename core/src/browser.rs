@@ -1,19 +1,41 @@
 use anyhow::{anyhow, Result};
-use std::future::Future;
+use futures::{
+    channel::oneshot::{channel, Receiver},
+    FutureExt,
+};
+use js_sys::{ArrayBuffer, Function, Promise, Reflect};
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
 use wasm_bindgen::{
     closure::WasmClosure, closure::WasmClosureFnOnce, prelude::Closure, JsCast, JsValue,
 };
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    CanvasRenderingContext2d, Document, HtmlCanvasElement, HtmlImageElement, Response, Window,
+    Blob, CanvasRenderingContext2d, CustomEvent, CustomEventInit, Document, Element, EventTarget,
+    HtmlAnchorElement, HtmlCanvasElement, HtmlImageElement, RequestInit, Response, Storage, Url,
+    Window,
 };
 
+#[cfg(target_arch = "wasm32")]
 macro_rules! log {
     ( $($t:tt)* ) => {
         web_sys::console::log_1(&format!( $($t)* ).into());
     }
 }
 
+// Native test builds have no console to log to.
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! log {
+    ( $($t:tt)* ) => {
+        eprintln!( $($t)* )
+    }
+}
+
 pub fn window() -> Result<Window> {
     web_sys::window().ok_or_else(|| anyhow!("No Window Found"))
 }
@@ -24,16 +46,172 @@ pub fn document() -> Result<Document> {
         .ok_or_else(|| anyhow!("No Document Found"))
 }
 
-pub fn canvas() -> Result<HtmlCanvasElement> {
+pub fn is_page_hidden() -> bool {
+    document().is_ok_and(|document| document.hidden())
+}
+
+pub fn has_focus(element: &Element) -> bool {
+    document()
+        .ok()
+        .and_then(|document| document.active_element())
+        .is_some_and(|active| &active == element)
+}
+
+/// Makes the canvas focusable and focuses it, so key events reach it
+/// after the page has been clicked elsewhere.
+pub fn focus_canvas(canvas: &HtmlCanvasElement) -> Result<()> {
+    if canvas.tab_index() < 0 {
+        canvas.set_tab_index(0);
+    }
+    canvas
+        .focus()
+        .map_err(|err| anyhow!("Could not focus canvas {:#?}", err))
+}
+
+/// The browser's preferred language, such as `en-US`.
+pub fn language() -> String {
+    window()
+        .ok()
+        .and_then(|window| window.navigator().language())
+        .unwrap_or_else(|| "en".to_string())
+}
+
+pub fn query_params() -> Result<HashMap<String, String>> {
+    let search = window()?
+        .location()
+        .search()
+        .map_err(|err| anyhow!("Could not read location.search {:#?}", err))?;
+
+    Ok(parse_query(&search))
+}
+
+fn parse_query(search: &str) -> HashMap<String, String> {
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_query_component(key), decode_query_component(value))
+        })
+        .collect()
+}
+
+/// Undoes `+` and `%XX` escapes, leaving the component as it was if the
+/// escapes don't decode to UTF-8.
+fn decode_query_component(component: &str) -> String {
+    let component = component.replace('+', " ");
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut rest = component.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|_| byte == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).unwrap_or(component)
+}
+
+pub fn canvas_by_id(id: &str) -> Result<HtmlCanvasElement> {
     document()?
-        .get_element_by_id("canvas")
-        .ok_or_else(|| anyhow!("No Canvas Element found with ID 'canvas'"))?
+        .get_element_by_id(id)
+        .ok_or_else(|| anyhow!("No Canvas Element found with ID '{}'", id))?
         .dyn_into::<web_sys::HtmlCanvasElement>()
         .map_err(|element| anyhow!("Error converting {:#?} to HtmlCanvasElement", element))
 }
 
-pub fn context() -> Result<CanvasRenderingContext2d> {
-    canvas()?
+/// The canvas the HUD is drawn on. One the game added to the page is taken
+/// off it again when this is dropped; one the page provided stays.
+pub struct HudCanvas {
+    canvas: HtmlCanvasElement,
+    created: bool,
+}
+
+impl HudCanvas {
+    pub fn canvas(&self) -> &HtmlCanvasElement {
+        &self.canvas
+    }
+}
+
+impl Drop for HudCanvas {
+    fn drop(&mut self) {
+        if self.created {
+            self.canvas.remove();
+        }
+    }
+}
+
+/// Finds or creates a canvas stacked over `canvas` for the HUD. It lets
+/// the pointer through, so clicks and touches still land on `canvas`. Only
+/// a canvas with an id has one to find, at `{id}-hud`; one without always
+/// gets a layer of its own.
+pub fn hud_canvas(canvas: &HtmlCanvasElement) -> Result<HudCanvas> {
+    let id = (!canvas.id().is_empty()).then(|| format!("{}-hud", canvas.id()));
+    let existing = match &id {
+        Some(id) => document()?.get_element_by_id(id),
+        None => None,
+    };
+    if let Some(element) = existing {
+        return element
+            .dyn_into::<HtmlCanvasElement>()
+            .map(|canvas| HudCanvas {
+                canvas,
+                created: false,
+            })
+            .map_err(|element| anyhow!("Error converting {:#?} to HtmlCanvasElement", element));
+    }
+    if canvas.parent_element().is_none() {
+        return Err(anyhow!("Canvas has no parent to hold a HUD layer"));
+    }
+
+    let hud = document()?
+        .create_element("canvas")
+        .map_err(|err| anyhow!("Could not create HUD canvas {:#?}", err))?
+        .dyn_into::<HtmlCanvasElement>()
+        .map_err(|element| anyhow!("Error converting {:#?} to HtmlCanvasElement", element))?;
+    if let Some(id) = &id {
+        hud.set_id(id);
+    }
+    hud.set_width(canvas.width());
+    hud.set_height(canvas.height());
+    canvas
+        .insert_adjacent_element("afterend", &hud)
+        .map_err(|err| anyhow!("Could not add HUD canvas {:#?}", err))?;
+    set_canvas_style(&hud, "position", "absolute")?;
+    set_canvas_style(&hud, "pointer-events", "none")?;
+    place_over(&hud, canvas)?;
+    Ok(HudCanvas {
+        canvas: hud,
+        created: true,
+    })
+}
+
+/// Lines `layer` up with `canvas`, for when the page reflows.
+pub fn place_over(layer: &HtmlCanvasElement, canvas: &HtmlCanvasElement) -> Result<()> {
+    for (property, value) in [
+        ("left", canvas.offset_left()),
+        ("top", canvas.offset_top()),
+        ("width", canvas.offset_width()),
+        ("height", canvas.offset_height()),
+    ] {
+        set_canvas_style(layer, property, &format!("{}px", value))?;
+    }
+    Ok(())
+}
+
+pub fn context_from(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d> {
+    canvas
         .get_context("2d")
         .map_err(|js_value| anyhow!("Error getting 2d context {:#?}", js_value))?
         .ok_or_else(|| anyhow!("No 2d context found"))?
@@ -46,6 +224,147 @@ pub fn context() -> Result<CanvasRenderingContext2d> {
         })
 }
 
+pub fn set_canvas_cursor(canvas: &HtmlCanvasElement, style: &str) -> Result<()> {
+    set_canvas_style(canvas, "cursor", style)
+}
+
+pub fn set_canvas_style(canvas: &HtmlCanvasElement, property: &str, value: &str) -> Result<()> {
+    canvas
+        .style()
+        .set_property(property, value)
+        .map_err(|err| anyhow!("Could not set canvas {} {:#?}", property, err))
+}
+
+/// The canvas element's box in client coordinates, as left, top, width and
+/// height in CSS pixels.
+pub fn canvas_bounds(canvas: &HtmlCanvasElement) -> (f64, f64, f64, f64) {
+    let bounds = canvas.get_bounding_client_rect();
+    (bounds.left(), bounds.top(), bounds.width(), bounds.height())
+}
+
+pub fn device_pixel_ratio() -> Result<f64> {
+    Ok(window()?.device_pixel_ratio())
+}
+
+pub fn inner_size() -> Result<(f64, f64)> {
+    let window = window()?;
+    let width = window
+        .inner_width()
+        .map_err(|err| anyhow!("Could not read innerWidth {:#?}", err))?
+        .as_f64()
+        .ok_or_else(|| anyhow!("innerWidth is not a number"))?;
+    let height = window
+        .inner_height()
+        .map_err(|err| anyhow!("Could not read innerHeight {:#?}", err))?
+        .as_f64()
+        .ok_or_else(|| anyhow!("innerHeight is not a number"))?;
+    Ok((width, height))
+}
+
+pub fn is_fullscreen() -> Result<bool> {
+    Ok(document()?.fullscreen_element().is_some())
+}
+
+/// Requests fullscreen for `element`, or exits if something is already
+/// fullscreen. Browsers only honour this inside a user-gesture handler, and
+/// the returned promise rejects where fullscreen is forbidden, such as in
+/// iframes without `allowfullscreen`.
+pub fn toggle_fullscreen(element: &Element) -> Result<Promise> {
+    let (target, method): (JsValue, &str) = if is_fullscreen()? {
+        (document()?.into(), "exitFullscreen")
+    } else {
+        (element.into(), "requestFullscreen")
+    };
+
+    let function: Function = Reflect::get(&target, &JsValue::from(method))
+        .map_err(|err| anyhow!("Could not read {} {:#?}", method, err))?
+        .dyn_into()
+        .map_err(|element| anyhow!("Error converting {:#?} to Function", element))?;
+    function
+        .call0(&target)
+        .map_err(|err| anyhow!("Error calling {} {:#?}", method, err))?
+        .dyn_into()
+        .map_err(|element| anyhow!("Error converting {:#?} to Promise", element))
+}
+
+pub fn local_storage() -> Result<Storage> {
+    window()?
+        .local_storage()
+        .map_err(|err| anyhow!("Could not access localStorage {:#?}", err))?
+        .ok_or_else(|| anyhow!("No localStorage found"))
+}
+
+pub fn storage_get(key: &str) -> Result<Option<String>> {
+    local_storage()?
+        .get_item(key)
+        .map_err(|err| anyhow!("Could not read {} from localStorage {:#?}", key, err))
+}
+
+pub fn storage_set(key: &str, value: &str) -> Result<()> {
+    local_storage()?
+        .set_item(key, value)
+        .map_err(|err| anyhow!("Could not write {} to localStorage {:#?}", key, err))
+}
+
+pub fn storage_remove(key: &str) -> Result<()> {
+    local_storage()?
+        .remove_item(key)
+        .map_err(|err| anyhow!("Could not remove {} from localStorage {:#?}", key, err))
+}
+
+/// Owns an event listener's closure and removes the listener from its
+/// target when dropped, so nothing is left attached or leaked.
+pub struct EventListenerGuard {
+    target: EventTarget,
+    event: String,
+    function: Function,
+    _closure: Box<dyn Any>,
+}
+
+impl EventListenerGuard {
+    pub fn new<T: ?Sized + 'static>(
+        target: &EventTarget,
+        event: &str,
+        listener: Closure<T>,
+    ) -> Result<Self> {
+        let function: Function = listener.as_ref().unchecked_ref::<Function>().clone();
+        target
+            .add_event_listener_with_callback(event, &function)
+            .map_err(|err| anyhow!("Could not listen for {} {:#?}", event, err))?;
+        Ok(EventListenerGuard {
+            target: target.clone(),
+            event: event.to_string(),
+            function,
+            _closure: Box::new(listener),
+        })
+    }
+}
+
+impl Drop for EventListenerGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self
+            .target
+            .remove_event_listener_with_callback(&self.event, &self.function)
+        {
+            log!("Could not remove {} listener {:#?}", self.event, err);
+        }
+    }
+}
+
+pub fn add_window_listener<T: ?Sized + 'static>(
+    event: &str,
+    listener: Closure<T>,
+) -> Result<EventListenerGuard> {
+    EventListenerGuard::new(window()?.as_ref(), event, listener)
+}
+
+pub fn add_document_listener<T: ?Sized + 'static>(
+    event: &str,
+    listener: Closure<T>,
+) -> Result<EventListenerGuard> {
+    EventListenerGuard::new(document()?.as_ref(), event, listener)
+}
+
 pub fn spawn_local<F>(future: F)
 where
     F: Future<Output = ()> + 'static,
@@ -59,11 +378,15 @@ pub async fn fetch_with_str(resource: &str) -> Result<JsValue> {
         .map_err(|err| anyhow!("error fetching {:#?}", err))
 }
 
-pub async fn fetch_json(json_path: &str) -> Result<JsValue> {
-    let resp_value = fetch_with_str(json_path).await?;
-    let resp: Response = resp_value
+async fn fetch_response(resource: &str) -> Result<Response> {
+    fetch_with_str(resource)
+        .await?
         .dyn_into()
-        .map_err(|element| anyhow!("Error converting {:#?} to Response", element))?;
+        .map_err(|element| anyhow!("Error converting {:#?} to Response", element))
+}
+
+pub async fn fetch_json(json_path: &str) -> Result<JsValue> {
+    let resp = fetch_response(json_path).await?;
 
     JsFuture::from(
         resp.json()
@@ -73,6 +396,54 @@ pub async fn fetch_json(json_path: &str) -> Result<JsValue> {
     .map_err(|err| anyhow!("error fetching JSON {:#?}", err))
 }
 
+/// POSTs `body` as JSON, failing on a network error or a non-2xx status.
+pub async fn post_json(url: &str, body: &str) -> Result<()> {
+    let headers = js_sys::Object::new();
+    Reflect::set(&headers, &"Content-Type".into(), &"application/json".into())
+        .map_err(|err| anyhow!("Could not set headers {:#?}", err))?;
+    let mut init = RequestInit::new();
+    init.method("POST")
+        .headers(&headers)
+        .body(Some(&JsValue::from_str(body)));
+
+    let response: Response = JsFuture::from(window()?.fetch_with_str_and_init(url, &init))
+        .await
+        .map_err(|err| anyhow!("error posting to {} {:#?}", url, err))?
+        .dyn_into()
+        .map_err(|element| anyhow!("Error converting {:#?} to Response", element))?;
+    if !response.ok() {
+        return Err(anyhow!("POST to {} failed with {}", url, response.status()));
+    }
+    Ok(())
+}
+
+/// Queues `body` with `navigator.sendBeacon`, which outlives the page.
+/// False when the browser has no beacon or refused it.
+pub fn send_beacon(url: &str, body: &str) -> bool {
+    window()
+        .ok()
+        .and_then(|window| {
+            window
+                .navigator()
+                .send_beacon_with_opt_str(url, Some(body))
+                .ok()
+        })
+        .unwrap_or(false)
+}
+
+pub async fn fetch_array_buffer(resource: &str) -> Result<ArrayBuffer> {
+    let array_buffer = fetch_response(resource)
+        .await?
+        .array_buffer()
+        .map_err(|err| anyhow!("Error loading array buffer {:#?}", err))?;
+
+    JsFuture::from(array_buffer)
+        .await
+        .map_err(|err| anyhow!("Error converting array buffer into a future {:#?}", err))?
+        .dyn_into()
+        .map_err(|err| anyhow!("Error converting raw JSValue to ArrayBuffer {:#?}", err))
+}
+
 pub fn new_image() -> Result<HtmlImageElement> {
     HtmlImageElement::new().map_err(|err| anyhow!("Could not create HtmlImageElement {:#?}", err))
 }
@@ -100,9 +471,246 @@ pub fn request_animation_frame(callback: &LoopClosure) -> Result<i32> {
         .map_err(|err| anyhow!("Cannot request animation frame {:#?}", err))
 }
 
+/// A one-shot browser callback as a future. Dropping it before the
+/// callback fires cancels the callback, and the closure is freed with it
+/// rather than leaked.
+struct BrowserCallback<T, C: ?Sized> {
+    receiver: Receiver<T>,
+    pending: Option<(i32, fn(i32))>,
+    _closure: Closure<C>,
+}
+
+impl<T, C: ?Sized> Future for BrowserCallback<T, C> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = futures::ready!(self.receiver.poll_unpin(cx));
+        self.pending = None;
+        Poll::Ready(result.map_err(|err| anyhow!("Browser callback was dropped {:#?}", err)))
+    }
+}
+
+impl<T, C: ?Sized> Drop for BrowserCallback<T, C> {
+    fn drop(&mut self) {
+        if let Some((handle, cancel)) = self.pending.take() {
+            cancel(handle);
+        }
+    }
+}
+
+fn clear_timeout(handle: i32) {
+    if let Ok(window) = window() {
+        window.clear_timeout_with_handle(handle);
+    }
+}
+
+fn cancel_animation_frame(handle: i32) {
+    if let Err(err) = window().and_then(|window| {
+        window
+            .cancel_animation_frame(handle)
+            .map_err(|err| anyhow!("Cannot cancel animation frame {:#?}", err))
+    }) {
+        log!("{:#?}", err);
+    }
+}
+
+fn set_timeout(ms: u32) -> Result<BrowserCallback<(), dyn FnMut()>> {
+    let (sender, receiver) = channel();
+    let closure = closure_once(move || {
+        let _ = sender.send(());
+    });
+    let handle = window()?
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            closure.as_ref().unchecked_ref(),
+            ms.min(i32::MAX as u32) as i32,
+        )
+        .map_err(|err| anyhow!("Cannot set timeout {:#?}", err))?;
+    Ok(BrowserCallback {
+        receiver,
+        pending: Some((handle, clear_timeout)),
+        _closure: closure,
+    })
+}
+
+fn animation_frame() -> Result<BrowserCallback<f64, dyn FnMut(f64)>> {
+    let (sender, receiver) = channel();
+    let closure = closure_once(move |time: f64| {
+        let _ = sender.send(time);
+    });
+    let handle = window()?
+        .request_animation_frame(closure.as_ref().unchecked_ref())
+        .map_err(|err| anyhow!("Cannot request animation frame {:#?}", err))?;
+    Ok(BrowserCallback {
+        receiver,
+        pending: Some((handle, cancel_animation_frame)),
+        _closure: closure,
+    })
+}
+
+/// Resolves after `ms` milliseconds. Dropping it first clears the timeout.
+pub fn sleep(ms: u32) -> impl Future<Output = ()> {
+    let timeout = set_timeout(ms);
+    async move {
+        if let Err(err) = match timeout {
+            Ok(timeout) => timeout.await,
+            Err(err) => Err(err),
+        } {
+            log!("{:#?}", err);
+        }
+    }
+}
+
+/// Resolves with the timestamp of the next animation frame. Falls back to
+/// `now` if no frame can be requested.
+pub fn next_frame() -> impl Future<Output = f64> {
+    let frame = animation_frame();
+    async move {
+        let result = match frame {
+            Ok(frame) => frame.await,
+            Err(err) => Err(err),
+        };
+        result.unwrap_or_else(|err| {
+            log!("{:#?}", err);
+            now().unwrap_or_default()
+        })
+    }
+}
+
 pub fn now() -> Result<f64> {
     Ok(window()?
         .performance()
         .ok_or_else(|| anyhow!("Performance object not found"))?
         .now())
 }
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+/// Whole days since the Unix epoch, in UTC, so every player shares a day
+/// whatever their time zone.
+pub fn today() -> u32 {
+    (js_sys::Date::now() / MS_PER_DAY) as u32
+}
+
+pub fn call_function(function: &Function, arg: &JsValue) {
+    if let Err(err) = function.call1(&JsValue::NULL, arg) {
+        log!("Callback threw an error {:#?}", err);
+    }
+}
+
+pub fn dispatch_custom_event(target: &EventTarget, name: &str, detail: &JsValue) -> Result<()> {
+    let event = CustomEvent::new_with_event_init_dict(
+        name,
+        CustomEventInit::new().bubbles(true).detail(detail),
+    )
+    .map_err(|err| anyhow!("Could not create event {} {:#?}", name, err))?;
+
+    target
+        .dispatch_event(&event)
+        .map(|_| ())
+        .map_err(|err| anyhow!("Could not dispatch event {} {:#?}", name, err))
+}
+
+pub async fn copy_to_clipboard(text: &str) -> Result<()> {
+    let navigator = window()?.navigator();
+    let clipboard = Reflect::get(&navigator, &JsValue::from("clipboard"))
+        .map_err(|err| anyhow!("Could not read navigator.clipboard {:#?}", err))?;
+    if clipboard.is_undefined() {
+        return Err(anyhow!("Clipboard API is not available"));
+    }
+
+    let write_text: Function = Reflect::get(&clipboard, &JsValue::from("writeText"))
+        .map_err(|err| anyhow!("Could not read clipboard.writeText {:#?}", err))?
+        .dyn_into()
+        .map_err(|element| anyhow!("Error converting {:#?} to Function", element))?;
+    let promise: Promise = write_text
+        .call1(&clipboard, &JsValue::from(text))
+        .map_err(|err| anyhow!("Error calling clipboard.writeText {:#?}", err))?
+        .dyn_into()
+        .map_err(|element| anyhow!("Error converting {:#?} to Promise", element))?;
+
+    JsFuture::from(promise)
+        .await
+        .map(|_| ())
+        .map_err(|err| anyhow!("Clipboard write was rejected {:#?}", err))
+}
+
+/// The canvas as it was just drawn, as a PNG data URL.
+pub fn canvas_data_url(canvas: &HtmlCanvasElement) -> Result<String> {
+    canvas
+        .to_data_url()
+        .map_err(|err| anyhow!("Could not capture canvas {:#?}", err))
+}
+
+pub async fn canvas_to_png_blob(canvas: &HtmlCanvasElement) -> Result<Blob> {
+    let (blob_tx, blob_rx) = channel::<Option<Blob>>();
+    let callback: Closure<dyn FnMut(Option<Blob>)> = closure_once(move |blob: Option<Blob>| {
+        let _ = blob_tx.send(blob);
+    });
+    canvas
+        .to_blob(callback.as_ref().unchecked_ref())
+        .map_err(|err| anyhow!("Could not capture canvas {:#?}", err))?;
+
+    blob_rx
+        .await?
+        .ok_or_else(|| anyhow!("Canvas capture produced no image"))
+}
+
+pub fn download_blob(blob: &Blob, filename: &str) -> Result<()> {
+    let url = Url::create_object_url_with_blob(blob)
+        .map_err(|err| anyhow!("Could not create object URL {:#?}", err))?;
+    let anchor = document()?
+        .create_element("a")
+        .map_err(|err| anyhow!("Could not create anchor {:#?}", err))?
+        .dyn_into::<HtmlAnchorElement>()
+        .map_err(|element| anyhow!("Error converting {:#?} to HtmlAnchorElement", element))?;
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+
+    Url::revoke_object_url(&url).map_err(|err| anyhow!("Could not revoke object URL {:#?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    #[test]
+    fn parse_query_splits_pairs_and_decodes_escapes() {
+        let params = parse_query("?level=assets%2Flevels%2Ftest.json&debug&lang=pt+BR&bad=%zz");
+
+        assert_eq!(params["level"], "assets/levels/test.json");
+        assert_eq!(params["debug"], "");
+        assert_eq!(params["lang"], "pt BR");
+        assert_eq!(params["bad"], "%zz");
+        assert!(parse_query("").is_empty());
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    async fn sleep_resolves_no_sooner_than_asked() {
+        let started = now().unwrap();
+        sleep(50).await;
+
+        assert!(now().unwrap() - started >= 50.0);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    fn a_dropped_listener_hears_nothing_more() {
+        let target: EventTarget = document().unwrap().create_element("div").unwrap().into();
+        let heard = std::rc::Rc::new(std::cell::Cell::new(0));
+        let listener = {
+            let heard = std::rc::Rc::clone(&heard);
+            closure_wrap(Box::new(move || heard.set(heard.get() + 1)) as Box<dyn FnMut()>)
+        };
+        let guard = EventListenerGuard::new(&target, "ping", listener).unwrap();
+
+        dispatch_custom_event(&target, "ping", &JsValue::NULL).unwrap();
+        assert_eq!(heard.get(), 1);
+        drop(guard);
+        dispatch_custom_event(&target, "ping", &JsValue::NULL).unwrap();
+        assert_eq!(heard.get(), 1);
+    }
+}
@@ -2,11 +2,28 @@ use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::channel::{mpsc, oneshot::channel};
 use serde::Deserialize;
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Mutex};
-use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
-use web_sys::{CanvasRenderingContext2d, HtmlImageElement};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+    sync::Mutex,
+};
+use wasm_bindgen::{prelude::Closure, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, CanvasRenderingContext2d, EventTarget,
+    GainNode, HtmlCanvasElement, HtmlImageElement,
+};
 
-use crate::browser::{self, LoopClosure};
+use crate::{
+    browser::{self, EventListenerGuard, LoopClosure},
+    sound,
+};
+
+pub use walk_the_dog_sim::{
+    cutscene, tween, Anchor, Cooldown, Heading, Point, Rect, Segment, Stamina, StaminaConfig,
+    Timer, FRAME_SIZE,
+};
 
 #[derive(Deserialize, Clone)]
 pub struct SheetRect {
@@ -16,78 +33,322 @@ pub struct SheetRect {
     pub h: i16,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
-pub struct Point {
-    pub x: i16,
-    pub y: i16,
+#[derive(Deserialize, Clone)]
+pub struct SheetSize {
+    pub w: i16,
+    pub h: i16,
 }
 
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Cell {
     pub frame: SheetRect,
+    /// Where the trimmed frame sits inside the untrimmed source image.
     pub sprite_source_size: SheetRect,
+    /// The untrimmed source image, the same for every frame of an animation.
+    pub source_size: SheetSize,
 }
 
 #[derive(Deserialize, Clone)]
-pub struct Sheet {
-    pub frames: HashMap<String, Cell>,
+pub struct SheetMeta {
+    /// What frame sizes are multiplied by when drawn. TexturePacker writes
+    /// it as a string, so either form is read.
+    #[serde(deserialize_with = "deserialize_scale")]
+    pub scale: f32,
 }
 
-#[derive(Default, Debug)]
-pub struct Rect {
-    pub position: Point,
-    pub width: i16,
-    pub height: i16,
+impl Default for SheetMeta {
+    fn default() -> Self {
+        SheetMeta { scale: 1.0 }
+    }
 }
 
-impl Rect {
-    pub const fn new(position: Point, width: i16, height: i16) -> Self {
-        Rect {
-            position,
-            width,
-            height,
-        }
+fn deserialize_scale<'de, D>(deserializer: D) -> std::result::Result<f32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scale {
+        Number(f32),
+        Text(String),
     }
 
-    pub const fn new_from_x_y(x: i16, y: i16, width: i16, height: i16) -> Self {
-        Rect::new(Point { x, y }, width, height)
+    match Scale::deserialize(deserializer)? {
+        Scale::Number(scale) => Ok(scale),
+        Scale::Text(text) => text.parse().map_err(serde::de::Error::custom),
     }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Sheet {
+    pub frames: HashMap<String, Cell>,
+    #[serde(default)]
+    pub meta: SheetMeta,
+}
 
-    pub fn intersects(&self, rect: &Rect) -> bool {
-        self.x() < rect.right()
-            && self.right() > rect.x()
-            && self.y() < rect.bottom()
-            && self.bottom() > rect.y()
+impl Sheet {
+    /// `length` in sheet pixels, as drawn on screen.
+    pub fn scaled(&self, length: i16) -> i16 {
+        (f32::from(length) * self.meta.scale).round() as i16
     }
+}
+
+/// Canvas calls made while drawing one frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub images: u32,
+    pub rects: u32,
+    pub texts: u32,
+    /// Circles, lines, polygons and paths.
+    pub shapes: u32,
+}
 
-    pub fn right(&self) -> i16 {
-        self.x() + self.width
+/// Counts draw calls into the frame being drawn, keeping the counts of the
+/// last complete frame for display.
+#[derive(Default)]
+struct FrameCounter {
+    current: std::cell::Cell<RenderStats>,
+    last: std::cell::Cell<RenderStats>,
+}
+
+impl FrameCounter {
+    fn count(&self, update: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.current.get();
+        stats.draw_calls += 1;
+        update(&mut stats);
+        self.current.set(stats);
     }
 
-    pub fn bottom(&self) -> i16 {
-        self.y() + self.height
+    fn begin_frame(&self) {
+        self.last.set(self.current.take());
     }
+}
+
+/// One step of a canvas path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PathStep {
+    MoveTo(Point),
+    LineTo(Point),
+    Close,
+}
 
-    pub fn set_x(&mut self, x: i16) {
-        self.position.x = x
+/// The closed path through `points`, or nothing when there are too few
+/// points to enclose an area.
+fn polygon_path(points: &[Point]) -> Vec<PathStep> {
+    let [first, rest @ ..] = points else {
+        return Vec::new();
+    };
+    if rest.len() < 2 {
+        return Vec::new();
     }
+    std::iter::once(PathStep::MoveTo(*first))
+        .chain(rest.iter().map(|point| PathStep::LineTo(*point)))
+        .chain(std::iter::once(PathStep::Close))
+        .collect()
+}
+
+/// What a debug box outlines, so overlapping boxes can be told apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugClass {
+    Player,
+    Ground,
+    Hazard,
+    Collectible,
+    Sensor,
+    /// Invisible rects that only fire events; drawn dashed.
+    Trigger,
+    /// Where the player's box overlaps another box; drawn filled.
+    Overlap,
+}
 
-    pub fn x(&self) -> i16 {
-        self.position.x
+impl DebugClass {
+    pub const ALL: [DebugClass; 7] = [
+        DebugClass::Player,
+        DebugClass::Ground,
+        DebugClass::Hazard,
+        DebugClass::Collectible,
+        DebugClass::Sensor,
+        DebugClass::Trigger,
+        DebugClass::Overlap,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            DebugClass::Player => "player",
+            DebugClass::Ground => "ground",
+            DebugClass::Hazard => "hazard",
+            DebugClass::Collectible => "collectible",
+            DebugClass::Sensor => "sensor",
+            DebugClass::Trigger => "trigger",
+            DebugClass::Overlap => "overlap",
+        }
     }
+}
+
+/// Colors for each `DebugClass`. `HighContrast` uses the Okabe-Ito set,
+/// which stays distinct for the common kinds of color blindness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugPalette {
+    #[default]
+    Default,
+    HighContrast,
+}
 
-    pub fn y(&self) -> i16 {
-        self.position.y
+impl DebugPalette {
+    fn rgb(&self, class: DebugClass) -> (u8, u8, u8) {
+        match (self, class) {
+            (DebugPalette::Default, DebugClass::Player) => (255, 0, 0),
+            (DebugPalette::Default, DebugClass::Ground) => (0, 170, 0),
+            (DebugPalette::Default, DebugClass::Hazard) => (255, 140, 0),
+            (DebugPalette::Default, DebugClass::Collectible) => (255, 215, 0),
+            (DebugPalette::Default, DebugClass::Sensor) => (30, 120, 255),
+            (DebugPalette::Default, DebugClass::Trigger) => (255, 0, 255),
+            (DebugPalette::Default, DebugClass::Overlap) => (255, 255, 255),
+            (DebugPalette::HighContrast, DebugClass::Player) => (0, 114, 178),
+            (DebugPalette::HighContrast, DebugClass::Ground) => (0, 0, 0),
+            (DebugPalette::HighContrast, DebugClass::Hazard) => (213, 94, 0),
+            (DebugPalette::HighContrast, DebugClass::Collectible) => (240, 228, 66),
+            (DebugPalette::HighContrast, DebugClass::Sensor) => (204, 121, 167),
+            (DebugPalette::HighContrast, DebugClass::Trigger) => (86, 180, 233),
+            (DebugPalette::HighContrast, DebugClass::Overlap) => (0, 158, 115),
+        }
     }
 }
 
+/// The pixels of an image `size` between the fractions `from` and `to`
+/// of its width and height, as x, y, width and height.
+fn image_region(size: (f64, f64), from: (f32, f32), to: (f32, f32)) -> (f64, f64, f64, f64) {
+    let u = |fraction: f32| f64::from(fraction.clamp(0.0, 1.0)) * size.0;
+    let v = |fraction: f32| f64::from(fraction.clamp(0.0, 1.0)) * size.1;
+    (
+        u(from.0),
+        v(from.1),
+        u(to.0) - u(from.0),
+        v(to.1) - v(from.1),
+    )
+}
+
 pub struct Renderer {
     context: CanvasRenderingContext2d,
-    show_bounding_box: bool,
+    debug_mode: bool,
+    /// Draws debug boxes even outside debug mode, while inspecting.
+    forced_boxes: std::cell::Cell<bool>,
+    cursor: RefCell<String>,
+    debug_palette: std::cell::Cell<DebugPalette>,
+    debug_alpha: std::cell::Cell<f32>,
+    stats: FrameCounter,
+    /// The logical size drawing is clipped to while letterboxed.
+    letterbox: Option<(u32, u32)>,
 }
 
 impl Renderer {
+    fn new(canvas: &HtmlCanvasElement, debug_mode: bool) -> Result<Self> {
+        Ok(Renderer {
+            context: browser::context_from(canvas)?,
+            debug_mode,
+            forced_boxes: std::cell::Cell::new(false),
+            cursor: RefCell::new(String::new()),
+            debug_palette: std::cell::Cell::new(DebugPalette::Default),
+            debug_alpha: std::cell::Cell::new(1.0),
+            stats: FrameCounter::default(),
+            letterbox: None,
+        })
+    }
+
+    /// Counts for the previous complete frame, for the debug display.
+    pub fn last_frame_stats(&self) -> RenderStats {
+        self.stats.last.get()
+    }
+
+    /// Clips every frame to `logical_size`, so nothing drawn past the
+    /// edges of the play area lands on the letterbox bars.
+    fn with_letterbox(self, logical_size: (u32, u32)) -> Self {
+        Renderer {
+            letterbox: Some(logical_size),
+            ..self
+        }
+    }
+
+    fn begin_frame(&self) {
+        self.stats.begin_frame();
+        if let Some((width, height)) = self.letterbox {
+            // The last frame's clip comes off with the state saved under it.
+            self.context.restore();
+            self.context.save();
+            self.context.begin_path();
+            self.context
+                .rect(0.0, 0.0, f64::from(width), f64::from(height));
+            self.context.clip();
+        }
+    }
+
+    fn count(&self, update: impl FnOnce(&mut RenderStats)) {
+        self.stats.count(update);
+    }
+
+    /// Sets the CSS cursor over the canvas, touching the DOM only when the
+    /// style actually changes.
+    pub fn set_cursor(&self, style: &str) -> Result<()> {
+        if *self.cursor.borrow() == style {
+            return Ok(());
+        }
+
+        if let Some(canvas) = self.context.canvas() {
+            browser::set_canvas_cursor(&canvas, style)?;
+        }
+        *self.cursor.borrow_mut() = style.to_string();
+        Ok(())
+    }
+
+    /// Sets the palette and opacity used for debug bounding boxes.
+    pub fn set_debug_style(&self, palette: DebugPalette, alpha: f32) {
+        self.debug_palette.set(palette);
+        self.debug_alpha.set(alpha.clamp(0.0, 1.0));
+    }
+
+    fn debug_color(&self, class: DebugClass) -> String {
+        let (r, g, b) = self.debug_palette.get().rgb(class);
+        format!("rgba({}, {}, {}, {:.2})", r, g, b, self.debug_alpha.get())
+    }
+
+    /// A key to the debug box colors, with its bottom-left corner at
+    /// `origin`.
+    fn draw_debug_legend(&self, origin: &Point) -> Result<()> {
+        const ROW: i16 = 16;
+        let top = origin.y - ROW * DebugClass::ALL.len() as i16;
+        for (index, class) in DebugClass::ALL.iter().enumerate() {
+            let y = top + index as i16 * ROW;
+            self.fill_rect(
+                &Rect::new_from_x_y(origin.x, y + 3, 10, 10),
+                &self.debug_color(*class),
+            );
+            self.draw_text_with_font(
+                class.name(),
+                &Point {
+                    x: origin.x + 16,
+                    y: y + 12,
+                },
+                "12px sans-serif",
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+
+    /// Turns bounding boxes on regardless of debug mode, until unforced.
+    pub fn force_boxes(&self, forced: bool) {
+        self.forced_boxes.set(forced);
+    }
+
+    fn shows_boxes(&self) -> bool {
+        self.debug_mode || self.forced_boxes.get()
+    }
+
     pub fn clear(&self, rect: &Rect) {
         self.context.clear_rect(
             rect.x().into(),
@@ -97,10 +358,16 @@ impl Renderer {
         );
     }
 
-    pub fn draw_image(&self, image: &HtmlImageElement, frame: &Rect, destination: &Rect) {
+    pub fn draw_image(
+        &self,
+        image: &HtmlImageElement,
+        frame: &Rect,
+        destination: &Rect,
+    ) -> Result<()> {
+        self.count(|stats| stats.images += 1);
         self.context
             .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                &image,
+                image,
                 frame.x().into(),
                 frame.y().into(),
                 frame.width.into(),
@@ -110,28 +377,325 @@ impl Renderer {
                 destination.width.into(),
                 destination.height.into(),
             )
-            .expect("Drawing is throwing exceptions! Unrecoverable error.");
+            .map_err(|err| anyhow!("Error drawing image {:#?}", err))
+    }
+
+    /// Draws the part of `image` between (`u0`, `v0`) and (`u1`, `v1`),
+    /// given as fractions of its width and height and clamped to 0..=1,
+    /// into `destination`.
+    pub fn draw_image_region(
+        &self,
+        image: &HtmlImageElement,
+        u0: f32,
+        v0: f32,
+        u1: f32,
+        v1: f32,
+        destination: &Rect,
+    ) -> Result<()> {
+        let (x, y, width, height) = image_region(
+            (image.width().into(), image.height().into()),
+            (u0, v0),
+            (u1, v1),
+        );
+        self.count(|stats| stats.images += 1);
+        self.context
+            .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                image,
+                x,
+                y,
+                width,
+                height,
+                destination.x().into(),
+                destination.y().into(),
+                destination.width.into(),
+                destination.height.into(),
+            )
+            .map_err(|err| anyhow!("Error drawing image region {:#?}", err))
     }
 
-    pub fn draw_entire_image(&self, image: &HtmlImageElement, position: &Point) {
+    pub fn draw_entire_image(&self, image: &HtmlImageElement, position: &Point) -> Result<()> {
+        self.count(|stats| stats.images += 1);
         self.context
             .draw_image_with_html_image_element(image, position.x.into(), position.y.into())
-            .expect("Drawing is throwing exceptions! Unrecoverable error.");
+            .map_err(|err| anyhow!("Error drawing image {:#?}", err))
     }
 
-    pub fn draw_bounding_box(&self, rect: &Rect) {
-        if self.show_bounding_box == false {
+    pub fn draw_bounding_box(&self, rect: &Rect, class: DebugClass) {
+        if !self.shows_boxes() {
             return;
         }
 
-        self.context.set_stroke_style(&JsValue::from("#f00"));
+        self.count(|stats| stats.rects += 1);
+        let dashed = class == DebugClass::Trigger;
+        if dashed {
+            self.set_line_dash(&[6, 4]);
+        }
+        self.context
+            .set_stroke_style(&JsValue::from(self.debug_color(class)));
         self.context.stroke_rect(
             rect.x() as f64,
             rect.y() as f64,
             rect.width as f64,
             rect.height as f64,
         );
+        if dashed {
+            self.set_line_dash(&[]);
+        }
+    }
+
+    /// Fills `rect` in its debug class's color, when boxes are shown.
+    pub fn fill_debug_box(&self, rect: &Rect, class: DebugClass) {
+        if self.shows_boxes() {
+            self.fill_rect(rect, &self.debug_color(class));
+        }
+    }
+
+    /// Fills `rect` half-transparent in the overlap color.
+    pub fn draw_debug_overlap(&self, rect: &Rect) {
+        if !self.shows_boxes() {
+            return;
+        }
+
+        let (r, g, b) = self.debug_palette.get().rgb(DebugClass::Overlap);
+        self.fill_rect(
+            rect,
+            &format!(
+                "rgba({}, {}, {}, {:.2})",
+                r,
+                g,
+                b,
+                0.5 * self.debug_alpha.get()
+            ),
+        );
+    }
+
+    fn set_line_dash(&self, segments: &[i16]) {
+        let segments: js_sys::Array = segments.iter().map(|&n| JsValue::from(n)).collect();
+        if let Err(err) = self.context.set_line_dash(&segments) {
+            log!("Error setting line dash {:#?}", err);
+        }
+    }
+
+    pub fn draw_circle(&self, center: &Point, radius: i16, color: &str) -> Result<()> {
+        self.count(|stats| stats.shapes += 1);
+        self.context.begin_path();
+        self.context
+            .arc(
+                center.x.into(),
+                center.y.into(),
+                radius.into(),
+                0.0,
+                std::f64::consts::TAU,
+            )
+            .map_err(|err| anyhow!("Error drawing circle {:#?}", err))?;
+        self.context.set_fill_style(&JsValue::from(color));
+        self.context.fill();
+        Ok(())
+    }
+
+    pub fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.count(|stats| stats.rects += 1);
+        self.context.set_fill_style(&JsValue::from(color));
+        self.context.fill_rect(
+            rect.x().into(),
+            rect.y().into(),
+            rect.width.into(),
+            rect.height.into(),
+        );
+    }
+
+    /// Fills the left `fraction` of `rect` over a dim track.
+    pub fn draw_progress_bar(&self, rect: &Rect, fraction: f32, color: &str) {
+        self.fill_rect(rect, "rgba(0, 0, 0, 0.3)");
+        self.fill_rect(
+            &Rect {
+                width: (f32::from(rect.width) * fraction.clamp(0.0, 1.0)) as i16,
+                ..*rect
+            },
+            color,
+        );
+    }
+
+    pub fn draw_line(&self, from: &Point, to: &Point, color: &str) {
+        self.count(|stats| stats.shapes += 1);
+        self.context.begin_path();
+        self.context.move_to(from.x.into(), from.y.into());
+        self.context.line_to(to.x.into(), to.y.into());
+        self.context.set_stroke_style(&JsValue::from(color));
+        self.context.stroke();
+    }
+
+    /// Closes the shape through `points` and fills it, outlines it with a
+    /// line `width` wide, or both. Fewer than three points draw nothing.
+    pub fn draw_polygon(&self, points: &[Point], fill: Option<&str>, stroke: Option<(&str, i16)>) {
+        let path = polygon_path(points);
+        if path.is_empty() {
+            return;
+        }
+        self.count(|stats| stats.shapes += 1);
+        self.context.begin_path();
+        for step in path {
+            match step {
+                PathStep::MoveTo(point) => self.context.move_to(point.x.into(), point.y.into()),
+                PathStep::LineTo(point) => self.context.line_to(point.x.into(), point.y.into()),
+                PathStep::Close => self.context.close_path(),
+            }
+        }
+        if let Some(color) = fill {
+            self.context.set_fill_style(&JsValue::from(color));
+            self.context.fill();
+        }
+        if let Some((color, width)) = stroke {
+            self.context.save();
+            self.context.set_line_width(width.into());
+            self.context.set_stroke_style(&JsValue::from(color));
+            self.context.stroke();
+            self.context.restore();
+        }
+    }
+
+    /// Strokes a line through `points` in order.
+    pub fn draw_path(&self, points: &[Point], color: &str) {
+        let Some((first, rest)) = points.split_first() else {
+            return;
+        };
+        self.count(|stats| stats.shapes += 1);
+        self.context.begin_path();
+        self.context.move_to(first.x.into(), first.y.into());
+        for point in rest {
+            self.context.line_to(point.x.into(), point.y.into());
+        }
+        self.context.set_stroke_style(&JsValue::from(color));
+        self.context.stroke();
+    }
+
+    /// Runs `draw` with everything it draws faded to `alpha`.
+    pub fn draw_with_alpha(
+        &self,
+        alpha: f32,
+        draw: impl FnOnce(&Renderer) -> Result<()>,
+    ) -> Result<()> {
+        self.context.save();
+        self.context
+            .set_global_alpha(f64::from(alpha.clamp(0.0, 1.0)));
+        let result = draw(self);
+        self.context.restore();
+        result
+    }
+
+    /// Draws with the view moved so `camera` is at the top left.
+    pub fn draw_with_camera(
+        &self,
+        camera: Point,
+        draw: impl FnOnce(&Renderer) -> Result<()>,
+    ) -> Result<()> {
+        self.context.save();
+        let result = self
+            .context
+            .translate(-f64::from(camera.x), -f64::from(camera.y))
+            .map_err(|err| anyhow!("Could not move camera {:#?}", err))
+            .and_then(|_| draw(self));
+        self.context.restore();
+        result
+    }
+
+    /// Runs `draw` mirrored left to right about the vertical line at
+    /// `pivot_x`.
+    pub fn draw_mirrored(
+        &self,
+        pivot_x: i16,
+        draw: impl FnOnce(&Renderer) -> Result<()>,
+    ) -> Result<()> {
+        self.context.save();
+        let result = self
+            .context
+            .translate(2.0 * f64::from(pivot_x), 0.0)
+            .and_then(|_| self.context.scale(-1.0, 1.0))
+            .map_err(|err| anyhow!("Could not mirror {:#?}", err))
+            .and_then(|_| draw(self));
+        self.context.restore();
+        result
+    }
+
+    pub fn draw_text(&self, text: &str, location: &Point) -> Result<()> {
+        self.draw_text_with_font(text, location, "16px sans-serif")
+    }
+
+    pub fn draw_text_with_font(&self, text: &str, location: &Point, font: &str) -> Result<()> {
+        self.count(|stats| stats.texts += 1);
+        self.context.set_font(font);
+        self.context.set_fill_style(&JsValue::from("#000"));
+        self.context
+            .fill_text(text, location.x.into(), location.y.into())
+            .map_err(|err| anyhow!("Error filling text {:#?}", err))
+    }
+
+    /// Draws `text` broken into lines no wider than `max_width`, starting
+    /// with the first baseline at `origin`. Returns the height used.
+    pub fn draw_text_wrapped(
+        &self,
+        text: &str,
+        origin: &Point,
+        max_width: i16,
+        line_height: i16,
+        font: &str,
+    ) -> Result<i16> {
+        self.context.set_font(font);
+        let lines = wrap_text(text, f64::from(max_width), |line| {
+            self.context
+                .measure_text(line)
+                .map(|metrics| metrics.width())
+                .unwrap_or(0.0)
+        });
+
+        for (index, line) in lines.iter().enumerate() {
+            self.draw_text_with_font(
+                line,
+                &Point {
+                    x: origin.x,
+                    y: origin.y + index as i16 * line_height,
+                },
+                font,
+            )?;
+        }
+        Ok(lines.len() as i16 * line_height)
+    }
+}
+
+/// Breaks `text` at spaces so each line measures at most `max_width`.
+/// A word too long for a line of its own is split between characters.
+fn wrap_text(text: &str, max_width: f64, measure: impl Fn(&str) -> f64) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if measure(&candidate) <= max_width {
+            line = candidate;
+            continue;
+        }
+
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for character in word.chars() {
+            line.push(character);
+            if measure(&line) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(std::mem::take(&mut line));
+                line.push(character);
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
     }
+    lines
 }
 
 pub struct Image {
@@ -154,37 +718,53 @@ impl Image {
         }
     }
 
-    pub fn draw(&self, renderer: &Renderer) {
-        renderer.draw_entire_image(&self.element, &self.position);
-        renderer.draw_bounding_box(&self.bounding_box());
+    pub fn draw(&self, renderer: &Renderer) -> Result<()> {
+        renderer.draw_entire_image(&self.element, &self.position)
     }
 
     pub fn bounding_box(&self) -> &Rect {
         &self.bounding_box
     }
+
+    pub fn move_to(&mut self, position: Point) {
+        self.position = position;
+        self.bounding_box.position = position;
+    }
 }
 
+/// Loads an image, decoding it off the main thread with
+/// `HtmlImageElement::decode` where the browser has it so the first draw
+/// doesn't stall.
 pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
     let image = browser::new_image()?;
+    if js_sys::Reflect::has(&image, &"decode".into()).unwrap_or(false) {
+        image.set_src(source);
+        JsFuture::from(image.decode())
+            .await
+            .map_err(|err| anyhow!("Error decoding image {} {:#?}", source, err))?;
+        return Ok(image);
+    }
 
     let (success_tx, success_rx) = channel::<Result<()>>();
     let success_tx = Rc::new(Mutex::new(Some(success_tx)));
     let error_tx = Rc::clone(&success_tx);
-    let callback = browser::closure_once(move || {
+    let callback: Closure<dyn FnMut()> = browser::closure_once(move || {
         if let Some(tx) = success_tx.lock().ok().and_then(|mut opt| opt.take()) {
             let _ = tx.send(Ok(()));
         };
     });
+    let path = source.to_string();
     let error_callback: Closure<dyn FnMut(JsValue)> = browser::closure_once(move |err| {
         if let Some(tx) = error_tx.lock().ok().and_then(|mut opt| opt.take()) {
-            let _ = tx.send(Err(anyhow!("Error loading Image: {:#?}", err)));
+            let _ = tx.send(Err(anyhow!("Error loading image {} {:#?}", path, err)));
         };
     });
-    image.set_onload(Some(callback.as_ref().unchecked_ref()));
-    image.set_onerror(Some(error_callback.as_ref().unchecked_ref()));
+    // Both listeners are removed once the image has settled either way.
+    let _on_load = EventListenerGuard::new(&image, "load", callback)?;
+    let _on_error = EventListenerGuard::new(&image, "error", error_callback)?;
     image.set_src(source);
 
-    let _ = success_rx.await??;
+    success_rx.await??;
 
     Ok(image)
 }
@@ -192,128 +772,2042 @@ pub async fn load_image(source: &str) -> Result<HtmlImageElement> {
 #[async_trait(?Send)]
 pub trait Game {
     async fn initialize(&self) -> Result<Box<dyn Game>>;
+    /// How much of `initialize` is done, from 0.0 to 1.0.
+    fn loading_progress(&self) -> f32 {
+        0.0
+    }
+    /// Draws the HUD. With a HUD canvas this runs only when
+    /// `take_hud_dirty` says something changed; otherwise it runs every
+    /// frame on top of `draw`.
+    fn draw_hud(&self, _renderer: &Renderer) -> Result<()> {
+        Ok(())
+    }
+    /// Whether the HUD changed since it was last asked.
+    fn take_hud_dirty(&mut self) -> bool {
+        false
+    }
+    /// Draws a frame while `initialize` runs.
+    fn draw_loading(&self, _renderer: &Renderer, _progress: f32) -> Result<()> {
+        Ok(())
+    }
     fn update(&mut self, keystate: &KeyState);
-    fn draw(&self, renderer: &Renderer);
+    /// Draws the current frame.
+    ///
+    /// Errors are logged by the `GameLoop` and the loop keeps running, so a
+    /// failed canvas call costs a frame rather than the whole module.
+    fn draw(&self, renderer: &Renderer) -> Result<()>;
+    fn restart(&mut self);
+    fn dump_state(&self) {}
+    fn take_screenshot_request(&mut self) -> bool {
+        false
+    }
+    fn set_muted(&mut self, _muted: bool) {}
+    /// Called when the loop pauses or resumes, so the game can pause audio.
+    fn set_paused(&mut self, _paused: bool) {}
+    /// Called when the debug time scale changes; 1.0 is normal speed.
+    fn set_time_scale(&mut self, _scale: f32) {}
+    /// Called when the browser refused to go fullscreen, so the game can
+    /// tell the player.
+    fn fullscreen_denied(&mut self) {}
+    /// Key codes the game uses right now. Their browser defaults, such as
+    /// scrolling on arrows and Space, are suppressed.
+    fn guarded_keys(&self) -> &[String] {
+        &[]
+    }
 }
 
-const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameCommand {
+    Pause,
+    Resume,
+    Restart,
+    SetMuted(bool),
+    DumpState,
+    Screenshot,
+    FullscreenDenied,
+    /// Stops the loop for good and drops the game.
+    Destroy,
+}
 
-type SharedLoopClosure = Rc<RefCell<Option<LoopClosure>>>;
+pub type SharedCommandQueue = Rc<RefCell<VecDeque<GameCommand>>>;
 
-pub struct GameLoop {
-    last_frame: f64,
-    accumulated_delta: f32,
+/// Frames kept by a recording unless the host asks for fewer; ten seconds
+/// of PNGs is already a lot of memory.
+pub const MAX_RECORDED_FRAMES: usize = 600;
+
+/// Frames captured from the canvas after each draw, as PNG data URLs, for
+/// the host page to encode into a clip.
+#[derive(Default)]
+pub struct Recording {
+    frames: Vec<String>,
+    max_frames: usize,
+    active: bool,
 }
 
-impl GameLoop {
-    pub async fn start(game: impl Game + 'static) -> Result<()> {
-        let mut keyevent_rx = prepare_input()?;
-        let mut game = game.initialize().await?;
+pub type SharedRecording = Rc<RefCell<Recording>>;
 
-        let mut game_loop = GameLoop {
-            last_frame: browser::now()?,
-            accumulated_delta: 0.0,
-        };
+/// A fixed logical size fitted whole inside an area of another shape,
+/// centered, with bars filling the rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    /// Area units per game unit.
+    pub scale: f64,
+    /// Where the play area's top left sits in the area.
+    pub offset: (f64, f64),
+}
 
-        let renderer = Renderer {
-            context: browser::context()?,
-            show_bounding_box: true,
-        };
+impl Letterbox {
+    pub fn fit((width, height): (f64, f64), logical_size: (u32, u32)) -> Self {
+        let (logical_width, logical_height) =
+            (f64::from(logical_size.0), f64::from(logical_size.1));
+        let scale = (width / logical_width)
+            .min(height / logical_height)
+            .max(f64::EPSILON);
+        Letterbox {
+            scale,
+            offset: (
+                (width - logical_width * scale) / 2.0,
+                (height - logical_height * scale) / 2.0,
+            ),
+        }
+    }
+}
 
-        let f: SharedLoopClosure = Rc::new(RefCell::new(None));
-        let g = f.clone();
+/// Maps between client coordinates on the page and game coordinates on a
+/// canvas drawn at a fixed logical size, then scaled by CSS and letterboxed
+/// with `object-fit: contain`. A canvas letterboxed in its backing store
+/// puts the play area in the same place, so one mapping serves both.
+/// Client coordinates are CSS pixels, so the
+/// device pixel ratio, which only sizes the backing store, doesn't enter
+/// into it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenTransform {
+    /// The canvas element's box: left, top, width and height.
+    bounds: (f64, f64, f64, f64),
+    logical_size: (u32, u32),
+}
 
-        let mut keystate = KeyState::new();
+pub type SharedScreenTransform = Rc<std::cell::Cell<ScreenTransform>>;
 
-        *g.borrow_mut() = Some(browser::create_ref_closure(move |perf: f64| {
-            process_input(&mut keystate, &mut keyevent_rx);
+impl ScreenTransform {
+    pub fn new(bounds: (f64, f64, f64, f64), logical_size: (u32, u32)) -> Self {
+        ScreenTransform {
+            bounds,
+            logical_size,
+        }
+    }
 
-            game_loop.accumulated_delta += (perf - game_loop.last_frame) as f32;
-            while game_loop.accumulated_delta > FRAME_SIZE {
-                game.update(&keystate);
-                game_loop.accumulated_delta -= FRAME_SIZE;
-            }
-            game_loop.last_frame = perf;
+    /// Measures `canvas`, taking its current size as the logical one.
+    pub fn measure(canvas: &HtmlCanvasElement) -> Self {
+        ScreenTransform::new(
+            browser::canvas_bounds(canvas),
+            (canvas.width(), canvas.height()),
+        )
+    }
 
-            game.draw(&renderer);
+    /// Measures `canvas` again after the page moved or resized it.
+    fn remeasured(self, canvas: &HtmlCanvasElement) -> Self {
+        ScreenTransform::new(browser::canvas_bounds(canvas), self.logical_size)
+    }
 
-            let _ = browser::request_animation_frame(f.borrow().as_ref().unwrap());
-        }));
+    fn letterbox(&self) -> Letterbox {
+        let (_, _, width, height) = self.bounds;
+        Letterbox::fit((width, height), self.logical_size)
+    }
 
-        browser::request_animation_frame(
-            g.borrow()
-                .as_ref()
-                .ok_or_else(|| anyhow!("GameLoop: Loop is None"))?,
-        )?;
+    /// CSS pixels per game unit.
+    fn scale(&self) -> f64 {
+        self.letterbox().scale
+    }
 
-        Ok(())
+    /// Where the letterboxed play area starts in client coordinates.
+    fn origin(&self) -> (f64, f64) {
+        let (left, top, ..) = self.bounds;
+        let (offset_x, offset_y) = self.letterbox().offset;
+        (left + offset_x, top + offset_y)
     }
-}
 
-pub struct KeyState {
-    pressed_keys: HashMap<String, web_sys::KeyboardEvent>,
+    /// The game point under a pointer, or `None` on the letterbox bars or
+    /// off the canvas.
+    pub fn to_game(self, client_x: f64, client_y: f64) -> Option<Point> {
+        let scale = self.scale();
+        let (origin_x, origin_y) = self.origin();
+        let x = (client_x - origin_x) / scale;
+        let y = (client_y - origin_y) / scale;
+        let inside = (0.0..f64::from(self.logical_size.0)).contains(&x)
+            && (0.0..f64::from(self.logical_size.1)).contains(&y);
+        inside.then_some(Point {
+            x: x as i16,
+            y: y as i16,
+        })
+    }
+
+    /// Client coordinates of a game point, for placing page elements over
+    /// things in the game.
+    pub fn to_screen(self, point: Point) -> (f64, f64) {
+        let scale = self.scale();
+        let (origin_x, origin_y) = self.origin();
+        (
+            origin_x + f64::from(point.x) * scale,
+            origin_y + f64::from(point.y) * scale,
+        )
+    }
 }
 
-impl KeyState {
-    fn new() -> Self {
-        KeyState {
-            pressed_keys: HashMap::new(),
-        }
+impl Recording {
+    /// Drops any earlier frames and captures up to `max_frames` new ones.
+    pub fn start(&mut self, max_frames: usize) {
+        self.frames.clear();
+        self.max_frames = max_frames.clamp(1, MAX_RECORDED_FRAMES);
+        self.active = true;
     }
 
-    pub fn is_pressed(&self, code: &str) -> bool {
-        self.pressed_keys.contains_key(code)
+    pub fn stop(&mut self) {
+        self.active = false;
     }
 
-    pub fn set_pressed(&mut self, code: &str, ev: web_sys::KeyboardEvent) {
-        self.pressed_keys.insert(code.into(), ev);
+    pub fn take_frames(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.frames)
     }
 
-    pub fn set_released(&mut self, code: &str) {
-        self.pressed_keys.remove(code.into());
+    fn capture(&mut self, frame: String) {
+        self.frames.push(frame);
+        if self.frames.len() >= self.max_frames {
+            self.active = false;
+        }
     }
 }
 
-enum KeyPress {
-    KeyUp(web_sys::KeyboardEvent),
-    KeyDown(web_sys::KeyboardEvent),
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GameLoopConfig {
+    pub input_target: InputTarget,
+    pub debug_mode: bool,
+    /// Hands each update only the key events that happened before its
+    /// slice of time, instead of everything received since the last frame.
+    /// Makes input more responsive when frames are long.
+    pub input_per_step: bool,
+    /// Sizes the backing store to the canvas element's box, however the
+    /// page shapes it, and draws the game centered in it at its own aspect
+    /// ratio with black bars around it.
+    pub letterbox: bool,
 }
 
-type KeyEventChannel = (
-    mpsc::UnboundedSender<KeyPress>,
-    mpsc::UnboundedReceiver<KeyPress>,
-);
+/// Where keyboard listeners are attached. `Canvas` gives the canvas a
+/// tabindex so several games on one page only react while focused; it is
+/// focused at startup and again whenever it is clicked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputTarget {
+    #[default]
+    Document,
+    Canvas,
+}
 
-fn prepare_input() -> Result<mpsc::UnboundedReceiver<KeyPress>> {
-    let (tx, rx): KeyEventChannel = mpsc::unbounded();
-    let keydown_tx = Rc::new(RefCell::new(tx));
-    let keyup_tx = Rc::clone(&keydown_tx);
-    let on_keydown = browser::closure_wrap(Box::new(move |keycode: web_sys::KeyboardEvent| {
-        let _ = keydown_tx
-            .borrow_mut()
-            .start_send(KeyPress::KeyDown(keycode));
-    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
-    let on_keyup = browser::closure_wrap(Box::new(move |keycode: web_sys::KeyboardEvent| {
-        let _ = keyup_tx.borrow_mut().start_send(KeyPress::KeyUp(keycode));
-    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+const FULLSCREEN_KEY: &str = "KeyF";
+const STEP_PAUSE_KEY: &str = "KeyP";
+const STEP_KEY: &str = "KeyN";
+const STEP_REPEAT_MILLIS: f64 = 100.0;
+const MAX_UPDATES_PER_FRAME: u32 = 10;
+const MIN_TIME_SCALE: f32 = 0.1;
+const MAX_TIME_SCALE: f32 = 8.0;
+/// Keys that scale time by a factor, or reset it with `None`.
+const TIME_SCALE_KEYS: [(&str, Option<f32>); 3] = [
+    ("BracketLeft", Some(0.5)),
+    ("BracketRight", Some(2.0)),
+    ("Backslash", None),
+];
+
+type SharedLoopClosure = Rc<RefCell<Option<LoopClosure>>>;
 
-    browser::document()?.set_onkeydown(Some(on_keydown.as_ref().unchecked_ref()));
-    browser::document()?.set_onkeyup(Some(on_keyup.as_ref().unchecked_ref()));
-    on_keydown.forget();
-    on_keyup.forget();
+/// Runs once, after `Game::initialize` succeeds and before the first frame.
+pub type ReadyCallback = Box<dyn FnOnce(&mut dyn Game, &Renderer)>;
 
-    Ok(rx)
+pub struct GameLoop {
+    last_frame: f64,
+    accumulated_delta: f32,
+    paused: bool,
+    screenshot_requested: bool,
+    stepper: FrameStepper,
+    time_scale: TimeScale,
+    /// Fixed updates run so far.
+    updates: u64,
+    /// Whether the HUD canvas is showing the HUD, rather than the game canvas.
+    hud_layered: bool,
+    /// Whether the page is fullscreen, kept up to date by the
+    /// `fullscreenchange` listener so frames needn't ask the browser.
+    fullscreen: Rc<std::cell::Cell<bool>>,
+    /// Set by `GameCommand::Destroy`; the loop draws no more frames.
+    destroyed: bool,
+    /// Input and resize listeners, detached when the loop is dropped.
+    _listeners: Vec<EventListenerGuard>,
+    /// Taken off the page when the loop is dropped.
+    _hud_canvas: Option<browser::HudCanvas>,
 }
 
-fn process_input(state: &mut KeyState, keyevent_rx: &mut mpsc::UnboundedReceiver<KeyPress>) {
-    loop {
-        match keyevent_rx.try_next() {
-            Ok(None) => break,
-            Err(_err) => break,
-            Ok(Some(ev)) => match ev {
-                KeyPress::KeyUp(ev) => state.set_released(&ev.code()),
-                KeyPress::KeyDown(ev) => state.set_pressed(&ev.code(), ev),
-            },
+/// Debug frame stepping: P freezes fixed updates while drawing carries on,
+/// and N then runs a single update, repeating while it is held.
+#[derive(Default)]
+struct FrameStepper {
+    frozen: bool,
+    pause_held: bool,
+    next_step: Option<f64>,
+}
+
+impl FrameStepper {
+    /// Returns true when one update should run while frozen.
+    fn update(&mut self, keystate: &KeyState, now: f64) -> bool {
+        let pause_down = keystate.is_pressed(STEP_PAUSE_KEY);
+        if pause_down && !self.pause_held {
+            self.frozen = !self.frozen;
+            self.next_step = None;
+        }
+        self.pause_held = pause_down;
+
+        if !self.frozen || !keystate.is_pressed(STEP_KEY) {
+            self.next_step = None;
+            return false;
+        }
+        match self.next_step {
+            Some(at) if now < at => false,
+            _ => {
+                self.next_step = Some(now + STEP_REPEAT_MILLIS);
+                true
+            }
+        }
+    }
+}
+
+/// Debug time controls: `[` halves and `]` doubles how fast simulated time
+/// passes, and `\` puts it back to normal.
+struct TimeScale {
+    scale: f32,
+    held: [bool; TIME_SCALE_KEYS.len()],
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale {
+            scale: 1.0,
+            held: [false; TIME_SCALE_KEYS.len()],
+        }
+    }
+}
+
+impl TimeScale {
+    /// Returns true when the scale changed.
+    fn update(&mut self, keystate: &KeyState) -> bool {
+        let before = self.scale;
+        for (held, (key, factor)) in self.held.iter_mut().zip(TIME_SCALE_KEYS) {
+            let down = keystate.is_pressed(key);
+            if down && !*held {
+                self.scale = factor.map_or(1.0, |factor| {
+                    (self.scale * factor).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+                });
+            }
+            *held = down;
         }
+        self.scale != before
+    }
+}
+
+/// Runs `initialize`, drawing the game's loading screen every animation
+/// frame until it finishes.
+async fn initialize_drawing_progress(
+    game: &dyn Game,
+    renderer: &Renderer,
+) -> Result<Box<dyn Game>> {
+    let initialize = game.initialize();
+    let loading = async {
+        loop {
+            browser::next_frame().await;
+            renderer.begin_frame();
+            if let Err(err) = game.draw_loading(renderer, game.loading_progress()) {
+                log!("Error drawing loading screen {:#?}", err);
+            }
+        }
+    };
+    futures::pin_mut!(initialize, loading);
+    match futures::future::select(initialize, loading).await {
+        futures::future::Either::Left((game, _)) => game,
+        futures::future::Either::Right(_) => unreachable!("the loading screen never finishes"),
+    }
+}
+
+impl GameLoop {
+    pub async fn start(
+        game: impl Game + 'static,
+        canvas: HtmlCanvasElement,
+        config: GameLoopConfig,
+        commands: SharedCommandQueue,
+        recording: SharedRecording,
+        screen: SharedScreenTransform,
+        on_ready: Option<ReadyCallback>,
+    ) -> Result<()> {
+        let logical_size = (canvas.width(), canvas.height());
+        let guarded_keys: SharedGuardedKeys = Rc::new(RefCell::new(HashSet::new()));
+        let (mut keyevent_rx, mut listeners) = prepare_input(
+            &canvas,
+            config.input_target,
+            &commands,
+            &guarded_keys,
+            &screen,
+        )?;
+        listeners.extend(watch_layout(&canvas, &screen)?);
+        let mut renderer = Renderer::new(&canvas, config.debug_mode)?;
+        if config.letterbox {
+            renderer = renderer.with_letterbox(logical_size);
+            fit_canvas(&canvas, &renderer.context, logical_size, true)?;
+        }
+        // Without room for a second canvas the HUD is drawn over the game
+        // every frame instead. Letterboxing does the same, so the HUD
+        // shares the game's transform.
+        let hud_canvas = if config.letterbox {
+            None
+        } else {
+            match browser::hud_canvas(&canvas) {
+                Ok(hud_canvas) => Some(hud_canvas),
+                Err(err) => {
+                    log!("Drawing the HUD on the game canvas: {:#?}", err);
+                    None
+                }
+            }
+        };
+        let hud = match &hud_canvas {
+            Some(hud_canvas) => Some(Renderer::new(hud_canvas.canvas(), config.debug_mode)?),
+            None => None,
+        };
+        let mut game = initialize_drawing_progress(&game, &renderer).await?;
+
+        // Frame timestamps can lag performance.now(), so start from one to
+        // keep the first delta honest.
+        let mut game_loop = GameLoop::new(browser::next_frame().await);
+
+        game_loop
+            .fullscreen
+            .set(browser::is_fullscreen().unwrap_or(true));
+        listeners.extend(watch_fullscreen(
+            &canvas,
+            &renderer.context,
+            logical_size,
+            config.letterbox,
+            &game_loop.fullscreen,
+        )?);
+        if let Some(hud_canvas) = &hud_canvas {
+            let (hud_canvas, canvas) = (hud_canvas.canvas().clone(), canvas.clone());
+            let on_resize = browser::closure_wrap(Box::new(move || {
+                if let Err(err) = browser::place_over(&hud_canvas, &canvas) {
+                    log!("Could not place HUD canvas {:#?}", err);
+                }
+            }) as Box<dyn FnMut()>);
+            listeners.push(browser::add_window_listener("resize", on_resize)?);
+        }
+        game_loop._listeners = listeners;
+        game_loop._hud_canvas = hud_canvas;
+        if let Some(on_ready) = on_ready {
+            on_ready(game.as_mut(), &renderer);
+        }
+        let mut keystate = KeyState::new();
+        let mut buffered_input = VecDeque::new();
+
+        let f: SharedLoopClosure = Rc::new(RefCell::new(None));
+        let g = f.clone();
+
+        *g.borrow_mut() = Some(browser::create_ref_closure(move |perf: f64| {
+            buffer_input(&mut buffered_input, &mut keyevent_rx);
+            let step_once = config.debug_mode && game_loop.stepper.update(&keystate, perf);
+            if config.debug_mode && game_loop.time_scale.update(&keystate) {
+                game.set_time_scale(game_loop.time_scale.scale);
+            }
+            if !config.input_per_step || game_loop.paused || game_loop.stepper.frozen {
+                apply_input_until(&mut keystate, &mut buffered_input, f64::INFINITY);
+            }
+            keystate.forget_presses_before(perf - KEY_HISTORY_WINDOW);
+            game_loop.process_commands(game.as_mut(), &commands);
+            if game_loop.destroyed {
+                // The closure owns itself through `f`, and with it the game
+                // and the listeners. It can't be dropped while it runs, so
+                // that happens once this frame is over.
+                let f = Rc::clone(&f);
+                browser::spawn_local(async move {
+                    f.borrow_mut().take();
+                });
+                return;
+            }
+            let keys = game.guarded_keys();
+            if keys.len() != guarded_keys.borrow().len()
+                || keys.iter().any(|key| !guarded_keys.borrow().contains(key))
+            {
+                *guarded_keys.borrow_mut() = keys.iter().cloned().collect();
+            }
+
+            if game_loop.stepper.frozen {
+                if step_once {
+                    game.update(&keystate);
+                    keystate.advance(FRAME_SIZE);
+                    game_loop.updates += 1;
+                }
+            } else if !game_loop.paused {
+                game_loop.accumulated_delta +=
+                    (perf - game_loop.last_frame) as f32 * game_loop.time_scale.scale;
+                let mut updates = 0;
+                while game_loop.accumulated_delta > FRAME_SIZE {
+                    // Past the cap, drop the time owed rather than fall
+                    // further behind.
+                    if updates == MAX_UPDATES_PER_FRAME {
+                        game_loop.accumulated_delta = 0.0;
+                        break;
+                    }
+                    updates += 1;
+                    game_loop.accumulated_delta -= FRAME_SIZE;
+                    // Updates catch up oldest first, so this one ends where
+                    // the time still owed after it begins.
+                    let step_end = perf - f64::from(game_loop.accumulated_delta);
+                    apply_input_until(&mut keystate, &mut buffered_input, step_end);
+                    game.update(&keystate);
+                    keystate.advance(FRAME_SIZE);
+                    game_loop.updates += 1;
+                }
+            }
+            game_loop.last_frame = perf;
+
+            renderer.begin_frame();
+            if let Err(err) = game.draw(&renderer) {
+                log!("Error drawing frame {:#?}", err);
+            }
+            if renderer.debug_mode() {
+                let corner = Point {
+                    x: logical_size.0 as i16 - 110,
+                    y: logical_size.1 as i16 - 10,
+                };
+                if let Err(err) = renderer.draw_debug_legend(&corner) {
+                    log!("Error drawing debug legend {:#?}", err);
+                }
+                if game_loop.time_scale.scale != 1.0 {
+                    if let Err(err) = renderer.draw_text(
+                        &format!("time x{:.2}", game_loop.time_scale.scale),
+                        &Point {
+                            x: logical_size.0 as i16 - 110,
+                            y: logical_size.1 as i16 - 30,
+                        },
+                    ) {
+                        log!("Error drawing time scale {:#?}", err);
+                    }
+                }
+                if game_loop.stepper.frozen {
+                    if let Err(err) = renderer.draw_text(
+                        &format!("STEPPING update {}  (N: step, P: run)", game_loop.updates),
+                        &Point {
+                            x: logical_size.0 as i16 / 2 - 140,
+                            y: logical_size.1 as i16 - 10,
+                        },
+                    ) {
+                        log!("Error drawing step counter {:#?}", err);
+                    }
+                }
+            }
+
+            // A recording captures only the game canvas, so the HUD is
+            // drawn onto it while one is running.
+            let recording_active = recording.borrow().active;
+            game_loop.draw_hud(
+                game.as_mut(),
+                &renderer,
+                hud.as_ref(),
+                recording_active,
+                logical_size,
+            );
+
+            if recording.borrow().active {
+                match browser::canvas_data_url(&canvas) {
+                    Ok(frame) => recording.borrow_mut().capture(frame),
+                    Err(err) => {
+                        log!("Stopping recording {:#?}", err);
+                        recording.borrow_mut().stop();
+                    }
+                }
+            }
+
+            if game.take_screenshot_request() || game_loop.screenshot_requested {
+                game_loop.screenshot_requested = false;
+                save_screenshot(canvas.clone());
+            }
+
+            let _ = browser::request_animation_frame(f.borrow().as_ref().unwrap());
+        }));
+
+        browser::request_animation_frame(
+            g.borrow()
+                .as_ref()
+                .ok_or_else(|| anyhow!("GameLoop: Loop is None"))?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Redraws the HUD canvas when the game says the HUD changed. Without a
+    /// HUD canvas, in fullscreen where only the game canvas shows, or when
+    /// `single_canvas` asks for it, the HUD is drawn over the game every
+    /// frame.
+    fn draw_hud(
+        &mut self,
+        game: &mut dyn Game,
+        renderer: &Renderer,
+        hud: Option<&Renderer>,
+        single_canvas: bool,
+        (width, height): (u32, u32),
+    ) {
+        let bounds = Rect::new_from_x_y(0, 0, width as i16, height as i16);
+        let layer = hud.filter(|_| !single_canvas && !self.fullscreen.get());
+        let result = match layer {
+            Some(hud) => {
+                if game.take_hud_dirty() || !self.hud_layered {
+                    hud.clear(&bounds);
+                    game.draw_hud(hud)
+                } else {
+                    Ok(())
+                }
+            }
+            None => {
+                if let Some(hud) = hud.filter(|_| self.hud_layered) {
+                    hud.clear(&bounds);
+                }
+                game.draw_hud(renderer)
+            }
+        };
+        self.hud_layered = layer.is_some();
+        if let Err(err) = result {
+            log!("Error drawing HUD {:#?}", err);
+        }
+    }
+
+    fn new(last_frame: f64) -> Self {
+        GameLoop {
+            last_frame,
+            accumulated_delta: 0.0,
+            paused: false,
+            screenshot_requested: false,
+            stepper: FrameStepper::default(),
+            time_scale: TimeScale::default(),
+            updates: 0,
+            hud_layered: false,
+            fullscreen: Rc::new(std::cell::Cell::new(false)),
+            destroyed: false,
+            _listeners: Vec::new(),
+            _hud_canvas: None,
+        }
+    }
+
+    fn process_commands(&mut self, game: &mut dyn Game, commands: &SharedCommandQueue) {
+        let pending: Vec<GameCommand> = commands.borrow_mut().drain(..).collect();
+        for command in pending {
+            match command {
+                GameCommand::Pause => {
+                    self.paused = true;
+                    game.set_paused(true);
+                }
+                GameCommand::Resume => {
+                    self.paused = false;
+                    game.set_paused(false);
+                }
+                GameCommand::Restart => game.restart(),
+                GameCommand::SetMuted(muted) => game.set_muted(muted),
+                GameCommand::DumpState => game.dump_state(),
+                GameCommand::Screenshot => self.screenshot_requested = true,
+                GameCommand::FullscreenDenied => game.fullscreen_denied(),
+                GameCommand::Destroy => self.destroyed = true,
+            }
+        }
+    }
+}
+
+/// Toggles fullscreen on the canvas. Browsers require a user gesture, so
+/// call this straight from an input handler, never from `Game::update`.
+pub fn toggle_fullscreen(canvas: &HtmlCanvasElement, commands: &SharedCommandQueue) {
+    let commands = Rc::clone(commands);
+    let promise = match browser::toggle_fullscreen(canvas) {
+        Ok(promise) => promise,
+        Err(err) => {
+            log!("Could not toggle fullscreen {:#?}", err);
+            commands
+                .borrow_mut()
+                .push_back(GameCommand::FullscreenDenied);
+            return;
+        }
+    };
+
+    browser::spawn_local(async move {
+        if let Err(err) = JsFuture::from(promise).await {
+            log!("Fullscreen request was rejected {:#?}", err);
+            commands
+                .borrow_mut()
+                .push_back(GameCommand::FullscreenDenied);
+        }
+    });
+}
+
+/// Keeps `screen` measured as the page scrolls, resizes or goes fullscreen.
+fn watch_layout(
+    canvas: &HtmlCanvasElement,
+    screen: &SharedScreenTransform,
+) -> Result<Vec<EventListenerGuard>> {
+    let on_change = || {
+        let canvas = canvas.clone();
+        let screen = Rc::clone(screen);
+        browser::closure_wrap(Box::new(move || {
+            screen.set(screen.get().remeasured(&canvas));
+        }) as Box<dyn FnMut()>)
+    };
+    Ok(vec![
+        browser::add_document_listener("fullscreenchange", on_change())?,
+        browser::add_window_listener("resize", on_change())?,
+        browser::add_window_listener("scroll", on_change())?,
+    ])
+}
+
+/// Refits the canvas whenever fullscreen is entered or left, or the window
+/// is resized, and records in `fullscreen` whether it now is.
+fn watch_fullscreen(
+    canvas: &HtmlCanvasElement,
+    context: &CanvasRenderingContext2d,
+    logical_size: (u32, u32),
+    letterbox: bool,
+    fullscreen: &Rc<std::cell::Cell<bool>>,
+) -> Result<Vec<EventListenerGuard>> {
+    let refit = || {
+        let canvas = canvas.clone();
+        let context = context.clone();
+        move || {
+            if let Err(err) = fit_canvas(&canvas, &context, logical_size, letterbox) {
+                log!("Could not resize canvas {:#?}", err);
+            }
+        }
+    };
+    let on_change = {
+        let refit = refit();
+        let fullscreen = Rc::clone(fullscreen);
+        browser::closure_wrap(Box::new(move || {
+            fullscreen.set(browser::is_fullscreen().unwrap_or(true));
+            refit();
+        }) as Box<dyn FnMut()>)
+    };
+    Ok(vec![
+        browser::add_document_listener("fullscreenchange", on_change)?,
+        browser::add_window_listener(
+            "resize",
+            browser::closure_wrap(Box::new(refit()) as Box<dyn FnMut()>),
+        )?,
+    ])
+}
+
+/// Sizes the backing store to the screen at the device pixel ratio while
+/// fullscreen and scales drawing so the game keeps its logical coordinates.
+/// The browser letterboxes the canvas (`object-fit: contain`) on the black
+/// background. With `letterbox` the backing store follows the element's box
+/// instead and the bars are left undrawn in it.
+fn fit_canvas(
+    canvas: &HtmlCanvasElement,
+    context: &CanvasRenderingContext2d,
+    (width, height): (u32, u32),
+    letterbox: bool,
+) -> Result<()> {
+    if letterbox {
+        let ratio = browser::device_pixel_ratio()?;
+        let (_, _, client_width, client_height) = browser::canvas_bounds(canvas);
+        let area = if client_width > 0.0 && client_height > 0.0 {
+            (client_width * ratio, client_height * ratio)
+        } else {
+            (f64::from(width), f64::from(height))
+        };
+        canvas.set_width(area.0.round() as u32);
+        canvas.set_height(area.1.round() as u32);
+        let fit = Letterbox::fit(area, (width, height));
+        context
+            .set_transform(fit.scale, 0.0, 0.0, fit.scale, fit.offset.0, fit.offset.1)
+            .map_err(|err| anyhow!("Could not letterbox canvas {:#?}", err))?;
+        return browser::set_canvas_style(canvas, "background-color", "#000");
+    }
+
+    let fullscreen = browser::is_fullscreen()?;
+    let scale = if fullscreen {
+        let (screen_width, screen_height) = browser::inner_size()?;
+        (screen_width / f64::from(width)).min(screen_height / f64::from(height))
+            * browser::device_pixel_ratio()?
+    } else {
+        1.0
+    };
+
+    canvas.set_width((f64::from(width) * scale).round() as u32);
+    canvas.set_height((f64::from(height) * scale).round() as u32);
+    context
+        .set_transform(scale, 0.0, 0.0, scale, 0.0, 0.0)
+        .map_err(|err| anyhow!("Could not scale canvas {:#?}", err))?;
+    browser::set_canvas_style(
+        canvas,
+        "background-color",
+        if fullscreen { "#000" } else { "" },
+    )
+}
+
+/// Captures the canvas as it was just drawn and downloads it as a PNG.
+fn save_screenshot(canvas: HtmlCanvasElement) {
+    browser::spawn_local(async move {
+        let result = match browser::canvas_to_png_blob(&canvas).await {
+            Ok(blob) => browser::download_blob(&blob, "walk-the-dog.png"),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log!("Could not save screenshot {:#?}", err);
+        }
+    });
+}
+
+const KEY_HISTORY_LENGTH: usize = 16;
+const KEY_HISTORY_WINDOW: f64 = 2000.0;
+
+pub struct KeyState {
+    pressed_keys: HashMap<String, web_sys::KeyboardEvent>,
+    taps: u32,
+    last_tap: Option<Point>,
+    history: VecDeque<(String, f64)>,
+    /// Game time each pressed key has been down for, in milliseconds.
+    held_ms: HashMap<String, f32>,
+}
+
+impl KeyState {
+    pub fn new() -> Self {
+        KeyState {
+            pressed_keys: HashMap::new(),
+            held_ms: HashMap::new(),
+            taps: 0,
+            last_tap: None,
+            history: VecDeque::with_capacity(KEY_HISTORY_LENGTH),
+        }
+    }
+
+    /// True when the most recent presses were `sequence`, in order, with the
+    /// first and last no more than `window_ms` apart.
+    pub fn matches_sequence(&self, sequence: &[&str], window_ms: f64) -> bool {
+        if sequence.is_empty() || self.history.len() < sequence.len() {
+            return false;
+        }
+
+        let start = self.history.len() - sequence.len();
+        let in_order = self
+            .history
+            .range(start..)
+            .zip(sequence)
+            .all(|((code, _), expected)| code == expected);
+        let first = self.history[start].1;
+        let last = self.history[self.history.len() - 1].1;
+        in_order && last - first <= window_ms
+    }
+
+    /// Which way the held `up`, `down`, `left` and `right` keys point,
+    /// including diagonals. Opposite keys cancel out.
+    pub fn heading(&self, up: &str, down: &str, left: &str, right: &str) -> Option<Heading> {
+        let axis = |negative: &str, positive: &str| {
+            i8::from(self.is_pressed(positive)) - i8::from(self.is_pressed(negative))
+        };
+        Heading::from_axes(axis(left, right), axis(up, down))
+    }
+
+    /// When the latest key press happened, on the `performance.now()` clock.
+    pub fn last_press_time(&self) -> Option<f64> {
+        self.history.back().map(|(_, time)| *time)
+    }
+
+    fn record_press(&mut self, code: &str, time: f64) {
+        if self.history.len() == KEY_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back((code.to_string(), time));
+    }
+
+    fn forget_presses_before(&mut self, time: f64) {
+        while self
+            .history
+            .front()
+            .is_some_and(|(_, pressed)| *pressed < time)
+        {
+            self.history.pop_front();
+        }
+    }
+
+    /// The most recent click or touch in canvas coordinates, with a count
+    /// that increases on every tap so callers can tell new taps apart.
+    pub fn last_tap(&self) -> Option<(u32, Point)> {
+        self.last_tap.map(|point| (self.taps, point))
+    }
+
+    fn set_tapped(&mut self, point: Point) {
+        self.taps += 1;
+        self.last_tap = Some(point);
+    }
+
+    pub fn is_pressed(&self, code: &str) -> bool {
+        self.pressed_keys.contains_key(code)
+    }
+
+    /// How long `code` has been held in milliseconds of game time: 0 on
+    /// the update it goes down, growing a frame per update after that, and
+    /// 0 again once it is released.
+    pub fn held_duration(&self, code: &str) -> f32 {
+        self.held_ms.get(code).copied().unwrap_or(0.0)
+    }
+
+    /// Counts `ms` more towards every held key; called after each update.
+    fn advance(&mut self, ms: f32) {
+        for held in self.held_ms.values_mut() {
+            *held += ms;
+        }
+    }
+
+    pub fn pressed_codes(&self) -> impl Iterator<Item = &str> {
+        self.pressed_keys.keys().map(String::as_str)
+    }
+
+    pub fn set_pressed(&mut self, code: &str, ev: web_sys::KeyboardEvent) {
+        if !self.is_pressed(code) {
+            self.record_press(code, ev.time_stamp());
+            self.held_ms.insert(code.into(), 0.0);
+        }
+        self.pressed_keys.insert(code.into(), ev);
+    }
+
+    pub fn set_released(&mut self, code: &str) {
+        self.pressed_keys.remove(code);
+        self.held_ms.remove(code);
+    }
+}
+
+enum KeyPress {
+    KeyUp(web_sys::KeyboardEvent),
+    KeyDown(web_sys::KeyboardEvent),
+    Tap(Point),
+}
+
+type KeyEventChannel = (
+    mpsc::UnboundedSender<KeyPress>,
+    mpsc::UnboundedReceiver<KeyPress>,
+);
+
+type SharedGuardedKeys = Rc<RefCell<HashSet<String>>>;
+
+/// Stops the browser acting on keys the game uses.
+fn guard_key(
+    event: &web_sys::KeyboardEvent,
+    guarded: &SharedGuardedKeys,
+    canvas: &HtmlCanvasElement,
+) {
+    if suppresses_key(&event.code(), &guarded.borrow(), || {
+        browser::has_focus(canvas)
+    }) {
+        event.prevent_default();
+    }
+}
+
+/// Whether the browser's own handling of `code` is held back. Tab is only
+/// held back while the canvas has focus, so keyboard users can still leave
+/// it.
+fn suppresses_key(
+    code: &str,
+    guarded: &HashSet<String>,
+    canvas_focused: impl FnOnce() -> bool,
+) -> bool {
+    guarded.contains(code) && (code != "Tab" || canvas_focused())
+}
+
+fn prepare_input(
+    canvas: &HtmlCanvasElement,
+    input_target: InputTarget,
+    commands: &SharedCommandQueue,
+    guarded: &SharedGuardedKeys,
+    screen: &SharedScreenTransform,
+) -> Result<(mpsc::UnboundedReceiver<KeyPress>, Vec<EventListenerGuard>)> {
+    let (tx, rx): KeyEventChannel = mpsc::unbounded();
+    let keydown_tx = Rc::new(RefCell::new(tx));
+    let keyup_tx = Rc::clone(&keydown_tx);
+    let tap_tx = Rc::clone(&keydown_tx);
+    let fullscreen_canvas = canvas.clone();
+    let commands = Rc::clone(commands);
+    let keydown_guard = (Rc::clone(guarded), canvas.clone());
+    let keyup_guard = (Rc::clone(guarded), canvas.clone());
+    let on_keydown = browser::closure_wrap(Box::new(move |keycode: web_sys::KeyboardEvent| {
+        guard_key(&keycode, &keydown_guard.0, &keydown_guard.1);
+        if keycode.code() == FULLSCREEN_KEY && !keycode.repeat() {
+            toggle_fullscreen(&fullscreen_canvas, &commands);
+        }
+        let _ = keydown_tx
+            .borrow_mut()
+            .start_send(KeyPress::KeyDown(keycode));
+    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+    let on_keyup = browser::closure_wrap(Box::new(move |keycode: web_sys::KeyboardEvent| {
+        guard_key(&keycode, &keyup_guard.0, &keyup_guard.1);
+        let _ = keyup_tx.borrow_mut().start_send(KeyPress::KeyUp(keycode));
+    }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
+    let tap_canvas = canvas.clone();
+    let screen = Rc::clone(screen);
+    let on_pointerdown = browser::closure_wrap(Box::new(move |event: web_sys::MouseEvent| {
+        if input_target == InputTarget::Canvas {
+            if let Err(err) = browser::focus_canvas(&tap_canvas) {
+                log!("{:#?}", err);
+            }
+        }
+        let tap = screen
+            .get()
+            .to_game(f64::from(event.client_x()), f64::from(event.client_y()));
+        if let Some(point) = tap {
+            let _ = tap_tx.borrow_mut().start_send(KeyPress::Tap(point));
+        }
+    }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+
+    let key_target: EventTarget = match input_target {
+        InputTarget::Document => browser::document()?.into(),
+        InputTarget::Canvas => canvas.clone().into(),
+    };
+    let listeners = vec![
+        EventListenerGuard::new(canvas, "pointerdown", on_pointerdown)?,
+        EventListenerGuard::new(&key_target, "keydown", on_keydown)?,
+        EventListenerGuard::new(&key_target, "keyup", on_keyup)?,
+    ];
+    if input_target == InputTarget::Canvas {
+        browser::focus_canvas(canvas)?;
+    }
+
+    Ok((rx, listeners))
+}
+
+impl KeyPress {
+    /// Taps are applied as soon as they arrive.
+    fn time_stamp(&self) -> f64 {
+        match self {
+            KeyPress::KeyUp(ev) | KeyPress::KeyDown(ev) => ev.time_stamp(),
+            KeyPress::Tap(_) => f64::NEG_INFINITY,
+        }
+    }
+}
+
+fn buffer_input(
+    buffer: &mut VecDeque<KeyPress>,
+    keyevent_rx: &mut mpsc::UnboundedReceiver<KeyPress>,
+) {
+    while let Ok(Some(ev)) = keyevent_rx.try_next() {
+        buffer.push_back(ev);
+    }
+}
+
+/// Applies buffered events in arrival order up to the first one after
+/// `until`, leaving the rest for a later update.
+fn apply_input_until(state: &mut KeyState, buffer: &mut VecDeque<KeyPress>, until: f64) {
+    for press in drain_until(buffer, until, KeyPress::time_stamp) {
+        match press {
+            KeyPress::KeyUp(ev) => state.set_released(&ev.code()),
+            KeyPress::KeyDown(ev) => state.set_pressed(&ev.code(), ev),
+            KeyPress::Tap(point) => state.set_tapped(point),
+        }
+    }
+}
+
+/// Takes items off the front of `buffer` while they happened no later than
+/// `until`, stopping at the first one after it.
+fn drain_until<T>(
+    buffer: &mut VecDeque<T>,
+    until: f64,
+    time_stamp: fn(&T) -> f64,
+) -> impl Iterator<Item = T> + '_ {
+    std::iter::from_fn(move || {
+        if buffer.front().is_some_and(|item| time_stamp(item) <= until) {
+            buffer.pop_front()
+        } else {
+            None
+        }
+    })
+}
+
+/// Events published during one fixed update, kept in publish order so
+/// systems can react to them once the update is done. Publishing past the
+/// cap drops the event and warns once per update.
+pub struct EventBus<E> {
+    events: Vec<E>,
+    cap: usize,
+    overflowed: bool,
+}
+
+impl<E: std::fmt::Debug> EventBus<E> {
+    pub fn with_cap(cap: usize) -> Self {
+        EventBus {
+            events: Vec::with_capacity(cap),
+            cap,
+            overflowed: false,
+        }
+    }
+
+    pub fn publish(&mut self, event: E) {
+        if self.events.len() < self.cap {
+            self.events.push(event);
+        } else if !self.overflowed {
+            self.overflowed = true;
+            log!(
+                "Event bus hit its cap of {}, dropping {:#?}",
+                self.cap,
+                event
+            );
+        }
+    }
+
+    pub fn events_this_frame(&self) -> &[E] {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.overflowed = false;
+    }
+}
+
+/// Keeps released values for reuse, so objects that are expensive to build
+/// are only built when none are spare. `reset` runs on each value as it is
+/// released.
+pub struct Pool<T> {
+    free: Vec<T>,
+    reset: fn(&mut T),
+    allocations: u32,
+    acquisitions: u32,
+}
+
+impl<T> Pool<T> {
+    pub fn new(reset: fn(&mut T)) -> Self {
+        Pool {
+            free: Vec::new(),
+            reset,
+            allocations: 0,
+            acquisitions: 0,
+        }
+    }
+
+    /// Takes a spare value, or builds one with `create` if there is none.
+    pub fn acquire(&mut self, create: impl FnOnce() -> T) -> T {
+        self.acquisitions += 1;
+        self.free.pop().unwrap_or_else(|| {
+            self.allocations += 1;
+            create()
+        })
+    }
+
+    pub fn release(&mut self, mut value: T) {
+        (self.reset)(&mut value);
+        self.free.push(value);
+    }
+
+    /// How many values `create` has built so far.
+    pub fn allocations(&self) -> u32 {
+        self.allocations
+    }
+
+    /// How many values have been asked for so far, which is how many would
+    /// have been built without the pool.
+    pub fn acquisitions(&self) -> u32 {
+        self.acquisitions
+    }
+}
+
+const AUDIO_FADE_IN_SECONDS: f64 = 0.05;
+
+#[derive(Clone)]
+pub struct Audio {
+    context: AudioContext,
+    output: GainNode,
+    volume: Rc<std::cell::Cell<f32>>,
+    suspension: Rc<std::cell::Cell<Suspension>>,
+    /// Detached once the last handle to this audio is dropped. The
+    /// listeners' own copies have none, so they don't keep each other
+    /// alive.
+    _listeners: Option<Rc<Vec<EventListenerGuard>>>,
+}
+
+/// Why the audio context is suspended. It runs again only once none of
+/// the reasons hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Suspension {
+    paused: bool,
+    /// A menu is open over the game.
+    menu: bool,
+    hidden: bool,
+    /// Browsers only let audio start from a key press or click, so the
+    /// context waits for the first one.
+    locked: bool,
+}
+
+impl Default for Suspension {
+    fn default() -> Self {
+        Suspension {
+            paused: false,
+            menu: false,
+            hidden: false,
+            locked: true,
+        }
+    }
+}
+
+impl Suspension {
+    fn suspended(&self) -> bool {
+        self.paused || self.menu || self.hidden || self.locked
+    }
+}
+
+/// A decoded effect. Every play gets its own source, and with a
+/// `max_concurrent` cap the oldest still-playing source is stopped to make
+/// room for a new one.
+#[derive(Clone)]
+pub struct Sound {
+    buffer: AudioBuffer,
+    max_concurrent: Option<usize>,
+    playing: Rc<RefCell<VecDeque<(AudioBufferSourceNode, f64)>>>,
+}
+
+impl Sound {
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent.max(1));
+        self
+    }
+}
+
+impl Audio {
+    pub fn new() -> Result<Self> {
+        let context = sound::create_audio_context()?;
+        let output = sound::create_gain(&context)?;
+        let mut audio = Audio {
+            context,
+            output,
+            volume: Rc::new(std::cell::Cell::new(1.0)),
+            suspension: Rc::new(std::cell::Cell::new(Suspension::default())),
+            _listeners: None,
+        };
+
+        let watched = audio.clone();
+        let on_visibility_change = browser::closure_wrap(Box::new(move || {
+            let hidden = browser::is_page_hidden();
+            watched.update_suspension(|suspension| suspension.hidden = hidden);
+        }) as Box<dyn FnMut()>);
+        let mut listeners = vec![browser::add_document_listener(
+            "visibilitychange",
+            on_visibility_change,
+        )?];
+        for gesture in ["keydown", "pointerdown"] {
+            let unlocked = audio.clone();
+            let on_gesture = browser::closure_wrap(Box::new(move || {
+                unlocked.update_suspension(|suspension| suspension.locked = false);
+            }) as Box<dyn FnMut()>);
+            listeners.push(browser::add_document_listener(gesture, on_gesture)?);
+        }
+        audio._listeners = Some(Rc::new(listeners));
+
+        Ok(audio)
+    }
+
+    /// Suspends the audio context while the game is paused. Effects already
+    /// playing pick up where they left off on resume.
+    pub fn set_paused(&self, paused: bool) {
+        self.update_suspension(|suspension| suspension.paused = paused);
+    }
+
+    /// Suspends the audio context while a menu is open over the game. This
+    /// is kept apart from `set_paused`, so closing the menu doesn't resume
+    /// audio the page paused for its own reasons.
+    pub fn set_in_menu(&self, open: bool) {
+        self.update_suspension(|suspension| suspension.menu = open);
+    }
+
+    /// Suspends or resumes the context when the reasons for suspending
+    /// change. Resuming fades the volume back in so it doesn't pop.
+    fn update_suspension(&self, change: impl FnOnce(&mut Suspension)) {
+        let before = self.suspension.get();
+        let mut after = before;
+        change(&mut after);
+        self.suspension.set(after);
+        if before.suspended() == after.suspended() {
+            return;
+        }
+
+        let result = if after.suspended() {
+            sound::suspend(&self.context)
+        } else {
+            sound::ramp_gain(
+                &self.context,
+                &self.output,
+                0.0,
+                self.volume.get(),
+                AUDIO_FADE_IN_SECONDS,
+            )
+            .and_then(|_| sound::resume(&self.context))
+        };
+        if let Err(err) = result {
+            log!("{:#?}", err);
+        }
+    }
+
+    /// Fetches and decodes a sound up front, so playing it never waits.
+    pub async fn load_sound(&self, filename: &str) -> Result<Sound> {
+        let array_buffer = browser::fetch_array_buffer(filename)
+            .await
+            .map_err(|err| anyhow!("Could not fetch sound {} {:#?}", filename, err))?;
+        let buffer = sound::decode_audio_data(&self.context, &array_buffer)
+            .await
+            .map_err(|err| anyhow!("Could not decode sound {} {:#?}", filename, err))?;
+
+        Ok(Sound {
+            buffer,
+            max_concurrent: None,
+            playing: Rc::new(RefCell::new(VecDeque::new())),
+        })
+    }
+
+    pub fn play_sound(&self, sound: &Sound) -> Result<()> {
+        let now = self.context.current_time();
+        let mut playing = sound.playing.borrow_mut();
+        for (oldest, _) in make_room(&mut playing, now, sound.max_concurrent) {
+            sound::stop_sound(&oldest)?;
+        }
+
+        let source = sound::play_sound(&self.context, &sound.buffer, &self.output)?;
+        playing.push_back((source, now + sound.buffer.duration()));
+        Ok(())
+    }
+
+    /// Sets the level of every sound, from 0.0 for silence to 1.0.
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        self.volume.set(volume);
+        let param = self.output.gain();
+        if let Err(err) = param.cancel_scheduled_values(self.context.current_time()) {
+            log!("Could not cancel gain ramp {:#?}", err);
+        }
+        param.set_value(volume);
+    }
+}
+
+/// Forgets the sources in `playing` that finished by `now` and, with a
+/// `max` cap, takes out the oldest until one more fits.
+fn make_room<T>(
+    playing: &mut VecDeque<(T, f64)>,
+    now: f64,
+    max: Option<usize>,
+) -> std::collections::vec_deque::Drain<'_, (T, f64)> {
+    playing.retain(|(_, ends_at)| *ends_at > now);
+    let excess = max.map_or(0, |max| (playing.len() + 1).saturating_sub(max));
+    playing.drain(..excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    /// Plays source `id`, lasting a second, at `now`, returning the ids
+    /// stopped to make room for it.
+    fn play(playing: &mut VecDeque<(u32, f64)>, id: u32, now: f64, max: Option<usize>) -> Vec<u32> {
+        let stopped = make_room(playing, now, max).map(|(id, _)| id).collect();
+        playing.push_back((id, now + 1.0));
+        stopped
+    }
+
+    #[test]
+    fn rapid_plays_overlap_as_separate_sources() {
+        let mut playing = VecDeque::new();
+
+        assert!(play(&mut playing, 1, 0.0, None).is_empty());
+        assert!(play(&mut playing, 2, 0.1, None).is_empty());
+        assert_eq!(
+            playing.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn the_oldest_source_is_stopped_beyond_the_cap() {
+        let mut playing = VecDeque::new();
+        for id in 1..=3 {
+            assert!(play(&mut playing, id, 0.0, Some(3)).is_empty());
+        }
+
+        assert_eq!(play(&mut playing, 4, 0.5, Some(3)), [1]);
+        assert_eq!(play(&mut playing, 5, 0.5, Some(3)), [2]);
+        assert_eq!(
+            playing.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn finished_sources_do_not_count_towards_the_cap() {
+        let mut playing = VecDeque::new();
+        play(&mut playing, 1, 0.0, Some(2));
+        play(&mut playing, 2, 0.0, Some(2));
+
+        assert!(play(&mut playing, 3, 1.5, Some(2)).is_empty());
+        assert_eq!(playing.len(), 1);
+    }
+
+    /// Records the commands the loop hands it and counts the updates and
+    /// frames it is asked for.
+    #[derive(Default)]
+    struct CommandLog {
+        calls: Rc<RefCell<Vec<&'static str>>>,
+        updates: Rc<std::cell::Cell<u32>>,
+        draws: Rc<std::cell::Cell<u32>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Game for CommandLog {
+        async fn initialize(&self) -> Result<Box<dyn Game>> {
+            Ok(Box::new(CommandLog {
+                calls: Rc::clone(&self.calls),
+                updates: Rc::clone(&self.updates),
+                draws: Rc::clone(&self.draws),
+            }))
+        }
+
+        fn update(&mut self, _keystate: &KeyState) {
+            self.updates.set(self.updates.get() + 1);
+        }
+
+        fn draw(&self, _renderer: &Renderer) -> Result<()> {
+            self.draws.set(self.draws.get() + 1);
+            Ok(())
+        }
+
+        fn restart(&mut self) {
+            self.calls.borrow_mut().push("restart");
+        }
+
+        fn set_paused(&mut self, paused: bool) {
+            self.calls
+                .borrow_mut()
+                .push(if paused { "pause" } else { "resume" });
+        }
+
+        fn fullscreen_denied(&mut self) {
+            self.calls.borrow_mut().push("fullscreen denied");
+        }
+    }
+
+    /// Ten pixels per character, like a monospaced font.
+    fn monospace(text: &str) -> f64 {
+        text.chars().count() as f64 * 10.0
+    }
+
+    #[test]
+    fn wrap_text_breaks_at_spaces() {
+        assert_eq!(
+            wrap_text("the quick brown fox jumps", 100.0, monospace),
+            ["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(wrap_text("fits", 100.0, monospace), ["fits"]);
+        assert!(wrap_text("   ", 100.0, monospace).is_empty());
+    }
+
+    #[test]
+    fn wrap_text_splits_words_longer_than_a_line() {
+        assert_eq!(
+            wrap_text("a supercalifragilistic word", 50.0, monospace),
+            ["a", "super", "calif", "ragil", "istic", "word"]
+        );
+    }
+
+    fn pressed(presses: &[(&str, f64)]) -> KeyState {
+        let mut keystate = KeyState::new();
+        for (code, time) in presses {
+            keystate.record_press(code, *time);
+        }
+        keystate
+    }
+
+    #[test]
+    fn a_sequence_within_the_window_matches() {
+        let keystate = pressed(&[
+            ("KeyA", 0.0),
+            ("ArrowDown", 100.0),
+            ("ArrowRight", 200.0),
+            ("Space", 300.0),
+        ]);
+
+        assert!(keystate.matches_sequence(&["ArrowDown", "ArrowRight", "Space"], 200.0));
+        assert!(!keystate.matches_sequence(&["ArrowRight", "ArrowDown", "Space"], 200.0));
+    }
+
+    #[test]
+    fn a_sequence_slower_than_the_window_does_not_match() {
+        let keystate = pressed(&[("ArrowDown", 0.0), ("ArrowRight", 150.0), ("Space", 301.0)]);
+
+        assert!(!keystate.matches_sequence(&["ArrowDown", "ArrowRight", "Space"], 300.0));
+    }
+
+    #[test]
+    fn stale_and_overflowing_presses_are_forgotten() {
+        let mut keystate = pressed(&[("ArrowDown", 0.0), ("Space", 500.0)]);
+        keystate.forget_presses_before(100.0);
+        assert!(!keystate.matches_sequence(&["ArrowDown", "Space"], 1000.0));
+        assert!(keystate.matches_sequence(&["Space"], 0.0));
+
+        for press in 0..KEY_HISTORY_LENGTH * 2 {
+            keystate.record_press("KeyX", press as f64);
+        }
+        assert_eq!(keystate.history.len(), KEY_HISTORY_LENGTH);
+    }
+
+    fn queue(commands: &[GameCommand]) -> SharedCommandQueue {
+        Rc::new(RefCell::new(commands.iter().copied().collect()))
+    }
+
+    #[test]
+    fn pause_resume_and_restart_reach_the_game_in_order() {
+        let mut game_loop = GameLoop::new(0.0);
+        let mut game = CommandLog::default();
+        let commands = queue(&[GameCommand::Pause, GameCommand::Restart]);
+
+        game_loop.process_commands(&mut game, &commands);
+        assert!(game_loop.paused);
+        assert!(commands.borrow().is_empty());
+
+        commands.borrow_mut().push_back(GameCommand::Resume);
+        game_loop.process_commands(&mut game, &commands);
+        assert!(!game_loop.paused);
+        assert_eq!(*game.calls.borrow(), ["pause", "restart", "resume"]);
+    }
+
+    #[test]
+    fn a_recording_keeps_n_frames_over_more_draws() {
+        let mut recording = Recording::default();
+        recording.start(3);
+
+        for draw in 0..5 {
+            if recording.active {
+                recording.capture(format!("frame {}", draw));
+            }
+        }
+
+        assert!(!recording.active);
+        assert_eq!(recording.take_frames(), ["frame 0", "frame 1", "frame 2"]);
+    }
+
+    #[test]
+    fn a_recording_keeps_between_one_and_the_most_frames() {
+        let mut recording = Recording::default();
+
+        recording.start(0);
+        assert_eq!(recording.max_frames, 1);
+
+        recording.start(usize::MAX);
+        assert_eq!(recording.max_frames, MAX_RECORDED_FRAMES);
+    }
+
+    #[test]
+    fn a_refused_fullscreen_is_left_to_the_game_to_report() {
+        let mut game = CommandLog::default();
+
+        GameLoop::new(0.0).process_commands(&mut game, &queue(&[GameCommand::FullscreenDenied]));
+
+        assert_eq!(*game.calls.borrow(), ["fullscreen denied"]);
+    }
+
+    #[test]
+    fn destroy_marks_the_loop_destroyed() {
+        let mut game_loop = GameLoop::new(0.0);
+
+        game_loop.process_commands(&mut CommandLog::default(), &queue(&[GameCommand::Destroy]));
+
+        assert!(game_loop.destroyed);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    /// Takes a few animation frames to initialize into `game`, counting the
+    /// loading screens drawn meanwhile.
+    #[cfg(target_arch = "wasm32")]
+    #[derive(Default)]
+    struct SlowLoad {
+        game: CommandLog,
+        loading_draws: Rc<std::cell::Cell<u32>>,
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[async_trait(?Send)]
+    impl Game for SlowLoad {
+        async fn initialize(&self) -> Result<Box<dyn Game>> {
+            for _ in 0..3 {
+                browser::next_frame().await;
+            }
+            self.game.initialize().await
+        }
+
+        fn draw_loading(&self, _renderer: &Renderer, _progress: f32) -> Result<()> {
+            self.loading_draws.set(self.loading_draws.get() + 1);
+            Ok(())
+        }
+
+        fn update(&mut self, _keystate: &KeyState) {}
+
+        fn draw(&self, _renderer: &Renderer) -> Result<()> {
+            Ok(())
+        }
+
+        fn restart(&mut self) {}
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn test_canvas() -> HtmlCanvasElement {
+        use wasm_bindgen::JsCast;
+
+        browser::document()
+            .unwrap()
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into()
+            .unwrap()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn the_loading_screen_hands_over_to_the_game() {
+        let canvas = test_canvas();
+        let game = SlowLoad::default();
+        let loading_draws = Rc::clone(&game.loading_draws);
+        let draws = Rc::clone(&game.game.draws);
+        let commands = queue(&[]);
+        GameLoop::start(
+            game,
+            canvas.clone(),
+            GameLoopConfig::default(),
+            Rc::clone(&commands),
+            SharedRecording::default(),
+            Rc::new(std::cell::Cell::new(ScreenTransform::measure(&canvas))),
+            None,
+        )
+        .await
+        .unwrap();
+        let loading = loading_draws.get();
+        assert!(loading > 0);
+        assert_eq!(draws.get(), 0);
+
+        browser::next_frame().await;
+        browser::next_frame().await;
+
+        assert!(draws.get() > 0);
+        assert_eq!(loading_draws.get(), loading);
+        commands.borrow_mut().push_back(GameCommand::Destroy);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn no_frames_are_drawn_after_destroy() {
+        let canvas = test_canvas();
+        let game = CommandLog::default();
+        let draws = Rc::clone(&game.draws);
+        let commands = queue(&[]);
+        GameLoop::start(
+            game,
+            canvas.clone(),
+            GameLoopConfig::default(),
+            Rc::clone(&commands),
+            SharedRecording::default(),
+            Rc::new(std::cell::Cell::new(ScreenTransform::measure(&canvas))),
+            None,
+        )
+        .await
+        .unwrap();
+        browser::next_frame().await;
+        browser::next_frame().await;
+        assert!(draws.get() > 0);
+
+        commands.borrow_mut().push_back(GameCommand::Destroy);
+        browser::next_frame().await;
+        let drawn = draws.get();
+        for _ in 0..5 {
+            browser::next_frame().await;
+        }
+
+        assert_eq!(draws.get(), drawn);
+        assert_eq!(Rc::strong_count(&commands), 1);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn canvases_without_ids_get_their_own_hud_and_destroy_removes_it() {
+        let body = browser::document().unwrap().body().unwrap();
+        let mut games = Vec::new();
+        for _ in 0..2 {
+            let canvas = test_canvas();
+            body.append_child(&canvas).unwrap();
+            let commands = queue(&[]);
+            GameLoop::start(
+                CommandLog::default(),
+                canvas.clone(),
+                GameLoopConfig::default(),
+                Rc::clone(&commands),
+                SharedRecording::default(),
+                Rc::new(std::cell::Cell::new(ScreenTransform::measure(&canvas))),
+                None,
+            )
+            .await
+            .unwrap();
+            let hud = canvas.next_element_sibling().unwrap();
+            assert_eq!(hud.tag_name(), "CANVAS");
+            assert_eq!(hud.id(), "");
+            games.push((canvas, hud, commands));
+        }
+        assert!(!games[0].1.is_same_node(Some(&games[1].1)));
+
+        for (_, _, commands) in &games {
+            commands.borrow_mut().push_back(GameCommand::Destroy);
+        }
+        for _ in 0..3 {
+            browser::next_frame().await;
+        }
+
+        for (canvas, hud, _) in games {
+            assert!(hud.parent_element().is_none());
+            canvas.remove();
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn pausing_stops_the_updates_until_resumed() {
+        let canvas = test_canvas();
+        let game = CommandLog::default();
+        let updates = Rc::clone(&game.updates);
+        let commands = queue(&[]);
+        GameLoop::start(
+            game,
+            canvas.clone(),
+            GameLoopConfig::default(),
+            Rc::clone(&commands),
+            SharedRecording::default(),
+            Rc::new(std::cell::Cell::new(ScreenTransform::measure(&canvas))),
+            None,
+        )
+        .await
+        .unwrap();
+        browser::sleep(100).await;
+        assert!(updates.get() > 0);
+
+        commands.borrow_mut().push_back(GameCommand::Pause);
+        browser::next_frame().await;
+        let paused_at = updates.get();
+        browser::sleep(100).await;
+        assert_eq!(updates.get(), paused_at);
+
+        commands.borrow_mut().push_back(GameCommand::Resume);
+        browser::sleep(100).await;
+        assert!(updates.get() > paused_at);
+        commands.borrow_mut().push_back(GameCommand::Destroy);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    async fn on_ready_runs_once_before_the_first_update() {
+        let canvas = test_canvas();
+        let game = CommandLog::default();
+        let updates = Rc::clone(&game.updates);
+        let readied = Rc::new(RefCell::new(Vec::new()));
+        let on_ready: ReadyCallback = {
+            let readied = Rc::clone(&readied);
+            let updates = Rc::clone(&updates);
+            Box::new(move |_, _| readied.borrow_mut().push(updates.get()))
+        };
+        let commands = queue(&[]);
+        GameLoop::start(
+            game,
+            canvas.clone(),
+            GameLoopConfig::default(),
+            Rc::clone(&commands),
+            SharedRecording::default(),
+            Rc::new(std::cell::Cell::new(ScreenTransform::measure(&canvas))),
+            Some(on_ready),
+        )
+        .await
+        .unwrap();
+        browser::sleep(100).await;
+
+        assert!(updates.get() > 0);
+        assert_eq!(*readied.borrow(), [0]);
+        commands.borrow_mut().push_back(GameCommand::Destroy);
+    }
+
+    #[test]
+    fn image_regions_are_fractions_of_the_image() {
+        let size = (64.0, 32.0);
+
+        assert_eq!(
+            image_region(size, (0.0, 0.0), (1.0, 1.0)),
+            (0.0, 0.0, 64.0, 32.0)
+        );
+        assert_eq!(
+            image_region(size, (0.5, 0.5), (1.0, 1.0)),
+            (32.0, 16.0, 32.0, 16.0)
+        );
+        assert_eq!(
+            image_region(size, (-1.0, 0.0), (0.25, 2.0)),
+            (0.0, 0.0, 16.0, 32.0)
+        );
+    }
+
+    #[test]
+    fn resuming_from_a_menu_restores_the_suspension_it_opened_over() {
+        let paused_by_the_page = Suspension {
+            paused: true,
+            locked: false,
+            ..Suspension::default()
+        };
+        let mut suspension = paused_by_the_page;
+
+        suspension.menu = true;
+        assert!(suspension.suspended());
+        suspension.menu = false;
+
+        assert_eq!(suspension, paused_by_the_page);
+        assert!(suspension.suspended());
+    }
+
+    #[test]
+    fn a_pillarboxed_canvas_maps_past_the_left_bar() {
+        // 1600x600 on the page, 10 from the left and 20 from the top.
+        let transform = ScreenTransform::new((10.0, 20.0, 1600.0, 600.0), (1200, 600));
+
+        assert_eq!(transform.to_game(210.0, 20.0), Some(Point { x: 0, y: 0 }));
+        assert_eq!(
+            transform.to_game(810.0, 320.0),
+            Some(Point { x: 600, y: 300 })
+        );
+        assert_eq!(transform.to_game(100.0, 300.0), None);
+        assert_eq!(transform.to_game(1410.0, 300.0), None);
+        assert_eq!(
+            transform.to_screen(Point { x: 600, y: 300 }),
+            (810.0, 320.0)
+        );
+    }
+
+    #[test]
+    fn a_letterboxed_canvas_maps_past_the_top_bar() {
+        let transform = ScreenTransform::new((0.0, 0.0, 1200.0, 1000.0), (1200, 600));
+
+        assert_eq!(transform.to_game(0.0, 200.0), Some(Point { x: 0, y: 0 }));
+        assert_eq!(
+            transform.to_game(600.0, 500.0),
+            Some(Point { x: 600, y: 300 })
+        );
+        assert_eq!(transform.to_game(600.0, 100.0), None);
+        assert_eq!(transform.to_game(600.0, 850.0), None);
+        assert_eq!(transform.to_screen(Point { x: 0, y: 600 }), (0.0, 800.0));
+    }
+
+    #[test]
+    fn the_device_pixel_ratio_does_not_move_the_mapping() {
+        // At a ratio of 2 the backing store is 1200x600 but the element is
+        // 600x300 CSS pixels, which is what pointers report in.
+        let transform = ScreenTransform::new((0.0, 0.0, 600.0, 300.0), (1200, 600));
+
+        assert_eq!(
+            transform.to_game(300.0, 150.0),
+            Some(Point { x: 600, y: 300 })
+        );
+        assert_eq!(
+            transform.to_game(599.0, 299.0),
+            Some(Point { x: 1198, y: 598 })
+        );
+        assert_eq!(
+            transform.to_screen(Point { x: 1200, y: 600 }),
+            (600.0, 300.0)
+        );
+    }
+
+    #[test]
+    fn a_wide_area_is_pillarboxed() {
+        assert_eq!(
+            Letterbox::fit((2400.0, 600.0), (1200, 600)),
+            Letterbox {
+                scale: 1.0,
+                offset: (600.0, 0.0),
+            }
+        );
+        assert_eq!(
+            Letterbox::fit((1920.0, 480.0), (1200, 600)),
+            Letterbox {
+                scale: 0.8,
+                offset: (480.0, 0.0),
+            }
+        );
+    }
+
+    #[test]
+    fn a_tall_area_is_letterboxed() {
+        assert_eq!(
+            Letterbox::fit((1200.0, 1200.0), (1200, 600)),
+            Letterbox {
+                scale: 1.0,
+                offset: (0.0, 300.0),
+            }
+        );
+        assert_eq!(
+            Letterbox::fit((600.0, 800.0), (1200, 600)),
+            Letterbox {
+                scale: 0.5,
+                offset: (0.0, 250.0),
+            }
+        );
+    }
+
+    #[test]
+    fn a_held_key_counts_up_until_it_is_released() {
+        let mut keystate = KeyState::new();
+        keystate.held_ms.insert("Space".into(), 0.0);
+
+        keystate.advance(FRAME_SIZE);
+        keystate.advance(FRAME_SIZE);
+        assert_eq!(keystate.held_duration("Space"), 2.0 * FRAME_SIZE);
+        assert_eq!(keystate.held_duration("KeyA"), 0.0);
+
+        keystate.set_released("Space");
+        keystate.advance(FRAME_SIZE);
+        assert_eq!(keystate.held_duration("Space"), 0.0);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    fn pressing_a_key_again_starts_its_duration_over() {
+        let press = || web_sys::KeyboardEvent::new("keydown").unwrap();
+        let mut keystate = KeyState::new();
+
+        keystate.set_pressed("Space", press());
+        assert_eq!(keystate.held_duration("Space"), 0.0);
+        keystate.advance(FRAME_SIZE);
+        // Key repeat fires keydown again without restarting the count.
+        keystate.set_pressed("Space", press());
+        keystate.advance(FRAME_SIZE);
+        assert_eq!(keystate.held_duration("Space"), 2.0 * FRAME_SIZE);
+
+        keystate.set_released("Space");
+        keystate.set_pressed("Space", press());
+        assert_eq!(keystate.held_duration("Space"), 0.0);
+    }
+
+    #[test]
+    fn a_pool_builds_only_when_nothing_is_spare() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(Vec::clear);
+
+        let mut first = pool.acquire(|| Vec::with_capacity(8));
+        first.push(1);
+        let second = pool.acquire(Vec::new);
+        pool.release(first);
+        let reused = pool.acquire(Vec::new);
+
+        assert!(reused.is_empty());
+        assert_eq!(reused.capacity(), 8);
+        assert_eq!(pool.allocations(), 2);
+        assert_eq!(pool.acquisitions(), 3);
+        drop(second);
+    }
+
+    #[test]
+    fn frame_stats_count_each_kind_of_call_and_start_over_each_frame() {
+        let stats = FrameCounter::default();
+        for _ in 0..3 {
+            stats.count(|stats| stats.images += 1);
+        }
+        for _ in 0..2 {
+            stats.count(|stats| stats.rects += 1);
+        }
+        stats.count(|stats| stats.shapes += 1);
+
+        stats.begin_frame();
+        assert_eq!(
+            stats.last.get(),
+            RenderStats {
+                draw_calls: 6,
+                images: 3,
+                rects: 2,
+                texts: 0,
+                shapes: 1,
+            }
+        );
+
+        stats.count(|stats| stats.texts += 1);
+        stats.begin_frame();
+        assert_eq!(
+            stats.last.get(),
+            RenderStats {
+                draw_calls: 1,
+                texts: 1,
+                ..RenderStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn a_triangle_is_traced_corner_to_corner_and_closed() {
+        let corners = [
+            Point { x: 0, y: 10 },
+            Point { x: 5, y: 0 },
+            Point { x: 10, y: 10 },
+        ];
+
+        assert_eq!(
+            polygon_path(&corners),
+            vec![
+                PathStep::MoveTo(corners[0]),
+                PathStep::LineTo(corners[1]),
+                PathStep::LineTo(corners[2]),
+                PathStep::Close,
+            ]
+        );
+    }
+
+    #[test]
+    fn fewer_than_three_points_trace_nothing() {
+        let points = [Point { x: 0, y: 0 }, Point { x: 10, y: 10 }];
+
+        assert!(polygon_path(&[]).is_empty());
+        assert!(polygon_path(&points[..1]).is_empty());
+        assert!(polygon_path(&points).is_empty());
+    }
+
+    #[test]
+    fn presses_straddling_a_step_are_applied_in_the_step_they_happened_in() {
+        let step_end = 100.0;
+        let mut buffer = VecDeque::from([
+            (90.0, "ArrowRight"),
+            (99.0, "Space"),
+            (101.0, "ArrowDown"),
+            (115.0, "ArrowLeft"),
+        ]);
+        let drain = |buffer: &mut VecDeque<(f64, &'static str)>, until| {
+            drain_until(buffer, until, |(time, _)| *time)
+                .map(|(_, code)| code)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(drain(&mut buffer, step_end), ["ArrowRight", "Space"]);
+        assert_eq!(drain(&mut buffer, step_end), Vec::<&str>::new());
+        assert_eq!(
+            drain(&mut buffer, step_end + f64::from(FRAME_SIZE)),
+            ["ArrowDown", "ArrowLeft"]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn only_guarded_keys_are_suppressed_and_tab_only_while_focused() {
+        let guarded: HashSet<String> = ["Space", "ArrowDown", "Tab"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert!(suppresses_key("Space", &guarded, || false));
+        assert!(suppresses_key("ArrowDown", &guarded, || true));
+        assert!(!suppresses_key("KeyF", &guarded, || true));
+        assert!(suppresses_key("Tab", &guarded, || true));
+        assert!(!suppresses_key("Tab", &guarded, || false));
+        assert!(!suppresses_key("Tab", &HashSet::new(), || true));
     }
 }
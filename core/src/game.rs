@@ -1,55 +1,339 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use web_sys::HtmlImageElement;
+use js_sys::Function;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    str::FromStr,
+};
+use wasm_bindgen::JsValue;
+use web_sys::{EventTarget, HtmlImageElement};
 
-use self::red_hat_boy_states::*;
 use crate::{
     browser,
-    engine::{self, Cell, Game, Image, KeyState, Point, Rect, Renderer, Sheet},
+    engine::{
+        self,
+        cutscene::{Actor, Command, CutscenePlayer, Script},
+        tween::{self, Alpha, Tween},
+        Anchor, Audio, Cell, Cooldown, DebugClass, DebugPalette, EventBus, Game, Heading, Image,
+        KeyState, Point, Pool, Rect, Renderer, Segment, Sheet, Sound, Stamina, StaminaConfig,
+        Timer, FRAME_SIZE,
+    },
+    locale::Strings,
+    metrics::Metrics,
+};
+use walk_the_dog_sim::{
+    collision::{self, Footing, Proximity},
+    red_hat_boy::{red_hat_boy_states::*, Event, RedHatBoyStateMachine, StateKind},
+    Projectile, GROUND,
 };
 
-const HEIGHT: i16 = 600;
+/// The ground runs along the bottom of the canvas.
+const HEIGHT: i16 = GROUND;
 const WIDTH: i16 = 1200;
-const LOW_PLATFORM: i16 = 420;
+const BIOMES_PATH: &str = "assets/biomes.json";
+const BIOME_FADE_TICKS: u32 = 120;
 const HIGH_PLATFORM: i16 = 375;
+const LOW_PLATFORM: i16 = 420;
+const STONE_Y: i16 = 546;
+const SCORE_REPORT_INTERVAL: u32 = 15;
+const NEAR_MISS_MARGIN: i16 = 16;
+const NEAR_MISS_POINTS: u32 = 5;
+const PIXELS_PER_METER: i32 = 10;
+const MILESTONE_METERS: i32 = 500;
+const PLAYER_SPACING: i16 = 80;
+// Used when a frame is missing from its sheet, so a bad atlas draws nothing
+// instead of taking the module down.
+const RHB_FRAME_SIZE: (i16, i16) = (160, 136);
+const PLATFORM_TILE_SIZE: (i16, i16) = (128, 93);
+const PREDICTION_TICKS: usize = 30;
+const JUMP_PREVIEW_TICKS: usize = 60;
+const JUMP_PREVIEW_COLOR: &str = "rgba(0, 120, 255, 0.8)";
+const LANDING_CROSS: i16 = 5;
+const DIFFICULTY_HISTORY: usize = 5;
+const DIFFICULTY_STEP: f32 = 0.1;
+const STRUGGLING_METERS: i32 = 50;
+const THRIVING_METERS: i32 = 300;
+const DIFFICULTY_SPEED_RANGE: f32 = 2.0;
+const DUMP_STATE_KEY: &str = "F9";
+const SCREENSHOT_KEY: &str = "KeyC";
+const RESUME_KEY: &str = "KeyR";
+const RUN_SAVE_KEY: &str = "walk-the-dog.run";
+const RUN_SAVE_INTERVAL: u32 = 180;
+const COIN_RADIUS: i16 = 10;
+const MAGNET_RADIUS: f32 = 200.0;
+const MAGNET_PULL: f32 = 0.15;
+const MAGNET_MILLIS: f32 = 10_000.0;
+const JETPACK_MILLIS: f32 = 8_000.0;
+const GRAVITY_ZONE_COLOR: &str = "rgba(150, 110, 255, 0.15)";
+const TITLE_KEY: &str = "KeyT";
+const GODMODE_KEY: &str = "KeyG";
+const INSPECT_KEY: &str = "F2";
+const INSPECT_PAN_SPEED: i16 = 8;
+const INSPECT_FAST_PAN: i16 = 4;
+const SPARED_HIT_FRAMES: u32 = 30;
+const MENU_UP_KEY: &str = "ArrowUp";
+const MENU_DOWN_KEY: &str = "ArrowDown";
+const MENU_SELECT_KEY: &str = "Enter";
+const MENU_LEFT_KEY: &str = "ArrowLeft";
+const MENU_RIGHT_KEY: &str = "ArrowRight";
+const MENU_BACK_KEY: &str = "Escape";
+const SETTINGS_KEY: &str = "walk-the-dog.settings";
+const LEVEL_RESULTS_KEY: &str = "walk-the-dog.medals";
+const MEDAL_RADIUS: i16 = 10;
+const VOLUME_STEP: f32 = 0.1;
+/// The most assist mode may slow the boy down by.
+const ASSIST_MAX_SLOWDOWN: f32 = 0.5;
+const SUPER_JUMP_WINDOW: f64 = 400.0;
+const LEVEL_ONE: &str = "assets/levels/level1.json";
+const DAY_CYCLE_FRAMES: u32 = 60 * 120;
+const NIGHT_ALPHA: f32 = 0.55;
+const MAX_RAINDROPS: usize = 400;
+const RAIN_SPEED: f32 = 14.0;
+const RAIN_SHEAR: f32 = 2.5;
+const RAIN_GROUND: f32 = 590.0;
+const SPLASH_FRAMES: u8 = 6;
+/// The running speed above which speed lines show by default: only the
+/// top of the difficulty ramp and boosts get there.
+const SPEED_LINES_ABOVE: i16 = 5;
+const MAX_SPEED_LINES: usize = 40;
+const SPEED_LINES_PER_SPEED: usize = 8;
+const SPEED_LINE_SCROLL: f32 = 3.0;
+const SPEED_LINE_MAX_LENGTH: i16 = 200;
+const PORTAL_COOLDOWN: u32 = 45;
+/// Least of a stopped boy's feet that has to be on a platform for him to
+/// stand there without teetering.
+const EDGE_FOOTING: f32 = 0.4;
+const TEETER_TICKS: u32 = 20;
+/// How far above and below his soles a platform top still counts as under
+/// his feet.
+const FOOTING_DEPTH: i16 = 4;
+const PORTAL_FLASH: u32 = 10;
+const JUMP_SOUND_VOICES: usize = 3;
+const SPAWN_REROLLS: usize = 5;
+const SLOPE_SNAP: i16 = 8;
+/// How close to the left edge a boy gets before it glows red.
+const EDGE_WARNING_DISTANCE: i16 = 100;
+const EDGE_WARNING_BANDS: i16 = 4;
+const MINIMAP_SIZE: Point = Point { x: 200, y: 100 };
+const KNOCK_BACK: Point = Point { x: 4, y: 12 };
+const SLOPE_COLOR: &str = "#7a5230";
+const PIT_COLOR: &str = "#111";
+const PIT_DEPTH: i16 = 12;
+const CRATE_Y: i16 = 523;
+const CRATE_PUSHBACK: i16 = -4;
+const CRATE_COINS: std::ops::RangeInclusive<usize> = 3..=5;
+const COIN_GRAVITY: i16 = 1;
+const WOOD_CHIPS: usize = 8;
+const DEBRIS_FRAMES: u8 = 24;
+const WOOD_COLOR: &str = "#8b5a2b";
+const DUST_COLOR: &str = "#c8b48c";
+const DUST_PUFFS: usize = 6;
+const LANDING_PUFFS: usize = 3;
+const MAX_DEBRIS: usize = 64;
+const FRAMES_PER_MINUTE: u32 = (60_000.0 / FRAME_SIZE) as u32;
+const EVENT_CAP: usize = 64;
+const LEVEL_FETCH_ATTEMPTS: u32 = 3;
+const LEVEL_RETRY_MILLIS: u32 = 250;
+const HINT_TICKS: u32 = 180;
+const MILESTONE_TOAST_TICKS: u32 = 120;
+const HIGH_SCORE_TOAST_TICKS: u32 = 150;
+const TOAST_SLOTS: usize = 2;
+const TOAST_FADE_TICKS: u32 = 12;
+const TOAST_WIDTH: i16 = 320;
+const TOAST_HEIGHT: i16 = 30;
+const TOAST_TOP: i16 = 40;
+const TOAST_SLIDE: f32 = 8.0;
+const BOSS_WIND_UP: u32 = 40;
+const BOSS_SCALE: i16 = 3;
+const GOAL_WIDTH: i16 = 30;
+const GOAL_FLAG_HEIGHT: i16 = 20;
+const CHECKPOINT_FLAG_WIDTH: i16 = 20;
+const CHECKPOINT_COLOR: &str = "#999";
+const CHECKPOINT_REACHED_COLOR: &str = "#e0b000";
+const RESPAWN_TICKS: u32 = 60;
+const BUBBLE_WIDTH: i16 = 220;
+const BUBBLE_HEIGHT: i16 = 30;
+const MENU_SLIDE_TICKS: u32 = 8;
+const PANEL_FADE_TICKS: u32 = 20;
 
 pub struct RedHatBoy {
     state_machine: RedHatBoyStateMachine,
     sprite_sheet: Sheet,
     image: HtmlImageElement,
+    start_offset: i16,
+    /// Effects drawn over him in the order they were added.
+    overlays: Vec<Overlay>,
+    /// Sideways pixels his sprite is drawn off by while he teeters.
+    jitter: i16,
 }
 
 impl RedHatBoy {
-    fn new(sprite_sheet: Sheet, image: HtmlImageElement) -> Self {
+    fn new(
+        sprite_sheet: Sheet,
+        image: HtmlImageElement,
+        start_offset: i16,
+        physics: PhysicsConfig,
+    ) -> Self {
         RedHatBoy {
-            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new()),
+            state_machine: RedHatBoyStateMachine::Idle(RedHatBoyState::new(start_offset, physics)),
             sprite_sheet,
             image,
+            start_offset,
+            overlays: Vec::new(),
+            jitter: 0,
         }
     }
 
+    fn reset(&mut self, physics: PhysicsConfig) {
+        self.state_machine =
+            RedHatBoyStateMachine::Idle(RedHatBoyState::new(self.start_offset, physics));
+        self.jitter = 0;
+    }
+
     fn run_right(&mut self) {
         self.state_machine = self.state_machine.transition(Event::Run);
     }
 
+    /// Shifts a standing boy `dx` over the edge he was teetering on and
+    /// lets him fall.
+    fn tip_off(&mut self, dx: i16) {
+        self.teleport(Point {
+            x: self.pos_x() + dx,
+            y: self.pos_y(),
+        });
+        self.state_machine = self.state_machine.transition(Event::TipOff);
+    }
+
+    fn stop(&mut self) {
+        self.state_machine = self.state_machine.transition(Event::Stop);
+    }
+
     fn slide(&mut self) {
         self.state_machine = self.state_machine.transition(Event::Slide);
     }
 
+    /// Stands the boy still at `x`, his own start offset added.
+    fn place_at(&mut self, x: i16) {
+        let mut save = self.snapshot();
+        save.state = StateKind::Idle;
+        save.position.x = x + self.start_offset;
+        save.velocity = Point { x: 0, y: 0 };
+        self.restore(&save);
+    }
+
+    /// Sets the boy down running with his feet on `surface`.
+    fn stand_on(&mut self, surface: Point) {
+        let mut save = self.snapshot();
+        save.state = StateKind::Running;
+        save.position = Point {
+            x: surface.x - (self.feet().x - self.pos_x()),
+            y: surface.y - PLAYER_HEIGHT,
+        };
+        save.velocity.y = 0;
+        self.restore(&save);
+    }
+
     fn jump(&mut self) {
         self.state_machine = self.state_machine.transition(Event::Jump);
     }
 
-    fn update(&mut self) {
-        self.state_machine = self.state_machine.transition(Event::Update);
+    fn super_jump(&mut self) {
+        self.state_machine = self.state_machine.transition(Event::SuperJump);
+    }
+
+    /// Returns the new facing when this update turned the boy around.
+    fn update(&mut self) -> Option<Direction> {
+        let (state_machine, turned) = self.state_machine.update();
+        self.state_machine = state_machine;
+        for overlay in &mut self.overlays {
+            overlay.update();
+        }
+        turned
+    }
+
+    fn apply_external_force(&mut self, x: i16) {
+        self.state_machine.context_mut().apply_external_force(x);
+    }
+
+    /// Swaps in new physics mid-run, carrying a change of running speed
+    /// over to a boy already on his way.
+    fn set_physics(&mut self, physics: PhysicsConfig) {
+        let context = self.state_machine.context_mut();
+        if context.target_velocity_x > 0 {
+            let change = physics.running_speed - context.physics.running_speed;
+            context.target_velocity_x += change;
+            context.velocity.x += change;
+        }
+        context.physics = physics;
+    }
+
+    /// Adds or removes the `kind` overlay. Adding one already shown keeps
+    /// it going rather than restarting its loop.
+    fn show_overlay(&mut self, kind: OverlayKind, shown: bool) {
+        let present = self.overlays.iter().any(|overlay| overlay.kind == kind);
+        if shown && !present {
+            self.overlays.push(Overlay::new(kind));
+        } else if !shown && present {
+            self.overlays.retain(|overlay| overlay.kind != kind);
+        }
+    }
+
+    fn scale_gravity(&mut self, scale: f32) {
+        self.state_machine.context_mut().scale_gravity(scale);
+    }
+
+    fn strap_on_jetpack(&mut self) {
+        let fuel = (JETPACK_MILLIS / FRAME_SIZE) as u16;
+        self.state_machine = self.state_machine.transition(Event::Jetpack(fuel));
+    }
+
+    fn thrust(&mut self) {
+        self.state_machine = self.state_machine.transition(Event::Thrust);
+    }
+
+    fn set_ground(&mut self, ground: Option<i16>) {
+        self.state_machine.context_mut().set_ground(ground);
+    }
+
+    /// Moves the boy's sprite origin to `position`, keeping his velocity.
+    fn teleport(&mut self, position: Point) {
+        self.state_machine.context_mut().position = position;
     }
 
     fn knock_out(&mut self) {
         self.state_machine = self.state_machine.transition(Event::KnockOut);
     }
 
-    fn land_on(&mut self, position: i16) {
-        self.state_machine = self.state_machine.transition(Event::Land(position));
+    fn touch_wall(&mut self, side: Direction) {
+        self.state_machine = self.state_machine.transition(Event::WallContact(side));
+    }
+
+    fn grab_ledge(&mut self, side: Direction, top: i16) {
+        self.state_machine = self
+            .state_machine
+            .transition(Event::LedgeContact(side, top));
+    }
+
+    fn land_on(&mut self, position: i16, surface: Surface) {
+        self.state_machine = self
+            .state_machine
+            .transition(Event::Land(position, surface));
+    }
+
+    /// The middle of the bottom of his feet.
+    fn feet(&self) -> Point {
+        Point {
+            x: self.bounding_box().center().x,
+            y: self.pos_y() + PLAYER_HEIGHT,
+        }
+    }
+
+    fn pos_x(&self) -> i16 {
+        self.state_machine.context().position.x
     }
 
     fn pos_y(&self) -> i16 {
@@ -64,6 +348,165 @@ impl RedHatBoy {
         self.state_machine.context().velocity.x
     }
 
+    fn facing(&self) -> Direction {
+        self.state_machine.context().facing
+    }
+
+    fn knocked_out(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::KnockedOut
+    }
+
+    fn sliding(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::Sliding
+    }
+
+    fn jumping(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::Jumping
+    }
+
+    fn landing(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::Landing
+    }
+
+    fn idle(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::Idle
+    }
+
+    /// The strip across his soles that has to rest on something to hold
+    /// him up.
+    fn footing(&self) -> Rect {
+        let bounding_box = self.bounding_box();
+        Rect::new_from_x_y(
+            bounding_box.x(),
+            self.feet().y - FOOTING_DEPTH,
+            bounding_box.width,
+            FOOTING_DEPTH * 2,
+        )
+    }
+
+    fn wall_sliding(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::WallSliding
+    }
+
+    fn climbing(&self) -> bool {
+        self.state_machine.state_kind() == StateKind::Climbing
+    }
+
+    fn airborne(&self) -> bool {
+        self.jumping() || self.wall_sliding()
+    }
+
+    /// The side `wall` is on when the boy is in the air and has run into
+    /// its side rather than its top or bottom.
+    fn wall_side(&self, wall: &Rect) -> Option<Direction> {
+        if !self.airborne() {
+            return None;
+        }
+        let bounding_box = self.bounding_box();
+        let overlap_x = bounding_box.right().min(wall.right()) - bounding_box.x().max(wall.x());
+        let overlap_y = bounding_box.bottom().min(wall.bottom()) - bounding_box.y().max(wall.y());
+        if overlap_x >= overlap_y {
+            return None;
+        }
+        if bounding_box.center().x < wall.center().x {
+            Some(Direction::Right)
+        } else {
+            Some(Direction::Left)
+        }
+    }
+
+    fn snapshot(&self) -> BoySave {
+        let context = self.state_machine.context();
+        BoySave {
+            state: self.state_machine.state_kind(),
+            frame: context.frame,
+            position: context.position,
+            velocity: context.velocity,
+            facing: context.facing,
+        }
+    }
+
+    fn restore(&mut self, save: &BoySave) {
+        let context = RedHatBoyContext {
+            frame: save.frame,
+            position: save.position,
+            velocity: save.velocity,
+            facing: save.facing,
+            target_velocity_x: save.velocity.x,
+            ..*self.state_machine.context()
+        };
+        self.state_machine = RedHatBoyStateMachine::restore(save.state, context);
+    }
+
+    fn debug_state(&self, hazards: &[Rect]) -> BoyDump {
+        let context = self.state_machine.context();
+        let center = self.bounding_box().center();
+        BoyDump {
+            hazard_distance: hazards
+                .iter()
+                .map(|hazard| center.distance_to(&hazard.center()))
+                .fold(f32::INFINITY, f32::min),
+            state: self.state_machine.state_kind(),
+            position: context.position,
+            velocity: context.velocity,
+            facing: context.facing,
+            frame: context.frame,
+            bounding_box: self.bounding_box(),
+        }
+    }
+
+    /// The path his feet would take if he jumped now, and where they would
+    /// come down: on the first of `surfaces` they drop onto, or the floor.
+    fn jump_preview(&self, surfaces: &[Rect]) -> (Vec<Point>, Option<Point>) {
+        let context = self.state_machine.context();
+        let bounding_box = self.bounding_box();
+        let feet = |position: Point| Point {
+            x: position.x + bounding_box.center().x - context.position.x,
+            y: position.y + bounding_box.bottom() - context.position.y,
+        };
+
+        let mut position = context.position;
+        let mut velocity = Point {
+            x: context.velocity.x,
+            y: JUMP_SPEED,
+        };
+        let mut path = vec![feet(position)];
+        for _ in 0..JUMP_PREVIEW_TICKS {
+            let (next, next_velocity) = integrate_step(position, velocity, context.floor);
+            let (from, to) = (feet(position), feet(next));
+            if next_velocity.y > 0 {
+                let top = surfaces.iter().find(|surface| {
+                    from.y <= surface.y()
+                        && to.y >= surface.y()
+                        && (surface.x()..=surface.right()).contains(&to.x)
+                });
+                if let Some(top) = top {
+                    let landing = Point {
+                        x: to.x,
+                        y: top.y(),
+                    };
+                    path.push(landing);
+                    return (path, Some(landing));
+                }
+            }
+            path.push(to);
+            // The floor clamped the step.
+            if next.y != position.y + next_velocity.y {
+                return (path, Some(to));
+            }
+            position = next;
+            velocity = next_velocity;
+        }
+        (path, None)
+    }
+
+    fn predicted_position(&self, ticks: usize) -> Point {
+        self.state_machine
+            .simulate(&vec![Event::Update; ticks])
+            .context()
+            .position
+    }
+
     fn frame_name(&self) -> String {
         format!(
             "{} ({}).png",
@@ -80,7 +523,7 @@ impl RedHatBoy {
         const X_OFFSET: i16 = 56;
         const Y_OFFSET: i16 = 14;
         const WIDTH_OFFSET: i16 = 82;
-        let destination_box = self.destination_box();
+        let destination_box = self.frame_box();
 
         Rect::new_from_x_y(
             destination_box.x() + X_OFFSET,
@@ -90,649 +533,7584 @@ impl RedHatBoy {
         )
     }
 
+    fn proximity_to(&self, hazard: &Rect) -> Proximity {
+        Proximity::between(&self.bounding_box(), hazard, NEAR_MISS_MARGIN)
+    }
+
+    /// The point of his frame he is placed and turned by in his current
+    /// state.
+    fn anchor(&self) -> Anchor {
+        self.state_machine.state_kind().anchor()
+    }
+
+    /// Where his anchor sits. Physics places him by the top-left of a
+    /// standard frame, so the anchor is found on that.
+    fn pivot(&self) -> Point {
+        let sheet = &self.sprite_sheet;
+        let (width, height) = RHB_FRAME_SIZE;
+        self.anchor().point(&Rect::new(
+            self.state_machine.context().position,
+            sheet.scaled(width),
+            sheet.scaled(height),
+        ))
+    }
+
+    /// The untrimmed frame around the boy's pivot. Trimming differs from
+    /// frame to frame, so anything that must stay put, like the feet and
+    /// the hitbox, is measured from here rather than the drawn box.
+    fn frame_box(&self) -> Rect {
+        let sheet = &self.sprite_sheet;
+        let (width, height) = self.current_sprite().map_or(RHB_FRAME_SIZE, |sprite| {
+            (sprite.source_size.w, sprite.source_size.h)
+        });
+
+        self.anchor()
+            .rect(self.pivot(), sheet.scaled(width), sheet.scaled(height))
+    }
+
+    /// Where the trimmed frame lands inside `frame_box`.
     fn destination_box(&self) -> Rect {
-        let sprite = self.current_sprite().expect("Cell not found");
+        let Some(sprite) = self.current_sprite() else {
+            return self.frame_box();
+        };
+        let sheet = &self.sprite_sheet;
+        let frame_box = self.frame_box();
 
         Rect::new_from_x_y(
-            self.state_machine.context().position.x + sprite.sprite_source_size.x,
-            self.state_machine.context().position.y + sprite.sprite_source_size.y,
-            sprite.frame.w,
-            sprite.frame.h,
+            frame_box.x() + sheet.scaled(sprite.sprite_source_size.x),
+            frame_box.y() + sheet.scaled(sprite.sprite_source_size.y),
+            sheet.scaled(sprite.frame.w),
+            sheet.scaled(sprite.frame.h),
         )
     }
 
-    fn draw(&self, renderer: &Renderer) {
-        let sprite = self.current_sprite().expect("Cell not found");
-
-        renderer.draw_image(
-            &self.image,
-            &Rect::new_from_x_y(
-                sprite.frame.x,
-                sprite.frame.y,
-                sprite.frame.w,
-                sprite.frame.h,
-            ),
-            &self.destination_box(),
-        );
-        renderer.draw_bounding_box(&self.bounding_box());
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        let sprite = self
+            .current_sprite()
+            .ok_or_else(|| anyhow!("Cell not found {}", self.frame_name()))?;
+
+        let destination_box = self.destination_box();
+        let draw_sprite = |renderer: &Renderer| {
+            renderer.draw_image(
+                &self.image,
+                &Rect::new_from_x_y(
+                    sprite.frame.x,
+                    sprite.frame.y,
+                    sprite.frame.w,
+                    sprite.frame.h,
+                ),
+                &Rect {
+                    position: Point {
+                        x: destination_box.x() + self.jitter,
+                        ..destination_box.position
+                    },
+                    ..destination_box
+                },
+            )
+        };
+        // The sheet only has him facing right; facing left turns him about
+        // his anchor, so his feet stay where they were.
+        match self.facing() {
+            Direction::Right => draw_sprite(renderer)?,
+            Direction::Left => renderer.draw_mirrored(self.pivot().x, draw_sprite)?,
+        }
+        for overlay in &self.overlays {
+            overlay.draw(renderer, &self.destination_box())?;
+        }
+        renderer.draw_bounding_box(&self.bounding_box(), DebugClass::Player);
+        if renderer.debug_mode() {
+            renderer.draw_bounding_box(
+                &Rect::new(self.predicted_position(PREDICTION_TICKS), 4, 4),
+                DebugClass::Player,
+            );
+        }
+        Ok(())
     }
 }
 
-pub enum Event {
-    Run,
-    Slide,
-    Jump,
-    Update,
-    KnockOut,
-    Land(i16),
+/// Summary of a finished run, passed to the JavaScript `on_game_over`
+/// callback as `{ seed, score, distance, coins, durationMs, godmode,
+/// timeScaled, daily, assist }`. Runs with `godmode` or `timeScaled` set
+/// should be kept off any leaderboard; `daily` is the day number of a daily
+/// challenge run, so its scores can go on that day's board, and `assist`
+/// runs belong on a board of their own.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    pub seed: u32,
+    pub score: u32,
+    pub distance: i32,
+    pub coins: u32,
+    pub duration_ms: f32,
+    pub godmode: bool,
+    pub time_scaled: bool,
+    pub daily: Option<u32>,
+    pub assist: bool,
 }
 
-#[derive(Copy, Clone)]
-enum RedHatBoyStateMachine {
-    Idle(RedHatBoyState<Idle>),
-    Running(RedHatBoyState<Running>),
-    Sliding(RedHatBoyState<Sliding>),
-    Jumping(RedHatBoyState<Jumping>),
-    Falling(RedHatBoyState<Falling>),
-    KnockedOut(RedHatBoyState<KnockedOut>),
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+struct RunStats {
+    seed: u32,
+    distance: i32,
+    coins: u32,
+    near_misses: u32,
+    frames: u32,
+    /// Godmode was on at some point during the run.
+    #[serde(default)]
+    godmode: bool,
+    /// Time ran faster or slower than normal at some point during the run.
+    #[serde(default)]
+    time_scaled: bool,
+    #[serde(default)]
+    daily: Option<u32>,
+    /// Assist mode was on at some point during the run; its scores are
+    /// kept apart from the rest.
+    #[serde(default)]
+    assist: bool,
 }
 
-impl RedHatBoyStateMachine {
-    fn transition(self, event: Event) -> Self {
-        match (self, event) {
-            (RedHatBoyStateMachine::Idle(state), Event::Run) => state.run().into(),
-            (RedHatBoyStateMachine::Idle(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Running(state), Event::Slide) => state.slide().into(),
-            (RedHatBoyStateMachine::Running(state), Event::Jump) => state.jump().into(),
-            (RedHatBoyStateMachine::Running(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Sliding(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Jumping(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Running(state), Event::KnockOut) => state.knock_out().into(),
-            (RedHatBoyStateMachine::Jumping(state), Event::KnockOut) => state.knock_out().into(),
-            (RedHatBoyStateMachine::Sliding(state), Event::KnockOut) => state.knock_out().into(),
-            (RedHatBoyStateMachine::Falling(state), Event::Update) => state.update().into(),
-            (RedHatBoyStateMachine::Running(state), Event::Land(position)) => {
-                state.land_on(position).into()
-            }
-            (RedHatBoyStateMachine::Jumping(state), Event::Land(position)) => {
-                state.land_on(position).into()
-            }
-            (RedHatBoyStateMachine::Sliding(state), Event::Land(position)) => {
-                state.land_on(position).into()
+impl RunStats {
+    fn meters(&self) -> i32 {
+        self.distance.max(0) / PIXELS_PER_METER
+    }
+
+    fn score(&self) -> u32 {
+        self.meters() as u32 + self.coins * 10 + self.near_misses * NEAR_MISS_POINTS
+    }
+
+    /// Whether the run can count towards best scores.
+    fn is_fair(&self) -> bool {
+        !self.godmode && !self.time_scaled
+    }
+
+    fn summary(&self) -> RunSummary {
+        RunSummary {
+            seed: self.seed,
+            score: self.score(),
+            distance: self.distance,
+            coins: self.coins,
+            duration_ms: self.frames as f32 * FRAME_SIZE,
+            godmode: self.godmode,
+            time_scaled: self.time_scaled,
+            daily: self.daily,
+            assist: self.assist,
+        }
+    }
+}
+
+/// Notifies the host page about the game, both through the registered
+/// JavaScript callbacks and through bubbling `wtd:*` DOM events dispatched
+/// on the canvas.
+#[derive(Default)]
+pub struct GameCallbacks {
+    score_changed: Option<Function>,
+    game_over: Option<Function>,
+    event_target: Option<EventTarget>,
+}
+
+pub type SharedCallbacks = Rc<RefCell<GameCallbacks>>;
+
+impl GameCallbacks {
+    pub fn set_score_changed(&mut self, callback: Function) {
+        self.score_changed = Some(callback);
+    }
+
+    pub fn set_game_over(&mut self, callback: Function) {
+        self.game_over = Some(callback);
+    }
+
+    pub fn set_event_target(&mut self, target: EventTarget) {
+        self.event_target = Some(target);
+    }
+
+    pub fn error(&self, message: &str) {
+        self.dispatch("wtd:error", &JsValue::from(message));
+    }
+
+    fn dispatch(&self, name: &str, detail: &JsValue) {
+        if let Some(target) = &self.event_target {
+            if let Err(err) = browser::dispatch_custom_event(target, name, detail) {
+                log!("{:#?}", err);
             }
-            _ => self,
         }
     }
 
-    fn frame_name(&self) -> &str {
-        match self {
-            RedHatBoyStateMachine::Idle(state) => state.frame_name(),
-            RedHatBoyStateMachine::Running(state) => state.frame_name(),
-            RedHatBoyStateMachine::Jumping(state) => state.frame_name(),
-            RedHatBoyStateMachine::Sliding(state) => state.frame_name(),
-            RedHatBoyStateMachine::Falling(state) => state.frame_name(),
-            RedHatBoyStateMachine::KnockedOut(state) => state.frame_name(),
+    fn started(&self) {
+        self.dispatch("wtd:started", &JsValue::UNDEFINED);
+    }
+
+    fn milestone(&self, meters: i32) {
+        self.dispatch("wtd:milestone", &JsValue::from(meters));
+    }
+
+    fn triggered(&self, id: &str) {
+        self.dispatch("wtd:trigger", &JsValue::from(id));
+    }
+
+    fn turned(&self, facing: Direction) {
+        let name = match facing {
+            Direction::Left => "left",
+            Direction::Right => "right",
+        };
+        self.dispatch("wtd:turn", &JsValue::from(name));
+    }
+
+    fn score_changed(&self, score: u32) {
+        if let Some(callback) = &self.score_changed {
+            browser::call_function(callback, &JsValue::from(score));
         }
     }
 
-    fn context(&self) -> &RedHatBoyContext {
-        match self {
-            RedHatBoyStateMachine::Idle(state) => state.context(),
-            RedHatBoyStateMachine::Running(state) => state.context(),
-            RedHatBoyStateMachine::Jumping(state) => state.context(),
-            RedHatBoyStateMachine::Sliding(state) => state.context(),
-            RedHatBoyStateMachine::Falling(state) => state.context(),
-            RedHatBoyStateMachine::KnockedOut(state) => state.context(),
+    fn game_over(&self, summary: &RunSummary) {
+        if self.game_over.is_none() && self.event_target.is_none() {
+            return;
+        }
+
+        match serde_wasm_bindgen::to_value(summary) {
+            Ok(value) => {
+                if let Some(callback) = &self.game_over {
+                    browser::call_function(callback, &value);
+                }
+                self.dispatch("wtd:gameover", &value);
+            }
+            Err(err) => {
+                log!("Could not serialize run summary {:#?}", err);
+            }
         }
     }
 }
 
-impl From<RedHatBoyState<Idle>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<Idle>) -> Self {
-        RedHatBoyStateMachine::Idle(state)
-    }
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    pub players: usize,
+    pub physics: PhysicsConfig,
+    pub seed: u32,
+    pub level: Option<String>,
+    pub godmode: bool,
+    /// Enables debug-only keys, such as G to toggle godmode.
+    pub debug: bool,
+    /// Pixels per step the inspect camera pans.
+    pub pan_speed: i16,
+    /// Frames per day/night cycle; zero keeps it day.
+    pub day_length: u32,
+    /// Forces the high-contrast debug palette whatever the settings say.
+    pub high_contrast: bool,
+    /// Makes jumps cost stamina; `None` leaves them free.
+    pub stamina: Option<StaminaConfig>,
+    /// Forces auto-run on or off; `None` follows the player's setting.
+    pub auto_run: Option<bool>,
+    /// Rerolls spawns the boy couldn't get past instead of only logging them.
+    pub strict_spawns: bool,
+    /// Where gameplay metrics are posted; `None` records nothing.
+    pub metrics_url: Option<String>,
+    /// The locale for on-screen text; `None` follows the browser.
+    pub lang: Option<String>,
+    /// Keeps the seed across restarts instead of rolling a new course.
+    pub fixed_seed: bool,
+    /// The day number of a daily challenge; its seed comes from the date,
+    /// so everyone playing that day gets the same course.
+    pub daily: Option<u32>,
+    /// The running speed above which speed lines streak across the screen.
+    pub speed_lines_above: i16,
 }
 
-impl From<RedHatBoyState<Running>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<Running>) -> Self {
-        RedHatBoyStateMachine::Running(state)
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            players: 1,
+            physics: PhysicsConfig::default(),
+            seed: rand::random(),
+            level: None,
+            godmode: false,
+            debug: false,
+            pan_speed: INSPECT_PAN_SPEED,
+            day_length: DAY_CYCLE_FRAMES,
+            high_contrast: false,
+            stamina: None,
+            auto_run: None,
+            strict_spawns: false,
+            metrics_url: None,
+            lang: None,
+            fixed_seed: false,
+            daily: None,
+            speed_lines_above: SPEED_LINES_ABOVE,
+        }
     }
 }
 
-impl From<RedHatBoyState<Sliding>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<Sliding>) -> Self {
-        RedHatBoyStateMachine::Sliding(state)
-    }
+/// Turns a held key into a single press.
+#[derive(Default)]
+struct KeyLatch {
+    held: bool,
 }
 
-impl From<RedHatBoyState<Jumping>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<Jumping>) -> Self {
-        RedHatBoyStateMachine::Jumping(state)
+impl KeyLatch {
+    fn pressed(&mut self, down: bool) -> bool {
+        let pressed = down && !self.held;
+        self.held = down;
+        pressed
     }
 }
 
-impl From<SlidingEndState> for RedHatBoyStateMachine {
-    fn from(state: SlidingEndState) -> Self {
-        match state {
-            SlidingEndState::Sliding(sliding) => sliding.into(),
-            SlidingEndState::Running(running) => running.into(),
+/// Debug switches read from the page's query string, e.g.
+/// `?debug=1&speed=8&seed=123&level=assets/levels/test.json&godmode=1&day=30`,
+/// where `day` is the day/night cycle in seconds and `day=0` turns it off.
+/// `contrast=1` draws debug boxes with the high-contrast palette and
+/// `stamina=1` makes jumps cost stamina. `pan` sets how fast the F2 inspect
+/// camera moves. `strict=1` rerolls spawns the boy couldn't get past, and
+/// `knockback=1` throws the boy back when he is knocked out. `lang=de`
+/// overrides the browser's language for on-screen text, and `hardcore=1`
+/// turns off ledge grabs. `speedlines` is the running speed above which
+/// speed lines show.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugOptions {
+    pub debug: bool,
+    pub running_speed: Option<i16>,
+    pub seed: Option<u32>,
+    pub level: Option<String>,
+    pub godmode: bool,
+    pub day_seconds: Option<u32>,
+    pub high_contrast: bool,
+    pub stamina: bool,
+    pub pan_speed: Option<i16>,
+    pub strict_spawns: bool,
+    pub knock_back: bool,
+    pub lang: Option<String>,
+    pub hardcore: bool,
+    pub speed_lines_above: Option<i16>,
+}
+
+impl DebugOptions {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        DebugOptions {
+            debug: flag_param(params, "debug"),
+            running_speed: number_param(params, "speed"),
+            seed: number_param(params, "seed"),
+            level: params.get("level").cloned(),
+            godmode: flag_param(params, "godmode"),
+            day_seconds: number_param(params, "day"),
+            high_contrast: flag_param(params, "contrast"),
+            stamina: flag_param(params, "stamina"),
+            pan_speed: number_param(params, "pan"),
+            strict_spawns: flag_param(params, "strict"),
+            knock_back: flag_param(params, "knockback"),
+            lang: params.get("lang").cloned(),
+            hardcore: flag_param(params, "hardcore"),
+            speed_lines_above: number_param(params, "speedlines"),
         }
     }
 }
 
-impl From<JumpingEndState> for RedHatBoyStateMachine {
-    fn from(state: JumpingEndState) -> Self {
-        match state {
-            JumpingEndState::Jumping(jumping) => jumping.into(),
-            JumpingEndState::Landing(landing) => landing.into(),
+fn flag_param(params: &HashMap<String, String>, key: &str) -> bool {
+    matches!(
+        params.get(key).map(String::as_str),
+        Some("1") | Some("true")
+    )
+}
+
+fn number_param<T: FromStr>(params: &HashMap<String, String>, key: &str) -> Option<T> {
+    let value = params.get(key)?;
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            log!("Ignoring malformed query parameter {}={}", key, value);
+            None
         }
     }
 }
 
-impl From<RedHatBoyState<Falling>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<Falling>) -> Self {
-        RedHatBoyStateMachine::Falling(state.into())
+/// Fetches a level file, retrying with a doubling delay when the request
+/// fails. Parse and validation errors are not retried.
+async fn fetch_level_json(path: &str) -> Result<JsValue> {
+    let mut delay = LEVEL_RETRY_MILLIS;
+    let mut attempt = 1;
+    loop {
+        match browser::fetch_json(path).await {
+            Err(err) if attempt < LEVEL_FETCH_ATTEMPTS => {
+                log!(
+                    "Could not fetch level {} ({:#?}), retrying in {}ms",
+                    path,
+                    err,
+                    delay
+                );
+                browser::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
     }
 }
 
-impl From<RedHatBoyState<KnockedOut>> for RedHatBoyStateMachine {
-    fn from(state: RedHatBoyState<KnockedOut>) -> Self {
-        RedHatBoyStateMachine::KnockedOut(state.into())
-    }
+/// How many of the assets `initialize` asked for have arrived.
+#[derive(Default)]
+pub struct LoadProgress {
+    loaded: std::cell::Cell<u32>,
+    total: std::cell::Cell<u32>,
 }
 
-impl From<FallingEndState> for RedHatBoyStateMachine {
-    fn from(state: FallingEndState) -> Self {
-        match state {
-            FallingEndState::Falling(falling) => falling.into(),
-            FallingEndState::KnockedOut(knocked_out) => knocked_out.into(),
+impl LoadProgress {
+    fn fraction(&self) -> f32 {
+        match self.total.get() {
+            0 => 0.0,
+            total => self.loaded.get() as f32 / total as f32,
         }
     }
 }
 
-mod red_hat_boy_states {
-    use super::HEIGHT;
-    use crate::engine::Point;
+/// Adds `asset` to the loading bar's total right away, and bumps the bar
+/// once it has arrived.
+fn counted<'a, T: 'a>(
+    progress: &'a LoadProgress,
+    asset: impl std::future::Future<Output = Result<T>> + 'a,
+) -> impl std::future::Future<Output = Result<T>> + 'a {
+    progress.total.set(progress.total.get() + 1);
+    async move {
+        let asset = asset.await?;
+        progress.loaded.set(progress.loaded.get() + 1);
+        Ok(asset)
+    }
+}
 
-    const FLOOR: i16 = 479;
-    const PLAYER_HEIGHT: i16 = HEIGHT - FLOOR;
-    const STARTING_POINT: i16 = -20;
-    const IDLE_FRAMES: u8 = 29;
-    const RUNNING_FRAMES: u8 = 23;
-    const JUMPING_FRAMES: u8 = 35;
-    const SLIDING_FRAMES: u8 = 14;
-    const FALLING_FRAMES: u8 = 29;
-    const RUNNING_SPEED: i16 = 4;
-    const IDLE_FRAME_NAME: &str = "Idle";
-    const RUN_FRAME_NAME: &str = "Run";
-    const SLIDING_FRAME_NAME: &str = "Slide";
-    const JUMPING_FRAME_NAME: &str = "Jump";
-    const FALLING_FRAME_NAME: &str = "Dead";
-    const JUMP_SPEED: i16 = -25;
-    const GRAVITY: i16 = 1;
-    const TERMINAL_VELOCITY: i16 = 20;
+async fn load_level(path: &str) -> Result<Level> {
+    parse_level(path, fetch_level_json(path).await?)
+}
 
-    #[derive(Copy, Clone)]
-    pub struct RedHatBoyState<S> {
-        context: RedHatBoyContext,
-        _state: S,
-    }
+fn parse_level(path: &str, json: JsValue) -> Result<Level> {
+    let level: Level = serde_wasm_bindgen::from_value(json)
+        .map_err(|err| anyhow!("Could not parse level {} {:#?}", path, err))?;
+    checked_level(path, level)
+}
 
-    impl<S> RedHatBoyState<S> {
-        pub fn context(&self) -> &RedHatBoyContext {
-            &self.context
-        }
+/// Puts a freshly parsed level's ground in order and validates it.
+fn checked_level(path: &str, mut level: Level) -> Result<Level> {
+    level.ground.sort_by_key(|section| section.start);
+    level
+        .validate()
+        .map_err(|err| anyhow!("Invalid level {}: {}", path, err))?;
+    Ok(level)
+}
 
-        fn update_context(&mut self, frames: u8) {
-            self.context = self.context.update(frames);
+/// Obstacle and collectible placement loaded from a level file. Each
+/// `spawn_slots` x position gets one obstacle drawn from `spawns`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Level {
+    pub stone: Point,
+    pub platform: Point,
+    #[serde(default)]
+    pub platform_surface: Surface,
+    #[serde(default)]
+    pub coins: Vec<Point>,
+    #[serde(default)]
+    pub magnet: Option<Point>,
+    #[serde(default)]
+    pub jetpack: Option<Point>,
+    /// Top-left corners; crates sit on the ground, so only `x` is used.
+    #[serde(default)]
+    pub crates: Vec<Point>,
+    #[serde(default)]
+    pub wind_zones: Vec<WindZone>,
+    #[serde(default)]
+    pub gravity_zones: Vec<GravityZone>,
+    #[serde(default)]
+    pub pendulums: Vec<Pendulum>,
+    /// Sloped ground the boy runs along, filled down to the bottom of the
+    /// screen. Flat stretches between ramps are level segments.
+    #[serde(default)]
+    pub slopes: Vec<Segment>,
+    /// Stretches of floor by x range. Gaps between them are pits; leaving
+    /// this empty keeps the single floor along the bottom of the screen.
+    /// Sorted by `start` once the level is loaded.
+    #[serde(default)]
+    pub ground: Vec<GroundSection>,
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub boss: Option<BossFight>,
+    /// Top of the flagpole that finishes the level.
+    #[serde(default)]
+    pub goal: Option<Point>,
+    /// Level file loaded after the goal is reached.
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub spawn_slots: Vec<i16>,
+    #[serde(default)]
+    pub spawns: SpawnTable,
+    /// Caps spawn difficulty by distance; empty leaves every entry open.
+    #[serde(default)]
+    pub difficulty_ramp: Vec<DifficultyStep>,
+    /// Raindrops spawned per second; 0 keeps the sky dry.
+    #[serde(default)]
+    pub rain: u16,
+    /// What finishing the level earns a bronze, silver or gold medal.
+    #[serde(default)]
+    pub medals: Option<MedalThresholds>,
+    /// Played before the player takes control.
+    #[serde(default)]
+    pub intro: Option<Script>,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level {
+            stone: Point { x: 150, y: 546 },
+            platform: Point {
+                x: 370,
+                y: HIGH_PLATFORM,
+            },
+            platform_surface: Surface::Ground,
+            coins: vec![
+                Point { x: 250, y: 420 },
+                Point { x: 290, y: 400 },
+                Point { x: 330, y: 420 },
+                Point { x: 450, y: 320 },
+                Point { x: 500, y: 320 },
+                Point { x: 550, y: 320 },
+                Point { x: 800, y: 520 },
+                Point { x: 850, y: 440 },
+                Point { x: 900, y: 520 },
+            ],
+            magnet: Some(Point { x: 90, y: 520 }),
+            jetpack: None,
+            crates: vec![Point {
+                x: 1000,
+                y: CRATE_Y,
+            }],
+            wind_zones: vec![WindZone {
+                rect: Rect::new_from_x_y(560, 200, 220, 400),
+                force: 2,
+            }],
+            gravity_zones: Vec::new(),
+            pendulums: Vec::new(),
+            slopes: Vec::new(),
+            ground: Vec::new(),
+            portals: Vec::new(),
+            triggers: Vec::new(),
+            boss: None,
+            goal: None,
+            next: None,
+            spawn_slots: vec![820],
+            spawns: SpawnTable::default(),
+            difficulty_ramp: vec![
+                DifficultyStep {
+                    distance: 0,
+                    max_difficulty: 0.5,
+                },
+                DifficultyStep {
+                    distance: 1200,
+                    max_difficulty: 1.0,
+                },
+            ],
+            rain: 0,
+            medals: None,
+            intro: None,
         }
     }
+}
 
-    #[derive(Copy, Clone)]
-    pub struct Idle;
+impl Level {
+    /// The hardest spawn allowed `distance` pixels in: the last ramp step
+    /// reached, or the first one before any is.
+    fn max_difficulty_at(&self, distance: i16) -> f32 {
+        self.difficulty_ramp
+            .iter()
+            .rev()
+            .find(|step| step.distance <= distance)
+            .or(self.difficulty_ramp.first())
+            .map_or(1.0, |step| step.max_difficulty)
+    }
 
-    impl RedHatBoyState<Idle> {
-        pub fn new() -> Self {
-            RedHatBoyState {
-                context: RedHatBoyContext {
-                    frame: 0,
-                    position: Point {
-                        x: STARTING_POINT,
-                        y: FLOOR,
-                    },
-                    velocity: Point { x: 0, y: 0 },
-                },
-                _state: Idle {},
-            }
+    /// The spawn table for the slot at `x`. Levels without a difficulty
+    /// ramp use `spawns` as it is.
+    fn slot_spawns(
+        &self,
+        spawns: &SpawnTable,
+        x: i16,
+        speed: i16,
+        previous: Option<(ObstacleKind, i16)>,
+    ) -> SpawnTable {
+        if self.difficulty_ramp.is_empty() {
+            return spawns.clone();
         }
+        spawns.for_slot(
+            self.max_difficulty_at(x),
+            speed,
+            previous.map(|(kind, _)| kind),
+        )
+    }
 
-        pub fn frame_name(&self) -> &str {
-            IDLE_FRAME_NAME
-        }
+    /// The obstacle for each spawn slot, drawn from `spawns` with `seed` so
+    /// the same seed always lays out the same course. A pick `problem`
+    /// finds fault with is drawn again, up to `rerolls` times.
+    fn spawn_layout(
+        &self,
+        spawns: &SpawnTable,
+        speed: i16,
+        seed: u32,
+        rerolls: usize,
+        problem: impl Fn(Option<(ObstacleKind, i16)>, (ObstacleKind, i16)) -> Option<String>,
+    ) -> Vec<(Option<ObstacleKind>, i16)> {
+        let mut rng = StdRng::seed_from_u64(u64::from(seed));
+        let mut previous = None;
+        self.spawn_slots
+            .iter()
+            .map(|&x| {
+                let spawns = self.slot_spawns(spawns, x, speed, previous);
+                let mut kind = spawns.choose(&mut rng);
+                let mut found = kind.and_then(|kind| problem(previous, (kind, x)));
+                let mut tries = 0;
+                while found.is_some() && tries < rerolls {
+                    tries += 1;
+                    kind = spawns.choose(&mut rng);
+                    found = kind.and_then(|kind| problem(previous, (kind, x)));
+                }
+                if let Some(found) = found {
+                    log!("Unreachable spawn: {}", found);
+                }
+                previous = kind.map(|kind| (kind, x));
+                (kind, x)
+            })
+            .collect()
+    }
 
-        pub fn run(self) -> RedHatBoyState<Running> {
-            RedHatBoyState {
-                context: self.context.reset_frame().run_right(),
-                _state: Running {},
-            }
+    /// The top of the ground under `feet`, or `None` over a pit. Sections
+    /// higher than his feet are walls, not floor.
+    fn ground_under(&self, feet: Point) -> Option<i16> {
+        if self.ground.is_empty() {
+            return Some(HEIGHT);
         }
+        self.ground
+            .iter()
+            .filter(|section| section.spans(feet.x) && feet.y <= section.y + SLOPE_SNAP)
+            .map(|section| section.y)
+            .min()
+    }
 
-        pub fn update(mut self) -> RedHatBoyState<Idle> {
-            self.update_context(IDLE_FRAMES);
-            self
-        }
+    /// Where a boy who can't fall to his death is set down after dropping
+    /// into the pit at `x`: the start of the ground beyond it, or the end
+    /// of the ground before it when the pit runs off the screen.
+    fn surface_past(&self, x: i16) -> Point {
+        self.ground
+            .iter()
+            .find(|section| section.start > x)
+            .map(|section| Point {
+                x: section.start,
+                y: section.y,
+            })
+            .or_else(|| {
+                self.ground
+                    .iter()
+                    .max_by_key(|section| section.end)
+                    .map(|section| Point {
+                        x: section.end - 1,
+                        y: section.y,
+                    })
+            })
+            .unwrap_or(Point { x, y: HEIGHT })
     }
 
-    #[derive(Copy, Clone)]
-    pub struct Running;
+    /// Checks what the file format can't express, so an unplayable level is
+    /// reported when it loads instead of surfacing mid-run. The ground is
+    /// expected sorted, as `checked_level` leaves it.
+    pub fn validate(&self) -> Result<()> {
+        let screen = Rect::new_from_x_y(0, 0, WIDTH, HEIGHT);
+        let on_screen = |name: &str, point: &Point| {
+            if screen.contains(point) {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "{} at ({}, {}) is outside the {}x{} screen",
+                    name,
+                    point.x,
+                    point.y,
+                    WIDTH,
+                    HEIGHT
+                ))
+            }
+        };
 
-    impl RedHatBoyState<Running> {
-        pub fn frame_name(&self) -> &str {
-            RUN_FRAME_NAME
+        on_screen("stone", &self.stone)?;
+        on_screen("platform", &self.platform)?;
+        if self.platform.y < HEIGHT - max_jump_height() {
+            return Err(anyhow!(
+                "platform top at y={} is higher than a jump reaches (y={})",
+                self.platform.y,
+                HEIGHT - max_jump_height()
+            ));
         }
 
-        pub fn update(mut self) -> RedHatBoyState<Running> {
-            self.update_context(RUNNING_FRAMES);
-            self
+        for (index, coin) in self.coins.iter().enumerate() {
+            on_screen(&format!("coin {}", index), coin)?;
+        }
+        if let Some(magnet) = &self.magnet {
+            on_screen("magnet", magnet)?;
+        }
+        if let Some(jetpack) = &self.jetpack {
+            on_screen("jetpack", jetpack)?;
+        }
+        for (index, crate_position) in self.crates.iter().enumerate() {
+            on_screen(&format!("crate {}", index), crate_position)?;
         }
 
-        pub fn slide(&self) -> RedHatBoyState<Sliding> {
-            RedHatBoyState {
-                context: self.context.reset_frame(),
-                _state: Sliding {},
+        for (index, zone) in self.wind_zones.iter().enumerate() {
+            if zone.rect.width <= 0 || zone.rect.height <= 0 {
+                return Err(anyhow!("wind zone {} has an empty rect", index));
             }
+            on_screen(&format!("wind zone {}", index), &zone.rect.position)?;
         }
 
-        pub fn jump(&self) -> RedHatBoyState<Jumping> {
-            RedHatBoyState {
-                context: self.context.reset_frame().set_vertical_velocity(JUMP_SPEED),
-                _state: Jumping {},
+        for (index, zone) in self.gravity_zones.iter().enumerate() {
+            if zone.rect.width <= 0 || zone.rect.height <= 0 {
+                return Err(anyhow!("gravity zone {} has an empty rect", index));
             }
+            if !zone.scale.is_finite() || zone.scale < 0.0 {
+                return Err(anyhow!("gravity zone {} needs a scale of 0 or more", index));
+            }
+            on_screen(&format!("gravity zone {}", index), &zone.rect.position)?;
         }
 
-        pub fn knock_out(self) -> RedHatBoyState<Falling> {
-            RedHatBoyState {
-                context: self.context.reset_frame().stop(),
-                _state: Falling {},
+        for (index, pendulum) in self.pendulums.iter().enumerate() {
+            on_screen(&format!("pendulum {}", index), &pendulum.anchor)?;
+            if pendulum.period == 0 {
+                return Err(anyhow!("pendulum {} has a zero period", index));
             }
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
-            RedHatBoyState {
-                context: self.context.set_on(position),
-                _state: Running,
+        for (index, pair) in self.difficulty_ramp.windows(2).enumerate() {
+            if pair[1].distance <= pair[0].distance {
+                return Err(anyhow!(
+                    "difficulty ramp step {} is not after the one before it",
+                    index + 1
+                ));
             }
         }
-    }
-
-    #[derive(Copy, Clone)]
-    pub struct Sliding;
-
-    pub enum SlidingEndState {
-        Sliding(RedHatBoyState<Sliding>),
-        Running(RedHatBoyState<Running>),
-    }
 
-    impl RedHatBoyState<Sliding> {
-        pub fn frame_name(&self) -> &str {
-            SLIDING_FRAME_NAME
+        // Slopes may run along the very bottom edge of the screen.
+        let ground = Rect::new_from_x_y(0, 0, WIDTH + 1, HEIGHT + 1);
+        for (index, slope) in self.slopes.iter().enumerate() {
+            if slope.start.x == slope.end.x {
+                return Err(anyhow!("slope {} is vertical", index));
+            }
+            if !ground.contains(&slope.start) || !ground.contains(&slope.end) {
+                return Err(anyhow!("slope {} is outside the screen", index));
+            }
         }
 
-        pub fn update(mut self) -> SlidingEndState {
-            self.update_context(SLIDING_FRAMES);
-
-            if self.context.frame >= SLIDING_FRAMES {
-                SlidingEndState::Running(self.stand())
-            } else {
-                SlidingEndState::Sliding(self)
+        for (index, section) in self.ground.iter().enumerate() {
+            if section.start >= section.end {
+                return Err(anyhow!("ground section {} is empty", index));
+            }
+            if !(0..=HEIGHT).contains(&section.y) {
+                return Err(anyhow!("ground section {} is outside the screen", index));
             }
         }
-
-        pub fn stand(self) -> RedHatBoyState<Running> {
-            RedHatBoyState {
-                context: self.context.reset_frame(),
-                _state: Running {},
+        for (index, pair) in self.ground.windows(2).enumerate() {
+            if pair[1].start < pair[0].end {
+                return Err(anyhow!(
+                    "ground sections {} and {} overlap",
+                    index,
+                    index + 1
+                ));
             }
         }
 
-        pub fn knock_out(self) -> RedHatBoyState<Falling> {
-            RedHatBoyState {
-                context: self.context.reset_frame().stop(),
-                _state: Falling {},
+        for (index, portal) in self.portals.iter().enumerate() {
+            on_screen(&format!("portal {}", index), &portal.entrance.position)?;
+            on_screen(&format!("portal {} exit", index), &portal.exit)?;
+            if portal.exit.x <= portal.entrance.right() {
+                return Err(anyhow!("portal {} must lead forward", index));
             }
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
-            RedHatBoyState {
-                context: self.context.set_on(position),
-                _state: Running,
+        for (index, trigger) in self.triggers.iter().enumerate() {
+            if trigger.id.is_empty() || trigger.rect.width <= 0 || trigger.rect.height <= 0 {
+                return Err(anyhow!(
+                    "trigger {} needs an id and a non-empty rect",
+                    index
+                ));
+            }
+            on_screen(&format!("trigger {}", index), &trigger.rect.position)?;
+            if self.triggers[..index]
+                .iter()
+                .any(|earlier| earlier.id == trigger.id)
+            {
+                return Err(anyhow!("trigger id {} is used twice", trigger.id));
             }
         }
-    }
-
-    #[derive(Copy, Clone)]
-    pub struct Jumping;
-
-    pub enum JumpingEndState {
-        Jumping(RedHatBoyState<Jumping>),
-        Landing(RedHatBoyState<Running>),
-    }
 
-    impl RedHatBoyState<Jumping> {
-        pub fn frame_name(&self) -> &str {
-            JUMPING_FRAME_NAME
+        if let Some(goal) = &self.goal {
+            on_screen("goal", goal)?;
         }
-
-        pub fn update(mut self) -> JumpingEndState {
-            self.update_context(JUMPING_FRAMES);
-
-            if self.context.position.y >= FLOOR {
-                JumpingEndState::Landing(self.land_on(HEIGHT.into()))
-            } else {
-                JumpingEndState::Jumping(self)
-            }
+        if self
+            .next
+            .as_ref()
+            .is_some_and(|next| next.trim().is_empty())
+        {
+            return Err(anyhow!("next level path is empty"));
         }
 
-        pub fn land_on(self, position: i16) -> RedHatBoyState<Running> {
-            RedHatBoyState {
-                context: self.context.reset_frame().set_on(position),
-                _state: Running {},
+        if let Some(medals) = &self.medals {
+            let ordered = match medals.by {
+                MedalMeasure::Score => {
+                    medals.bronze <= medals.silver && medals.silver <= medals.gold
+                }
+                MedalMeasure::Time => {
+                    medals.bronze >= medals.silver && medals.silver >= medals.gold
+                }
+            };
+            if !ordered {
+                return Err(anyhow!(
+                    "medal thresholds must get harder from bronze to gold"
+                ));
             }
         }
 
-        pub fn knock_out(self) -> RedHatBoyState<Falling> {
-            RedHatBoyState {
-                context: self.context.reset_frame().stop(),
-                _state: Falling {},
+        if let Some(boss) = &self.boss {
+            if boss.throws == 0 || boss.interval <= BOSS_WIND_UP || boss.flight_frames == 0 {
+                return Err(anyhow!(
+                    "boss needs at least one throw, an interval over {} frames and a flight time",
+                    BOSS_WIND_UP
+                ));
             }
         }
-    }
 
-    #[derive(Copy, Clone)]
-    pub struct Falling;
-
-    impl RedHatBoyState<Falling> {
-        pub fn frame_name(&self) -> &str {
-            FALLING_FRAME_NAME
+        for x in &self.spawn_slots {
+            on_screen("spawn slot", &Point { x: *x, y: STONE_Y })?;
         }
-
-        pub fn update(mut self) -> FallingEndState {
-            self.update_context(FALLING_FRAMES);
-
-            if self.context.frame >= FALLING_FRAMES {
-                FallingEndState::KnockedOut(self.knock_out())
-            } else {
-                FallingEndState::Falling(self)
-            }
+        if let Some(entry) = self
+            .spawns
+            .entries
+            .iter()
+            .find(|entry| !entry.weight.is_finite() || entry.weight < 0.0)
+        {
+            return Err(anyhow!(
+                "spawn weight {} for {:?} must be zero or more",
+                entry.weight,
+                entry.kind
+            ));
         }
-
-        pub fn knock_out(self) -> RedHatBoyState<KnockedOut> {
-            RedHatBoyState {
-                context: self.context,
-                _state: KnockedOut {},
-            }
+        if !self.spawn_slots.is_empty() && self.spawns.entries.iter().all(|e| e.weight == 0.0) {
+            return Err(anyhow!(
+                "level has spawn slots but no spawn entry with a positive weight"
+            ));
         }
-    }
 
-    pub enum FallingEndState {
-        Falling(RedHatBoyState<Falling>),
-        KnockedOut(RedHatBoyState<KnockedOut>),
+        Ok(())
     }
+}
 
-    #[derive(Copy, Clone)]
-    pub struct KnockedOut;
+/// Effects that can be layered over the boy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OverlayKind {
+    /// A bubble while godmode is on.
+    Shield,
+    /// Sparks circling him while the magnet is active.
+    Magnet,
+}
 
-    impl RedHatBoyState<KnockedOut> {
-        pub fn frame_name(&self) -> &str {
-            FALLING_FRAME_NAME
-        }
+/// An effect drawn over the boy on its own loop, independent of his
+/// animation frames.
+#[derive(Clone, Copy, Debug)]
+struct Overlay {
+    kind: OverlayKind,
+    frame: u8,
+}
+
+impl Overlay {
+    const FRAMES: u8 = 60;
+    const SPARKS: i16 = 3;
+
+    fn new(kind: OverlayKind) -> Self {
+        Overlay { kind, frame: 0 }
     }
 
-    #[derive(Copy, Clone)]
-    pub struct RedHatBoyContext {
-        pub frame: u8,
-        pub position: Point,
-        pub velocity: Point,
+    fn update(&mut self) {
+        self.frame = (self.frame + 1) % Self::FRAMES;
     }
 
-    impl RedHatBoyContext {
-        pub fn update(mut self, frame_count: u8) -> Self {
-            if self.velocity.y < TERMINAL_VELOCITY {
-                self.velocity.y += GRAVITY;
-            }
+    /// How far through its loop the overlay is, from 0 up to 1.
+    fn phase(&self) -> f32 {
+        f32::from(self.frame) / f32::from(Self::FRAMES)
+    }
 
-            if self.frame < frame_count {
-                self.frame += 1;
-            } else {
-                self.frame = 0;
+    /// Draws over `target`, the box the boy's sprite covers.
+    fn draw(&self, renderer: &Renderer, target: &Rect) -> Result<()> {
+        let center = target.center();
+        let radius = target.width.max(target.height) / 2;
+        let angle = self.phase() * std::f32::consts::TAU;
+        match self.kind {
+            OverlayKind::Shield => {
+                let pulse = (angle.sin() * 4.0) as i16;
+                renderer.draw_with_alpha(0.25, |renderer| {
+                    renderer.draw_circle(&center, radius + 8 + pulse, "#6cf")
+                })
             }
-
-            self.position.x += self.velocity.x;
-            self.position.y += self.velocity.y;
-
-            if self.position.y > FLOOR {
-                self.position.y = FLOOR;
+            OverlayKind::Magnet => {
+                for spark in 0..Self::SPARKS {
+                    let spark_angle =
+                        angle + f32::from(spark) * std::f32::consts::TAU / f32::from(Self::SPARKS);
+                    let position = Point {
+                        x: center.x + (spark_angle.cos() * f32::from(radius)) as i16,
+                        y: center.y + (spark_angle.sin() * f32::from(radius)) as i16,
+                    };
+                    renderer.draw_circle(&position, 4, "#fc3")?;
+                }
+                Ok(())
             }
-
-            self
         }
+    }
+}
 
-        fn reset_frame(mut self) -> Self {
-            self.frame = 0;
-            self
-        }
+/// Medals for finishing a level, worst first so the best compares highest.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
 
-        fn set_vertical_velocity(mut self, y: i16) -> Self {
-            self.velocity.y = y;
-            self
-        }
+impl Medal {
+    const ALL: [Medal; 3] = [Medal::Bronze, Medal::Silver, Medal::Gold];
 
-        fn run_right(mut self) -> Self {
-            self.velocity.x += RUNNING_SPEED;
-            self
+    fn color(self) -> &'static str {
+        match self {
+            Medal::Bronze => "#cd7f32",
+            Medal::Silver => "#c0c0c0",
+            Medal::Gold => "#ffd700",
         }
+    }
 
-        fn stop(mut self) -> Self {
-            self.velocity.x = 0;
-            self.velocity.y = 0;
-            self
+    fn label_key(self) -> &'static str {
+        match self {
+            Medal::Bronze => "medal.bronze",
+            Medal::Silver => "medal.silver",
+            Medal::Gold => "medal.gold",
         }
+    }
 
-        fn set_on(mut self, position: i16) -> Self {
-            self.position.y = position - PLAYER_HEIGHT;
-            self
-        }
+    /// Draws the medal, or a faded outline of it when it wasn't earned.
+    fn draw(self, renderer: &Renderer, center: &Point, earned: bool) -> Result<()> {
+        let alpha = if earned { 1.0 } else { 0.25 };
+        renderer.draw_with_alpha(alpha, |renderer| {
+            renderer.draw_circle(center, MEDAL_RADIUS, self.color())
+        })
     }
 }
 
-pub struct Walk {
-    boy: RedHatBoy,
-    background: Image,
-    stone: Image,
-    platform: Platform,
+/// Whether medals go by score, where more is better, or by finishing time
+/// in seconds, where less is.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MedalMeasure {
+    Score,
+    Time,
 }
 
-pub enum WalkTheDog {
-    Loading,
-    Loaded(Walk),
+/// Per-level medal thresholds, e.g.
+/// `{ "by": "time", "bronze": 20, "silver": 12, "gold": 8 }`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct MedalThresholds {
+    pub by: MedalMeasure,
+    pub bronze: u32,
+    pub silver: u32,
+    pub gold: u32,
 }
 
-impl WalkTheDog {
-    pub fn new() -> Self {
-        WalkTheDog::Loading {}
+impl MedalThresholds {
+    /// The best medal `summary` earns, if any.
+    fn award(&self, summary: &RunSummary) -> Option<Medal> {
+        let thresholds = [
+            (Medal::Gold, self.gold),
+            (Medal::Silver, self.silver),
+            (Medal::Bronze, self.bronze),
+        ];
+        thresholds
+            .into_iter()
+            .find(|(_, threshold)| match self.by {
+                MedalMeasure::Score => summary.score >= *threshold,
+                MedalMeasure::Time => summary.duration_ms <= *threshold as f32 * 1000.0,
+            })
+            .map(|(medal, _)| medal)
     }
 }
 
-#[async_trait(?Send)]
-impl Game for WalkTheDog {
-    async fn initialize(&self) -> Result<Box<dyn Game>> {
-        match self {
-            WalkTheDog::Loading => {
-                let sheet: Sheet = serde_wasm_bindgen::from_value(
-                    browser::fetch_json("assets/sprite_sheets/rhb.json").await?,
-                )
-                .expect("rhb.json seed require");
+/// The best medal earned on each level file, kept in localStorage.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+struct LevelResults {
+    medals: HashMap<String, Medal>,
+}
 
-                let background =
-                    engine::load_image("assets/resized/freetileset/png/BG/BG.png").await?;
+impl LevelResults {
+    fn load() -> LevelResults {
+        match browser::storage_get(LEVEL_RESULTS_KEY) {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                log!("Ignoring malformed level results {:#?}", err);
+                LevelResults::default()
+            }),
+            Ok(None) => LevelResults::default(),
+            Err(err) => {
+                log!("{:#?}", err);
+                LevelResults::default()
+            }
+        }
+    }
 
-                let stone =
-                    engine::load_image("assets/resized/freetileset/png/Object/Stone.png").await?;
+    fn store(&self) {
+        let result = serde_json::to_string(self)
+            .map_err(|err| anyhow!("Could not serialize level results {:#?}", err))
+            .and_then(|json| browser::storage_set(LEVEL_RESULTS_KEY, &json));
+        if let Err(err) = result {
+            log!("{:#?}", err);
+        }
+    }
 
-                let rhb = RedHatBoy::new(
-                    sheet,
-                    engine::load_image("assets/sprite_sheets/rhb.png").await?,
-                );
+    fn medal(&self, level: &str) -> Option<Medal> {
+        self.medals.get(level).copied()
+    }
 
-                let platform_sheet = serde_wasm_bindgen::from_value(
-                    browser::fetch_json("assets/sprite_sheets/tiles.json").await?,
-                )
-                .expect("tiles.json does not exist");
-                let platform = Platform::new(
-                    platform_sheet,
-                    engine::load_image("assets/sprite_sheets/tiles.png").await?,
-                    Point {
-                        x: 370,
-                        y: HIGH_PLATFORM,
-                    },
-                );
+    /// Keeps `medal` for `level` if it beats the one already recorded; a
+    /// worse result never takes a medal away. Returns whether it was kept.
+    fn record(&mut self, level: &str, medal: Medal) -> bool {
+        let improved = self.medal(level).is_none_or(|best| medal > best);
+        if improved {
+            self.medals.insert(level.to_string(), medal);
+        }
+        improved
+    }
+}
 
-                Ok(Box::new(WalkTheDog::Loaded(Walk {
-                    boy: rhb,
-                    background: Image::new(background, Point { x: 0, y: 0 }),
-                    stone: Image::new(stone, Point { x: 150, y: 546 }),
-                    platform,
-                })))
-            }
-            WalkTheDog::Loaded(_) => Err(anyhow!("Error: Game is already initialized")),
+/// A flat stretch of floor from `start` to `end`, with its top at `y`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GroundSection {
+    pub start: i16,
+    pub end: i16,
+    pub y: i16,
+}
+
+impl GroundSection {
+    fn spans(&self, x: i16) -> bool {
+        (self.start..self.end).contains(&x)
+    }
+
+    /// The section's side, from its top down to the bottom of the screen.
+    fn rect(&self) -> Rect {
+        Rect::new_from_x_y(self.start, self.y, self.end - self.start, HEIGHT - self.y)
+    }
+}
+
+/// A region that scales gravity while the boy overlaps it; below 1 he
+/// floats, above 1 he drops like a stone.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GravityZone {
+    pub rect: Rect,
+    pub scale: f32,
+}
+
+impl GravityZone {
+    fn draw(&self, renderer: &Renderer) {
+        renderer.fill_rect(&self.rect, GRAVITY_ZONE_COLOR);
+    }
+}
+
+/// A region that pushes the boy horizontally while he overlaps it: positive
+/// forces are tailwinds, negative ones headwinds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct WindZone {
+    pub rect: Rect,
+    pub force: i16,
+}
+
+impl WindZone {
+    const STREAKS: i16 = 6;
+    const STREAK_LENGTH: i16 = 40;
+
+    fn draw(&self, renderer: &Renderer, frame: u32, darkness: f32) -> Result<()> {
+        let travel = (frame as i32 * i32::from(self.force) * 3)
+            .rem_euclid(i32::from(self.rect.width.max(1))) as i16;
+        for streak in 0..WindZone::STREAKS {
+            let x = self.rect.x()
+                + (travel + streak * self.rect.width / WindZone::STREAKS) % self.rect.width.max(1);
+            let y = self.rect.y() + (streak * 2 + 1) * self.rect.height / (WindZone::STREAKS * 2);
+            let end = (x + WindZone::STREAK_LENGTH).min(self.rect.right());
+            renderer.draw_line(
+                &Point { x, y },
+                &Point { x: end, y },
+                &format!("rgba(255, 255, 255, {:.2})", 0.6 - 0.3 * darkness),
+            );
         }
+        renderer.draw_bounding_box(&self.rect, DebugClass::Sensor);
+        Ok(())
     }
+}
 
-    fn update(&mut self, keystate: &KeyState) {
-        if let WalkTheDog::Loaded(walk) = self {
-            if keystate.is_pressed("ArrowRight") {
-                walk.boy.run_right();
-            }
+/// An invisible rect that fires `id` when a boy walks into it, with no
+/// collision response. A `once` trigger fires the first time only; others
+/// fire again each time a boy re-enters. Level files may call the id
+/// `name`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Trigger {
+    pub rect: Rect,
+    #[serde(alias = "name")]
+    pub id: String,
+    #[serde(default = "Trigger::default_once")]
+    pub once: bool,
+    #[serde(default)]
+    pub kind: TriggerKind,
+}
 
-            if keystate.is_pressed("Space") {
-                walk.boy.jump();
-            }
+/// What a trigger does besides firing its id. A checkpoint also saves
+/// where a knocked out boy comes back.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerKind {
+    #[default]
+    Event,
+    Checkpoint,
+}
+
+impl Trigger {
+    fn default_once() -> bool {
+        true
+    }
+}
+
+/// Why a boy at `bounding_box` is out, if he has left the screen: wholly
+/// past its left edge, unless a cutscene is walking him in, or below the
+/// bottom.
+fn out_of_bounds(bounding_box: &Rect, in_cutscene: bool) -> Option<KnockOutCause> {
+    if bounding_box.right() < 0 && !in_cutscene {
+        Some(KnockOutCause::SweptAway)
+    } else if bounding_box.y() > HEIGHT {
+        Some(KnockOutCause::Pit)
+    } else {
+        None
+    }
+}
+
+/// A level trigger and whether it has been entered.
+struct ArmedTrigger {
+    trigger: Trigger,
+    occupied: bool,
+    spent: bool,
+}
+
+impl ArmedTrigger {
+    fn new(trigger: Trigger) -> Self {
+        ArmedTrigger {
+            trigger,
+            occupied: false,
+            spent: false,
+        }
+    }
+
+    /// Returns true when a boy has just entered an armed trigger.
+    fn update(&mut self, boxes: &[Rect]) -> bool {
+        let occupied = boxes.iter().any(|rect| rect.intersects(&self.trigger.rect));
+        let entered = occupied && !self.occupied && !self.spent;
+        self.occupied = occupied;
+        if entered && self.trigger.once {
+            self.spent = true;
+        }
+        entered
+    }
+
+    /// A pole at the left of a checkpoint, its pennant lit once reached.
+    fn draw_flag(&self, renderer: &Renderer) {
+        let rect = &self.trigger.rect;
+        let top = rect.bottom() - GOAL_FLAG_HEIGHT * 3;
+        renderer.draw_line(
+            &Point {
+                x: rect.x(),
+                y: top,
+            },
+            &Point {
+                x: rect.x(),
+                y: rect.bottom(),
+            },
+            "#444",
+        );
+        let color = if self.spent {
+            CHECKPOINT_REACHED_COLOR
+        } else {
+            CHECKPOINT_COLOR
+        };
+        renderer.draw_polygon(
+            &[
+                Point {
+                    x: rect.x(),
+                    y: top,
+                },
+                Point {
+                    x: rect.x() + CHECKPOINT_FLAG_WIDTH,
+                    y: top + GOAL_FLAG_HEIGHT / 2,
+                },
+                Point {
+                    x: rect.x(),
+                    y: top + GOAL_FLAG_HEIGHT,
+                },
+            ],
+            Some(color),
+            None,
+        );
+    }
+}
 
-            if keystate.is_pressed("ArrowDown") {
-                walk.boy.slide();
+/// A boss at the right edge that throws at the boy every `interval` frames,
+/// each throw taking `flight_frames` to land, until he survives `throws`
+/// of them. Both timings tighten as the difficulty goes up.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BossFight {
+    pub throws: u32,
+    pub interval: u32,
+    pub flight_frames: u32,
+}
+
+struct Boss {
+    fight: BossFight,
+    countdown: Timer,
+    thrown: u32,
+    survived: u32,
+}
+
+impl Boss {
+    fn new(fight: BossFight) -> Self {
+        Boss {
+            fight,
+            countdown: Timer::new(fight.interval),
+            thrown: 0,
+            survived: 0,
+        }
+    }
+
+    fn bounds(image: &HtmlImageElement) -> Rect {
+        let width = image.width() as i16 / BOSS_SCALE;
+        let height = image.height() as i16 / BOSS_SCALE;
+        Anchor::BottomCenter.rect(
+            Point {
+                x: WIDTH - width / 2 - 10,
+                y: HEIGHT,
+            },
+            width,
+            height,
+        )
+    }
+
+    fn hand(&self) -> Point {
+        Point {
+            x: WIDTH - 120,
+            y: HEIGHT - 150,
+        }
+    }
+
+    fn winding_up(&self) -> bool {
+        self.thrown < self.fight.throws && self.countdown.remaining() <= BOSS_WIND_UP
+    }
+
+    fn defeated(&self) -> bool {
+        self.survived >= self.fight.throws
+    }
+
+    fn scaled(frames: u32, difficulty: f32) -> f32 {
+        frames as f32 * (1.25 - difficulty * 0.5)
+    }
+
+    /// Counts down to the next throw and returns it when it is due.
+    fn update(&mut self, difficulty: f32) -> Option<Projectile> {
+        if self.thrown >= self.fight.throws {
+            return None;
+        }
+
+        self.countdown.tick();
+        if self.countdown.is_active() {
+            return None;
+        }
+
+        self.thrown += 1;
+        self.countdown = Timer::new(
+            (Boss::scaled(self.fight.interval, difficulty) as u32).max(BOSS_WIND_UP + 1),
+        );
+        Some(Projectile::new(
+            self.hand(),
+            Boss::scaled(self.fight.flight_frames, difficulty).max(1.0),
+        ))
+    }
+
+    /// Runs a frame of the fight against the boys at `targets`, `None` for
+    /// those knocked out. Throws at the nearest one when a throw is due and
+    /// moves `projectiles`, taking out those that hit a boy or the ground.
+    /// Returns the indices of the boys hit and where the other projectiles
+    /// landed; only those count as survived.
+    fn fight(
+        &mut self,
+        projectiles: &mut Vec<Projectile>,
+        targets: &[Option<Rect>],
+        difficulty: f32,
+    ) -> (Vec<usize>, Vec<Point>) {
+        let hand = self.hand().x;
+        let target = targets
+            .iter()
+            .flatten()
+            .map(|target| target.center().x)
+            .min_by_key(|x| (x - hand).abs());
+        if let Some(target) = target {
+            if let Some(projectile) = self.update(difficulty) {
+                projectiles.push(projectile.aimed_at(target));
             }
+        }
 
-            walk.boy.update();
+        for projectile in projectiles.iter_mut() {
+            projectile.update();
+        }
 
-            for bounding_box in &walk.platform.bounding_boxes() {
-                if walk.boy.bounding_box().intersects(bounding_box) {
-                    if walk.boy.velocity_y() > 0 && walk.boy.pos_y() < walk.platform.position.y {
-                        walk.boy.land_on(bounding_box.position.y);
-                    } else {
-                        walk.boy.knock_out();
-                    }
-                }
+        let mut hit = Vec::new();
+        for (index, target) in targets.iter().enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+            let before = projectiles.len();
+            projectiles.retain(|projectile| !projectile.hits(target));
+            if projectiles.len() < before {
+                hit.push(index);
             }
+        }
 
-            if walk
-                .boy
-                .bounding_box()
-                .intersects(walk.stone.bounding_box())
-            {
-                walk.boy.knock_out();
+        let landed: Vec<Point> = projectiles
+            .iter()
+            .filter(|projectile| projectile.landed())
+            .map(Projectile::center)
+            .collect();
+        projectiles.retain(|projectile| !projectile.landed());
+        self.survived += landed.len() as u32;
+        (hit, landed)
+    }
+
+    fn draw(
+        &self,
+        renderer: &Renderer,
+        images: &(HtmlImageElement, HtmlImageElement),
+    ) -> Result<()> {
+        let image = if self.winding_up() {
+            &images.1
+        } else {
+            &images.0
+        };
+        let destination = Boss::bounds(image);
+        renderer.draw_image_region(image, 0.0, 0.0, 1.0, 1.0, &destination)?;
+        renderer.draw_bounding_box(&destination, DebugClass::Hazard);
+        renderer.draw_text(
+            &format!("{} / {}", self.survived, self.fight.throws),
+            &Point {
+                x: destination.x(),
+                y: destination.y() - 10,
+            },
+        )
+    }
+}
+
+fn draw_projectile(renderer: &Renderer, projectile: &Projectile) -> Result<()> {
+    renderer.draw_bounding_box(
+        &circle_bounds(projectile.center(), Projectile::RADIUS),
+        DebugClass::Hazard,
+    );
+    renderer.draw_circle(&projectile.center(), Projectile::RADIUS, "#4a3b2a")
+}
+
+/// Maps `rect` from `world` coordinates into `map`, keeping it at least a
+/// pixel across so small things stay visible.
+fn scale_into(world: &Rect, map: &Rect, rect: &Rect) -> Rect {
+    let scale = |offset: i16, from: i16, to: i16| {
+        (i32::from(offset) * i32::from(to) / i32::from(from.max(1))) as i16
+    };
+    Rect::new_from_x_y(
+        map.x() + scale(rect.x() - world.x(), world.width, map.width),
+        map.y() + scale(rect.y() - world.y(), world.height, map.height),
+        scale(rect.width, world.width, map.width).max(1),
+        scale(rect.height, world.height, map.height).max(1),
+    )
+}
+
+/// The square around a round hitbox, for debug drawing.
+fn circle_bounds(center: Point, radius: i16) -> Rect {
+    Rect::new_from_x_y(center.x - radius, center.y - radius, radius * 2, radius * 2)
+}
+
+/// Sends the boy from `entrance` to `exit` when he presses his enter key
+/// inside it. Portals only lead forward. With `counts_distance` the skipped
+/// stretch is added to the run's distance.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Portal {
+    pub entrance: Rect,
+    pub exit: Point,
+    #[serde(default)]
+    pub counts_distance: bool,
+}
+
+impl Portal {
+    fn skipped(&self) -> i32 {
+        i32::from(self.exit.x - self.entrance.x())
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        let radius = self.entrance.width.min(self.entrance.height) / 2;
+        renderer.draw_circle(&self.entrance.center(), radius, "rgba(140, 60, 220, 0.5)")?;
+        renderer.draw_circle(&self.exit, 8, "rgba(140, 60, 220, 0.8)")?;
+        renderer.draw_bounding_box(&self.entrance, DebugClass::Sensor);
+        Ok(())
+    }
+}
+
+/// A blade swinging under `anchor`. Its angle from straight down is
+/// `amplitude * sin(2π * (frame + phase) / period)` radians, so pendulums
+/// with different phases swing in a pattern off the same frame counter.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Pendulum {
+    pub anchor: Point,
+    pub length: i16,
+    pub amplitude: f32,
+    pub period: u32,
+    #[serde(default)]
+    pub phase: u32,
+}
+
+impl Pendulum {
+    const BLADE_RADIUS: i16 = 18;
+
+    fn blade(&self, frame: f32) -> Point {
+        let turn = (frame + self.phase as f32) / self.period.max(1) as f32;
+        let angle = self.amplitude * (turn * std::f32::consts::TAU).sin();
+        Point {
+            x: self.anchor.x + (f32::from(self.length) * angle.sin()).round() as i16,
+            y: self.anchor.y + (f32::from(self.length) * angle.cos()).round() as i16,
+        }
+    }
+
+    /// Checks the blade where it is now and halfway back to the previous
+    /// tick, so a fast swing can't pass through the boy between frames.
+    fn hits(&self, target: &Rect, frame: u32) -> bool {
+        [frame as f32 - 0.5, frame as f32]
+            .iter()
+            .any(|at| target.intersects_circle(&self.blade(*at), Pendulum::BLADE_RADIUS))
+    }
+
+    fn draw(&self, renderer: &Renderer, frame: u32) -> Result<()> {
+        let blade = self.blade(frame as f32);
+        renderer.draw_line(&self.anchor, &blade, "#555");
+        renderer.draw_circle(&blade, Pendulum::BLADE_RADIUS, "#9aa4ad")?;
+        renderer.draw_bounding_box(
+            &circle_bounds(blade, Pendulum::BLADE_RADIUS),
+            DebugClass::Hazard,
+        );
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ObstacleKind {
+    LowPlatform,
+    HighPlatform,
+    IcePlatform,
+    Stone,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnEntry {
+    pub kind: ObstacleKind,
+    pub weight: f32,
+    /// How hard the obstacle is, from 0.0 to 1.0. Only used by levels with
+    /// a `difficultyRamp`.
+    #[serde(default)]
+    pub difficulty: f32,
+    /// Running speed below which the obstacle isn't spawned.
+    #[serde(default)]
+    pub min_speed: i16,
+}
+
+/// From `distance` pixels into the level on, spawns may be up to
+/// `max_difficulty` hard, e.g. `{ "distance": 600, "maxDifficulty": 0.5 }`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DifficultyStep {
+    pub distance: i16,
+    pub max_difficulty: f32,
+}
+
+/// Weighted obstacle choices for generated spawn slots, e.g.
+/// `[{ "kind": "stone", "weight": 0.2 }, ...]` in a level file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(transparent)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnEntry>,
+}
+
+impl Default for SpawnTable {
+    fn default() -> Self {
+        SpawnTable {
+            entries: vec![
+                SpawnEntry {
+                    kind: ObstacleKind::LowPlatform,
+                    weight: 0.7,
+                    difficulty: 0.2,
+                    min_speed: 0,
+                },
+                SpawnEntry {
+                    kind: ObstacleKind::Stone,
+                    weight: 0.2,
+                    difficulty: 0.5,
+                    min_speed: 0,
+                },
+                SpawnEntry {
+                    kind: ObstacleKind::HighPlatform,
+                    weight: 0.1,
+                    difficulty: 0.7,
+                    min_speed: 0,
+                },
+            ],
+        }
+    }
+}
+
+/// A stretch of an endless run, starting `from` meters in, with its own
+/// background, tint and obstacles.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Biome {
+    pub name: String,
+    pub from: i32,
+    pub background: String,
+    /// Laid over the scene, e.g. `"rgba(255, 170, 60, 0.18)"`.
+    #[serde(default)]
+    pub tint: Option<String>,
+    /// The obstacle kinds spawn slots may pick; empty allows every kind.
+    #[serde(default)]
+    pub spawns: Vec<ObstacleKind>,
+}
+
+impl Biome {
+    /// `spawns` narrowed to this biome's kinds. If the table has none of
+    /// them, only its easiest entry is left rather than letting in kinds
+    /// the biome rules out.
+    fn pool(&self, spawns: &SpawnTable) -> SpawnTable {
+        let mut entries: Vec<SpawnEntry> = spawns
+            .entries
+            .iter()
+            .filter(|entry| self.spawns.is_empty() || self.spawns.contains(&entry.kind))
+            .copied()
+            .collect();
+        if entries.is_empty() {
+            entries.extend(
+                spawns
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.weight > 0.0)
+                    .min_by(|a, b| a.difficulty.total_cmp(&b.difficulty))
+                    .copied(),
+            );
+        }
+        SpawnTable { entries }
+    }
+}
+
+/// Distance bands for endless runs, in the order they are reached.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct Biomes {
+    pub bands: Vec<Biome>,
+}
+
+impl Biomes {
+    /// The band `meters` falls in: the last one starting at or before it.
+    fn index_at(&self, meters: i32) -> Option<usize> {
+        self.bands.iter().rposition(|biome| biome.from <= meters)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(first) = self.bands.first() {
+            if first.from != 0 {
+                return Err(anyhow!("first biome {} must start at 0m", first.name));
+            }
+        }
+        for pair in self.bands.windows(2) {
+            if pair[1].from <= pair[0].from {
+                return Err(anyhow!(
+                    "biome {} must start after {}",
+                    pair[1].name,
+                    pair[0].name
+                ));
             }
         }
+        Ok(())
     }
+}
 
-    fn draw(&self, renderer: &Renderer) {
-        renderer.clear(&&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT));
+fn parse_biomes(json: JsValue) -> Result<Biomes> {
+    let biomes: Biomes = serde_wasm_bindgen::from_value(json)
+        .map_err(|err| anyhow!("Could not parse biomes {:#?}", err))?;
+    biomes
+        .validate()
+        .map_err(|err| anyhow!("Invalid biomes: {}", err))?;
+    Ok(biomes)
+}
 
-        if let WalkTheDog::Loaded(walk) = self {
-            walk.background.draw(renderer);
-            walk.boy.draw(renderer);
-            walk.stone.draw(renderer);
-            walk.platform.draw(renderer);
+/// A biome background fetched ahead of its band. `None` inside means the
+/// fetch failed and the band keeps the old background.
+type BiomeImage = Rc<RefCell<Option<Option<HtmlImageElement>>>>;
+
+/// The outgoing biome, fading out over the new one.
+struct BiomeFade {
+    background: Image,
+    tint: Option<String>,
+    alpha: Tween<Alpha>,
+}
+
+impl SpawnTable {
+    /// Makes stones more likely as `difficulty` goes from 0.0 to 1.0.
+    fn reweighted(&self, difficulty: f32) -> SpawnTable {
+        SpawnTable {
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| SpawnEntry {
+                    weight: match entry.kind {
+                        ObstacleKind::Stone => entry.weight * (1.0 + difficulty * 2.0),
+                        _ => entry.weight,
+                    },
+                    ..*entry
+                })
+                .collect(),
+        }
+    }
+
+    /// The entries allowed in a slot: no harder than `max_difficulty`, fast
+    /// enough for `speed` and not `previous` again. Harder entries weigh
+    /// more as the cap rises. If none qualify, a repeat is allowed; if
+    /// still none do, only the easiest entry is left.
+    fn for_slot(
+        &self,
+        max_difficulty: f32,
+        speed: i16,
+        previous: Option<ObstacleKind>,
+    ) -> SpawnTable {
+        let fits = |entry: &SpawnEntry| {
+            entry.difficulty <= max_difficulty && entry.min_speed <= speed && entry.weight > 0.0
+        };
+        let weighted = |entry: &SpawnEntry| SpawnEntry {
+            weight: entry.weight * (1.0 + entry.difficulty * max_difficulty),
+            ..*entry
+        };
+        let mut entries: Vec<SpawnEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| fits(entry) && Some(entry.kind) != previous)
+            .map(weighted)
+            .collect();
+        if entries.is_empty() {
+            entries = self
+                .entries
+                .iter()
+                .filter(|entry| fits(entry))
+                .map(weighted)
+                .collect();
         }
+        if entries.is_empty() {
+            entries.extend(
+                self.entries
+                    .iter()
+                    .filter(|entry| entry.weight > 0.0)
+                    .min_by(|a, b| a.difficulty.total_cmp(&b.difficulty))
+                    .copied(),
+            );
+        }
+        SpawnTable { entries }
+    }
+
+    fn choose(&self, rng: &mut StdRng) -> Option<ObstacleKind> {
+        self.entries
+            .choose_weighted(rng, |entry| entry.weight)
+            .ok()
+            .map(|entry| entry.kind)
     }
 }
 
-struct Platform {
-    sheet: Sheet,
-    image: HtmlImageElement,
+struct Coin {
     position: Point,
+    collected: bool,
+    /// Set while a coin popped out of a crate is still in the air.
+    velocity: Option<Point>,
 }
 
-impl Platform {
-    fn new(sheet: Sheet, image: HtmlImageElement, position: Point) -> Self {
-        Platform {
-            sheet,
-            image,
+impl Coin {
+    fn new(position: Point) -> Self {
+        Coin {
             position,
+            collected: false,
+            velocity: None,
         }
     }
 
-    fn draw(&self, renderer: &Renderer) {
-        let platform = self
-            .sheet
-            .frames
-            .get("13.png")
-            .expect("13.png does not exist");
-
-        renderer.draw_image(
-            &self.image,
-            &Rect::new_from_x_y(
-                platform.frame.x.into(),
-                platform.frame.y.into(),
-                (platform.frame.w * 3).into(),
-                platform.frame.h.into(),
-            ),
-            &&self.destination_box(),
-        );
-
-        for x in self.bounding_boxes() {
-            renderer.draw_bounding_box(&x);
+    fn popped(position: Point, velocity: Point) -> Self {
+        Coin {
+            velocity: Some(velocity),
+            ..Coin::new(position)
         }
     }
 
-    fn destination_box(&self) -> Rect {
-        let platform = self
-            .sheet
-            .frames
-            .get("13.png")
-            .expect("13.png does not exist");
+    /// Moves an airborne coin and settles it on the first of `surfaces`
+    /// it comes down on, or on the ground.
+    fn fall(&mut self, surfaces: &[Rect]) {
+        let Some(mut velocity) = self.velocity else {
+            return;
+        };
 
+        velocity.y += COIN_GRAVITY;
+        let previous_bottom = self.position.y + COIN_RADIUS;
+        self.position.x += velocity.x;
+        self.position.y += velocity.y;
+        self.velocity = Some(velocity);
+
+        let bottom = self.position.y + COIN_RADIUS;
+        let landing = surfaces.iter().find(|surface| {
+            velocity.y > 0
+                && previous_bottom <= surface.y()
+                && bottom >= surface.y()
+                && self.position.x >= surface.x()
+                && self.position.x <= surface.right()
+        });
+        if let Some(surface) = landing {
+            self.position.y = surface.y() - COIN_RADIUS;
+            self.velocity = None;
+        } else if bottom >= HEIGHT {
+            self.position.y = HEIGHT - COIN_RADIUS;
+            self.velocity = None;
+        }
+    }
+
+    fn bounding_box(&self) -> Rect {
         Rect::new_from_x_y(
-            self.position.x.into(),
-            self.position.y.into(),
-            (platform.frame.w * 3).into(),
-            platform.frame.h.into(),
+            self.position.x - COIN_RADIUS,
+            self.position.y - COIN_RADIUS,
+            COIN_RADIUS * 2,
+            COIN_RADIUS * 2,
         )
     }
 
-    fn bounding_boxes(&self) -> Vec<Rect> {
-        const X_OFFSET: i16 = 60;
-        const END_HEIGHT: i16 = 54;
-        let destination_box = self.destination_box();
+    /// Moves the coin a fraction of the way toward `target` if it is inside
+    /// the magnet's reach, always by at least a pixel so it converges.
+    fn pull_toward(&mut self, target: &Point) {
+        let distance = self.position.distance_to(target);
+        if self.collected || !(1.0..=MAGNET_RADIUS).contains(&distance) {
+            return;
+        }
 
-        let bounding_box_one = Rect::new_from_x_y(
-            destination_box.position.x,
-            destination_box.position.y,
-            X_OFFSET,
-            END_HEIGHT,
-        );
-        let bounding_box_two = Rect::new_from_x_y(
-            destination_box.position.x + X_OFFSET,
-            destination_box.position.y,
-            destination_box.width - (X_OFFSET * 2),
-            destination_box.height,
-        );
-        let bounding_box_three = Rect::new_from_x_y(
-            destination_box.position.x + destination_box.width - X_OFFSET,
-            destination_box.position.y,
-            X_OFFSET,
-            END_HEIGHT,
+        let step = (distance * MAGNET_PULL).clamp(1.0, distance);
+        let dx = f32::from(target.x) - f32::from(self.position.x);
+        let dy = f32::from(target.y) - f32::from(self.position.y);
+        self.position.x += (dx / distance * step).round() as i16;
+        self.position.y += (dy / distance * step).round() as i16;
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        if self.collected {
+            return Ok(());
+        }
+
+        renderer.draw_circle(&self.position, COIN_RADIUS, "#f5c518")?;
+        renderer.draw_bounding_box(&self.bounding_box(), DebugClass::Collectible);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GameMode {
+    Endless,
+    DailyChallenge,
+    LevelOne,
+}
+
+impl GameMode {
+    const ALL: [GameMode; 3] = [
+        GameMode::Endless,
+        GameMode::DailyChallenge,
+        GameMode::LevelOne,
+    ];
+
+    fn label_key(self) -> &'static str {
+        match self {
+            GameMode::Endless => "mode.endless",
+            GameMode::DailyChallenge => "mode.daily_challenge",
+            GameMode::LevelOne => "mode.level_one",
+        }
+    }
+
+    fn configure(self, builder: WalkTheDogBuilder) -> WalkTheDogBuilder {
+        match self {
+            GameMode::Endless => builder.practice().level(None),
+            GameMode::DailyChallenge => builder.daily(browser::today()).level(None),
+            GameMode::LevelOne => builder.practice().level(Some(LEVEL_ONE.to_string())),
+        }
+    }
+
+    /// The level file the mode starts on, for modes that play one.
+    fn level_path(self) -> Option<&'static str> {
+        match self {
+            GameMode::Endless | GameMode::DailyChallenge => None,
+            GameMode::LevelOne => Some(LEVEL_ONE),
+        }
+    }
+}
+
+/// A level file being fetched after a mode was picked on the title screen.
+struct LevelLoad {
+    config: GameConfig,
+    result: Rc<RefCell<Option<Result<Level>>>>,
+    frames: u32,
+}
+
+impl LevelLoad {
+    fn start(config: GameConfig, path: String) -> Self {
+        let result = Rc::new(RefCell::new(None));
+        let slot = Rc::clone(&result);
+        browser::spawn_local(async move {
+            *slot.borrow_mut() = Some(load_level(&path).await);
+        });
+
+        LevelLoad {
+            config,
+            result,
+            frames: 0,
+        }
+    }
+
+    /// Fetches report no progress, so the bar eases toward full instead.
+    fn progress(&self) -> f32 {
+        1.0 - 0.97_f32.powi(self.frames as i32)
+    }
+}
+
+/// Shown after the goal is reached, until the player moves on to the next
+/// level or, after the last one, back to the title.
+struct LevelComplete {
+    fade: Tween<Alpha>,
+    summary: RunSummary,
+    /// The medal this run earned, on levels that award them.
+    medal: Option<Medal>,
+    has_medals: bool,
+    next: Option<String>,
+    continue_key: KeyLatch,
+    loading: Option<LevelLoad>,
+    error: Option<String>,
+}
+
+impl LevelComplete {
+    fn draw(&self, renderer: &Renderer, level_index: u32, strings: &Strings) -> Result<()> {
+        renderer.fill_rect(
+            &Rect::new_from_x_y(WIDTH / 2 - 220, 150, 440, 170),
+            &format!("rgba(255, 255, 255, {:.2})", self.fade.value().0),
         );
+        renderer.draw_text_with_font(
+            &strings.format("level_complete.title", &[&(level_index + 1)]),
+            &Point {
+                x: WIDTH / 2 - 190,
+                y: 200,
+            },
+            "32px sans-serif",
+        )?;
+        renderer.draw_text(
+            &strings.format(
+                "level_complete.summary",
+                &[
+                    &strings.number(self.summary.score),
+                    &strings.number(self.summary.distance / PIXELS_PER_METER),
+                    &strings.number(self.summary.coins),
+                ],
+            ),
+            &Point {
+                x: WIDTH / 2 - 190,
+                y: 240,
+            },
+        )?;
+        if self.has_medals {
+            self.draw_medals(renderer, strings)?;
+        }
+
+        let prompt = match (&self.error, &self.loading, &self.next) {
+            (Some(error), _, _) => error.as_str(),
+            (None, Some(_), _) => strings.t("level_complete.loading"),
+            (None, None, Some(_)) => strings.t("level_complete.next"),
+            (None, None, None) => strings.t("level_complete.last"),
+        };
+        renderer.draw_text(
+            prompt,
+            &Point {
+                x: WIDTH / 2 - 190,
+                y: 290,
+            },
+        )
+    }
+
+    fn draw_medals(&self, renderer: &Renderer, strings: &Strings) -> Result<()> {
+        for (index, medal) in Medal::ALL.into_iter().enumerate() {
+            medal.draw(
+                renderer,
+                &Point {
+                    x: WIDTH / 2 + 120 + index as i16 * (MEDAL_RADIUS * 2 + 8),
+                    y: 190,
+                },
+                self.medal.is_some_and(|earned| medal <= earned),
+            )?;
+        }
+        if let Some(medal) = self.medal {
+            renderer.draw_text(
+                strings.t(medal.label_key()),
+                &Point {
+                    x: WIDTH / 2 + 110,
+                    y: 225,
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct TitleMenu {
+    nav: MenuNav,
+    highlight: Tween<Point>,
+    seen_taps: u32,
+    loading: Option<LevelLoad>,
+    error: Option<String>,
+    results: LevelResults,
+}
+
+impl TitleMenu {
+    fn new(seen_taps: u32) -> Self {
+        TitleMenu {
+            nav: MenuNav::new(),
+            highlight: Tween::new(
+                TitleMenu::item_rect(0).position,
+                TitleMenu::item_rect(0).position,
+                MENU_SLIDE_TICKS,
+                tween::ease_out_quad,
+            ),
+            seen_taps,
+            loading: None,
+            error: None,
+            results: LevelResults::load(),
+        }
+    }
+
+    /// The modes followed by a Settings entry.
+    fn item_count() -> usize {
+        GameMode::ALL.len() + 1
+    }
+
+    fn item_key(index: usize) -> &'static str {
+        GameMode::ALL
+            .get(index)
+            .map_or("title.settings", |mode| mode.label_key())
+    }
+
+    fn item_rect(index: usize) -> Rect {
+        Rect::new_from_x_y(WIDTH / 2 - 150, 240 + index as i16 * 56, 300, 48)
+    }
+
+    /// Returns the item the player confirmed with Enter or a tap, if any.
+    fn update(&mut self, keystate: &KeyState) -> Option<TitleChoice> {
+        let previous = self.nav.selected;
+        let choice = self.update_selection(keystate);
+        if self.nav.selected != previous {
+            self.highlight
+                .retarget(TitleMenu::item_rect(self.nav.selected).position);
+        }
+        self.highlight.tick();
+        choice
+    }
+
+    fn update_selection(&mut self, keystate: &KeyState) -> Option<TitleChoice> {
+        let count = TitleMenu::item_count();
+        let mut confirmed = self.nav.update(keystate, count).is_some();
+        if let Some((taps, point)) = keystate.last_tap() {
+            if taps != self.seen_taps {
+                self.seen_taps = taps;
+                if let Some(index) =
+                    (0..count).find(|index| TitleMenu::item_rect(*index).contains(&point))
+                {
+                    self.nav.selected = index;
+                    confirmed = true;
+                }
+            }
+        }
+
+        confirmed.then(|| match GameMode::ALL.get(self.nav.selected) {
+            Some(mode) => TitleChoice::Play(*mode),
+            None => TitleChoice::Settings,
+        })
+    }
+
+    fn draw(&self, renderer: &Renderer, strings: &Strings) -> Result<()> {
+        renderer.draw_text_with_font(
+            strings.t("title.name"),
+            &Point {
+                x: WIDTH / 2 - 190,
+                y: 180,
+            },
+            "bold 64px sans-serif",
+        )?;
+
+        renderer.fill_rect(
+            &Rect {
+                position: self.highlight.value(),
+                ..TitleMenu::item_rect(self.nav.selected)
+            },
+            "rgba(255, 255, 255, 0.7)",
+        );
+        for index in 0..TitleMenu::item_count() {
+            let rect = TitleMenu::item_rect(index);
+            renderer.draw_text_with_font(
+                strings.t(TitleMenu::item_key(index)),
+                &Point {
+                    x: rect.x() + 20,
+                    y: rect.bottom() - 14,
+                },
+                "24px sans-serif",
+            )?;
+            let medal = GameMode::ALL
+                .get(index)
+                .and_then(|mode| mode.level_path())
+                .and_then(|path| self.results.medal(path));
+            if let Some(medal) = medal {
+                medal.draw(
+                    renderer,
+                    &Point {
+                        x: rect.right() - 24,
+                        y: rect.center().y,
+                    },
+                    true,
+                )?;
+            }
+        }
+
+        let footer = Point {
+            x: WIDTH / 2 - 150,
+            y: 490,
+        };
+        if let Some(load) = &self.loading {
+            let bar = Rect::new_from_x_y(footer.x, footer.y, 300, 12);
+            renderer.fill_rect(&bar, "rgba(0, 0, 0, 0.3)");
+            renderer.fill_rect(
+                &Rect {
+                    width: (f32::from(bar.width) * load.progress()) as i16,
+                    ..bar
+                },
+                "#000",
+            );
+            renderer.draw_text(
+                strings.t("title.loading_level"),
+                &Point { y: 530, ..footer },
+            )
+        } else if let Some(error) = &self.error {
+            renderer.draw_text(error, &Point { y: 530, ..footer })
+        } else {
+            renderer.draw_text(strings.t("title.hint"), &footer)
+        }
+    }
+}
+
+/// Which way the held menu arrows point, as the sign of each axis.
+fn menu_axes(keystate: &KeyState) -> (i8, i8) {
+    keystate
+        .heading(MENU_UP_KEY, MENU_DOWN_KEY, MENU_LEFT_KEY, MENU_RIGHT_KEY)
+        .map_or((0, 0), Heading::axes)
+}
+
+enum TitleChoice {
+    Play(GameMode),
+    Settings,
+}
+
+/// Up/down selection over a list of items, confirmed with Enter.
+struct MenuNav {
+    selected: usize,
+    up_key: KeyLatch,
+    down_key: KeyLatch,
+    select_key: KeyLatch,
+}
+
+impl MenuNav {
+    /// Starts on the first item. Keys already held when the menu opens
+    /// have to be let go before they count.
+    fn new() -> Self {
+        MenuNav {
+            selected: 0,
+            up_key: KeyLatch { held: true },
+            down_key: KeyLatch { held: true },
+            select_key: KeyLatch { held: true },
+        }
+    }
+
+    /// Moves through `count` items, wrapping at either end. Returns the
+    /// selected item once it is confirmed.
+    fn update(&mut self, keystate: &KeyState, count: usize) -> Option<usize> {
+        let (_, vertical) = menu_axes(keystate);
+        self.navigate(vertical, keystate.is_pressed(MENU_SELECT_KEY), count)
+    }
+
+    /// `update` for arrows held along `vertical` and Enter held or not.
+    fn navigate(&mut self, vertical: i8, select: bool, count: usize) -> Option<usize> {
+        if self.up_key.pressed(vertical < 0) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if self.down_key.pressed(vertical > 0) {
+            self.selected = (self.selected + 1) % count;
+        }
+        self.select_key.pressed(select).then_some(self.selected)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PauseChoice {
+    Resume,
+    Restart,
+    Assist,
+    Quit,
+}
+
+impl PauseChoice {
+    const ALL: [PauseChoice; 4] = [
+        PauseChoice::Resume,
+        PauseChoice::Restart,
+        PauseChoice::Assist,
+        PauseChoice::Quit,
+    ];
+
+    fn label_key(self) -> &'static str {
+        match self {
+            PauseChoice::Resume => "pause.resume",
+            PauseChoice::Restart => "pause.restart",
+            PauseChoice::Assist => "pause.assist",
+            PauseChoice::Quit => "pause.quit",
+        }
+    }
+}
+
+/// A line of script text over an actor.
+struct Bubble {
+    actor: Actor,
+    text: String,
+    timer: Timer,
+}
+
+/// A level intro playing out. The boy is walked with Run and Stop so he
+/// animates as he would under the player; the dog only appears here.
+struct Cutscene {
+    player: CutscenePlayer,
+    dog: Option<Tween<Point>>,
+    /// Where the boy is walking to; he stops once he gets there.
+    boy_target: Option<i16>,
+    bubbles: Vec<Bubble>,
+    skip_key: KeyLatch,
+}
+
+impl Cutscene {
+    fn new(script: Script) -> Self {
+        Cutscene {
+            player: CutscenePlayer::new(script),
+            dog: None,
+            boy_target: None,
+            bubbles: Vec::new(),
+            // Enter may still be down from the menu that started the level.
+            skip_key: KeyLatch { held: true },
+        }
+    }
+
+    /// Runs one frame of the script and returns true once it is over.
+    fn update(&mut self, players: &mut [Player], audio: &Audio, sounds: &[(&str, &Sound)]) -> bool {
+        for command in self.player.update() {
+            match command {
+                Command::MoveEntity {
+                    actor: Actor::Boy,
+                    from,
+                    to,
+                    ..
+                } => {
+                    for player in players.iter_mut() {
+                        if let Some(from) = from {
+                            player.boy.place_at(from.x);
+                        }
+                        player.boy.run_right();
+                    }
+                    self.boy_target = Some(to.x);
+                }
+                Command::MoveEntity {
+                    actor: Actor::Dog,
+                    from,
+                    to,
+                    frames,
+                } => {
+                    let from = from
+                        .or_else(|| self.dog.as_ref().map(Tween::value))
+                        .unwrap_or(to);
+                    self.dog = Some(Tween::new(from, to, frames, tween::linear));
+                }
+                Command::ShowText {
+                    actor,
+                    text,
+                    frames,
+                } => self.bubbles.push(Bubble {
+                    actor,
+                    text,
+                    timer: Timer::new(frames),
+                }),
+                Command::Wait { .. } => {}
+                Command::PlaySound { sound } => {
+                    match sounds.iter().find(|(name, _)| *name == sound) {
+                        Some((_, sound)) => {
+                            if let Err(err) = audio.play_sound(sound) {
+                                log!("Could not play cutscene sound {:#?}", err);
+                            }
+                        }
+                        None => {
+                            log!("Unknown cutscene sound {}", sound);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(target) = self.boy_target {
+            for player in players.iter_mut() {
+                if player.boy.pos_x() >= target + player.boy.start_offset {
+                    player.boy.stop();
+                }
+            }
+            if players
+                .iter()
+                .all(|player| player.boy.state_machine.state_kind() != StateKind::Running)
+            {
+                self.boy_target = None;
+            }
+        }
+        if let Some(dog) = &mut self.dog {
+            dog.tick();
+        }
+        for bubble in self.bubbles.iter_mut() {
+            bubble.timer.tick();
+        }
+        self.bubbles.retain(|bubble| bubble.timer.is_active());
+
+        self.player.is_finished() && self.boy_target.is_none()
+    }
+
+    /// Puts every actor where the script would have left it.
+    fn skip(&self, players: &mut [Player]) {
+        if let Some(to) = self.player.script().final_position(Actor::Boy) {
+            for player in players.iter_mut() {
+                player.boy.place_at(to.x);
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        renderer: &Renderer,
+        dog_image: &HtmlImageElement,
+        players: &[Player],
+        strings: &Strings,
+    ) -> Result<()> {
+        if let Some(dog) = &self.dog {
+            renderer.draw_entire_image(dog_image, &dog.value())?;
+        }
+        for bubble in &self.bubbles {
+            let speaker = match bubble.actor {
+                Actor::Boy => players.first().map(|player| {
+                    let bounding_box = player.boy.bounding_box();
+                    Point {
+                        x: bounding_box.center().x,
+                        y: bounding_box.y(),
+                    }
+                }),
+                Actor::Dog => self.dog.as_ref().map(|dog| {
+                    let position = dog.value();
+                    Point {
+                        x: position.x + dog_image.width() as i16 / 2,
+                        y: position.y,
+                    }
+                }),
+            };
+            let Some(speaker) = speaker else {
+                continue;
+            };
+            let left = (speaker.x - BUBBLE_WIDTH / 2).clamp(0, WIDTH - BUBBLE_WIDTH);
+            let top = speaker.y - BUBBLE_HEIGHT - 12;
+            renderer.draw_polygon(
+                &[
+                    Point {
+                        x: speaker.x - 6,
+                        y: top + BUBBLE_HEIGHT,
+                    },
+                    Point {
+                        x: speaker.x,
+                        y: speaker.y - 2,
+                    },
+                    Point {
+                        x: speaker.x + 6,
+                        y: top + BUBBLE_HEIGHT,
+                    },
+                ],
+                Some("#fff"),
+                None,
+            );
+            renderer.fill_rect(
+                &Rect::new_from_x_y(left, top, BUBBLE_WIDTH, BUBBLE_HEIGHT),
+                "#fff",
+            );
+            renderer.draw_text(
+                strings.lookup(&bubble.text).unwrap_or(&bubble.text),
+                &Point {
+                    x: left + 10,
+                    y: top + 20,
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Drawn over the dimmed, frozen run while it is paused.
+struct PauseMenu {
+    nav: MenuNav,
+    back_key: KeyLatch,
+}
+
+impl PauseMenu {
+    fn new() -> Self {
+        PauseMenu {
+            nav: MenuNav::new(),
+            // Escape opened the menu and is probably still down.
+            back_key: KeyLatch { held: true },
+        }
+    }
+
+    fn item_rect(index: usize) -> Rect {
+        Rect::new_from_x_y(WIDTH / 2 - 150, 220 + index as i16 * 56, 300, 48)
+    }
+
+    /// Returns the option picked with Enter; Escape resumes too.
+    fn update(&mut self, keystate: &KeyState) -> Option<PauseChoice> {
+        if self.back_key.pressed(keystate.is_pressed(MENU_BACK_KEY)) {
+            return Some(PauseChoice::Resume);
+        }
+        self.nav
+            .update(keystate, PauseChoice::ALL.len())
+            .map(|index| PauseChoice::ALL[index])
+    }
+
+    fn draw(&self, renderer: &Renderer, strings: &Strings, assist: bool) -> Result<()> {
+        renderer.fill_rect(
+            &Rect::new_from_x_y(0, 0, WIDTH, HEIGHT),
+            "rgba(0, 0, 0, 0.5)",
+        );
+        renderer.draw_text_with_font(
+            strings.t("pause.title"),
+            &Point {
+                x: WIDTH / 2 - 150,
+                y: 190,
+            },
+            "bold 48px sans-serif",
+        )?;
+        for (index, choice) in PauseChoice::ALL.iter().enumerate() {
+            let rect = PauseMenu::item_rect(index);
+            let fill = if index == self.nav.selected {
+                "rgba(255, 255, 255, 0.9)"
+            } else {
+                "rgba(255, 255, 255, 0.5)"
+            };
+            renderer.fill_rect(&rect, fill);
+            let label = match choice {
+                PauseChoice::Assist => {
+                    strings.format(choice.label_key(), &[&strings.on_off(assist)])
+                }
+                _ => strings.t(choice.label_key()).to_string(),
+            };
+            renderer.draw_text_with_font(
+                &label,
+                &Point {
+                    x: rect.x() + 20,
+                    y: rect.bottom() - 14,
+                },
+                "24px sans-serif",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Player preferences, kept in localStorage and applied as soon as they
+/// change. Volumes and screen shake are stored for the audio and effects
+/// that read them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub screen_shake: bool,
+    pub high_contrast: bool,
+    /// Fills where the boy's box overlaps others in debug mode.
+    pub show_overlaps: bool,
+    pub keys: InputMap,
+    /// The boy keeps running by himself once a jump or slide starts him.
+    pub auto_run: bool,
+    /// One-button play: auto-run, with the jump key jumping or sliding as
+    /// the obstacle ahead needs, and the boy slowed down.
+    pub assist: bool,
+    /// How much slower the boy runs in assist mode, from 0 to
+    /// `ASSIST_MAX_SLOWDOWN`.
+    pub assist_slowdown: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            music_volume: 0.8,
+            sfx_volume: 0.8,
+            screen_shake: true,
+            high_contrast: false,
+            show_overlaps: true,
+            keys: InputMap::for_player(0),
+            auto_run: false,
+            assist: false,
+            assist_slowdown: 0.3,
+        }
+    }
+}
+
+impl Settings {
+    fn load() -> Settings {
+        match browser::storage_get(SETTINGS_KEY) {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                log!("Ignoring malformed settings {:#?}", err);
+                Settings::default()
+            }),
+            Ok(None) => Settings::default(),
+            Err(err) => {
+                log!("{:#?}", err);
+                Settings::default()
+            }
+        }
+    }
+
+    fn store(&self) {
+        let result = serde_json::to_string(self)
+            .map_err(|err| anyhow!("Could not serialize settings {:#?}", err))
+            .and_then(|json| browser::storage_set(SETTINGS_KEY, &json));
+        if let Err(err) = result {
+            log!("{:#?}", err);
+        }
+    }
+
+    fn debug_palette(&self) -> DebugPalette {
+        if self.high_contrast {
+            DebugPalette::HighContrast
+        } else {
+            DebugPalette::Default
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SettingsRow {
+    MusicVolume,
+    SfxVolume,
+    ScreenShake,
+    HighContrast,
+    AutoRun,
+    Assist,
+    ShowOverlaps,
+    RebindKeys,
+    ResetDefaults,
+    Back,
+}
+
+impl SettingsRow {
+    const ALL: [SettingsRow; 10] = [
+        SettingsRow::MusicVolume,
+        SettingsRow::SfxVolume,
+        SettingsRow::ScreenShake,
+        SettingsRow::HighContrast,
+        SettingsRow::AutoRun,
+        SettingsRow::Assist,
+        SettingsRow::ShowOverlaps,
+        SettingsRow::RebindKeys,
+        SettingsRow::ResetDefaults,
+        SettingsRow::Back,
+    ];
+}
+
+enum SettingsAction {
+    None,
+    Changed,
+    Back,
+}
+
+const REBIND_ACTIONS: [&str; 3] = ["action.run", "action.jump", "action.slide"];
+
+struct SettingsMenu {
+    settings: Settings,
+    selected: usize,
+    rebinding: Option<Vec<String>>,
+    message: Option<String>,
+}
+
+impl SettingsMenu {
+    fn new(settings: Settings) -> Self {
+        SettingsMenu {
+            settings,
+            selected: 0,
+            rebinding: None,
+            message: None,
+        }
+    }
+
+    fn update(&mut self, keystate: &KeyState, strings: &Strings) -> SettingsAction {
+        // Keys that went down this update; ones held since before don't
+        // count, like the Enter that opened the menu.
+        let fresh: Vec<String> = keystate
+            .pressed_codes()
+            .filter(|code| keystate.held_duration(code) == 0.0)
+            .map(str::to_string)
+            .collect();
+
+        if self.rebinding.is_some() {
+            return match fresh.first() {
+                Some(code) => self.rebind(code, strings),
+                None => SettingsAction::None,
+            };
+        }
+
+        let pressed = |code: &str| fresh.iter().any(|fresh| fresh == code);
+        let count = SettingsRow::ALL.len();
+        if pressed(MENU_UP_KEY) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if pressed(MENU_DOWN_KEY) {
+            self.selected = (self.selected + 1) % count;
+        }
+        if pressed(MENU_BACK_KEY) {
+            return SettingsAction::Back;
+        }
+
+        let step = Heading::from_axes(
+            i8::from(pressed(MENU_RIGHT_KEY)) - i8::from(pressed(MENU_LEFT_KEY)),
+            0,
+        )
+        .map_or(0.0, |heading| f32::from(heading.axes().0) * VOLUME_STEP);
+        let confirmed = pressed(MENU_SELECT_KEY);
+        let adjust = |volume: f32| ((volume + step) * 10.0).round().clamp(0.0, 10.0) / 10.0;
+
+        match SettingsRow::ALL[self.selected] {
+            SettingsRow::MusicVolume if step != 0.0 => {
+                self.settings.music_volume = adjust(self.settings.music_volume);
+            }
+            SettingsRow::SfxVolume if step != 0.0 => {
+                self.settings.sfx_volume = adjust(self.settings.sfx_volume);
+            }
+            SettingsRow::ScreenShake if confirmed || step != 0.0 => {
+                self.settings.screen_shake = !self.settings.screen_shake;
+            }
+            SettingsRow::HighContrast if confirmed || step != 0.0 => {
+                self.settings.high_contrast = !self.settings.high_contrast;
+            }
+            SettingsRow::AutoRun if confirmed || step != 0.0 => {
+                self.settings.auto_run = !self.settings.auto_run;
+            }
+            SettingsRow::Assist if confirmed => {
+                self.settings.assist = !self.settings.assist;
+            }
+            SettingsRow::Assist if step != 0.0 => {
+                self.settings.assist_slowdown =
+                    adjust(self.settings.assist_slowdown).min(ASSIST_MAX_SLOWDOWN);
+            }
+            SettingsRow::ShowOverlaps if confirmed || step != 0.0 => {
+                self.settings.show_overlaps = !self.settings.show_overlaps;
+            }
+            SettingsRow::RebindKeys if confirmed => {
+                self.rebinding = Some(Vec::new());
+                self.message = None;
+                return SettingsAction::None;
+            }
+            SettingsRow::ResetDefaults if confirmed => {
+                self.settings = Settings::default();
+                self.message = Some(strings.t("settings.was_reset").to_string());
+            }
+            SettingsRow::Back if confirmed => return SettingsAction::Back,
+            _ => return SettingsAction::None,
+        }
+        SettingsAction::Changed
+    }
+
+    /// Takes the next key for the action being rebound. Escape cancels, and a
+    /// key already given to an earlier action is refused.
+    fn rebind(&mut self, code: &str, strings: &Strings) -> SettingsAction {
+        let Some(keys) = &mut self.rebinding else {
+            return SettingsAction::None;
+        };
+
+        if code == MENU_BACK_KEY {
+            self.rebinding = None;
+            self.message = Some(strings.t("settings.rebind_cancelled").to_string());
+            return SettingsAction::None;
+        }
+
+        if let Some(index) = keys.iter().position(|key| key == code) {
+            self.message = Some(strings.format(
+                "settings.already_bound",
+                &[&code, &strings.t(REBIND_ACTIONS[index])],
+            ));
+            return SettingsAction::None;
+        }
+
+        keys.push(code.to_string());
+        self.message = None;
+        if keys.len() < REBIND_ACTIONS.len() {
+            return SettingsAction::None;
+        }
+
+        self.settings.keys = InputMap {
+            enter: self.settings.keys.enter.clone(),
+            ..InputMap::new(&keys[0], &keys[1], &keys[2])
+        };
+        self.rebinding = None;
+        self.message = Some(strings.t("settings.keys_saved").to_string());
+        SettingsAction::Changed
+    }
+
+    fn row_label(&self, row: SettingsRow, strings: &Strings) -> String {
+        let toggle = |key, on| strings.format(key, &[&strings.on_off(on)]);
+        match row {
+            SettingsRow::MusicVolume => strings.t("settings.music_volume").to_string(),
+            SettingsRow::SfxVolume => strings.t("settings.sfx_volume").to_string(),
+            SettingsRow::ScreenShake => toggle("settings.screen_shake", self.settings.screen_shake),
+            SettingsRow::HighContrast => {
+                toggle("settings.high_contrast", self.settings.high_contrast)
+            }
+            SettingsRow::AutoRun => toggle("settings.auto_run", self.settings.auto_run),
+            SettingsRow::Assist => strings.format(
+                "settings.assist",
+                &[
+                    &strings.on_off(self.settings.assist),
+                    &(self.settings.assist_slowdown * 100.0).round(),
+                ],
+            ),
+            SettingsRow::ShowOverlaps => {
+                toggle("settings.show_overlaps", self.settings.show_overlaps)
+            }
+            SettingsRow::RebindKeys => strings.format(
+                "settings.rebind_keys",
+                &[
+                    &self.settings.keys.run,
+                    &self.settings.keys.jump,
+                    &self.settings.keys.slide,
+                ],
+            ),
+            SettingsRow::ResetDefaults => strings.t("settings.reset").to_string(),
+            SettingsRow::Back => strings.t("settings.back").to_string(),
+        }
+    }
+
+    fn draw(&self, renderer: &Renderer, strings: &Strings) -> Result<()> {
+        renderer.draw_text_with_font(
+            strings.t("settings.title"),
+            &Point { x: 200, y: 90 },
+            "bold 40px sans-serif",
+        )?;
+
+        for (index, row) in SettingsRow::ALL.iter().enumerate() {
+            let rect = Rect::new_from_x_y(200, 110 + index as i16 * 38, 800, 34);
+            if index == self.selected {
+                renderer.fill_rect(&rect, "rgba(255, 255, 255, 0.7)");
+            }
+            let text = Point {
+                x: rect.x() + 16,
+                y: rect.bottom() - 12,
+            };
+            renderer.draw_text_with_font(
+                &self.row_label(*row, strings),
+                &text,
+                "22px sans-serif",
+            )?;
+
+            let volume = match row {
+                SettingsRow::MusicVolume => Some(self.settings.music_volume),
+                SettingsRow::SfxVolume => Some(self.settings.sfx_volume),
+                _ => None,
+            };
+            if let Some(volume) = volume {
+                let slider = Rect::new_from_x_y(rect.x() + 300, rect.y() + 11, 300, 12);
+                renderer.fill_rect(&slider, "rgba(0, 0, 0, 0.3)");
+                renderer.fill_rect(
+                    &Rect {
+                        width: (f32::from(slider.width) * volume) as i16,
+                        ..slider
+                    },
+                    "#000",
+                );
+            }
+        }
+
+        let footer = Point { x: 200, y: 520 };
+        if let Some(keys) = &self.rebinding {
+            renderer.draw_text(
+                &strings.format(
+                    "settings.press_key",
+                    &[&strings.t(REBIND_ACTIONS[keys.len()])],
+                ),
+                &footer,
+            )?;
+        } else {
+            renderer.draw_text(strings.t("settings.hint"), &footer)?;
+        }
+        if let Some(message) = &self.message {
+            renderer.draw_text(message, &Point { y: 550, ..footer })?;
+        }
+        Ok(())
+    }
+}
+
+/// Tints the scene from day through dusk to night and back.
+#[derive(Clone, Copy, Debug)]
+struct DayCycle {
+    length: u32,
+}
+
+impl DayCycle {
+    /// 0.0 at midday and 1.0 at midnight, following a cosine so the tint
+    /// changes smoothly every frame.
+    fn darkness(&self, frame: u32) -> f32 {
+        if self.length == 0 {
+            return 0.0;
+        }
+        let phase = (frame % self.length) as f32 / self.length as f32;
+        (1.0 - (phase * std::f32::consts::TAU).cos()) / 2.0
+    }
+
+    /// Dusk orange deepening into night blue, over the whole screen.
+    fn draw(&self, renderer: &Renderer, frame: u32) {
+        let darkness = self.darkness(frame);
+        if darkness <= 0.0 {
+            return;
+        }
+
+        let mix = |dusk: f32, night: f32| (dusk + (night - dusk) * darkness) as u8;
+        renderer.fill_rect(
+            &Rect::new_from_x_y(0, 0, WIDTH, HEIGHT),
+            &format!(
+                "rgba({}, {}, {}, {:.3})",
+                mix(255.0, 20.0),
+                mix(140.0, 24.0),
+                mix(60.0, 82.0),
+                darkness * NIGHT_ALPHA
+            ),
+        );
+    }
+}
+
+struct Raindrop {
+    x: f32,
+    y: f32,
+    splash: u8,
+}
+
+/// Rain falling across the foreground. Drops become a short splash when
+/// they reach the ground, and the drop count is capped so a long catch-up
+/// after the tab was hidden can't pile up particles.
+struct Rain {
+    per_second: u16,
+    owed: f32,
+    drops: Vec<Raindrop>,
+    rng: StdRng,
+}
+
+impl Rain {
+    fn new(per_second: u16, seed: u32) -> Self {
+        Rain {
+            per_second,
+            owed: 0.0,
+            drops: Vec::new(),
+            rng: StdRng::seed_from_u64(u64::from(seed)),
+        }
+    }
+
+    fn update(&mut self) {
+        if self.per_second == 0 && self.drops.is_empty() {
+            return;
+        }
+
+        self.owed += f32::from(self.per_second) / 60.0;
+        while self.owed >= 1.0 {
+            self.owed -= 1.0;
+            if self.drops.len() < MAX_RAINDROPS {
+                // Start left of the screen too, so the shear doesn't leave
+                // a dry strip along the left edge.
+                let x = self.rng.gen_range(-100.0..f32::from(WIDTH));
+                self.drops.push(Raindrop {
+                    x,
+                    y: 0.0,
+                    splash: 0,
+                });
+            }
+        }
+
+        for drop in self.drops.iter_mut() {
+            if drop.splash > 0 {
+                drop.splash -= 1;
+            } else {
+                drop.x += RAIN_SHEAR;
+                drop.y += RAIN_SPEED;
+                if drop.y >= RAIN_GROUND {
+                    drop.y = RAIN_GROUND;
+                    drop.splash = SPLASH_FRAMES;
+                }
+            }
+        }
+        self.drops
+            .retain(|drop| drop.y < RAIN_GROUND || drop.splash > 0);
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        const COLOR: &str = "rgba(170, 190, 255, 0.6)";
+        for drop in &self.drops {
+            let x = drop.x as i16;
+            let y = drop.y as i16;
+            if drop.splash > 0 {
+                let spread = (SPLASH_FRAMES - drop.splash) as i16 + 2;
+                let origin = Point { x, y };
+                renderer.draw_line(
+                    &origin,
+                    &Point {
+                        x: x - spread,
+                        y: y - spread / 2,
+                    },
+                    COLOR,
+                );
+                renderer.draw_line(
+                    &origin,
+                    &Point {
+                        x: x + spread,
+                        y: y - spread / 2,
+                    },
+                    COLOR,
+                );
+            } else {
+                renderer.draw_line(
+                    &Point { x, y },
+                    &Point {
+                        x: x - RAIN_SHEAR as i16 * 2,
+                        y: y - RAIN_SPEED as i16,
+                    },
+                    COLOR,
+                );
+            }
+        }
+    }
+}
+
+struct SpeedLine {
+    y: i16,
+    length: i16,
+    offset: f32,
+}
+
+/// Streaks across the screen once the boy runs faster than `threshold`,
+/// more of them the faster he goes. The lines are laid out once from the
+/// seed and only scroll, so they don't flicker from frame to frame.
+struct SpeedLines {
+    threshold: i16,
+    lines: Vec<SpeedLine>,
+    scroll: f32,
+}
+
+impl SpeedLines {
+    /// How far a line travels before it comes round again.
+    const SPAN: f32 = (WIDTH + SPEED_LINE_MAX_LENGTH) as f32;
+
+    fn new(threshold: i16, seed: u32) -> Self {
+        let mut rng = StdRng::seed_from_u64(u64::from(seed));
+        let lines = (0..MAX_SPEED_LINES)
+            .map(|_| SpeedLine {
+                y: rng.gen_range(40..HEIGHT - 40),
+                length: rng.gen_range(60..SPEED_LINE_MAX_LENGTH),
+                offset: rng.gen_range(0.0..Self::SPAN),
+            })
+            .collect();
+        SpeedLines {
+            threshold,
+            lines,
+            scroll: 0.0,
+        }
+    }
+
+    /// How many lines show at `speed`.
+    fn count(&self, speed: i16) -> usize {
+        let over = usize::try_from(speed - self.threshold).unwrap_or(0);
+        (over * SPEED_LINES_PER_SPEED).min(MAX_SPEED_LINES)
+    }
+
+    fn update(&mut self, speed: i16) {
+        if speed > self.threshold {
+            // Kept within one span so a long run doesn't lose precision.
+            self.scroll =
+                (self.scroll + f32::from(speed) * SPEED_LINE_SCROLL).rem_euclid(Self::SPAN);
+        }
+    }
+
+    fn draw(&self, renderer: &Renderer, speed: i16) {
+        const COLOR: &str = "rgba(255, 255, 255, 0.5)";
+        for line in self.lines.iter().take(self.count(speed)) {
+            let x =
+                (line.offset - self.scroll).rem_euclid(Self::SPAN) as i16 - SPEED_LINE_MAX_LENGTH;
+            renderer.draw_line(
+                &Point { x, y: line.y },
+                &Point {
+                    x: x + line.length,
+                    y: line.y,
+                },
+                COLOR,
+            );
+        }
+    }
+}
+
+enum Phase {
+    Title(TitleMenu),
+    Settings(SettingsMenu),
+    Playing,
+    /// The run is frozen behind the pause menu.
+    Paused(PauseMenu),
+    /// The level intro plays with the player's input ignored.
+    Cutscene(Cutscene),
+    LevelComplete(LevelComplete),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub run: String,
+    pub jump: String,
+    pub slide: String,
+    /// Takes a portal while standing in it.
+    #[serde(default = "InputMap::default_enter")]
+    pub enter: String,
+}
+
+impl InputMap {
+    fn new(run: &str, jump: &str, slide: &str) -> Self {
+        InputMap {
+            run: run.to_string(),
+            jump: jump.to_string(),
+            slide: slide.to_string(),
+            enter: InputMap::default_enter(),
+        }
+    }
+
+    fn default_enter() -> String {
+        "ArrowUp".to_string()
+    }
+
+    fn for_player(index: usize) -> Self {
+        match index {
+            0 => InputMap::new("ArrowRight", "Space", "ArrowDown"),
+            _ => InputMap {
+                enter: "KeyE".to_string(),
+                ..InputMap::new("KeyD", "KeyW", "KeyS")
+            },
+        }
+    }
+}
+
+/// What a stopped boy does on his footing this update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Teeter {
+    Steady,
+    Started,
+    /// Rocking by this many pixels.
+    Wobbling(i16),
+    /// Going over the edge, moved this far along x to clear it.
+    TipOff(i16),
+}
+
+/// Counts `timer` down while `feet` rest on too little of `found` and
+/// resets it once they don't. `found` is `None` for a boy standing on no
+/// platform or not standing still.
+fn teeter(timer: &mut Option<Timer>, feet: &Rect, found: Option<Footing>) -> Teeter {
+    let Some(found) = found.filter(|found| found.covered < EDGE_FOOTING) else {
+        *timer = None;
+        return Teeter::Steady;
+    };
+    let Some(teeter) = timer else {
+        *timer = Some(Timer::new(TEETER_TICKS));
+        return Teeter::Started;
+    };
+    teeter.tick();
+    if !teeter.is_finished() {
+        return Teeter::Wobbling(if teeter.remaining() % 4 < 2 { 1 } else { -1 });
+    }
+
+    *timer = None;
+    // Over whichever edge more of him already hangs past.
+    Teeter::TipOff(if feet.center().x > (found.left + found.right) / 2 {
+        found.right - feet.x()
+    } else {
+        found.left - feet.right()
+    })
+}
+
+/// What the assist button does next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AssistAction {
+    Jump,
+    Slide,
+}
+
+/// Picks the assist action for a boy in `boy`: the nearest obstacle level
+/// with him and no further ahead than `reach` decides, and with none there
+/// he jumps.
+fn assist_action(boy: &Rect, obstacles: &[(AssistAction, Rect)], reach: i16) -> AssistAction {
+    obstacles
+        .iter()
+        .filter(|(_, rect)| rect.y() < boy.bottom() && rect.bottom() > boy.y())
+        .filter(|(_, rect)| rect.right() > boy.x() && rect.x() <= boy.right() + reach)
+        .min_by_key(|(_, rect)| rect.x())
+        .map_or(AssistAction::Jump, |(action, _)| *action)
+}
+
+struct Player {
+    boy: RedHatBoy,
+    input: InputMap,
+    near_miss_pending: bool,
+    /// Where the boy stood at the start of the step, so events fire on
+    /// the step things change.
+    was_knocked_out: bool,
+    was_airborne: bool,
+    invincible: bool,
+    auto_run: bool,
+    /// The box of a hit godmode swallowed this frame.
+    spared: Option<Rect>,
+    knocked_out_by: Option<KnockOutCause>,
+    combo_press: Option<f64>,
+    portal_cooldown: Cooldown,
+    stamina: Option<Stamina>,
+    /// Counts down while he stands teetering on a platform edge.
+    teeter: Option<Timer>,
+}
+
+impl Player {
+    fn new(
+        boy: RedHatBoy,
+        input: InputMap,
+        invincible: bool,
+        stamina: Option<StaminaConfig>,
+    ) -> Self {
+        Player {
+            boy,
+            input,
+            near_miss_pending: false,
+            was_knocked_out: false,
+            was_airborne: false,
+            invincible,
+            auto_run: false,
+            spared: None,
+            knocked_out_by: None,
+            combo_press: None,
+            portal_cooldown: Cooldown::new(PORTAL_COOLDOWN),
+            stamina: stamina.map(Stamina::new),
+            teeter: None,
+        }
+    }
+
+    fn can_jump(&self) -> bool {
+        self.stamina.is_none_or(|stamina| stamina.can_jump())
+    }
+
+    /// Charges stamina when `jump` actually took the boy off the ground.
+    fn try_jump(&mut self, jump: impl FnOnce(&mut RedHatBoy)) {
+        if !self.can_jump() {
+            return;
+        }
+        let was_jumping = self.boy.jumping();
+        jump(&mut self.boy);
+        if !was_jumping && self.boy.jumping() {
+            if let Some(stamina) = &mut self.stamina {
+                stamina.spend_jump();
+            }
+        }
+    }
+
+    fn knock_out(&mut self, cause: KnockOutCause) {
+        if !self.invincible {
+            if !self.boy.knocked_out() {
+                self.knocked_out_by = Some(cause);
+            }
+            self.boy.knock_out();
+        } else if !self.boy.knocked_out() {
+            self.spared = Some(self.boy.bounding_box());
+        }
+    }
+
+    /// Which way this player's keys point: the enter key up, slide down
+    /// and run right. Nothing runs left.
+    fn heading(&self, keystate: &KeyState) -> Option<Heading> {
+        keystate.heading(&self.input.enter, &self.input.slide, "", &self.input.run)
+    }
+
+    /// `assist` is what the jump key does in assist mode, where it is the
+    /// only key that counts.
+    fn handle_input(&mut self, keystate: &KeyState, assist: Option<AssistAction>) {
+        if let Some(stamina) = &mut self.stamina {
+            stamina.tick();
+        }
+        if let Some(action) = assist {
+            if keystate.is_pressed(&self.input.jump) {
+                self.boy.run_right();
+                match action {
+                    AssistAction::Jump => {
+                        self.boy.thrust();
+                        self.try_jump(RedHatBoy::jump);
+                    }
+                    AssistAction::Slide => self.boy.slide(),
+                }
+            }
+            return;
+        }
+        if self.auto_run {
+            // Jump or slide gets him going; from then on he never stops.
+            if keystate.is_pressed(&self.input.jump) || keystate.is_pressed(&self.input.slide) {
+                self.boy.run_right();
+            }
+        } else if self.heading(keystate).and_then(Direction::along) == Some(Direction::Right) {
+            self.boy.run_right();
+        }
+
+        let super_jump = [
+            self.input.slide.as_str(),
+            self.input.run.as_str(),
+            self.input.jump.as_str(),
+        ];
+        if keystate.matches_sequence(&super_jump, SUPER_JUMP_WINDOW)
+            && keystate.last_press_time() != self.combo_press
+        {
+            self.combo_press = keystate.last_press_time();
+            self.try_jump(RedHatBoy::super_jump);
+        }
+
+        if keystate.is_pressed(&self.input.jump) {
+            self.boy.thrust();
+            self.try_jump(RedHatBoy::jump);
+        }
+
+        if keystate.is_pressed(&self.input.slide) {
+            self.boy.slide();
+        }
+    }
+
+    fn check_platform(&mut self, platform: &Platform) {
+        for bounding_box in &platform.bounding_boxes() {
+            if self.boy.bounding_box().intersects(bounding_box) {
+                if self.boy.velocity_y() > 0 && self.boy.pos_y() < platform.position.y {
+                    self.boy.land_on(bounding_box.position.y, platform.surface);
+                } else if !self.grab_ledge(bounding_box) && !self.cling_to(bounding_box) {
+                    self.knock_out(KnockOutCause::Platform);
+                }
+            }
+        }
+    }
+
+    /// Stopped with less than `EDGE_FOOTING` of his feet on a platform, the
+    /// boy teeters for `TEETER_TICKS` and then tips off its edge, unless he
+    /// sets off or something moves him back on first. Returns true on the
+    /// update the teetering starts.
+    fn check_footing(&mut self, platforms: &[Platform]) -> bool {
+        let feet = self.boy.footing();
+        let found = collision::footing(&feet, platforms.iter().flat_map(Platform::bounding_boxes));
+        match teeter(&mut self.teeter, &feet, found.filter(|_| self.boy.idle())) {
+            Teeter::Steady => {
+                self.boy.jitter = 0;
+                false
+            }
+            Teeter::Started => {
+                self.boy.jitter = 1;
+                true
+            }
+            Teeter::Wobbling(jitter) => {
+                self.boy.jitter = jitter;
+                false
+            }
+            Teeter::TipOff(dx) => {
+                self.boy.jitter = 0;
+                self.boy.tip_off(dx);
+                false
+            }
+        }
+    }
+
+    /// Keeps the boy's feet on any slope under him, snapping up as he runs
+    /// uphill and down as he runs off a crest.
+    fn check_slopes(&mut self, slopes: &[Segment]) {
+        if self.boy.knocked_out() || self.boy.velocity_y() < 0 {
+            return;
+        }
+        if let Some(top) = collision::slope_top(slopes, self.boy.feet(), SLOPE_SNAP) {
+            self.boy.land_on(top, Surface::Ground);
+        }
+    }
+
+    /// Running into the side of a raised ground section is like hitting a
+    /// platform: he clings to it mid-air and is knocked out otherwise.
+    fn check_ground(&mut self, ground: &[GroundSection]) {
+        let feet = self.boy.feet();
+        for section in ground {
+            if feet.y > section.y + SLOPE_SNAP
+                && self.boy.bounding_box().intersects(&section.rect())
+                && !self.grab_ledge(&section.rect())
+                && !self.cling_to(&section.rect())
+            {
+                self.knock_out(KnockOutCause::Platform);
+            }
+        }
+    }
+
+    /// Hitting the side of `ledge` with his feet just below its top pulls
+    /// him up onto it. Returns whether he is now climbing.
+    fn grab_ledge(&mut self, ledge: &Rect) -> bool {
+        let side = if self.boy.bounding_box().center().x < ledge.center().x {
+            Direction::Right
+        } else {
+            Direction::Left
+        };
+        self.boy.grab_ledge(side, ledge.y());
+        self.boy.climbing()
+    }
+
+    /// Hitting the side of `wall` mid-air while moving into it starts a
+    /// wall slide. Returns whether the boy is now clinging.
+    fn cling_to(&mut self, wall: &Rect) -> bool {
+        if let Some(side) = self.boy.wall_side(wall) {
+            self.boy.touch_wall(side);
+        }
+        self.boy.wall_sliding()
+    }
+
+    /// Moves the boy to the exit of the first portal he is standing in while
+    /// holding the enter key, returning that portal. A cooldown stops him
+    /// from being sent on again straight away.
+    fn take_portal<'a>(
+        &mut self,
+        keystate: &KeyState,
+        portals: &'a [Portal],
+    ) -> Option<&'a Portal> {
+        self.portal_cooldown.tick();
+        if !self.portal_cooldown.is_ready()
+            || self.boy.knocked_out()
+            || !keystate.is_pressed(&self.input.enter)
+        {
+            return None;
+        }
+
+        let bounding_box = self.boy.bounding_box();
+        let portal = portals
+            .iter()
+            .find(|portal| bounding_box.intersects(&portal.entrance))?;
+        self.boy.teleport(portal.exit);
+        self.portal_cooldown.try_use();
+        Some(portal)
+    }
+
+    /// Sliding into a crate or coming down on top of it breaks it. Running
+    /// into it otherwise pushes the boy back, so it stops him without
+    /// ending the run.
+    fn check_crate(&mut self, crate_box: &Rect) -> CrateHit {
+        if self.boy.knocked_out() || !self.boy.bounding_box().intersects(crate_box) {
+            return CrateHit::Clear;
+        }
+
+        let stomped = self.boy.velocity_y() > 0 && self.boy.pos_y() < crate_box.y();
+        if stomped || self.boy.sliding() {
+            CrateHit::Broken
+        } else if self.cling_to(crate_box) {
+            CrateHit::Blocked
+        } else {
+            self.boy.apply_external_force(CRATE_PUSHBACK);
+            CrateHit::Blocked
+        }
+    }
+
+    /// Returns true when the player has just cleared a near miss.
+    fn check_hazards(&mut self, hazards: &[Rect]) -> bool {
+        let proximity = hazards
+            .iter()
+            .map(|hazard| self.boy.proximity_to(hazard))
+            .min()
+            .unwrap_or(Proximity::Clear);
+        match proximity {
+            Proximity::Hit => {
+                self.near_miss_pending = false;
+                self.knock_out(KnockOutCause::Hazard);
+            }
+            Proximity::NearMiss => self.near_miss_pending = true,
+            Proximity::Clear => {
+                if self.near_miss_pending {
+                    self.near_miss_pending = false;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn reset(&mut self, physics: PhysicsConfig) {
+        self.boy.reset(physics);
+        self.teeter = None;
+        self.near_miss_pending = false;
+        self.knocked_out_by = None;
+        self.portal_cooldown.clear();
+        if let Some(stamina) = &mut self.stamina {
+            stamina.refill();
+        }
+    }
+
+    fn draw_stamina(&self, renderer: &Renderer) {
+        if let Some(stamina) = &self.stamina {
+            let bounding_box = self.boy.bounding_box();
+            let color = if stamina.can_jump() { "#3c3" } else { "#c33" };
+            renderer.draw_progress_bar(
+                &Rect::new_from_x_y(bounding_box.x(), bounding_box.y() - 10, 50, 4),
+                stamina.fraction(),
+                color,
+            );
+        }
+    }
+}
+
+/// Something that happened during an update, for sound, effects and stats
+/// to react to once the update is done.
+#[derive(Clone, Debug)]
+enum GameEvent {
+    StartedJump,
+    /// Where the boy's feet came down.
+    Landed(Point),
+    KnockedOut(KnockOutCause),
+    CoinCollected,
+    /// A boy entered the level trigger with this id.
+    Triggered(String),
+    /// A boy entered a checkpoint trigger.
+    CheckpointReached,
+    /// A stopped boy started teetering on a platform edge.
+    Teetering,
+}
+
+/// What knocked a boy out, for metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KnockOutCause {
+    Hazard,
+    Platform,
+    Projectile,
+    Pendulum,
+    /// Pushed back past the left edge of the screen.
+    SweptAway,
+    /// Fell through a gap in the ground.
+    Pit,
+}
+
+impl KnockOutCause {
+    fn label(self) -> &'static str {
+        match self {
+            KnockOutCause::Hazard => "hazard",
+            KnockOutCause::Platform => "platform",
+            KnockOutCause::Projectile => "projectile",
+            KnockOutCause::Pendulum => "pendulum",
+            KnockOutCause::SweptAway => "swept_away",
+            KnockOutCause::Pit => "pit",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CrateHit {
+    Clear,
+    Blocked,
+    Broken,
+}
+
+struct Toast {
+    text: String,
+    timer: Timer,
+    top: Tween<f32>,
+}
+
+impl Toast {
+    fn slot_top(slot: usize) -> f32 {
+        f32::from(TOAST_TOP + slot as i16 * (TOAST_HEIGHT + 6))
+    }
+
+    /// Fades in over the first few ticks and out over the last few.
+    fn alpha(&self) -> f32 {
+        let shown = self.timer.duration() - self.timer.remaining();
+        let fade = TOAST_FADE_TICKS as f32;
+        (shown as f32 / fade)
+            .min(self.timer.remaining() as f32 / fade)
+            .min(1.0)
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        let top = self.top.value() as i16;
+        renderer.draw_with_alpha(self.alpha(), |renderer| {
+            renderer.fill_rect(
+                &Rect::new_from_x_y(WIDTH / 2 - TOAST_WIDTH / 2, top, TOAST_WIDTH, TOAST_HEIGHT),
+                "rgba(255, 255, 255, 0.85)",
+            );
+            renderer.draw_text(
+                &self.text,
+                &Point {
+                    x: WIDTH / 2 - TOAST_WIDTH / 2 + 12,
+                    y: top + 20,
+                },
+            )
+        })
+    }
+}
+
+/// Short HUD messages shown at the top center. At most two show at once;
+/// the rest wait their turn. Toasts only count down in `update`, so they
+/// hold still while the game is paused.
+#[derive(Default)]
+struct ToastQueue {
+    visible: VecDeque<Toast>,
+    waiting: VecDeque<(String, u32)>,
+}
+
+impl ToastQueue {
+    fn push(&mut self, text: impl Into<String>, duration_frames: u32) {
+        self.waiting.push_back((text.into(), duration_frames));
+    }
+
+    fn update(&mut self) {
+        for toast in self.visible.iter_mut() {
+            toast.timer.tick();
+            toast.top.tick();
+        }
+        let before = self.visible.len();
+        self.visible.retain(|toast| toast.timer.is_active());
+        if self.visible.len() != before {
+            for (slot, toast) in self.visible.iter_mut().enumerate() {
+                toast.top.retarget(Toast::slot_top(slot));
+            }
+        }
+
+        while self.visible.len() < TOAST_SLOTS {
+            let Some((text, duration)) = self.waiting.pop_front() else {
+                break;
+            };
+            let top = Toast::slot_top(self.visible.len());
+            self.visible.push_back(Toast {
+                text,
+                timer: Timer::new(duration.max(TOAST_FADE_TICKS * 2)),
+                top: Tween::new(
+                    top - TOAST_SLIDE,
+                    top,
+                    TOAST_FADE_TICKS,
+                    tween::ease_out_quad,
+                ),
+            });
+        }
+    }
+
+    fn clear(&mut self) {
+        self.visible.clear();
+        self.waiting.clear();
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        for toast in &self.visible {
+            toast.draw(renderer)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Debris {
+    position: Point,
+    velocity: Point,
+    frames: u8,
+    color: &'static str,
+}
+
+impl Debris {
+    fn update(&mut self) {
+        self.velocity.y += COIN_GRAVITY;
+        self.position.x += self.velocity.x;
+        self.position.y += self.velocity.y;
+        self.frames = self.frames.saturating_sub(1);
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        renderer.fill_rect(&Rect::new(self.position, 6, 4), self.color);
+    }
+}
+
+/// Debris in at most `MAX_DEBRIS` slots. Dead pieces go back to the pool
+/// and new ones are taken from it, so once the slots have filled a burst
+/// builds nothing; a burst past the cap is cut short instead.
+struct DebrisField {
+    pieces: Vec<Debris>,
+    pool: Pool<Debris>,
+}
+
+impl DebrisField {
+    fn new() -> Self {
+        DebrisField {
+            pieces: Vec::with_capacity(MAX_DEBRIS),
+            pool: Pool::new(|piece| piece.frames = 0),
+        }
+    }
+
+    fn spawn(&mut self, position: Point, velocity: Point, frames: u8, color: &'static str) {
+        if self.pieces.len() == MAX_DEBRIS {
+            return;
+        }
+        let mut piece = self.pool.acquire(Debris::default);
+        piece.position = position;
+        piece.velocity = velocity;
+        piece.frames = frames;
+        piece.color = color;
+        self.pieces.push(piece);
+    }
+
+    fn update(&mut self) {
+        let mut index = 0;
+        while index < self.pieces.len() {
+            self.pieces[index].update();
+            if self.pieces[index].frames == 0 {
+                let piece = self.pieces.swap_remove(index);
+                self.pool.release(piece);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for piece in self.pieces.drain(..) {
+            self.pool.release(piece);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Debris> {
+        self.pieces.iter()
+    }
+}
+
+/// How many pooled objects the last full minute of play built, next to how
+/// many it asked for, which is what it would have built without pooling.
+#[derive(Default)]
+struct AllocationRate {
+    frames: u32,
+    /// Built and asked-for totals when the current minute started.
+    start: (u32, u32),
+    last_minute: Option<(u32, u32)>,
+}
+
+impl AllocationRate {
+    fn update(&mut self, built: u32, acquired: u32) {
+        self.frames += 1;
+        if self.frames.is_multiple_of(FRAMES_PER_MINUTE) {
+            self.last_minute = Some((built - self.start.0, acquired - self.start.1));
+            self.start = (built, acquired);
+        }
+    }
+}
+
+/// Adapts the challenge to how the player is doing. Short recent runs ease
+/// the running speed off, long ones push it up; the level moves one step
+/// per run and stays within `0.0..=1.0`, with `0.5` meaning the base speed.
+struct DifficultyController {
+    level: f32,
+    recent_runs: VecDeque<i32>,
+}
+
+impl DifficultyController {
+    fn new() -> Self {
+        DifficultyController {
+            level: 0.5,
+            recent_runs: VecDeque::with_capacity(DIFFICULTY_HISTORY),
+        }
+    }
+
+    fn level(&self) -> f32 {
+        self.level
+    }
+
+    fn record_run(&mut self, meters: i32) {
+        if self.recent_runs.len() == DIFFICULTY_HISTORY {
+            self.recent_runs.pop_front();
+        }
+        self.recent_runs.push_back(meters);
+
+        let average = self.recent_runs.iter().sum::<i32>() / self.recent_runs.len() as i32;
+        if average < STRUGGLING_METERS {
+            self.level -= DIFFICULTY_STEP;
+        } else if average > THRIVING_METERS {
+            self.level += DIFFICULTY_STEP;
+        }
+        self.level = self.level.clamp(0.0, 1.0);
+    }
+
+    fn physics(&self, base: PhysicsConfig) -> PhysicsConfig {
+        let adjustment = ((self.level - 0.5) * 2.0 * DIFFICULTY_SPEED_RANGE).round() as i16;
+        let mut physics = base;
+        physics.running_speed = (base.running_speed + adjustment).max(1);
+        physics
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct BoySave {
+    state: StateKind,
+    frame: u8,
+    position: Point,
+    velocity: Point,
+    #[serde(default)]
+    facing: Direction,
+}
+
+/// A run in progress, kept in localStorage so a reload can pick it up.
+/// Saves written by a different crate version are discarded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RunSave {
+    version: String,
+    stats: RunStats,
+    boys: Vec<BoySave>,
+}
+
+impl RunSave {
+    fn load() -> Option<RunSave> {
+        let json = match browser::storage_get(RUN_SAVE_KEY) {
+            Ok(json) => json?,
+            Err(err) => {
+                log!("{:#?}", err);
+                return None;
+            }
+        };
+
+        match serde_json::from_str::<RunSave>(&json) {
+            Ok(save) if save.version == env!("CARGO_PKG_VERSION") => Some(save),
+            _ => {
+                log!("Discarding incompatible run save");
+                RunSave::clear();
+                None
+            }
+        }
+    }
+
+    fn store(&self) {
+        let result = serde_json::to_string(self)
+            .map_err(|err| anyhow!("Could not serialize run save {:#?}", err))
+            .and_then(|json| browser::storage_set(RUN_SAVE_KEY, &json));
+        if let Err(err) = result {
+            log!("{:#?}", err);
+        }
+    }
+
+    fn clear() {
+        if let Err(err) = browser::storage_remove(RUN_SAVE_KEY) {
+            log!("{:#?}", err);
+        }
+    }
+}
+
+/// The run as it was at the last checkpoint. Knocked out boys come back
+/// here with the pickups and triggers as they were; the score carries on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WalkSnapshot {
+    boys: Vec<BoySave>,
+    collected_coins: Vec<bool>,
+    magnet_pickup: Option<Point>,
+    jetpack_pickup: Option<Point>,
+    spent_triggers: Vec<bool>,
+}
+
+/// The last checkpoint reached and the countdown back to it once every
+/// boy is out.
+#[derive(Default)]
+struct Checkpoints {
+    last: Option<WalkSnapshot>,
+    respawn: Option<Timer>,
+}
+
+impl Checkpoints {
+    fn reach(&mut self, snapshot: WalkSnapshot) {
+        self.last = Some(snapshot);
+    }
+
+    /// Every boy out ends the run only if there is no checkpoint to bring
+    /// them back to.
+    fn game_over(&self, all_knocked_out: bool) -> bool {
+        all_knocked_out && self.last.is_none()
+    }
+
+    /// Counts down while every boy is out past a checkpoint, returning the
+    /// checkpoint to restore once the countdown runs out.
+    fn update(&mut self, all_knocked_out: bool) -> Option<WalkSnapshot> {
+        let last = self.last.as_ref().filter(|_| all_knocked_out)?;
+        let respawn = self
+            .respawn
+            .get_or_insert_with(|| Timer::new(RESPAWN_TICKS));
+        respawn.tick();
+        if !respawn.is_finished() {
+            return None;
+        }
+        self.respawn = None;
+        Some(last.clone())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BoyDump {
+    state: StateKind,
+    position: Point,
+    velocity: Point,
+    facing: Direction,
+    frame: u8,
+    bounding_box: Rect,
+    hazard_distance: f32,
+}
+
+#[derive(Serialize)]
+struct ObstacleDump {
+    kind: &'static str,
+    rect: Rect,
+}
+
+#[derive(Serialize)]
+struct StateDump<'a> {
+    boys: Vec<BoyDump>,
+    obstacles: Vec<ObstacleDump>,
+    summary: RunSummary,
+    level: &'a Level,
+}
+
+/// One fixed-step stage of a running level.
+type System = fn(&mut Walk, &KeyState);
+
+/// The stages `WalkTheDog::update` runs each step, in order. Collision sees
+/// where physics moved everything, and effects and scoring see every event
+/// collision raised.
+const SYSTEMS: [System; 5] = [
+    Walk::update_input,
+    Walk::update_physics,
+    Walk::update_collisions,
+    Walk::update_effects,
+    Walk::update_scoring,
+];
+
+/// Everything the HUD draws, compared to tell when it needs redrawing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct HudState {
+    shown: bool,
+    godmode: bool,
+    resume_meters: Option<i32>,
+    game_over: bool,
+    swept_away: bool,
+    /// The assist best, once a run that used assist mode is over.
+    assist_best: Option<u32>,
+    score: u32,
+    /// The magnet's time left and the jetpack's fuel, in percent.
+    magnet: Option<u8>,
+    jetpack: Option<u8>,
+}
+
+/// A bar's fill in whole percent, so the HUD only redraws when the bar
+/// visibly moves.
+fn bar_percent(fraction: f32) -> u8 {
+    (fraction.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+pub struct Walk {
+    players: Vec<Player>,
+    background: Image,
+    /// The background outside biomes, put back on reset.
+    base_background: HtmlImageElement,
+    biomes: Biomes,
+    /// The band an endless run is drawn in, once its background arrived.
+    biome: Option<usize>,
+    biome_images: HashMap<String, BiomeImage>,
+    biome_fade: Option<BiomeFade>,
+    stone_image: HtmlImageElement,
+    tiles: (Sheet, HtmlImageElement),
+    stones: Vec<Image>,
+    crate_image: HtmlImageElement,
+    dog_image: HtmlImageElement,
+    crates: Vec<Image>,
+    debris: DebrisField,
+    boss_images: (HtmlImageElement, HtmlImageElement),
+    boss: Option<Boss>,
+    projectiles: Vec<Projectile>,
+    portal_flash: Timer,
+    triggers: Vec<ArmedTrigger>,
+    toasts: ToastQueue,
+    best_score: u32,
+    /// Best score of runs that used assist mode.
+    assist_best_score: u32,
+    best_announced: bool,
+    godmode: bool,
+    godmode_key: KeyLatch,
+    time_scale: f32,
+    /// Where the free inspect camera looks; `None` follows the boy.
+    camera: Option<Point>,
+    inspect_key: KeyLatch,
+    /// Hits godmode swallowed, highlighted for a few frames.
+    spared_hits: Vec<(Rect, u32)>,
+    /// What the HUD canvas last showed.
+    hud_drawn: Option<HudState>,
+    audio: Audio,
+    jump_sound: Sound,
+    muted: bool,
+    level_index: u32,
+    /// The file the current level was loaded from; `None` for the built-in
+    /// endless course.
+    level_path: Option<String>,
+    platforms: Vec<Platform>,
+    /// Platforms and stones cleared from a layout, reused by the next one.
+    platform_pool: Pool<Platform>,
+    stone_pool: Pool<Image>,
+    allocation_rate: AllocationRate,
+    callbacks: SharedCallbacks,
+    stats: RunStats,
+    reported_score: u32,
+    events: EventBus<GameEvent>,
+    dump_key: KeyLatch,
+    screenshot_key: KeyLatch,
+    screenshot_requested: bool,
+    base_physics: PhysicsConfig,
+    difficulty: DifficultyController,
+    pending_resume: Option<RunSave>,
+    resume_key: KeyLatch,
+    latest_save: Rc<RefCell<Option<RunSave>>>,
+    /// Writes `latest_save` when the page unloads.
+    _on_unload: browser::EventListenerGuard,
+    metrics: Metrics,
+    strings: Strings,
+    level: Level,
+    coins: Vec<Coin>,
+    magnet_pickup: Option<Point>,
+    magnet: Timer,
+    jetpack_pickup: Option<Point>,
+    config: GameConfig,
+    phase: Phase,
+    day_cycle: DayCycle,
+    title_key: KeyLatch,
+    pause_key: KeyLatch,
+    settings: Settings,
+    rain: Rain,
+    speed_lines: SpeedLines,
+    checkpoints: Checkpoints,
+    guarded_keys: GuardedKeys,
+}
+
+/// The players' keys followed by the menu keys, kept so the game loop can
+/// ask for them every frame without building the list again.
+#[derive(Default)]
+struct GuardedKeys {
+    keys: Vec<String>,
+    playing: usize,
+}
+
+impl GuardedKeys {
+    fn new(players: &[Player]) -> Self {
+        let mut keys: Vec<String> = players
+            .iter()
+            .flat_map(|player| {
+                [
+                    player.input.run.clone(),
+                    player.input.jump.clone(),
+                    player.input.slide.clone(),
+                    player.input.enter.clone(),
+                ]
+            })
+            .collect();
+        let playing = keys.len();
+        keys.extend(
+            [MENU_UP_KEY, MENU_DOWN_KEY, MENU_LEFT_KEY, MENU_RIGHT_KEY]
+                .iter()
+                .map(|key| key.to_string()),
+        );
+        GuardedKeys { keys, playing }
+    }
+
+    /// The keys in play, including the menu keys when `menus` are open.
+    fn get(&self, menus: bool) -> &[String] {
+        if menus {
+            &self.keys
+        } else {
+            &self.keys[..self.playing]
+        }
+    }
+}
+
+/// Everything a run is built from, once fetched and parsed.
+struct WalkAssets {
+    sheet: Sheet,
+    rhb_image: HtmlImageElement,
+    background: HtmlImageElement,
+    stone: HtmlImageElement,
+    tiles: (Sheet, HtmlImageElement),
+    crate_image: HtmlImageElement,
+    dog_image: HtmlImageElement,
+    boss_images: (HtmlImageElement, HtmlImageElement),
+    audio: Audio,
+    jump_sound: Sound,
+    strings: Strings,
+    biomes: Biomes,
+    level: Level,
+}
+
+impl Walk {
+    /// Builds a run from its loaded assets, starting on the title menu
+    /// unless a level was asked for or a saved run waits to be resumed.
+    fn new(assets: WalkAssets, config: &GameConfig, callbacks: &SharedCallbacks) -> Result<Walk> {
+        let WalkAssets {
+            sheet,
+            rhb_image,
+            background,
+            stone,
+            tiles,
+            crate_image,
+            dog_image,
+            boss_images,
+            audio,
+            jump_sound,
+            strings,
+            biomes,
+            level,
+        } = assets;
+
+        let players = (0..config.players.max(1))
+            .map(|index| {
+                Player::new(
+                    RedHatBoy::new(
+                        sheet.clone(),
+                        rhb_image.clone(),
+                        PLAYER_SPACING * index as i16,
+                        config.physics,
+                    ),
+                    InputMap::for_player(index),
+                    config.godmode,
+                    config.stamina,
+                )
+            })
+            .collect();
+
+        let pending_resume = RunSave::load();
+        let latest_save: Rc<RefCell<Option<RunSave>>> = Rc::new(RefCell::new(None));
+        let unload_save = Rc::clone(&latest_save);
+        let on_unload = browser::closure_wrap(Box::new(move || {
+            if let Some(save) = unload_save.borrow().as_ref() {
+                save.store();
+            }
+        }) as Box<dyn FnMut()>);
+        let on_unload = browser::add_window_listener("beforeunload", on_unload)?;
+
+        callbacks.borrow().started();
+        let metrics = Metrics::new(config.metrics_url.clone())?;
+        metrics.run_started();
+
+        let mut walk = Walk {
+            players,
+            background: Image::new(background.clone(), Point { x: 0, y: 0 }),
+            base_background: background,
+            biomes,
+            biome: None,
+            biome_images: HashMap::new(),
+            biome_fade: None,
+            stone_image: stone,
+            tiles,
+            stones: Vec::new(),
+            crate_image,
+            dog_image,
+            crates: Vec::new(),
+            debris: DebrisField::new(),
+            boss_images,
+            boss: None,
+            projectiles: Vec::new(),
+            portal_flash: Timer::default(),
+            triggers: Vec::new(),
+            toasts: ToastQueue::default(),
+            best_score: 0,
+            assist_best_score: 0,
+            godmode: config.godmode,
+            godmode_key: KeyLatch::default(),
+            time_scale: 1.0,
+            camera: None,
+            inspect_key: KeyLatch::default(),
+            spared_hits: Vec::new(),
+            hud_drawn: None,
+            best_announced: false,
+            audio,
+            jump_sound,
+            muted: false,
+            level_index: 0,
+            level_path: config.level.clone(),
+            platforms: Vec::new(),
+            // A reused platform starts out as plain ground until the layout
+            // says otherwise.
+            platform_pool: Pool::new(|platform| platform.surface = Surface::Ground),
+            // Stones keep nothing but their position, which acquiring sets.
+            stone_pool: Pool::new(|_| {}),
+            allocation_rate: AllocationRate::default(),
+            callbacks: Rc::clone(callbacks),
+            stats: RunStats {
+                seed: config.seed,
+                godmode: config.godmode,
+                daily: config.daily,
+                ..RunStats::default()
+            },
+            reported_score: 0,
+            events: EventBus::with_cap(EVENT_CAP),
+            dump_key: KeyLatch::default(),
+            screenshot_key: KeyLatch::default(),
+            screenshot_requested: false,
+            base_physics: config.physics,
+            difficulty: DifficultyController::new(),
+            phase: if config.level.is_none() && pending_resume.is_none() {
+                Phase::Title(TitleMenu::new(0))
+            } else {
+                Phase::Playing
+            },
+            title_key: KeyLatch::default(),
+            pause_key: KeyLatch::default(),
+            settings: Settings::load(),
+            day_cycle: DayCycle {
+                length: config.day_length,
+            },
+            config: config.clone(),
+            pending_resume,
+            resume_key: KeyLatch::default(),
+            latest_save,
+            _on_unload: on_unload,
+            metrics,
+            strings,
+            coins: level.coins.iter().copied().map(Coin::new).collect(),
+            magnet_pickup: level.magnet,
+            magnet: Timer::default(),
+            jetpack_pickup: level.jetpack,
+            rain: Rain::new(level.rain, config.seed),
+            speed_lines: SpeedLines::new(config.speed_lines_above, config.seed),
+            level,
+            checkpoints: Checkpoints::default(),
+            guarded_keys: GuardedKeys::default(),
+        };
+        walk.spawn_obstacles();
+        walk.apply_settings();
+        if matches!(walk.phase, Phase::Playing) && walk.pending_resume.is_none() {
+            walk.start_intro();
+        }
+        Ok(walk)
+    }
+
+    fn debug_state(&self) -> StateDump<'_> {
+        let mut obstacles: Vec<ObstacleDump> = self
+            .hazards()
+            .into_iter()
+            .map(|rect| ObstacleDump {
+                kind: "stone",
+                rect,
+            })
+            .collect();
+        obstacles.extend(self.crates.iter().map(|crate_image| ObstacleDump {
+            kind: "crate",
+            rect: *crate_image.bounding_box(),
+        }));
+        obstacles.extend(
+            self.coins
+                .iter()
+                .filter(|coin| !coin.collected)
+                .map(|coin| ObstacleDump {
+                    kind: "coin",
+                    rect: coin.bounding_box(),
+                }),
+        );
+        obstacles.extend(
+            self.platforms
+                .iter()
+                .flat_map(|platform| platform.bounding_boxes())
+                .map(|rect| ObstacleDump {
+                    kind: "platform",
+                    rect,
+                }),
+        );
+
+        StateDump {
+            boys: self
+                .players
+                .iter()
+                .map(|player| player.boy.debug_state(&self.hazards()))
+                .collect(),
+            obstacles,
+            summary: self.stats.summary(),
+            level: &self.level,
+        }
+    }
+
+    fn dump_state(&self) {
+        let json = serde_wasm_bindgen::to_value(&self.debug_state())
+            .map_err(|err| anyhow!("Could not serialize game state {:#?}", err))
+            .and_then(|value| {
+                js_sys::JSON::stringify_with_replacer_and_space(
+                    &value,
+                    &JsValue::NULL,
+                    &JsValue::from(2),
+                )
+                .map_err(|err| anyhow!("Could not stringify game state {:#?}", err))
+            });
+
+        match json {
+            Ok(json) => {
+                let json = String::from(json);
+                log!("{}", json);
+                browser::spawn_local(async move {
+                    if let Err(err) = browser::copy_to_clipboard(&json).await {
+                        log!("Could not copy game state to clipboard {:#?}", err);
+                    }
+                });
+            }
+            Err(err) => {
+                log!("{:#?}", err);
+            }
+        }
+    }
+
+    /// Runs the title, settings and pause menus while one is open. Returns
+    /// false once gameplay should update instead.
+    fn update_menus(&mut self, keystate: &KeyState) -> bool {
+        let taps = keystate.last_tap().map_or(0, |(taps, _)| taps);
+        let menu = match &mut self.phase {
+            Phase::Title(menu) => menu,
+            Phase::Settings(menu) => {
+                match menu.update(keystate, &self.strings) {
+                    SettingsAction::None => {}
+                    SettingsAction::Changed => {
+                        self.settings = menu.settings.clone();
+                        self.settings.store();
+                        self.apply_settings();
+                    }
+                    SettingsAction::Back => self.phase = Phase::Title(TitleMenu::new(taps)),
+                }
+                return true;
+            }
+            Phase::Playing => {
+                if self.is_game_over() && self.title_key.pressed(keystate.is_pressed(TITLE_KEY)) {
+                    self.phase = Phase::Title(TitleMenu::new(taps));
+                    return true;
+                }
+                if self.pause_key.pressed(keystate.is_pressed(MENU_BACK_KEY))
+                    && !self.is_game_over()
+                {
+                    self.phase = Phase::Paused(PauseMenu::new());
+                    self.audio.set_in_menu(true);
+                    return true;
+                }
+                return false;
+            }
+            Phase::Cutscene(cutscene) => {
+                let finished = if cutscene
+                    .skip_key
+                    .pressed(keystate.is_pressed(MENU_SELECT_KEY))
+                {
+                    cutscene.skip(&mut self.players);
+                    true
+                } else {
+                    cutscene.update(
+                        &mut self.players,
+                        &self.audio,
+                        &[("jump", &self.jump_sound)],
+                    )
+                };
+                if finished {
+                    self.phase = Phase::Playing;
+                }
+                return false;
+            }
+            Phase::Paused(menu) => {
+                let Some(choice) = menu.update(keystate) else {
+                    return true;
+                };
+                self.audio.set_in_menu(false);
+                match choice {
+                    PauseChoice::Resume => self.phase = Phase::Playing,
+                    PauseChoice::Restart => {
+                        self.phase = Phase::Playing;
+                        self.reset();
+                    }
+                    PauseChoice::Assist => {
+                        self.phase = Phase::Playing;
+                        self.settings.assist = !self.settings.assist;
+                        self.settings.store();
+                        self.apply_settings();
+                    }
+                    PauseChoice::Quit => self.phase = Phase::Title(TitleMenu::new(taps)),
+                }
+                return true;
+            }
+            Phase::LevelComplete(complete) => {
+                complete.fade.tick();
+                if let Some(load) = &mut complete.loading {
+                    load.frames += 1;
+                    let result = load.result.borrow_mut().take();
+                    match result {
+                        Some(Ok(level)) => {
+                            let config = load.config.clone();
+                            self.level_index += 1;
+                            self.start_mode(config, level);
+                        }
+                        Some(Err(err)) => {
+                            log!("{:#?}", err);
+                            complete.loading = None;
+                            complete.error =
+                                Some(self.strings.t("level_complete.load_failed").to_string());
+                        }
+                        None => {}
+                    }
+                } else if complete
+                    .continue_key
+                    .pressed(keystate.is_pressed(MENU_SELECT_KEY))
+                {
+                    match complete.next.clone() {
+                        Some(path) => {
+                            let config = GameConfig {
+                                level: Some(path.clone()),
+                                ..self.config.clone()
+                            };
+                            complete.error = None;
+                            complete.loading = Some(LevelLoad::start(config, path));
+                        }
+                        None => self.phase = Phase::Title(TitleMenu::new(taps)),
+                    }
+                }
+                return true;
+            }
+        };
+
+        if let Some(load) = &mut menu.loading {
+            load.frames += 1;
+            let result = load.result.borrow_mut().take();
+            match result {
+                Some(Ok(level)) => {
+                    let config = load.config.clone();
+                    self.start_mode(config, level);
+                }
+                Some(Err(err)) => {
+                    log!("{:#?}", err);
+                    menu.loading = None;
+                    menu.error = Some(self.strings.t("title.load_failed").to_string());
+                }
+                None => {}
+            }
+            return true;
+        }
+
+        match menu.update(keystate) {
+            Some(TitleChoice::Play(mode)) => self.choose_mode(mode),
+            Some(TitleChoice::Settings) => {
+                self.phase = Phase::Settings(SettingsMenu::new(self.settings.clone()));
+            }
+            None => {}
+        }
+        true
+    }
+
+    fn choose_mode(&mut self, mode: GameMode) {
+        let config = mode
+            .configure(WalkTheDogBuilder::from_config(self.config.clone()))
+            .into_config();
+        match config.level.clone() {
+            Some(path) => {
+                if let Phase::Title(menu) = &mut self.phase {
+                    menu.error = None;
+                    menu.loading = Some(LevelLoad::start(config, path));
+                }
+            }
+            None => self.start_mode(config, Level::default()),
+        }
+        self.level_index = 0;
+    }
+
+    /// Ends the level once a boy reaches the flagpole. While a boss is
+    /// still throwing there is no pole to reach.
+    fn check_goal(&mut self) {
+        let Some(goal) = self.goal_rect() else {
+            return;
+        };
+        let reached = self
+            .players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+            .any(|player| player.boy.bounding_box().intersects(&goal));
+        if reached {
+            RunSave::clear();
+            let summary = self.stats.summary();
+            let medal = self
+                .level
+                .medals
+                .filter(|_| self.stats.is_fair() && !self.stats.assist)
+                .and_then(|medals| medals.award(&summary));
+            if let (Some(medal), Some(path)) = (medal, &self.level_path) {
+                let mut results = LevelResults::load();
+                if results.record(path, medal) {
+                    results.store();
+                }
+            }
+            self.phase = Phase::LevelComplete(LevelComplete {
+                fade: Tween::new(
+                    Alpha(0.0),
+                    Alpha(0.85),
+                    PANEL_FADE_TICKS,
+                    tween::ease_in_out_sine,
+                ),
+                summary,
+                medal,
+                has_medals: self.level.medals.is_some(),
+                next: self.level.next.clone(),
+                continue_key: KeyLatch { held: true },
+                loading: None,
+                error: None,
+            });
+        }
+    }
+
+    fn goal_rect(&self) -> Option<Rect> {
+        if self.boss.is_some() {
+            return None;
+        }
+        self.level
+            .goal
+            .map(|top| Rect::new_from_x_y(top.x, top.y, GOAL_WIDTH, HEIGHT - top.y))
+    }
+
+    /// Every box a boy can touch and what kind of thing it belongs to.
+    fn collidables(&self) -> Vec<(DebugClass, Rect)> {
+        let mut boxes: Vec<(DebugClass, Rect)> = self
+            .hazards()
+            .into_iter()
+            .map(|rect| (DebugClass::Hazard, rect))
+            .collect();
+        boxes.extend(
+            self.platforms
+                .iter()
+                .flat_map(Platform::bounding_boxes)
+                .chain(self.crates.iter().map(|image| *image.bounding_box()))
+                .map(|rect| (DebugClass::Ground, rect)),
+        );
+        boxes.extend(
+            self.coins
+                .iter()
+                .filter(|coin| !coin.collected)
+                .map(Coin::bounding_box)
+                .chain(
+                    self.magnet_pickup
+                        .map(|pickup| Coin::new(pickup).bounding_box()),
+                )
+                .map(|rect| (DebugClass::Collectible, rect)),
+        );
+        boxes.extend(
+            self.projectiles
+                .iter()
+                .map(|projectile| circle_bounds(projectile.center(), Projectile::RADIUS))
+                .chain(self.level.pendulums.iter().map(|pendulum| {
+                    circle_bounds(
+                        pendulum.blade(self.stats.frames as f32),
+                        Pendulum::BLADE_RADIUS,
+                    )
+                }))
+                .map(|rect| (DebugClass::Hazard, rect)),
+        );
+        boxes.extend(
+            self.level
+                .portals
+                .iter()
+                .map(|portal| portal.entrance)
+                .chain(self.level.wind_zones.iter().map(|zone| zone.rect))
+                .chain(self.goal_rect())
+                .map(|rect| (DebugClass::Sensor, rect)),
+        );
+        boxes.extend(
+            self.triggers
+                .iter()
+                .map(|armed| (DebugClass::Trigger, armed.trigger.rect)),
+        );
+        boxes
+    }
+
+    /// The area covering the screen and everything in the level.
+    fn world_bounds(&self) -> Rect {
+        self.collidables()
+            .iter()
+            .map(|(_, rect)| *rect)
+            .chain(self.players.iter().map(|player| player.boy.bounding_box()))
+            .fold(Rect::new_from_x_y(0, 0, WIDTH, HEIGHT), |bounds, rect| {
+                bounds.union(&rect)
+            })
+    }
+
+    /// Draws the whole level scaled down into a corner, in debug mode.
+    fn draw_minimap(&self, renderer: &Renderer) {
+        if !renderer.debug_mode() {
+            return;
+        }
+        let map = Rect::new_from_x_y(
+            WIDTH - MINIMAP_SIZE.x - 10,
+            40,
+            MINIMAP_SIZE.x,
+            MINIMAP_SIZE.y,
+        );
+        renderer.fill_rect(&map, "rgba(255, 255, 255, 0.6)");
+        let world = self.world_bounds();
+        let players = self
+            .players
+            .iter()
+            .map(|player| (DebugClass::Player, player.boy.bounding_box()));
+        for (class, rect) in self.collidables().into_iter().chain(players) {
+            renderer.fill_debug_box(&scale_into(&world, &map, &rect), class);
+        }
+    }
+
+    fn draw_overlaps(&self, renderer: &Renderer) {
+        let boxes: Vec<Rect> = self
+            .collidables()
+            .into_iter()
+            .map(|(_, rect)| rect)
+            .collect();
+        for player in self
+            .players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+        {
+            let bounding_box = player.boy.bounding_box();
+            for overlap in boxes
+                .iter()
+                .filter_map(|other| bounding_box.intersection(other))
+            {
+                renderer.draw_debug_overlap(&overlap);
+            }
+        }
+    }
+
+    fn draw_goal(&self, renderer: &Renderer) {
+        if let Some(goal) = self.goal_rect() {
+            renderer.draw_line(
+                &Point {
+                    x: goal.x(),
+                    y: goal.y(),
+                },
+                &Point {
+                    x: goal.x(),
+                    y: goal.bottom(),
+                },
+                "#444",
+            );
+            renderer.draw_polygon(
+                &[
+                    goal.position,
+                    Point {
+                        x: goal.x() + GOAL_WIDTH,
+                        y: goal.y() + GOAL_FLAG_HEIGHT / 2,
+                    },
+                    Point {
+                        x: goal.x(),
+                        y: goal.y() + GOAL_FLAG_HEIGHT,
+                    },
+                ],
+                Some("#2e9e44"),
+                Some(("#1d6b2d", 2)),
+            );
+            renderer.draw_bounding_box(&goal, DebugClass::Sensor);
+        }
+    }
+
+    fn apply_settings(&mut self) {
+        self.audio.set_volume(if self.muted {
+            0.0
+        } else {
+            self.settings.sfx_volume
+        });
+        if let Some(player) = self.players.first_mut() {
+            player.input = self.settings.keys.clone();
+        }
+        self.guarded_keys = GuardedKeys::new(&self.players);
+        let auto_run =
+            self.config.auto_run.unwrap_or(self.settings.auto_run) || self.settings.assist;
+        let physics = self.physics();
+        for player in self.players.iter_mut() {
+            player.auto_run = auto_run;
+            player.boy.set_physics(physics);
+        }
+        self.stats.assist |= self.settings.assist;
+    }
+
+    /// The boy's physics for this run: the difficulty's, slowed down in
+    /// assist mode.
+    fn physics(&self) -> PhysicsConfig {
+        let mut physics = self.difficulty.physics(self.base_physics);
+        if self.settings.assist {
+            let keep = 1.0
+                - self
+                    .settings
+                    .assist_slowdown
+                    .clamp(0.0, ASSIST_MAX_SLOWDOWN);
+            physics.running_speed =
+                ((f32::from(physics.running_speed) * keep).round() as i16).max(1);
+        }
+        physics
+    }
+
+    fn start_mode(&mut self, config: GameConfig, level: Level) {
+        self.phase = Phase::Playing;
+        self.level = level;
+        self.level_path = config.level.clone();
+        self.stats.seed = config.seed;
+        self.config.fixed_seed = config.fixed_seed;
+        self.config.daily = config.daily;
+        self.reset();
+        self.start_intro();
+    }
+
+    fn start_intro(&mut self) {
+        if let Some(script) = self.level.intro.clone() {
+            self.phase = Phase::Cutscene(Cutscene::new(script));
+        }
+    }
+
+    fn in_cutscene(&self) -> bool {
+        matches!(self.phase, Phase::Cutscene(_))
+    }
+
+    /// What assist mode reacts to: crates, which a slide breaks, and
+    /// everything else, which he jumps.
+    fn assist_obstacles(&self) -> Vec<(AssistAction, Rect)> {
+        self.hazards()
+            .into_iter()
+            .chain(self.platforms.iter().flat_map(Platform::bounding_boxes))
+            .map(|rect| (AssistAction::Jump, rect))
+            .chain(
+                self.crates
+                    .iter()
+                    .map(|image| (AssistAction::Slide, *image.bounding_box())),
+            )
+            .collect()
+    }
+
+    fn hazards(&self) -> Vec<Rect> {
+        self.stones
+            .iter()
+            .map(|stone| *stone.bounding_box())
+            .collect()
+    }
+
+    /// The spawn table for the biome the run is in; levels use their own.
+    fn spawn_pool(&self) -> SpawnTable {
+        match self.current_biome() {
+            Some(biome) => biome.pool(&self.level.spawns),
+            None => self.level.spawns.clone(),
+        }
+    }
+
+    fn current_biome(&self) -> Option<&Biome> {
+        if self.level_path.is_some() {
+            return None;
+        }
+        self.biomes
+            .index_at(self.stats.meters())
+            .map(|index| &self.biomes.bands[index])
+    }
+
+    /// Starts fetching the background for band `index` unless it already
+    /// has been.
+    fn prefetch_biome(&mut self, index: usize) {
+        let Some(biome) = self.biomes.bands.get(index) else {
+            return;
+        };
+        if self.biome_images.contains_key(&biome.background) {
+            return;
+        }
+        let slot: BiomeImage = Rc::new(RefCell::new(None));
+        let result = Rc::clone(&slot);
+        let path = biome.background.clone();
+        browser::spawn_local(async move {
+            let image = match engine::load_image(&path).await {
+                Ok(image) => Some(image),
+                Err(err) => {
+                    log!("Could not load biome background {:#?}", err);
+                    None
+                }
+            };
+            *result.borrow_mut() = Some(image);
+        });
+        self.biome_images.insert(biome.background.clone(), slot);
+    }
+
+    /// Moves into the band the run has reached once its background is in,
+    /// fading across from the old one, and fetches the band after it.
+    fn update_biome(&mut self) {
+        if let Some(fade) = &mut self.biome_fade {
+            fade.alpha.tick();
+            if fade.alpha.is_finished() {
+                self.biome_fade = None;
+            }
+        }
+        if self.level_path.is_some() {
+            return;
+        }
+        let Some(index) = self.biomes.index_at(self.stats.meters()) else {
+            return;
+        };
+        if self.biome == Some(index) {
+            return;
+        }
+        self.prefetch_biome(index);
+        let biome = &self.biomes.bands[index];
+        let Some(image) = self
+            .biome_images
+            .get(&biome.background)
+            .and_then(|slot| slot.borrow().clone())
+        else {
+            return;
+        };
+
+        if let Some(image) = image {
+            let old = std::mem::replace(
+                &mut self.background,
+                Image::new(image, Point { x: 0, y: 0 }),
+            );
+            // The first band of a run snaps in rather than fading.
+            if let Some(previous) = self.biome {
+                self.biome_fade = Some(BiomeFade {
+                    background: old,
+                    tint: self.biomes.bands[previous].tint.clone(),
+                    alpha: Tween::new(
+                        Alpha(1.0),
+                        Alpha(0.0),
+                        BIOME_FADE_TICKS,
+                        tween::ease_in_out_sine,
+                    ),
+                });
+            }
+        }
+        let crossed = self.biome.is_some();
+        self.biome = Some(index);
+        self.prefetch_biome(index + 1);
+        // The course ahead switches to the new band's obstacles.
+        if crossed {
+            let front = self
+                .players
+                .iter()
+                .map(|player| player.boy.bounding_box().right())
+                .max()
+                .unwrap_or(0);
+            self.lay_out_slots(front);
+        }
+    }
+
+    fn draw_background(&self, renderer: &Renderer) -> Result<()> {
+        self.background.draw(renderer)?;
+        if let Some(fade) = &self.biome_fade {
+            renderer.draw_with_alpha(fade.alpha.value().0, |renderer| {
+                fade.background.draw(renderer)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn draw_biome_tint(&self, renderer: &Renderer) -> Result<()> {
+        let screen = Rect::new_from_x_y(0, 0, WIDTH, HEIGHT);
+        let fading = self
+            .biome_fade
+            .as_ref()
+            .map_or(0.0, |fade| fade.alpha.value().0);
+        if let Some(tint) = self
+            .biome
+            .and_then(|index| self.biomes.bands[index].tint.as_ref())
+        {
+            renderer.draw_with_alpha(1.0 - fading, |renderer| {
+                renderer.fill_rect(&screen, tint);
+                Ok(())
+            })?;
+        }
+        if let Some(tint) = self.biome_fade.as_ref().and_then(|fade| fade.tint.as_ref()) {
+            renderer.draw_with_alpha(fading, |renderer| {
+                renderer.fill_rect(&screen, tint);
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Lays out the level's obstacles plus one generated obstacle per spawn
+    /// slot. The generator is seeded from the run, so a seed always gives the
+    /// same course.
+    fn spawn_obstacles(&mut self) {
+        for stone in std::mem::take(&mut self.stones) {
+            self.stone_pool.release(stone);
+        }
+        let stone = self.new_stone(self.level.stone);
+        self.stones.push(stone);
+        for platform in std::mem::take(&mut self.platforms) {
+            self.platform_pool.release(platform);
+        }
+        let platform = self.new_platform(self.level.platform, self.level.platform_surface);
+        self.platforms.push(platform);
+        self.crates = self
+            .level
+            .crates
+            .iter()
+            .map(|position| {
+                Image::new(
+                    self.crate_image.clone(),
+                    Point {
+                        x: position.x,
+                        y: CRATE_Y,
+                    },
+                )
+            })
+            .collect();
+        self.debris.clear();
+        self.boss = self.level.boss.map(Boss::new);
+        self.projectiles.clear();
+        self.lay_out_slots(i16::MIN);
+    }
+
+    /// Fills the spawn slots past `ahead_of` from the current spawn pool,
+    /// clearing whatever the slots held before. Slots already reached keep
+    /// their obstacles.
+    fn lay_out_slots(&mut self, ahead_of: i16) {
+        let from_slot = |x: i16| x > ahead_of && self.level.spawn_slots.contains(&x);
+        let (stone, platform) = (self.level.stone, self.level.platform);
+        let (kept, cleared): (Vec<Image>, Vec<Image>) = std::mem::take(&mut self.stones)
+            .into_iter()
+            .partition(|image| {
+                let position = image.bounding_box().position;
+                position == stone || !from_slot(position.x)
+            });
+        self.stones = kept;
+        for image in cleared {
+            self.stone_pool.release(image);
+        }
+        let (kept, cleared): (Vec<Platform>, Vec<Platform>) = std::mem::take(&mut self.platforms)
+            .into_iter()
+            .partition(|built| built.position == platform || !from_slot(built.position.x));
+        self.platforms = kept;
+        for built in cleared {
+            self.platform_pool.release(built);
+        }
+
+        let spawns = self.spawn_pool().reweighted(self.difficulty.level());
+        let speed = self.physics().running_speed;
+        let rerolls = if self.config.strict_spawns {
+            SPAWN_REROLLS
+        } else {
+            0
+        };
+        let layout = self.level.spawn_layout(
+            &spawns,
+            speed,
+            self.stats.seed,
+            rerolls,
+            |previous, next| self.unreachable_spawn(previous, next, speed),
+        );
+        for (kind, x) in layout.into_iter().filter(|(_, x)| *x > ahead_of) {
+            match kind {
+                Some(ObstacleKind::LowPlatform) => {
+                    let platform = self.new_platform(Point { x, y: LOW_PLATFORM }, Surface::Ground);
+                    self.platforms.push(platform);
+                }
+                Some(ObstacleKind::HighPlatform) => {
+                    let platform = self.new_platform(
+                        Point {
+                            x,
+                            y: HIGH_PLATFORM,
+                        },
+                        Surface::Ground,
+                    );
+                    self.platforms.push(platform);
+                }
+                Some(ObstacleKind::IcePlatform) => {
+                    let platform = self.new_platform(Point { x, y: LOW_PLATFORM }, Surface::Ice);
+                    self.platforms.push(platform);
+                }
+                Some(ObstacleKind::Stone) => {
+                    let stone = self.new_stone(Point { x, y: STONE_Y });
+                    self.stones.push(stone);
+                }
+                None => {
+                    log!("Spawn table has no usable weights");
+                }
+            }
+        }
+    }
+
+    /// Why the boy couldn't get past `next` after `previous` at `speed`,
+    /// if he couldn't.
+    fn unreachable_spawn(
+        &self,
+        previous: Option<(ObstacleKind, i16)>,
+        (kind, x): (ObstacleKind, i16),
+        speed: i16,
+    ) -> Option<String> {
+        if kind == ObstacleKind::HighPlatform && HIGH_PLATFORM < HEIGHT - max_jump_height() {
+            return Some(format!("high platform at x={} is above a jump's apex", x));
+        }
+        let (ObstacleKind::Stone, Some((ObstacleKind::Stone, previous_x))) = (kind, previous)
+        else {
+            return None;
+        };
+        let stone_width = self.stone_image.width() as i16;
+        let boy_width = self
+            .players
+            .first()
+            .map_or(0, |player| player.boy.bounding_box().width);
+        let span = x + stone_width - previous_x;
+        let gap = x - (previous_x + stone_width);
+        (span > max_jump_distance(speed) && gap < boy_width).then(|| {
+            format!(
+                "stones at x={} and x={} are too far apart to clear in one jump and too close to land between",
+                previous_x, x
+            )
+        })
+    }
+
+    /// Reuses a released platform when there is one, which saves cloning
+    /// the tile sheet and image handle again.
+    fn new_platform(&mut self, position: Point, surface: Surface) -> Platform {
+        let tiles = &self.tiles;
+        let mut platform = self
+            .platform_pool
+            .acquire(|| Platform::new(tiles.0.clone(), tiles.1.clone(), position, surface));
+        platform.position = position;
+        platform.surface = surface;
+        platform
+    }
+
+    fn new_stone(&mut self, position: Point) -> Image {
+        let element = &self.stone_image;
+        let mut stone = self
+            .stone_pool
+            .acquire(|| Image::new(element.clone(), position));
+        stone.move_to(position);
+        stone
+    }
+
+    /// Removes the crate at `index`, scattering coins and wood chips from
+    /// where it stood.
+    fn break_crate(&mut self, index: usize) {
+        let crate_box = *self.crates.remove(index).bounding_box();
+        let center = crate_box.center();
+        let mut rng = StdRng::seed_from_u64(u64::from(self.stats.seed) ^ crate_box.x() as u64);
+
+        let count = rng.gen_range(CRATE_COINS);
+        for coin in 0..count {
+            let spread = coin as i16 - count as i16 / 2;
+            self.coins.push(Coin::popped(
+                center,
+                Point {
+                    x: spread * 2,
+                    y: rng.gen_range(-14..=-10),
+                },
+            ));
+        }
+        for _ in 0..WOOD_CHIPS {
+            self.debris.spawn(
+                center,
+                Point {
+                    x: rng.gen_range(-5..=5),
+                    y: rng.gen_range(-10..=-4),
+                },
+                DEBRIS_FRAMES,
+                WOOD_COLOR,
+            );
+        }
+    }
+
+    fn update_crates(&mut self) {
+        let crate_boxes: Vec<Rect> = self
+            .crates
+            .iter()
+            .map(|crate_image| *crate_image.bounding_box())
+            .collect();
+
+        let mut broken = Vec::new();
+        for player in self.players.iter_mut() {
+            for (index, crate_box) in crate_boxes.iter().enumerate() {
+                if player.check_crate(crate_box) == CrateHit::Broken && !broken.contains(&index) {
+                    broken.push(index);
+                }
+            }
+        }
+        broken.sort_unstable();
+        for index in broken.into_iter().rev() {
+            self.break_crate(index);
+        }
+    }
+
+    fn update_debris(&mut self) {
+        self.debris.update();
+    }
+
+    /// Runs the level's boss: it winds up, throws at the nearest boy and
+    /// leaves once he has survived enough throws.
+    fn update_boss(&mut self) {
+        let Some(boss) = &mut self.boss else {
+            return;
+        };
+
+        let targets: Vec<Option<Rect>> = self
+            .players
+            .iter()
+            .map(|player| (!player.boy.knocked_out()).then(|| player.boy.bounding_box()))
+            .collect();
+        let (hit, landed) = boss.fight(&mut self.projectiles, &targets, self.difficulty.level());
+        for index in hit {
+            self.players[index].knock_out(KnockOutCause::Projectile);
+        }
+
+        for (index, center) in landed.into_iter().enumerate() {
+            for puff in 0..DUST_PUFFS {
+                let spread = puff as i16 - DUST_PUFFS as i16 / 2;
+                self.debris.spawn(
+                    center,
+                    Point {
+                        x: spread * 2 + index as i16,
+                        y: -3 - (puff as i16 % 3),
+                    },
+                    DEBRIS_FRAMES / 2,
+                    DUST_COLOR,
+                );
+            }
+        }
+
+        if boss.defeated() {
+            self.boss = None;
+            self.projectiles.clear();
+        }
+    }
+
+    pub fn is_magnet_active(&self) -> bool {
+        self.magnet.is_active()
+    }
+
+    fn update_collectibles(&mut self) {
+        self.magnet.tick();
+
+        let boxes: Vec<Rect> = self
+            .players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+            .map(|player| player.boy.bounding_box())
+            .collect();
+
+        if let Some(pickup) = self.magnet_pickup {
+            let pickup_box = Coin::new(pickup).bounding_box();
+            if boxes.iter().any(|rect| rect.intersects(&pickup_box)) {
+                self.magnet_pickup = None;
+                self.magnet = Timer::from_millis(MAGNET_MILLIS);
+            }
+        }
+
+        if let Some(pickup) = self.jetpack_pickup {
+            let pickup_box = Coin::new(pickup).bounding_box();
+            let wearer = self.players.iter_mut().find(|player| {
+                !player.boy.knocked_out() && player.boy.bounding_box().intersects(&pickup_box)
+            });
+            if let Some(player) = wearer {
+                player.boy.strap_on_jetpack();
+                self.jetpack_pickup = None;
+            }
+        }
+
+        let surfaces: Vec<Rect> = self
+            .platforms
+            .iter()
+            .flat_map(|platform| platform.bounding_boxes())
+            .collect();
+
+        for coin in self.coins.iter_mut().filter(|coin| !coin.collected) {
+            coin.fall(&surfaces);
+            if self.magnet.is_active() {
+                let nearest = boxes
+                    .iter()
+                    .map(|rect| rect.center())
+                    .min_by_key(|center| center.distance_squared(&coin.position));
+                if let Some(target) = nearest {
+                    coin.pull_toward(&target);
+                }
+            }
+
+            if boxes
+                .iter()
+                .any(|rect| rect.intersects(&coin.bounding_box()))
+            {
+                coin.collected = true;
+                self.events.publish(GameEvent::CoinCollected);
+            }
+        }
+    }
+
+    fn draw_collectibles(&self, renderer: &Renderer) -> Result<()> {
+        for coin in &self.coins {
+            coin.draw(renderer)?;
+        }
+
+        if let Some(pickup) = &self.magnet_pickup {
+            renderer.draw_circle(pickup, COIN_RADIUS, "#d33")?;
+        }
+        if let Some(pickup) = &self.jetpack_pickup {
+            renderer.draw_circle(pickup, COIN_RADIUS, "#39f")?;
+        }
+
+        Ok(())
+    }
+
+    /// Every boy is out and there is no checkpoint to bring them back.
+    fn is_game_over(&self) -> bool {
+        self.checkpoints.game_over(self.all_knocked_out())
+    }
+
+    fn all_knocked_out(&self) -> bool {
+        self.players.iter().all(|player| player.boy.knocked_out())
+    }
+
+    fn walking_speed(&self) -> i16 {
+        self.players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+            .map(|player| player.boy.walking_speed())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn snapshot(&self) -> RunSave {
+        RunSave {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            stats: self.stats.clone(),
+            boys: self
+                .players
+                .iter()
+                .map(|player| player.boy.snapshot())
+                .collect(),
+        }
+    }
+
+    fn restore(&mut self, save: RunSave) {
+        for (player, boy) in self.players.iter_mut().zip(&save.boys) {
+            player.boy.restore(boy);
+        }
+        self.stats = save.stats;
+        self.reported_score = 0;
+    }
+
+    /// Boys already out when the checkpoint is reached come back with the
+    /// one who reached it.
+    fn checkpoint_snapshot(&self) -> WalkSnapshot {
+        let standing = self
+            .players
+            .iter()
+            .find(|player| !player.boy.knocked_out())
+            .map(|player| player.boy.snapshot());
+        WalkSnapshot {
+            boys: self
+                .players
+                .iter()
+                .map(|player| match &standing {
+                    Some(save) if player.boy.knocked_out() => save.clone(),
+                    _ => player.boy.snapshot(),
+                })
+                .collect(),
+            collected_coins: self.coins.iter().map(|coin| coin.collected).collect(),
+            magnet_pickup: self.magnet_pickup,
+            jetpack_pickup: self.jetpack_pickup,
+            spent_triggers: self.triggers.iter().map(|armed| armed.spent).collect(),
+        }
+    }
+
+    fn respawn_at_checkpoint(&mut self, snapshot: WalkSnapshot) {
+        for (player, boy) in self.players.iter_mut().zip(&snapshot.boys) {
+            player.boy.restore(boy);
+            player.near_miss_pending = false;
+            player.knocked_out_by = None;
+            player.portal_cooldown.clear();
+            if let Some(stamina) = &mut player.stamina {
+                stamina.refill();
+            }
+        }
+        for (coin, collected) in self.coins.iter_mut().zip(&snapshot.collected_coins) {
+            coin.collected = *collected;
+            coin.velocity = None;
+        }
+        self.magnet_pickup = snapshot.magnet_pickup;
+        self.magnet = Timer::default();
+        self.jetpack_pickup = snapshot.jetpack_pickup;
+        for (armed, spent) in self.triggers.iter_mut().zip(&snapshot.spent_triggers) {
+            armed.spent = *spent;
+            armed.occupied = false;
+        }
+        self.projectiles.clear();
+    }
+
+    fn update_respawn(&mut self) {
+        if let Some(snapshot) = self.checkpoints.update(self.all_knocked_out()) {
+            self.respawn_at_checkpoint(snapshot);
+        }
+    }
+
+    fn update_input(&mut self, keystate: &KeyState) {
+        if self.in_cutscene() {
+            return;
+        }
+        self.update_resume(keystate);
+        let assist = self.settings.assist;
+        let obstacles = if assist {
+            self.assist_obstacles()
+        } else {
+            Vec::new()
+        };
+        let reach = max_jump_distance(self.physics().running_speed);
+        for player in self.players.iter_mut() {
+            player.was_knocked_out = player.boy.knocked_out();
+            let was_jumping = player.boy.jumping();
+            let action =
+                assist.then(|| assist_action(&player.boy.bounding_box(), &obstacles, reach));
+            player.handle_input(keystate, action);
+            if !was_jumping && player.boy.jumping() {
+                self.events.publish(GameEvent::StartedJump);
+            }
+            player.was_airborne = player.boy.airborne();
+            if let Some(portal) = player.take_portal(keystate, &self.level.portals) {
+                self.portal_flash = Timer::new(PORTAL_FLASH);
+                if portal.counts_distance {
+                    self.stats.distance += portal.skipped();
+                }
+            }
+        }
+    }
+
+    fn update_physics(&mut self, _keystate: &KeyState) {
+        self.portal_flash.tick();
+        for player in self.players.iter_mut() {
+            if !player.boy.knocked_out() {
+                let bounding_box = player.boy.bounding_box();
+                for zone in &self.level.wind_zones {
+                    if bounding_box.intersects(&zone.rect) {
+                        player.boy.apply_external_force(zone.force);
+                    }
+                }
+                for zone in &self.level.gravity_zones {
+                    if bounding_box.intersects(&zone.rect) {
+                        player.boy.scale_gravity(zone.scale);
+                    }
+                }
+            }
+            if !self.level.ground.is_empty() {
+                player
+                    .boy
+                    .set_ground(self.level.ground_under(player.boy.feet()));
+            }
+            if let Some(facing) = player.boy.update() {
+                self.callbacks.borrow().turned(facing);
+            }
+        }
+    }
+
+    fn update_collisions(&mut self, _keystate: &KeyState) {
+        self.update_spared_hits();
+        let hazards = self.hazards();
+        for player in self.players.iter_mut() {
+            for platform in &self.platforms {
+                player.check_platform(platform);
+            }
+            if player.check_footing(&self.platforms) {
+                self.events.publish(GameEvent::Teetering);
+            }
+            player.check_slopes(&self.level.slopes);
+            player.check_ground(&self.level.ground);
+            if player.was_airborne && player.boy.landing() {
+                let bounding_box = player.boy.bounding_box();
+                self.events.publish(GameEvent::Landed(Point {
+                    x: bounding_box.center().x,
+                    y: bounding_box.bottom(),
+                }));
+            }
+
+            if player.check_hazards(&hazards) {
+                self.stats.near_misses += 1;
+            }
+            let bounding_box = player.boy.bounding_box();
+            if self
+                .level
+                .pendulums
+                .iter()
+                .any(|pendulum| pendulum.hits(&bounding_box, self.stats.frames))
+            {
+                player.knock_out(KnockOutCause::Pendulum);
+            }
+        }
+
+        self.update_crates();
+        self.update_boss();
+        self.collect_spared_hits();
+        self.update_collectibles();
+        self.update_triggers();
+
+        // An intro may walk the boy in from off the left edge.
+        let in_cutscene = self.in_cutscene();
+        for player in self.players.iter_mut() {
+            match out_of_bounds(&player.boy.bounding_box(), in_cutscene) {
+                // Godmode shrugs off the fall, but with no floor under him
+                // he would fall forever, so he is set back on the ground.
+                Some(KnockOutCause::Pit) if player.invincible => {
+                    let surface = self.level.surface_past(player.boy.feet().x);
+                    player.boy.stand_on(surface);
+                }
+                Some(cause) => player.knock_out(cause),
+                None => {}
+            }
+        }
+
+        for player in &self.players {
+            if !player.was_knocked_out && player.boy.knocked_out() {
+                self.events.publish(GameEvent::KnockedOut(
+                    player.knocked_out_by.unwrap_or(KnockOutCause::Hazard),
+                ));
+            }
+        }
+    }
+
+    fn update_effects(&mut self, _keystate: &KeyState) {
+        self.update_biome();
+        for player in self.players.iter_mut() {
+            player.boy.show_overlay(OverlayKind::Shield, self.godmode);
+            player
+                .boy
+                .show_overlay(OverlayKind::Magnet, self.magnet.is_active());
+        }
+        self.update_debris();
+        self.handle_events();
+        self.toasts.update();
+        self.rain.update();
+        let speed = self.walking_speed();
+        self.speed_lines.update(speed);
+    }
+
+    fn update_scoring(&mut self, _keystate: &KeyState) {
+        self.update_respawn();
+        self.update_stats();
+        self.update_save();
+        self.check_goal();
+    }
+
+    fn update_resume(&mut self, keystate: &KeyState) {
+        if self.pending_resume.is_none() {
+            return;
+        }
+
+        if self.resume_key.pressed(keystate.is_pressed(RESUME_KEY)) {
+            if let Some(save) = self.pending_resume.take() {
+                self.restore(save);
+            }
+        } else if self
+            .players
+            .iter()
+            .any(|player| player.boy.state_machine.state_kind() != StateKind::Idle)
+        {
+            self.pending_resume = None;
+        }
+    }
+
+    fn update_save(&mut self) {
+        if self.pending_resume.is_some() {
+            return;
+        }
+
+        if self.is_game_over() {
+            if self.latest_save.borrow_mut().take().is_some() {
+                RunSave::clear();
+            }
+            return;
+        }
+
+        let save = self.snapshot();
+        if self.stats.frames.is_multiple_of(RUN_SAVE_INTERVAL) {
+            save.store();
+        }
+        *self.latest_save.borrow_mut() = Some(save);
+    }
+
+    /// Reacts to this update's events in the order they were published.
+    fn handle_events(&mut self) {
+        let events = self.events.events_this_frame().to_vec();
+        for event in events {
+            match event {
+                GameEvent::StartedJump => {
+                    if let Err(err) = self.audio.play_sound(&self.jump_sound) {
+                        log!("Could not play jump sound {:#?}", err);
+                    }
+                }
+                // There is no sound of its own for this yet, so the jump
+                // sound warns him.
+                GameEvent::Teetering => {
+                    if let Err(err) = self.audio.play_sound(&self.jump_sound) {
+                        log!("Could not play teeter sound {:#?}", err);
+                    }
+                }
+                GameEvent::Landed(feet) => {
+                    for puff in 0..LANDING_PUFFS {
+                        self.debris.spawn(
+                            feet,
+                            Point {
+                                x: puff as i16 - LANDING_PUFFS as i16 / 2,
+                                y: -2,
+                            },
+                            DEBRIS_FRAMES / 3,
+                            DUST_COLOR,
+                        );
+                    }
+                }
+                // Several boys can go down in one update; report the run
+                // once, when the last one does.
+                GameEvent::KnockedOut(cause) => {
+                    if self.is_game_over() {
+                        let summary = self.stats.summary();
+                        self.callbacks.borrow().game_over(&summary);
+                        self.metrics.run_ended(
+                            summary.duration_ms,
+                            self.stats.meters(),
+                            cause.label(),
+                        );
+                        self.metrics.flush();
+                        if self.stats.is_fair() {
+                            if self.stats.assist {
+                                self.assist_best_score =
+                                    self.assist_best_score.max(self.stats.score());
+                            } else {
+                                self.difficulty.record_run(self.stats.meters());
+                                self.best_score = self.best_score.max(self.stats.score());
+                            }
+                        }
+                        break;
+                    }
+                }
+                GameEvent::CoinCollected => self.stats.coins += 1,
+                GameEvent::Triggered(id) => self.handle_trigger(&id),
+                GameEvent::CheckpointReached => {
+                    self.checkpoints.reach(self.checkpoint_snapshot());
+                    self.toasts
+                        .push(self.strings.t("toast.checkpoint"), HINT_TICKS);
+                }
+            }
+        }
+    }
+
+    fn update_triggers(&mut self) {
+        let boxes: Vec<Rect> = self
+            .players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+            .map(|player| player.boy.bounding_box())
+            .collect();
+        for armed in self.triggers.iter_mut() {
+            if armed.update(&boxes) {
+                self.events
+                    .publish(GameEvent::Triggered(armed.trigger.id.clone()));
+                if armed.trigger.kind == TriggerKind::Checkpoint {
+                    self.events.publish(GameEvent::CheckpointReached);
+                }
+            }
+        }
+    }
+
+    /// Ids the game knows show a tutorial hint; every id is also passed on
+    /// to the page as a `wtd:trigger` event for anything scripted there.
+    fn handle_trigger(&mut self, id: &str) {
+        let hint = match id {
+            "jump_hint" => Some("hint.jump"),
+            "slide_hint" => Some("hint.slide"),
+            _ => None,
+        };
+        if let Some(key) = hint {
+            self.toasts.push(self.strings.t(key), HINT_TICKS);
+        }
+        self.callbacks.borrow().triggered(id);
+    }
+
+    /// Everything that scrolls with the level, drawn where the camera sees it.
+    fn draw_world(&self, renderer: &Renderer, darkness: f32) -> Result<()> {
+        self.draw_slopes(renderer);
+        self.draw_ground(renderer);
+        for player in &self.players {
+            player.boy.draw(renderer)?;
+            player.draw_stamina(renderer);
+        }
+        for zone in &self.level.wind_zones {
+            zone.draw(renderer, self.stats.frames, darkness)?;
+        }
+        for zone in &self.level.gravity_zones {
+            zone.draw(renderer);
+        }
+        for pendulum in &self.level.pendulums {
+            pendulum.draw(renderer, self.stats.frames)?;
+        }
+        for portal in &self.level.portals {
+            portal.draw(renderer)?;
+        }
+        for stone in &self.stones {
+            stone.draw(renderer)?;
+            renderer.draw_bounding_box(stone.bounding_box(), DebugClass::Hazard);
+        }
+        for crate_image in &self.crates {
+            crate_image.draw(renderer)?;
+            renderer.draw_bounding_box(crate_image.bounding_box(), DebugClass::Ground);
+        }
+        for piece in self.debris.iter() {
+            piece.draw(renderer);
+        }
+        if let Some(boss) = &self.boss {
+            boss.draw(renderer, &self.boss_images)?;
+        }
+        for projectile in &self.projectiles {
+            draw_projectile(renderer, projectile)?;
+        }
+        for platform in &self.platforms {
+            platform.draw(renderer)?;
+        }
+        self.draw_collectibles(renderer)?;
+        self.draw_goal(renderer);
+        self.draw_triggers(renderer);
+        if renderer.debug_mode() && self.settings.show_overlaps {
+            self.draw_overlaps(renderer);
+        }
+        if renderer.debug_mode() {
+            self.draw_jump_previews(renderer);
+        }
+        Ok(())
+    }
+
+    fn draw_jump_previews(&self, renderer: &Renderer) {
+        let surfaces: Vec<Rect> = self
+            .platforms
+            .iter()
+            .flat_map(Platform::bounding_boxes)
+            .chain(
+                self.crates
+                    .iter()
+                    .map(|crate_image| *crate_image.bounding_box()),
+            )
+            .chain(self.hazards())
+            .collect();
+        for player in &self.players {
+            if player.boy.state_machine.state_kind() != StateKind::Running {
+                continue;
+            }
+            let (path, landing) = player.boy.jump_preview(&surfaces);
+            renderer.draw_path(&path, JUMP_PREVIEW_COLOR);
+            if let Some(Point { x, y }) = landing {
+                renderer.draw_line(
+                    &Point {
+                        x: x - LANDING_CROSS,
+                        y: y - LANDING_CROSS,
+                    },
+                    &Point {
+                        x: x + LANDING_CROSS,
+                        y: y + LANDING_CROSS,
+                    },
+                    JUMP_PREVIEW_COLOR,
+                );
+                renderer.draw_line(
+                    &Point {
+                        x: x - LANDING_CROSS,
+                        y: y + LANDING_CROSS,
+                    },
+                    &Point {
+                        x: x + LANDING_CROSS,
+                        y: y - LANDING_CROSS,
+                    },
+                    JUMP_PREVIEW_COLOR,
+                );
+            }
+        }
+    }
+
+    /// Detaches the camera and freezes the level, or snaps back to the boy.
+    fn toggle_inspect(&mut self) {
+        self.camera = match self.camera {
+            Some(_) => None,
+            None => Some(Point { x: 0, y: 0 }),
+        };
+    }
+
+    fn update_inspect(&mut self, keystate: &KeyState) {
+        let Some(camera) = &mut self.camera else {
+            return;
+        };
+        let speed = if keystate.is_pressed("ShiftLeft") || keystate.is_pressed("ShiftRight") {
+            self.config.pan_speed * INSPECT_FAST_PAN
+        } else {
+            self.config.pan_speed
+        };
+        if let Some(heading) =
+            keystate.heading(MENU_UP_KEY, MENU_DOWN_KEY, MENU_LEFT_KEY, MENU_RIGHT_KEY)
+        {
+            let step = heading.step(speed);
+            camera.x += step.x;
+            camera.y += step.y;
+        }
+    }
+
+    fn draw_inspect(&self, renderer: &Renderer) -> Result<()> {
+        let Some(camera) = self.camera else {
+            return Ok(());
+        };
+        renderer.draw_text(
+            &format!("INSPECT camera x: {} y: {}", camera.x, camera.y),
+            &Point {
+                x: 10,
+                y: HEIGHT - 10,
+            },
+        )
+    }
+
+    fn set_godmode(&mut self, enabled: bool) {
+        log!("Godmode {}", if enabled { "on" } else { "off" });
+        self.godmode = enabled;
+        self.stats.godmode |= enabled;
+        for player in self.players.iter_mut() {
+            player.invincible = enabled;
+            player.spared = None;
+        }
+        self.spared_hits.clear();
+    }
+
+    /// Logs each new hit godmode swallowed; touching the same thing over
+    /// several frames only keeps its highlight alive.
+    fn collect_spared_hits(&mut self) {
+        for player in self.players.iter_mut() {
+            if let Some(rect) = player.spared.take() {
+                if !self
+                    .spared_hits
+                    .iter()
+                    .any(|(hit, _)| hit.intersects(&rect))
+                {
+                    log!("Godmode spared a hit at {:?}", rect);
+                }
+                self.spared_hits.push((rect, SPARED_HIT_FRAMES));
+            }
+        }
+    }
+
+    fn update_spared_hits(&mut self) {
+        for (_, frames) in self.spared_hits.iter_mut() {
+            *frames -= 1;
+        }
+        self.spared_hits.retain(|(_, frames)| *frames > 0);
+    }
+
+    fn draw_godmode(&self, renderer: &Renderer) -> Result<()> {
+        if !self.godmode {
+            return Ok(());
+        }
+        for (rect, _) in &self.spared_hits {
+            renderer.fill_rect(rect, "rgba(255, 0, 0, 0.4)");
+        }
+        Ok(())
+    }
+
+    /// What the HUD shows; it's only redrawn when this changes.
+    fn hud_state(&self) -> HudState {
+        HudState {
+            shown: matches!(
+                self.phase,
+                Phase::Playing | Phase::Paused(_) | Phase::LevelComplete(_)
+            ),
+            godmode: self.godmode,
+            resume_meters: self.pending_resume.as_ref().map(|save| save.stats.meters()),
+            game_over: self.is_game_over(),
+            swept_away: self.is_game_over()
+                && self
+                    .players
+                    .iter()
+                    .any(|player| player.knocked_out_by == Some(KnockOutCause::SweptAway)),
+            assist_best: (self.is_game_over() && self.stats.assist)
+                .then_some(self.assist_best_score),
+            score: self.stats.score(),
+            magnet: self
+                .is_magnet_active()
+                .then(|| bar_percent(self.magnet.remaining_fraction())),
+            jetpack: self
+                .players
+                .iter()
+                .find_map(|player| player.boy.state_machine.jetpack_fuel())
+                .map(bar_percent),
+        }
+    }
+
+    /// Reddens the left edge as a boy gets pushed toward it.
+    fn draw_edge_warning(&self, renderer: &Renderer) {
+        let Some(nearest) = self
+            .players
+            .iter()
+            .filter(|player| !player.boy.knocked_out())
+            .map(|player| player.boy.bounding_box().x())
+            .min()
+        else {
+            return;
+        };
+        if nearest >= EDGE_WARNING_DISTANCE {
+            return;
+        }
+        let strength = 1.0 - f32::from(nearest.max(0)) / f32::from(EDGE_WARNING_DISTANCE);
+        for band in 0..EDGE_WARNING_BANDS {
+            let fade = 1.0 - band as f32 / EDGE_WARNING_BANDS as f32;
+            renderer.fill_rect(
+                &Rect::new_from_x_y(band * 10, 0, 10, HEIGHT),
+                &format!("rgba(200, 0, 0, {:.2})", 0.4 * strength * fade),
+            );
+        }
+    }
+
+    fn draw_hud(&self, renderer: &Renderer) -> Result<()> {
+        let hud = self.hud_state();
+        if !hud.shown {
+            return Ok(());
+        }
+        if hud.godmode {
+            renderer.fill_rect(&Rect::new_from_x_y(WIDTH - 60, 8, 52, 24), "gold");
+            renderer.draw_text(
+                "GOD",
+                &Point {
+                    x: WIDTH - 52,
+                    y: 27,
+                },
+            )?;
+        }
+        if let Some(meters) = hud.resume_meters {
+            renderer.draw_text_wrapped(
+                &self
+                    .strings
+                    .format("hud.resume", &[&self.strings.number(meters)]),
+                &Point { x: 10, y: 50 },
+                320,
+                20,
+                "16px sans-serif",
+            )?;
+        }
+        if hud.swept_away {
+            renderer.draw_text(self.strings.t("hud.swept_away"), &Point { x: 10, y: 110 })?;
+        }
+        if let Some(best) = hud.assist_best {
+            renderer.draw_text(
+                &self
+                    .strings
+                    .format("hud.assist_run", &[&self.strings.number(best)]),
+                &Point { x: 10, y: 140 },
+            )?;
+        }
+        if hud.game_over {
+            renderer.draw_text(self.strings.t("hud.game_over"), &Point { x: 10, y: 80 })?;
+        }
+        renderer.draw_text(
+            &self
+                .strings
+                .format("hud.score", &[&self.strings.number(hud.score)]),
+            &Point {
+                x: WIDTH / 2 - 40,
+                y: 20,
+            },
+        )?;
+        for (key, percent, y, color) in [
+            ("hud.magnet", hud.magnet, 20, "#d33"),
+            ("hud.jetpack", hud.jetpack, 46, "#39f"),
+        ] {
+            let Some(percent) = percent else {
+                continue;
+            };
+            renderer.draw_text(self.strings.t(key), &Point { x: WIDTH - 90, y })?;
+            renderer.draw_progress_bar(
+                &Rect::new_from_x_y(WIDTH - 90, y + 6, 70, 4),
+                f32::from(percent) / 100.0,
+                color,
+            );
+        }
+        Ok(())
+    }
+
+    fn draw_slopes(&self, renderer: &Renderer) {
+        for slope in &self.level.slopes {
+            renderer.draw_polygon(
+                &[
+                    slope.start,
+                    slope.end,
+                    Point {
+                        x: slope.end.x,
+                        y: HEIGHT,
+                    },
+                    Point {
+                        x: slope.start.x,
+                        y: HEIGHT,
+                    },
+                ],
+                Some(SLOPE_COLOR),
+                None,
+            );
+            if renderer.debug_mode() {
+                renderer.draw_line(&slope.start, &slope.end, "#0f0");
+            }
+        }
+    }
+
+    /// Raised sections filled down to the bottom of the screen, and a dark
+    /// strip along each pit.
+    fn draw_ground(&self, renderer: &Renderer) {
+        if self.level.ground.is_empty() {
+            return;
+        }
+        let mut covered = 0;
+        for section in &self.level.ground {
+            if section.start > covered {
+                renderer.fill_rect(
+                    &Rect::new_from_x_y(
+                        covered,
+                        HEIGHT - PIT_DEPTH,
+                        section.start - covered,
+                        PIT_DEPTH,
+                    ),
+                    PIT_COLOR,
+                );
+            }
+            if section.y < HEIGHT {
+                renderer.fill_rect(&section.rect(), SLOPE_COLOR);
+            }
+            covered = covered.max(section.end);
+        }
+        if covered < WIDTH {
+            renderer.fill_rect(
+                &Rect::new_from_x_y(covered, HEIGHT - PIT_DEPTH, WIDTH - covered, PIT_DEPTH),
+                PIT_COLOR,
+            );
+        }
+    }
+
+    fn draw_triggers(&self, renderer: &Renderer) {
+        for armed in &self.triggers {
+            if armed.trigger.kind == TriggerKind::Checkpoint {
+                armed.draw_flag(renderer);
+            }
+            renderer.draw_bounding_box(&armed.trigger.rect, DebugClass::Trigger);
+        }
+    }
+
+    fn update_stats(&mut self) {
+        if self.is_game_over() || self.in_cutscene() {
+            return;
+        }
+
+        let previous_milestone = self.stats.meters() / MILESTONE_METERS;
+        self.stats.frames += 1;
+        let pools = [
+            (
+                self.platform_pool.allocations(),
+                self.platform_pool.acquisitions(),
+            ),
+            (
+                self.stone_pool.allocations(),
+                self.stone_pool.acquisitions(),
+            ),
+            (
+                self.debris.pool.allocations(),
+                self.debris.pool.acquisitions(),
+            ),
+        ];
+        self.allocation_rate.update(
+            pools.iter().map(|pool| pool.0).sum(),
+            pools.iter().map(|pool| pool.1).sum(),
+        );
+        self.stats.distance += i32::from(self.walking_speed());
+
+        let milestone = self.stats.meters() / MILESTONE_METERS;
+        if milestone > previous_milestone {
+            self.callbacks
+                .borrow()
+                .milestone(milestone * MILESTONE_METERS);
+            self.toasts.push(
+                self.strings.format(
+                    "toast.milestone",
+                    &[&self.strings.number(milestone * MILESTONE_METERS)],
+                ),
+                MILESTONE_TOAST_TICKS,
+            );
+        }
+
+        let score = self.stats.score();
+        let best_score = if self.stats.assist {
+            self.assist_best_score
+        } else {
+            self.best_score
+        };
+        if best_score > 0 && score > best_score && !self.best_announced && self.stats.is_fair() {
+            self.best_announced = true;
+            self.toasts
+                .push(self.strings.t("toast.high_score"), HIGH_SCORE_TOAST_TICKS);
+        }
+        if self.stats.frames.is_multiple_of(SCORE_REPORT_INTERVAL) && score != self.reported_score {
+            self.callbacks.borrow().score_changed(score);
+            self.reported_score = score;
+        }
+    }
+
+    fn draw_stats(&self, renderer: &Renderer) -> Result<()> {
+        renderer.draw_text(
+            &format!(
+                "score {}  distance {}m  speed {}  difficulty {:.1}  seed {}",
+                self.stats.score(),
+                self.stats.meters(),
+                self.walking_speed(),
+                self.difficulty.level(),
+                self.stats.seed
+            ),
+            &Point { x: 10, y: 20 },
+        )?;
+
+        let render = renderer.last_frame_stats();
+        let allocations = match self.allocation_rate.last_minute {
+            Some((built, acquired)) => format!("{} of {}", built, acquired),
+            None => "-".to_string(),
+        };
+        renderer.draw_text(
+            &format!(
+                "draw calls {}  images {}  rects {}  texts {}  shapes {}  pooled builds/min {}",
+                render.draw_calls,
+                render.images,
+                render.rects,
+                render.texts,
+                render.shapes,
+                allocations
+            ),
+            &Point { x: 10, y: 110 },
+        )?;
+
+        for (index, player) in self.players.iter().enumerate() {
+            let boy = player.boy.debug_state(&[]);
+            renderer.draw_text(
+                &format!(
+                    "boy {}: {:?} {:?}  position ({}, {})  velocity ({}, {})",
+                    index + 1,
+                    boy.state,
+                    player.boy.state_machine.handled_events(),
+                    boy.position.x,
+                    boy.position.y,
+                    boy.velocity.x,
+                    boy.velocity.y
+                ),
+                &Point {
+                    x: 10,
+                    y: 130 + 20 * index as i16,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Starts another run, on a new course unless the seed was pinned.
+    fn restart(&mut self) {
+        if !self.config.fixed_seed {
+            self.stats.seed = rand::random();
+        }
+        self.reset();
+    }
+
+    /// Puts the run back at its start for the current seed, reusing the
+    /// loaded images and sounds.
+    fn reset(&mut self) {
+        RunSave::clear();
+        self.pending_resume = None;
+        *self.latest_save.borrow_mut() = None;
+        self.background = Image::new(self.base_background.clone(), Point { x: 0, y: 0 });
+        self.biome = None;
+        self.biome_fade = None;
+
+        let physics = self.physics();
+        for player in self.players.iter_mut() {
+            player.reset(physics);
+        }
+        self.coins = self.level.coins.iter().copied().map(Coin::new).collect();
+        self.magnet_pickup = self.level.magnet;
+        self.magnet = Timer::default();
+        self.jetpack_pickup = self.level.jetpack;
+        self.triggers = self
+            .level
+            .triggers
+            .iter()
+            .cloned()
+            .map(ArmedTrigger::new)
+            .collect();
+        self.toasts.clear();
+        self.spared_hits.clear();
+        self.portal_flash = Timer::default();
+        self.rain = Rain::new(self.level.rain, self.stats.seed);
+        self.speed_lines = SpeedLines::new(self.config.speed_lines_above, self.stats.seed);
+        self.checkpoints = Checkpoints::default();
+        self.reset_stats();
+        self.spawn_obstacles();
+        self.callbacks.borrow().started();
+        self.metrics.run_started();
+    }
+
+    fn reset_stats(&mut self) {
+        self.stats = RunStats {
+            seed: self.stats.seed,
+            godmode: self.godmode,
+            time_scaled: self.time_scale != 1.0,
+            daily: self.config.daily,
+            assist: self.settings.assist,
+            ..RunStats::default()
+        };
+        self.reported_score = 0;
+        self.best_announced = false;
+    }
+}
+
+pub enum WalkTheDog {
+    Loading {
+        config: GameConfig,
+        callbacks: SharedCallbacks,
+        progress: Rc<LoadProgress>,
+    },
+    Loaded(Box<Walk>),
+}
+
+pub struct WalkTheDogBuilder {
+    config: GameConfig,
+}
+
+impl WalkTheDogBuilder {
+    pub fn new() -> Self {
+        WalkTheDogBuilder {
+            config: GameConfig::default(),
+        }
+    }
+
+    pub fn players(mut self, players: usize) -> Self {
+        self.config.players = players;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.config.seed = seed;
+        self.config.fixed_seed = true;
+        self
+    }
+
+    /// Plays `day`'s daily challenge: the seed is the day number, so the
+    /// same date always lays out the same course.
+    pub fn daily(mut self, day: u32) -> Self {
+        self = self.seed(day);
+        self.config.daily = Some(day);
+        self
+    }
+
+    /// Leaves a daily challenge for a random course. A seed pinned any
+    /// other way is kept.
+    pub fn practice(mut self) -> Self {
+        if self.config.daily.take().is_some() {
+            self.config.seed = rand::random();
+            self.config.fixed_seed = false;
+        }
+        self
+    }
+
+    pub fn level(mut self, level: Option<String>) -> Self {
+        self.config.level = level;
+        self
+    }
+
+    pub fn metrics_url(mut self, metrics_url: Option<String>) -> Self {
+        self.config.metrics_url = metrics_url;
+        self
+    }
+
+    pub fn auto_run(mut self, auto_run: Option<bool>) -> Self {
+        self.config.auto_run = auto_run;
+        self
+    }
+
+    fn from_config(config: GameConfig) -> Self {
+        WalkTheDogBuilder { config }
+    }
+
+    fn into_config(self) -> GameConfig {
+        self.config
+    }
+
+    pub fn debug_options(mut self, options: &DebugOptions) -> Self {
+        if let Some(running_speed) = options.running_speed {
+            self.config.physics.running_speed = running_speed;
+        }
+        if options.knock_back {
+            self.config.physics.knock_back = Some(KNOCK_BACK);
+        }
+        if options.hardcore {
+            self.config.physics.ledge_grab = None;
+        }
+        if options.lang.is_some() {
+            self.config.lang = options.lang.clone();
+        }
+        if let Some(seed) = options.seed {
+            self.config.seed = seed;
+            self.config.fixed_seed = true;
+        }
+        if options.level.is_some() {
+            self.config.level = options.level.clone();
+        }
+        self.config.godmode = options.godmode;
+        self.config.debug = options.debug;
+        if let Some(pan_speed) = options.pan_speed {
+            self.config.pan_speed = pan_speed;
+        }
+        if let Some(speed) = options.speed_lines_above {
+            self.config.speed_lines_above = speed;
+        }
+        self.config.strict_spawns = options.strict_spawns;
+        if let Some(seconds) = options.day_seconds {
+            self.config.day_length = seconds.saturating_mul(60);
+        }
+        self.config.high_contrast = options.high_contrast;
+        if options.stamina {
+            self.config.stamina = Some(StaminaConfig::default());
+        }
+        self
+    }
+
+    pub fn build(self, callbacks: SharedCallbacks) -> WalkTheDog {
+        WalkTheDog::Loading {
+            config: self.config,
+            callbacks,
+            progress: Rc::default(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Game for WalkTheDog {
+    async fn initialize(&self) -> Result<Box<dyn Game>> {
+        match self {
+            WalkTheDog::Loading {
+                config,
+                callbacks,
+                progress,
+            } => {
+                let started = browser::now().unwrap_or_default();
+                // Every fetch is in flight at once; parsing happens after,
+                // one step per frame, so the loading bar keeps moving.
+                // Sounds are fetched and decoded alongside the images, so
+                // the first jump plays from a ready buffer.
+                let lang = config.lang.clone().unwrap_or_else(browser::language);
+                let audio = Audio::new()?;
+                let (
+                    sheet_json,
+                    platform_json,
+                    level_json,
+                    rhb_image,
+                    tile_image,
+                    strings,
+                    background,
+                    stone,
+                    crate_image,
+                    boss_idle,
+                    boss_jump,
+                    jump_sound,
+                    dog_image,
+                    biomes_json,
+                ) = futures::try_join!(
+                    counted(
+                        progress,
+                        browser::fetch_json("assets/sprite_sheets/rhb.json")
+                    ),
+                    counted(
+                        progress,
+                        browser::fetch_json("assets/sprite_sheets/tiles.json")
+                    ),
+                    counted(progress, async {
+                        match &config.level {
+                            Some(path) => fetch_level_json(path).await.map(Some),
+                            None => Ok(None),
+                        }
+                    }),
+                    counted(progress, engine::load_image("assets/sprite_sheets/rhb.png")),
+                    counted(
+                        progress,
+                        engine::load_image("assets/sprite_sheets/tiles.png")
+                    ),
+                    counted(progress, async { Ok(Strings::load(&lang).await) }),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/freetileset/png/BG/BG.png")
+                    ),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/freetileset/png/Object/Stone.png")
+                    ),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/freetileset/png/Object/Crate.png")
+                    ),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/cat/Idle (1).png")
+                    ),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/cat/Jump (3).png")
+                    ),
+                    counted(progress, audio.load_sound("assets/sounds/SFX_Jump_23.mp3")),
+                    counted(
+                        progress,
+                        engine::load_image("assets/resized/dog/run/rr_000.png")
+                    ),
+                    counted(progress, async {
+                        // Biomes are optional; without them every run keeps
+                        // one look and one spawn table.
+                        Ok(match browser::fetch_json(BIOMES_PATH).await {
+                            Ok(json) => Some(json),
+                            Err(err) => {
+                                log!("Running without biomes {:#?}", err);
+                                None
+                            }
+                        })
+                    }),
+                )?;
+                let boss_images = (boss_idle, boss_jump);
+                let jump_sound = jump_sound.with_max_concurrent(JUMP_SOUND_VOICES);
+
+                browser::next_frame().await;
+                let sheet: Sheet = serde_wasm_bindgen::from_value(sheet_json)
+                    .map_err(|err| anyhow!("Could not parse rhb.json {:#?}", err))?;
+                browser::next_frame().await;
+                let platform_sheet = serde_wasm_bindgen::from_value(platform_json)
+                    .map_err(|err| anyhow!("Could not parse tiles.json {:#?}", err))?;
+                browser::next_frame().await;
+                let biomes = match biomes_json {
+                    Some(json) => parse_biomes(json)?,
+                    None => Biomes::default(),
+                };
+                let level = match (&config.level, level_json) {
+                    (Some(path), Some(json)) => parse_level(path, json)?,
+                    _ => Level::default(),
+                };
+                browser::next_frame().await;
+
+                let walk = Walk::new(
+                    WalkAssets {
+                        sheet,
+                        rhb_image,
+                        background,
+                        stone,
+                        tiles: (platform_sheet, tile_image),
+                        crate_image,
+                        dog_image,
+                        boss_images,
+                        audio,
+                        jump_sound,
+                        strings,
+                        biomes,
+                        level,
+                    },
+                    config,
+                    callbacks,
+                )?;
+                log!(
+                    "Initialized in {:.0}ms",
+                    browser::now().unwrap_or_default() - started
+                );
+
+                Ok(Box::new(WalkTheDog::Loaded(Box::new(walk))))
+            }
+            WalkTheDog::Loaded(_) => Err(anyhow!("Error: Game is already initialized")),
+        }
+    }
+
+    fn draw_hud(&self, renderer: &Renderer) -> Result<()> {
+        match self {
+            WalkTheDog::Loaded(walk) => walk.draw_hud(renderer),
+            WalkTheDog::Loading { .. } => Ok(()),
+        }
+    }
+
+    fn take_hud_dirty(&mut self) -> bool {
+        let WalkTheDog::Loaded(walk) = self else {
+            return false;
+        };
+        let hud = walk.hud_state();
+        let dirty = walk.hud_drawn != Some(hud);
+        walk.hud_drawn = Some(hud);
+        dirty
+    }
+
+    fn loading_progress(&self) -> f32 {
+        match self {
+            WalkTheDog::Loading { progress, .. } => progress.fraction(),
+            WalkTheDog::Loaded(_) => 1.0,
+        }
+    }
+
+    fn draw_loading(&self, renderer: &Renderer, progress: f32) -> Result<()> {
+        renderer.clear(&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT));
+        renderer.draw_text_with_font(
+            "Loading…",
+            &Point {
+                x: WIDTH / 2 - 60,
+                y: HEIGHT / 2 - 20,
+            },
+            "32px sans-serif",
+        )?;
+        renderer.draw_progress_bar(
+            &Rect::new_from_x_y(WIDTH / 2 - 150, HEIGHT / 2, 300, 16),
+            progress,
+            "#3c3",
+        );
+        Ok(())
+    }
+
+    fn update(&mut self, keystate: &KeyState) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.events.clear();
+            if walk.dump_key.pressed(keystate.is_pressed(DUMP_STATE_KEY)) {
+                walk.dump_state();
+            }
+            if walk.config.debug && walk.godmode_key.pressed(keystate.is_pressed(GODMODE_KEY)) {
+                walk.set_godmode(!walk.godmode);
+            }
+            if walk.config.debug && walk.inspect_key.pressed(keystate.is_pressed(INSPECT_KEY)) {
+                walk.toggle_inspect();
+            }
+            if walk.camera.is_some() {
+                walk.update_inspect(keystate);
+                return;
+            }
+
+            if walk
+                .screenshot_key
+                .pressed(keystate.is_pressed(SCREENSHOT_KEY))
+                && walk.is_game_over()
+            {
+                walk.screenshot_requested = true;
+            }
+
+            if walk.update_menus(keystate) {
+                return;
+            }
+
+            for system in SYSTEMS {
+                system(walk, keystate);
+            }
+        }
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        renderer.clear(&Rect::new_from_x_y(0, 0, WIDTH, HEIGHT));
+
+        if let WalkTheDog::Loaded(walk) = self {
+            let playing = matches!(walk.phase, Phase::Playing);
+            renderer.set_cursor(if walk.is_game_over() || !playing {
+                "default"
+            } else {
+                "none"
+            })?;
+            let darkness = walk.day_cycle.darkness(walk.stats.frames);
+            let palette = if walk.config.high_contrast {
+                DebugPalette::HighContrast
+            } else {
+                walk.settings.debug_palette()
+            };
+            // Boxes fade a little at night so they don't glare.
+            renderer.set_debug_style(palette, 1.0 - 0.4 * darkness);
+            renderer.force_boxes(walk.camera.is_some());
+
+            walk.draw_background(renderer)?;
+            match &walk.phase {
+                Phase::Title(menu) => return menu.draw(renderer, &walk.strings),
+                Phase::Settings(menu) => return menu.draw(renderer, &walk.strings),
+                Phase::Playing
+                | Phase::Paused(_)
+                | Phase::LevelComplete(_)
+                | Phase::Cutscene(_) => {}
+            }
+
+            match walk.camera {
+                Some(camera) => renderer
+                    .draw_with_camera(camera, |renderer| walk.draw_world(renderer, darkness))?,
+                None => walk.draw_world(renderer, darkness)?,
+            }
+            walk.rain.draw(renderer);
+            walk.speed_lines.draw(renderer, walk.walking_speed());
+            walk.draw_biome_tint(renderer)?;
+            walk.day_cycle.draw(renderer, walk.stats.frames);
+            walk.draw_edge_warning(renderer);
+            if walk.portal_flash.is_active() {
+                renderer.fill_rect(
+                    &Rect::new_from_x_y(0, 0, WIDTH, HEIGHT),
+                    "rgba(255, 255, 255, 0.35)",
+                );
+            }
+            walk.toasts.draw(renderer)?;
+            walk.draw_minimap(renderer);
+            walk.draw_godmode(renderer)?;
+            walk.draw_inspect(renderer)?;
+
+            match &walk.phase {
+                Phase::LevelComplete(complete) => {
+                    complete.draw(renderer, walk.level_index, &walk.strings)?
+                }
+                Phase::Paused(menu) => menu.draw(renderer, &walk.strings, walk.settings.assist)?,
+                Phase::Cutscene(cutscene) => {
+                    cutscene.draw(renderer, &walk.dog_image, &walk.players, &walk.strings)?
+                }
+                _ => {}
+            }
+
+            if renderer.debug_mode() {
+                walk.draw_stats(renderer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restart(&mut self) {
+        if let WalkTheDog::Loaded(walk) = self {
+            log!("Restarting game");
+            walk.restart();
+        }
+    }
+
+    fn dump_state(&self) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.dump_state();
+        }
+    }
+
+    fn guarded_keys(&self) -> &[String] {
+        let WalkTheDog::Loaded(walk) = self else {
+            return &[];
+        };
+
+        walk.guarded_keys
+            .get(!matches!(walk.phase, Phase::Playing) || walk.camera.is_some())
+    }
+
+    fn set_muted(&mut self, muted: bool) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.muted = muted;
+            walk.apply_settings();
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.audio.set_paused(paused);
+        }
+    }
+
+    fn set_time_scale(&mut self, scale: f32) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.time_scale = scale;
+            walk.stats.time_scaled |= scale != 1.0;
+        }
+    }
+
+    fn fullscreen_denied(&mut self) {
+        if let WalkTheDog::Loaded(walk) = self {
+            walk.toasts
+                .push(walk.strings.t("toast.fullscreen_denied"), HINT_TICKS);
+        }
+    }
+
+    fn take_screenshot_request(&mut self) -> bool {
+        match self {
+            WalkTheDog::Loaded(walk) => std::mem::take(&mut walk.screenshot_requested),
+            WalkTheDog::Loading { .. } => false,
+        }
+    }
+}
+
+struct Platform {
+    sheet: Sheet,
+    image: HtmlImageElement,
+    position: Point,
+    surface: Surface,
+}
+
+impl Platform {
+    fn new(sheet: Sheet, image: HtmlImageElement, position: Point, surface: Surface) -> Self {
+        Platform {
+            sheet,
+            image,
+            position,
+            surface,
+        }
+    }
+
+    /// The tiles laid side by side, left to right.
+    fn tiles(&self) -> [&'static str; 3] {
+        match self.surface {
+            Surface::Ground => ["13.png", "14.png", "15.png"],
+            Surface::Ice => ["17.png"; 3],
+        }
+    }
+
+    fn draw(&self, renderer: &Renderer) -> Result<()> {
+        let mut x = self.position.x;
+        for tile in self.tiles() {
+            let cell = self
+                .sheet
+                .frames
+                .get(tile)
+                .ok_or_else(|| anyhow!("{} does not exist", tile))?;
+            let width = self.sheet.scaled(cell.frame.w);
+            renderer.draw_image(
+                &self.image,
+                &Rect::new_from_x_y(cell.frame.x, cell.frame.y, cell.frame.w, cell.frame.h),
+                &Rect::new_from_x_y(x, self.position.y, width, self.sheet.scaled(cell.frame.h)),
+            )?;
+            x += width;
+        }
+
+        for x in self.bounding_boxes() {
+            renderer.draw_bounding_box(&x, DebugClass::Ground);
+        }
+
+        Ok(())
+    }
+
+    fn destination_box(&self) -> Rect {
+        let (width, height) = tile_row_size(&self.sheet, &self.tiles());
+
+        Rect::new_from_x_y(self.position.x, self.position.y, width, height)
+    }
+
+    fn bounding_boxes(&self) -> Vec<Rect> {
+        platform_boxes(&self.destination_box())
+    }
+}
+
+/// The on-screen size of `tiles` drawn side by side from `sheet`: their
+/// scaled widths added up, and the tallest of their scaled heights.
+fn tile_row_size(sheet: &Sheet, tiles: &[&str]) -> (i16, i16) {
+    tiles
+        .iter()
+        .map(|tile| {
+            sheet
+                .frames
+                .get(*tile)
+                .map_or(PLATFORM_TILE_SIZE, |cell| (cell.frame.w, cell.frame.h))
+        })
+        .fold((0, 0), |(width, height), (w, h)| {
+            (width + sheet.scaled(w), height.max(sheet.scaled(h)))
+        })
+}
+
+/// A platform drawn at `destination_box` collides as a full-height middle
+/// with a shallower end on each side.
+fn platform_boxes(destination_box: &Rect) -> Vec<Rect> {
+    const X_OFFSET: i16 = 60;
+    const END_HEIGHT: i16 = 54;
+
+    let bounding_box_one = Rect::new_from_x_y(
+        destination_box.position.x,
+        destination_box.position.y,
+        X_OFFSET,
+        END_HEIGHT,
+    );
+    let bounding_box_two = Rect::new_from_x_y(
+        destination_box.position.x + X_OFFSET,
+        destination_box.position.y,
+        destination_box.width - (X_OFFSET * 2),
+        destination_box.height,
+    );
+    let bounding_box_three = Rect::new_from_x_y(
+        destination_box.position.x + destination_box.width - X_OFFSET,
+        destination_box.position.y,
+        X_OFFSET,
+        END_HEIGHT,
+    );
+
+    vec![bounding_box_one, bounding_box_two, bounding_box_three]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn debug_options_read_known_params_and_ignore_the_rest() {
+        let options = DebugOptions::from_params(&params(&[
+            ("debug", "1"),
+            ("speed", "8"),
+            ("seed", "123"),
+            ("level", "assets/levels/test.json"),
+            ("godmode", "true"),
+            ("unknown", "1"),
+        ]));
+
+        assert_eq!(
+            options,
+            DebugOptions {
+                debug: true,
+                running_speed: Some(8),
+                seed: Some(123),
+                level: Some("assets/levels/test.json".to_string()),
+                godmode: true,
+                ..DebugOptions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_numbers_fall_back_to_defaults() {
+        let options = DebugOptions::from_params(&params(&[
+            ("speed", "fast"),
+            ("seed", "-1"),
+            ("debug", "yes"),
+        ]));
+
+        assert_eq!(options, DebugOptions::default());
+    }
+
+    #[test]
+    fn spawn_choices_follow_the_table_weights() {
+        const DRAWS: usize = 10_000;
+        let spawns = SpawnTable::default();
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = [0; 3];
+        for _ in 0..DRAWS {
+            let kind = spawns.choose(&mut rng).unwrap();
+            let index = spawns
+                .entries
+                .iter()
+                .position(|entry| entry.kind == kind)
+                .unwrap();
+            counts[index] += 1;
+        }
+
+        for (entry, count) in spawns.entries.iter().zip(counts) {
+            let observed = count as f32 / DRAWS as f32;
+            assert!(
+                (observed - entry.weight).abs() < 0.02,
+                "{:?} drawn {} of the time, expected {}",
+                entry.kind,
+                observed,
+                entry.weight
+            );
+        }
+    }
+
+    #[test]
+    fn a_boy_is_swept_away_once_wholly_past_the_left_edge() {
+        let boy = |x| Rect::new_from_x_y(x, 400, 60, 100);
+
+        assert_eq!(out_of_bounds(&boy(-59), false), None);
+        assert_eq!(out_of_bounds(&boy(-60), false), None);
+        assert_eq!(
+            out_of_bounds(&boy(-61), false),
+            Some(KnockOutCause::SweptAway)
+        );
+        assert_eq!(out_of_bounds(&boy(-61), true), None);
+    }
+
+    #[test]
+    fn a_boy_below_the_screen_fell_in_a_pit() {
+        let boy = |y| Rect::new_from_x_y(100, y, 60, 100);
+
+        assert_eq!(out_of_bounds(&boy(HEIGHT), false), None);
+        assert_eq!(
+            out_of_bounds(&boy(HEIGHT + 1), true),
+            Some(KnockOutCause::Pit)
+        );
+    }
+
+    fn trigger(once: bool) -> ArmedTrigger {
+        ArmedTrigger::new(Trigger {
+            rect: Rect::new_from_x_y(100, 0, 50, 600),
+            id: "door".to_string(),
+            once,
+            kind: TriggerKind::Event,
+        })
+    }
+
+    #[test]
+    fn a_once_trigger_fires_on_the_first_entry_only() {
+        let inside = [Rect::new_from_x_y(110, 400, 20, 40)];
+        let outside = [Rect::new_from_x_y(300, 400, 20, 40)];
+        let mut trigger = trigger(true);
+
+        assert!(!trigger.update(&outside));
+        assert!(trigger.update(&inside));
+        assert!(!trigger.update(&inside));
+        assert!(!trigger.update(&outside));
+        assert!(!trigger.update(&inside));
+    }
+
+    #[test]
+    fn a_repeating_trigger_fires_on_each_entry() {
+        let inside = [Rect::new_from_x_y(110, 400, 20, 40)];
+        let mut trigger = trigger(false);
+
+        assert!(trigger.update(&inside));
+        assert!(!trigger.update(&inside));
+        assert!(!trigger.update(&[]));
+        assert!(trigger.update(&inside));
+    }
+
+    #[test]
+    fn loading_progress_counts_every_asset_asked_for() {
+        let progress = LoadProgress::default();
+        let first = counted(&progress, async { Ok(1) });
+        let second = counted(&progress, async { Ok(2) });
+        assert_eq!(progress.fraction(), 0.0);
+
+        futures::executor::block_on(first).unwrap();
+        assert_eq!(progress.fraction(), 0.5);
+
+        futures::executor::block_on(second).unwrap();
+        assert_eq!(progress.fraction(), 1.0);
+    }
+
+    #[test]
+    fn a_reset_lays_out_the_same_course_for_the_same_seed() {
+        let level = Level {
+            spawn_slots: (1..=20).map(|slot| slot * 150).collect(),
+            ..Level::default()
+        };
+        let layout = |seed| level.spawn_layout(&level.spawns, 10, seed, 0, |_, _| None);
+
+        assert_eq!(layout(99), layout(99));
+        assert_ne!(layout(99), layout(100));
+    }
+
+    fn stepped_level() -> Level {
+        let section = |start, end, y| GroundSection { start, end, y };
+        Level {
+            ground: vec![
+                section(0, 400, HEIGHT),
+                section(400, 600, HEIGHT - SLOPE_SNAP),
+                section(600, 700, HEIGHT - 60),
+                section(800, 1200, HEIGHT),
+            ],
+            ..Level::default()
+        }
+    }
+
+    #[test]
+    fn walking_up_a_low_step_snaps_onto_it() {
+        let level = stepped_level();
+        let feet = |x| Point { x, y: HEIGHT };
+
+        assert_eq!(level.ground_under(feet(399)), Some(HEIGHT));
+        assert_eq!(level.ground_under(feet(400)), Some(HEIGHT - SLOPE_SNAP));
+        // Too tall to step onto, so it is a wall and there is no floor.
+        assert_eq!(level.ground_under(feet(650)), None);
+    }
+
+    #[test]
+    fn walking_into_a_gap_finds_no_ground() {
+        let level = stepped_level();
+
+        assert_eq!(level.ground_under(Point { x: 750, y: HEIGHT }), None);
+        assert_eq!(
+            level.ground_under(Point { x: 800, y: HEIGHT }),
+            Some(HEIGHT)
+        );
+    }
+
+    #[test]
+    fn godmode_is_set_down_past_the_pit_it_fell_into() {
+        let level = stepped_level();
+
+        assert_eq!(level.surface_past(750), Point { x: 800, y: HEIGHT });
+        // Nothing beyond the last pit, so back to the ground before it.
+        assert_eq!(level.surface_past(1250), Point { x: 1199, y: HEIGHT });
+        assert_eq!(
+            Level::default().surface_past(50),
+            Point { x: 50, y: HEIGHT }
+        );
+    }
+
+    #[test]
+    fn problem_spawns_are_rerolled() {
+        let level = Level {
+            spawn_slots: (1..=20).map(|slot| slot * 150).collect(),
+            difficulty_ramp: Vec::new(),
+            ..Level::default()
+        };
+        let no_stones = |_, (kind, x): (ObstacleKind, i16)| {
+            (kind == ObstacleKind::Stone).then(|| format!("stone at x={}", x))
+        };
+
+        let layout = level.spawn_layout(&level.spawns, 10, 5, 50, no_stones);
+
+        assert!(layout
+            .iter()
+            .all(|(kind, _)| *kind != Some(ObstacleKind::Stone)));
+    }
+
+    fn tile_sheet(scale: &str) -> Sheet {
+        let cell = |x: i16, w: i16, h: i16| {
+            serde_json::json!({
+                "frame": { "x": x, "y": 0, "w": w, "h": h },
+                "spriteSourceSize": { "x": 0, "y": 0, "w": w, "h": h },
+                "sourceSize": { "w": w, "h": h },
+            })
+        };
+        serde_json::from_value(serde_json::json!({
+            "frames": {
+                "13.png": cell(0, 128, 93),
+                "14.png": cell(130, 128, 93),
+                "15.png": cell(260, 100, 90),
+            },
+            "meta": { "scale": scale },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_tile_row_is_as_wide_as_its_tiles() {
+        let sheet = tile_sheet("1");
+
+        let size = tile_row_size(&sheet, &["13.png", "14.png", "15.png"]);
+
+        assert_eq!(size, (128 + 128 + 100, 93));
+    }
+
+    #[test]
+    fn a_sheet_scale_of_two_doubles_the_drawn_size() {
+        let tiles = ["13.png", "14.png", "15.png"];
+        let (width, height) = tile_row_size(&tile_sheet("1"), &tiles);
+
+        let doubled = tile_sheet("2");
+
+        assert_eq!(doubled.scaled(93), 186);
+        assert_eq!(tile_row_size(&doubled, &tiles), (width * 2, height * 2));
+    }
+
+    #[test]
+    fn platform_boxes_span_the_platform_without_gaps() {
+        let platform = Rect::new_from_x_y(200, 400, 384, 93);
+
+        let boxes = platform_boxes(&platform);
+
+        assert_eq!(boxes.first().unwrap().x(), platform.x());
+        assert_eq!(boxes.last().unwrap().right(), platform.right());
+        for pair in boxes.windows(2) {
+            assert_eq!(pair[0].right(), pair[1].x());
+        }
+        for rect in &boxes {
+            assert_eq!(rect.y(), platform.y());
+            assert!(rect.bottom() <= platform.bottom());
+        }
+    }
+
+    #[test]
+    fn scale_into_maps_world_corners_onto_the_map() {
+        let world = Rect::new_from_x_y(-200, 0, 2000, 600);
+        let map = Rect::new_from_x_y(1000, 40, 200, 60);
+
+        let whole = scale_into(&world, &map, &world);
+        assert_eq!(whole, map);
+
+        let middle = scale_into(&world, &map, &Rect::new_from_x_y(800, 300, 100, 60));
+        assert_eq!(middle, Rect::new_from_x_y(1100, 70, 10, 6));
+    }
+
+    #[test]
+    fn scale_into_keeps_tiny_rects_visible() {
+        let world = Rect::new_from_x_y(0, 0, 2000, 600);
+        let map = Rect::new_from_x_y(0, 0, 200, 60);
+
+        let coin = scale_into(&world, &map, &Rect::new_from_x_y(500, 300, 4, 4));
+
+        assert_eq!((coin.width, coin.height), (1, 1));
+    }
+
+    fn kinds(spawns: &SpawnTable) -> Vec<ObstacleKind> {
+        spawns.entries.iter().map(|entry| entry.kind).collect()
+    }
+
+    #[test]
+    fn a_slot_repeats_the_previous_kind_before_breaking_the_cap() {
+        let spawns = SpawnTable::default();
+
+        let slot = spawns.for_slot(0.3, 0, Some(ObstacleKind::LowPlatform));
+
+        assert_eq!(kinds(&slot), [ObstacleKind::LowPlatform]);
+    }
+
+    #[test]
+    fn a_slot_nothing_fits_gets_the_easiest_entry() {
+        let spawns = SpawnTable::default();
+
+        let slot = spawns.for_slot(0.0, 0, None);
+
+        assert_eq!(kinds(&slot), [ObstacleKind::LowPlatform]);
+    }
+
+    #[test]
+    fn seeded_spawns_stay_under_the_ramp() {
+        let level = Level {
+            spawn_slots: (1..=20).map(|slot| slot * 150).collect(),
+            ..Level::default()
+        };
+
+        let run: Vec<(ObstacleKind, i16)> = level
+            .spawn_layout(&level.spawns, 10, 7, 0, |_, _| None)
+            .into_iter()
+            .map(|(kind, x)| (kind.unwrap(), x))
+            .collect();
+
+        for pair in run.windows(2) {
+            assert_ne!(
+                pair[0].0, pair[1].0,
+                "{:?} spawned twice in a row",
+                pair[0].0
+            );
+        }
+        for &(kind, x) in &run {
+            let entry = level.spawns.entries.iter().find(|entry| entry.kind == kind);
+            assert!(entry.unwrap().difficulty <= level.max_difficulty_at(x));
+        }
+        assert!(run
+            .iter()
+            .any(|&(kind, _)| kind == ObstacleKind::HighPlatform));
+    }
+
+    const FIGHT: BossFight = BossFight {
+        throws: 3,
+        interval: 60,
+        flight_frames: 30,
+    };
+
+    /// Fights `boss` to the end against a boy at `target`, returning how
+    /// many frames it took and how often the boy was hit.
+    fn fight_to_the_end(boss: &mut Boss, target: Rect) -> (u32, usize) {
+        let mut projectiles = Vec::new();
+        let mut hits = 0;
+        for frame in 1..=1000 {
+            let (hit, _) = boss.fight(&mut projectiles, &[Some(target)], 0.0);
+            hits += hit.len();
+            if boss.thrown == FIGHT.throws && projectiles.is_empty() {
+                return (frame, hits);
+            }
+        }
+        panic!("The fight never ended");
+    }
+
+    #[test]
+    fn surviving_every_throw_defeats_the_boss() {
+        let out_of_reach = Rect::new_from_x_y(180, 0, 40, 100);
+        let mut boss = Boss::new(FIGHT);
+
+        let (frames, hits) = fight_to_the_end(&mut boss, out_of_reach);
+
+        assert_eq!(hits, 0);
+        assert_eq!(boss.survived, FIGHT.throws);
+        assert!(boss.defeated());
+        assert_eq!(
+            fight_to_the_end(&mut Boss::new(FIGHT), out_of_reach).0,
+            frames
+        );
+    }
+
+    #[test]
+    fn throws_that_hit_do_not_count_as_survived() {
+        let on_the_ground = Rect::new_from_x_y(180, HEIGHT - 100, 40, 100);
+        let mut boss = Boss::new(FIGHT);
+
+        let (_, hits) = fight_to_the_end(&mut boss, on_the_ground);
+
+        assert_eq!(hits, FIGHT.throws as usize);
+        assert_eq!(boss.survived, 0);
+        assert!(!boss.defeated());
+    }
+
+    #[test]
+    fn the_boss_holds_its_throws_without_a_target() {
+        let mut boss = Boss::new(FIGHT);
+        let mut projectiles = Vec::new();
+        for _ in 0..FIGHT.interval * 2 {
+            boss.fight(&mut projectiles, &[None], 0.0);
+        }
+
+        assert_eq!(boss.thrown, 0);
+        assert!(projectiles.is_empty());
+    }
+
+    #[test]
+    fn run_summary_serializes_to_the_documented_shape() {
+        let stats = RunStats {
+            seed: 7,
+            distance: 1234,
+            coins: 3,
+            near_misses: 2,
+            frames: 120,
+            daily: Some(20_000),
+            ..RunStats::default()
+        };
+
+        let json = serde_json::to_value(stats.summary()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "seed": 7,
+                "score": 123 + 3 * 10 + 2 * NEAR_MISS_POINTS,
+                "distance": 1234,
+                "coins": 3,
+                "durationMs": 120.0 * FRAME_SIZE,
+                "godmode": false,
+                "timeScaled": false,
+                "daily": 20_000,
+                "assist": false,
+            })
+        );
+    }
+
+    #[test]
+    fn an_overlay_advances_a_frame_per_update_and_loops() {
+        let mut overlay = Overlay::new(OverlayKind::Shield);
+
+        overlay.update();
+        assert_eq!(overlay.frame, 1);
+        assert_eq!(overlay.phase(), 1.0 / f32::from(Overlay::FRAMES));
+
+        for _ in 1..Overlay::FRAMES {
+            overlay.update();
+        }
+        assert_eq!(overlay.frame, 0);
+        assert_eq!(overlay.phase(), 0.0);
+    }
+
+    #[test]
+    fn the_same_date_lays_out_the_same_daily_course() {
+        let level = Level {
+            spawn_slots: (1..=20).map(|slot| slot * 150).collect(),
+            ..Level::default()
+        };
+        let course = |day| {
+            let config = WalkTheDogBuilder::new().daily(day).config;
+            level.spawn_layout(&level.spawns, 10, config.seed, 0, |_, _| None)
+        };
+
+        assert_eq!(course(20_000), course(20_000));
+        assert_ne!(course(20_000), course(20_001));
+    }
+
+    #[test]
+    fn level_results_survive_a_round_trip_through_json() {
+        let mut results = LevelResults::default();
+        results.record("assets/levels/level1.json", Medal::Silver);
+        results.record("assets/levels/steps.json", Medal::Gold);
+
+        let json = serde_json::to_string(&results).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<LevelResults>(&json).unwrap(),
+            results
+        );
+        assert_eq!(
+            serde_json::to_value(&results).unwrap()["medals"]["assets/levels/steps.json"],
+            "gold"
+        );
+    }
+
+    #[test]
+    fn a_better_medal_replaces_the_stored_one_but_a_worse_never_does() {
+        let level = "assets/levels/level1.json";
+        let mut results = LevelResults::default();
+
+        assert!(results.record(level, Medal::Bronze));
+        assert!(results.record(level, Medal::Gold));
+        assert!(!results.record(level, Medal::Silver));
+        assert!(!results.record(level, Medal::Gold));
+
+        assert_eq!(results.medal(level), Some(Medal::Gold));
+    }
+
+    #[test]
+    fn menu_arrows_move_once_per_press_and_wrap() {
+        let mut nav = MenuNav::new();
+        nav.navigate(0, false, 3);
+        let mut press = |vertical, select| {
+            let choice = nav.navigate(vertical, select, 3);
+            // Let go again so the next press counts.
+            nav.navigate(0, false, 3);
+            choice
+        };
+
+        assert_eq!(press(1, false), None);
+        assert_eq!(press(1, false), None);
+        assert_eq!(press(1, false), None);
+        assert_eq!(press(-1, false), None);
+        assert_eq!(press(0, true), Some(2));
+    }
+
+    #[test]
+    fn keys_held_as_a_menu_opens_do_not_count() {
+        let mut nav = MenuNav::new();
+
+        assert_eq!(nav.navigate(1, true, 3), None);
+        assert_eq!(nav.navigate(1, true, 3), None);
+        assert_eq!(nav.selected, 0);
+    }
+
+    fn checkpoint_at(x: i16) -> WalkSnapshot {
+        WalkSnapshot {
+            boys: vec![BoySave {
+                state: StateKind::Running,
+                frame: 0,
+                position: Point { x, y: 400 },
+                velocity: Point { x: 4, y: 0 },
+                facing: Direction::Right,
+            }],
+            collected_coins: Vec::new(),
+            magnet_pickup: None,
+            jetpack_pickup: None,
+            spent_triggers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dying_after_a_checkpoint_respawns_there() {
+        let mut checkpoints = Checkpoints::default();
+        checkpoints.reach(checkpoint_at(700));
+
+        assert!(!checkpoints.game_over(true));
+        assert!(checkpoints.update(false).is_none());
+        let mut ticks = 1;
+        let restored = loop {
+            if let Some(snapshot) = checkpoints.update(true) {
+                break snapshot;
+            }
+            ticks += 1;
+        };
+
+        assert_eq!(ticks, RESPAWN_TICKS);
+        assert_eq!(restored.boys[0].position, Point { x: 700, y: 400 });
+    }
+
+    #[test]
+    fn dying_before_any_checkpoint_ends_the_run() {
+        let mut checkpoints = Checkpoints::default();
+
+        assert!(checkpoints.game_over(true));
+        assert!(!checkpoints.game_over(false));
+        assert!((0..RESPAWN_TICKS * 2).all(|_| checkpoints.update(true).is_none()));
+    }
+
+    fn biome(name: &str, from: i32, spawns: &[ObstacleKind]) -> Biome {
+        Biome {
+            name: name.to_string(),
+            from,
+            background: String::new(),
+            tint: None,
+            spawns: spawns.to_vec(),
+        }
+    }
+
+    fn biomes() -> Biomes {
+        Biomes {
+            bands: vec![
+                biome("grass", 0, &[]),
+                biome(
+                    "desert",
+                    2000,
+                    &[ObstacleKind::Stone, ObstacleKind::HighPlatform],
+                ),
+                biome("snow", 4000, &[ObstacleKind::IcePlatform]),
+            ],
+        }
+    }
+
+    #[test]
+    fn a_distance_falls_in_the_last_band_starting_at_or_before_it() {
+        let biomes = biomes();
+
+        assert_eq!(biomes.index_at(0), Some(0));
+        assert_eq!(biomes.index_at(1999), Some(0));
+        assert_eq!(biomes.index_at(2000), Some(1));
+        assert_eq!(biomes.index_at(100_000), Some(2));
+        assert_eq!(biomes.index_at(-1), None);
+        assert_eq!(Biomes::default().index_at(500), None);
+    }
+
+    #[test]
+    fn crossing_into_a_band_switches_the_seeded_layout_to_its_pool() {
+        let level = Level {
+            spawn_slots: (1..=20).map(|slot| slot * 150).collect(),
+            ..Level::default()
+        };
+        let biomes = biomes();
+        let layout = |meters| {
+            let pool = biomes.bands[biomes.index_at(meters).unwrap()].pool(&level.spawns);
+            level.spawn_layout(&pool, 10, 42, 0, |_, _| None)
+        };
+
+        let desert = layout(2500);
+        assert_eq!(desert, layout(2500));
+        assert!(desert.iter().all(|(kind, _)| matches!(
+            kind,
+            Some(ObstacleKind::Stone | ObstacleKind::HighPlatform)
+        )));
+        assert_ne!(layout(0), desert);
+    }
+
+    #[test]
+    fn a_biome_with_none_of_its_kinds_in_the_table_keeps_only_the_easiest() {
+        // The default table has no ice platforms for snow to keep.
+        let pool = biomes().bands[2].pool(&SpawnTable::default());
+
+        assert_eq!(kinds(&pool), [ObstacleKind::LowPlatform]);
+    }
+
+    /// A boy 60 wide standing on the ground from x=100.
+    const ASSIST_BOY: Rect = Rect {
+        position: Point { x: 100, y: 480 },
+        width: 60,
+        height: 120,
+    };
+
+    fn crate_at(x: i16) -> (AssistAction, Rect) {
+        (AssistAction::Slide, Rect::new_from_x_y(x, 530, 70, 70))
+    }
+
+    fn stone_at(x: i16) -> (AssistAction, Rect) {
+        (AssistAction::Jump, Rect::new_from_x_y(x, 550, 90, 50))
+    }
+
+    #[test]
+    fn assist_slides_into_a_crate_and_jumps_a_stone_whichever_is_nearer() {
+        let obstacles = [stone_at(400), crate_at(250)];
+
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &obstacles, 300),
+            AssistAction::Slide
+        );
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[crate_at(400), stone_at(250)], 300),
+            AssistAction::Jump
+        );
+    }
+
+    #[test]
+    fn assist_ignores_a_crate_beyond_its_reach() {
+        // The boy's right edge is at 160, so 300 ahead reaches 460.
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[crate_at(460)], 300),
+            AssistAction::Slide
+        );
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[crate_at(461)], 300),
+            AssistAction::Jump
+        );
+        // Nor one he has already passed.
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[crate_at(20)], 300),
+            AssistAction::Jump
+        );
+    }
+
+    #[test]
+    fn assist_ignores_a_crate_above_his_head() {
+        let overhead = (AssistAction::Slide, Rect::new_from_x_y(200, 400, 70, 70));
+        let touching = (AssistAction::Slide, Rect::new_from_x_y(200, 411, 70, 70));
+
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[overhead], 300),
+            AssistAction::Jump
+        );
+        assert_eq!(
+            assist_action(&ASSIST_BOY, &[touching], 300),
+            AssistAction::Slide
+        );
+    }
+
+    #[test]
+    fn speed_lines_start_past_the_threshold_and_top_out() {
+        let lines = SpeedLines::new(5, 1);
+
+        assert_eq!(lines.count(0), 0);
+        assert_eq!(lines.count(5), 0);
+        assert_eq!(lines.count(6), SPEED_LINES_PER_SPEED);
+        assert_eq!(lines.count(7), 2 * SPEED_LINES_PER_SPEED);
+        assert_eq!(lines.count(i16::MAX), MAX_SPEED_LINES);
+    }
+
+    #[test]
+    fn speed_lines_scroll_only_when_fast_and_stay_within_a_span() {
+        let mut lines = SpeedLines::new(5, 1);
+
+        lines.update(5);
+        assert_eq!(lines.scroll, 0.0);
+
+        for _ in 0..100_000 {
+            lines.update(20);
+        }
+        assert!((0.0..SpeedLines::SPAN).contains(&lines.scroll));
+    }
+
+    const FEET: Rect = Rect::new_from_x_y(100, 490, 40, 20);
+    const SOLID: Footing = Footing {
+        covered: 1.0,
+        left: 0,
+        right: 300,
+    };
+    const EDGE: Footing = Footing {
+        covered: 0.25,
+        left: 0,
+        right: 110,
+    };
+
+    #[test]
+    fn teeter_stays_steady_on_solid_footing() {
+        let mut timer = None;
+
+        for _ in 0..TEETER_TICKS * 2 {
+            assert_eq!(teeter(&mut timer, &FEET, Some(SOLID)), Teeter::Steady);
+        }
+        assert!(timer.is_none());
+    }
+
+    #[test]
+    fn teeter_recovers_once_the_feet_are_back_on() {
+        let mut timer = None;
+
+        assert_eq!(teeter(&mut timer, &FEET, Some(EDGE)), Teeter::Started);
+        assert!(matches!(
+            teeter(&mut timer, &FEET, Some(EDGE)),
+            Teeter::Wobbling(_)
+        ));
+        assert_eq!(teeter(&mut timer, &FEET, Some(SOLID)), Teeter::Steady);
+        assert!(timer.is_none());
+        assert_eq!(teeter(&mut timer, &FEET, Some(EDGE)), Teeter::Started);
+    }
+
+    #[test]
+    fn teeter_tips_off_over_the_nearer_edge() {
+        let mut timer = None;
+
+        assert_eq!(teeter(&mut timer, &FEET, Some(EDGE)), Teeter::Started);
+        let mut wobbles = 0;
+        let dx = loop {
+            match teeter(&mut timer, &FEET, Some(EDGE)) {
+                Teeter::Wobbling(_) => wobbles += 1,
+                Teeter::TipOff(dx) => break dx,
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+
+        assert_eq!(wobbles, TEETER_TICKS - 1);
+        assert_eq!(dx, 10);
+        assert!(timer.is_none());
+    }
+
+    #[test]
+    fn a_minute_of_debris_builds_no_more_pieces_than_its_slots() {
+        let mut field = DebrisField::new();
+        let center = Point { x: 300, y: 500 };
+        let up = Point { x: 0, y: -4 };
+
+        for frame in 0..FRAMES_PER_MINUTE {
+            if frame % 45 == 0 {
+                for _ in 0..LANDING_PUFFS {
+                    field.spawn(center, up, DEBRIS_FRAMES / 3, DUST_COLOR);
+                }
+            }
+            if frame % 300 == 0 {
+                for _ in 0..WOOD_CHIPS {
+                    field.spawn(center, up, DEBRIS_FRAMES, WOOD_COLOR);
+                }
+            }
+            field.update();
+            assert!(field.iter().count() <= MAX_DEBRIS);
+        }
+
+        let spawned = (FRAMES_PER_MINUTE.div_ceil(45) as usize * LANDING_PUFFS
+            + FRAMES_PER_MINUTE.div_ceil(300) as usize * WOOD_CHIPS) as u32;
+        assert_eq!(field.pool.acquisitions(), spawned);
+        // Only the biggest burst, a landing on top of a broken crate.
+        assert_eq!(
+            field.pool.allocations(),
+            (LANDING_PUFFS + WOOD_CHIPS) as u32
+        );
+    }
+
+    #[test]
+    fn debris_past_its_slots_is_dropped_and_cleared_pieces_are_reused() {
+        let mut field = DebrisField::new();
+        let origin = Point { x: 0, y: 0 };
+
+        for _ in 0..MAX_DEBRIS + 5 {
+            field.spawn(origin, origin, DEBRIS_FRAMES, DUST_COLOR);
+        }
+        assert_eq!(field.iter().count(), MAX_DEBRIS);
+
+        field.clear();
+        field.spawn(origin, origin, DEBRIS_FRAMES, DUST_COLOR);
+        assert_eq!(field.iter().count(), 1);
+        assert_eq!(field.pool.allocations(), MAX_DEBRIS as u32);
+    }
+
+    #[test]
+    fn allocation_rate_reports_each_full_minute() {
+        let mut rate = AllocationRate::default();
+
+        for _ in 1..FRAMES_PER_MINUTE {
+            rate.update(4, 10);
+        }
+        assert_eq!(rate.last_minute, None);
+        rate.update(4, 10);
+        assert_eq!(rate.last_minute, Some((4, 10)));
+
+        for _ in 0..FRAMES_PER_MINUTE {
+            rate.update(5, 30);
+        }
+        assert_eq!(rate.last_minute, Some((1, 20)));
+    }
+
+    /// Parses a level fixture: the required stone and platform, with
+    /// `fields`, the members of a JSON object, added or replacing them.
+    fn level_fixture(fields: &str) -> Result<Level> {
+        let mut json = serde_json::json!({
+            "stone": { "x": 150, "y": 546 },
+            "platform": { "x": 370, "y": 375 },
+        });
+        let fields: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&format!("{{ {} }}", fields)).expect("fixtures are valid JSON");
+        for (name, value) in fields {
+            json[name] = value;
+        }
+        checked_level("fixture.json", serde_json::from_value(json)?)
+    }
+
+    fn fixture_error(fields: &str) -> String {
+        match level_fixture(fields) {
+            Ok(_) => panic!("{} was accepted", fields),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn malformed_levels_are_rejected_with_what_is_wrong() {
+        let cases = [
+            (
+                r#""stone": { "x": -5, "y": 546 }"#,
+                "stone at (-5, 546) is outside the 1200x600 screen",
+            ),
+            (
+                r#""platform": { "x": 370, "y": 100 }"#,
+                "platform top at y=100 is higher than a jump reaches (y=300)",
+            ),
+            (
+                r#""ground": [{ "start": 500, "end": 500, "y": 600 }]"#,
+                "ground section 0 is empty",
+            ),
+            (
+                r#""ground": [{ "start": 400, "end": 900, "y": 550 }, { "start": 0, "end": 500, "y": 600 }]"#,
+                "ground sections 0 and 1 overlap",
+            ),
+            (
+                r#""slopes": [{ "start": { "x": 100, "y": 500 }, "end": { "x": 100, "y": 400 } }]"#,
+                "slope 0 is vertical",
+            ),
+            (
+                r#""portals": [{ "entrance": { "position": { "x": 600, "y": 400 }, "width": 40, "height": 80 }, "exit": { "x": 300, "y": 400 } }]"#,
+                "portal 0 must lead forward",
+            ),
+            (
+                r#""triggers": [
+                    { "rect": { "position": { "x": 100, "y": 400 }, "width": 20, "height": 20 }, "id": "door" },
+                    { "rect": { "position": { "x": 300, "y": 400 }, "width": 20, "height": 20 }, "id": "door" }
+                ]"#,
+                "trigger id door is used twice",
+            ),
+            (r#""next": " ""#, "next level path is empty"),
+            (
+                r#""medals": { "by": "score", "bronze": 300, "silver": 200, "gold": 100 }"#,
+                "medal thresholds must get harder from bronze to gold",
+            ),
+            (
+                r#""spawnSlots": [820], "spawns": [{ "kind": "stone", "weight": 0 }]"#,
+                "level has spawn slots but no spawn entry with a positive weight",
+            ),
+        ];
+
+        for (fields, message) in cases {
+            assert_eq!(
+                fixture_error(fields),
+                format!("Invalid level fixture.json: {}", message)
+            );
+        }
+    }
+
+    #[test]
+    fn unsorted_ground_is_put_in_order_when_loaded() {
+        let level = level_fixture(
+            r#""ground": [{ "start": 600, "end": 1200, "y": 550 }, { "start": 0, "end": 500, "y": 600 }]"#,
+        )
+        .unwrap();
+
+        let starts: Vec<i16> = level.ground.iter().map(|section| section.start).collect();
+        assert_eq!(starts, [0, 600]);
+    }
+
+    #[test]
+    fn a_valid_level_survives_a_serde_round_trip() {
+        let json = serde_json::to_value(Level::default()).unwrap();
+
+        let level =
+            checked_level("default", serde_json::from_value(json.clone()).unwrap()).unwrap();
+
+        assert_eq!(serde_json::to_value(level).unwrap(), json);
+    }
+
+    /// A run on blank images and a silent jump, already playing.
+    #[cfg(target_arch = "wasm32")]
+    async fn blank_walk() -> Walk {
+        const SILENCE: &str = "data:audio/wav;base64,UklGRjQAAABXQVZFZm10IBAAAAABAAEAQB8AAIA+AAACABAAZGF0YRAAAAAAAAAAAAAAAAAAAAAAAAAA";
+        let blank = || HtmlImageElement::new().unwrap();
+        let sheet = || serde_json::from_str::<Sheet>(r#"{"frames": {}}"#).unwrap();
+        let audio = Audio::new().unwrap();
+        let jump_sound = audio.load_sound(SILENCE).await.unwrap();
+        let assets = WalkAssets {
+            sheet: sheet(),
+            rhb_image: blank(),
+            background: blank(),
+            stone: blank(),
+            tiles: (sheet(), blank()),
+            crate_image: blank(),
+            dog_image: blank(),
+            boss_images: (blank(), blank()),
+            audio,
+            jump_sound,
+            strings: Strings::load("en").await,
+            biomes: Biomes::default(),
+            level: Level::default(),
+        };
+
+        let mut walk =
+            Walk::new(assets, &GameConfig::default(), &SharedCallbacks::default()).unwrap();
+        walk.pending_resume = None;
+        walk.phase = Phase::Playing;
+        walk
+    }
+
+    /// Everything a paused run must keep: the boys, the run's stats and
+    /// the obstacles around them.
+    #[cfg(target_arch = "wasm32")]
+    fn frozen(game: &WalkTheDog) -> serde_json::Value {
+        let WalkTheDog::Loaded(walk) = game else {
+            panic!("the game is still loading");
+        };
+        serde_json::json!({
+            "run": walk.snapshot(),
+            "hazards": walk.hazards(),
+            "platforms": walk.platforms.iter().map(|platform| platform.position).collect::<Vec<_>>(),
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    async fn resuming_from_the_pause_menu_puts_the_run_back_as_it_was() {
+        let mut game = WalkTheDog::Loaded(Box::new(blank_walk().await));
+        let key = || web_sys::KeyboardEvent::new("keydown").unwrap();
+        let mut keystate = KeyState::new();
+        keystate.set_pressed("ArrowRight", key());
+        let start = frozen(&game);
+        for _ in 0..30 {
+            game.update(&keystate);
+        }
+        let before = frozen(&game);
+        assert_ne!(before, start, "the run should be under way");
+
+        keystate.set_pressed(MENU_BACK_KEY, key());
+        game.update(&keystate);
+        for _ in 0..30 {
+            game.update(&keystate);
+        }
+        keystate.set_released(MENU_BACK_KEY);
+        game.update(&keystate);
+        let WalkTheDog::Loaded(walk) = &game else {
+            unreachable!();
+        };
+        assert!(matches!(walk.phase, Phase::Paused(_)));
+        keystate.set_pressed(MENU_BACK_KEY, key());
+        game.update(&keystate);
+
+        let WalkTheDog::Loaded(walk) = &game else {
+            unreachable!();
+        };
+        assert!(matches!(walk.phase, Phase::Playing));
+        assert_eq!(frozen(&game), before);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[test]
+    async fn the_gameover_event_carries_the_final_score() {
+        let mut walk = blank_walk().await;
+        let target: EventTarget = browser::document()
+            .unwrap()
+            .create_element("div")
+            .unwrap()
+            .into();
+        walk.callbacks.borrow_mut().set_event_target(target.clone());
+        let scores = Rc::new(RefCell::new(Vec::new()));
+        let heard = Rc::clone(&scores);
+        let on_game_over = browser::closure_wrap(Box::new(move |event: web_sys::CustomEvent| {
+            let score = js_sys::Reflect::get(&event.detail(), &JsValue::from("score")).unwrap();
+            heard.borrow_mut().push(score.as_f64().unwrap() as u32);
+        })
+            as Box<dyn FnMut(web_sys::CustomEvent)>);
+        let _listener =
+            browser::EventListenerGuard::new(&target, "wtd:gameover", on_game_over).unwrap();
+
+        walk.stats.coins = 3;
+        walk.players[0].knock_out(KnockOutCause::Hazard);
+        let mut game = WalkTheDog::Loaded(Box::new(walk));
+        let keystate = KeyState::new();
+        for _ in 0..120 {
+            game.update(&keystate);
+        }
 
-        vec![bounding_box_one, bounding_box_two, bounding_box_three]
+        let WalkTheDog::Loaded(walk) = &game else {
+            unreachable!();
+        };
+        assert!(walk.is_game_over());
+        assert!(walk.stats.score() >= 30);
+        assert_eq!(*scores.borrow(), [walk.stats.score()]);
     }
 }
@@ -2,22 +2,288 @@
 mod browser;
 mod engine;
 mod game;
+mod locale;
+mod metrics;
+mod sound;
 
-use engine::GameLoop;
-use game::WalkTheDog;
+use engine::{
+    GameCommand, GameLoop, GameLoopConfig, InputTarget, Point, ReadyCallback, ScreenTransform,
+    SharedCommandQueue, SharedRecording, SharedScreenTransform, MAX_RECORDED_FRAMES,
+};
+use game::{DebugOptions, SharedCallbacks, WalkTheDogBuilder};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
 use wasm_bindgen::prelude::*;
+use web_sys::HtmlCanvasElement;
+
+const DEFAULT_CANVAS_ID: &str = "canvas";
 
 #[wasm_bindgen(start)]
 pub fn main_js() -> Result<(), JsValue> {
     console_error_panic_hook::set_once();
 
-    browser::spawn_local(async move {
-        let game = WalkTheDog::new();
+    Ok(())
+}
+
+/// Controls a running game from JavaScript.
+///
+/// Commands are queued and applied at the top of the next frame, so calls
+/// made while the game is still loading take effect once it starts.
+#[wasm_bindgen]
+pub struct GameHandle {
+    commands: SharedCommandQueue,
+    callbacks: SharedCallbacks,
+    canvas: HtmlCanvasElement,
+    recording: SharedRecording,
+    screen: SharedScreenTransform,
+}
+
+#[wasm_bindgen]
+impl GameHandle {
+    pub fn pause(&self) {
+        self.send(GameCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(GameCommand::Resume);
+    }
+
+    pub fn restart(&self) {
+        self.send(GameCommand::Restart);
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.send(GameCommand::SetMuted(muted));
+    }
+
+    /// Downloads a PNG of the next drawn frame.
+    pub fn screenshot(&self) {
+        self.send(GameCommand::Screenshot);
+    }
 
-        GameLoop::start(game)
-            .await
-            .expect("Could not start game loop");
+    /// Enters or leaves fullscreen. Call it from a click or key handler;
+    /// browsers ignore fullscreen requests made outside a user gesture.
+    pub fn toggle_fullscreen(&self) {
+        engine::toggle_fullscreen(&self.canvas, &self.commands);
+    }
+
+    /// Captures every drawn frame as a PNG data URL until `stop_recording`
+    /// or `max_frames` (at most 600) is reached. Earlier frames are dropped.
+    pub fn start_recording(&self, max_frames: Option<usize>) {
+        self.recording
+            .borrow_mut()
+            .start(max_frames.unwrap_or(MAX_RECORDED_FRAMES));
+    }
+
+    pub fn stop_recording(&self) {
+        self.recording.borrow_mut().stop();
+    }
+
+    /// Hands over the frames recorded so far, oldest first, as data URLs
+    /// ready to encode.
+    pub fn take_recording(&self) -> js_sys::Array {
+        self.recording
+            .borrow_mut()
+            .take_frames()
+            .into_iter()
+            .map(JsValue::from)
+            .collect()
+    }
+
+    /// Page coordinates of game point (`x`, `y`) as `[clientX, clientY]`,
+    /// for placing page elements over things in the game.
+    pub fn screen_point(&self, x: i16, y: i16) -> js_sys::Array {
+        let (client_x, client_y) = self.screen.get().to_screen(Point { x, y });
+        js_sys::Array::of2(&client_x.into(), &client_y.into())
+    }
+
+    /// Logs the current game state as JSON and copies it to the clipboard.
+    pub fn dump_state(&self) {
+        self.send(GameCommand::DumpState);
+    }
+
+    /// Stops the game for good: no more frames are drawn, its listeners are
+    /// removed and its memory is freed. Other calls do nothing afterwards.
+    pub fn destroy(&self) {
+        self.send(GameCommand::Destroy);
+    }
+
+    /// Called with the current score a few times per second while it changes.
+    pub fn on_score_changed(&self, callback: js_sys::Function) {
+        self.callbacks.borrow_mut().set_score_changed(callback);
+    }
+
+    /// Called once per run with `{ seed, score, distance, coins, durationMs }`.
+    pub fn on_game_over(&self, callback: js_sys::Function) {
+        self.callbacks.borrow_mut().set_game_over(callback);
+    }
+}
+
+impl GameHandle {
+    fn send(&self, command: GameCommand) {
+        self.commands.borrow_mut().push_back(command);
+    }
+}
+
+/// Options for `start`. Without a canvas or id, the element with id
+/// `canvas` is used and keys are read from the whole document.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct StartOptions {
+    canvas: Option<HtmlCanvasElement>,
+    canvas_id: Option<String>,
+    input_target: InputTarget,
+    two_players: bool,
+    input_per_step: bool,
+    letterbox: bool,
+    auto_run: Option<bool>,
+    on_ready: Option<js_sys::Function>,
+    metrics_url: Option<String>,
+}
+
+#[wasm_bindgen]
+impl StartOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> StartOptions {
+        Default::default()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_canvas(&mut self, canvas: HtmlCanvasElement) {
+        self.canvas = Some(canvas);
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_canvas_id(&mut self, canvas_id: String) {
+        self.canvas_id = Some(canvas_id);
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_keys_on_canvas(&mut self, keys_on_canvas: bool) {
+        self.input_target = if keys_on_canvas {
+            InputTarget::Canvas
+        } else {
+            InputTarget::Document
+        };
+    }
+
+    /// Adds a second player controlled with WASD.
+    #[wasm_bindgen(setter)]
+    pub fn set_two_players(&mut self, two_players: bool) {
+        self.two_players = two_players;
+    }
+
+    /// Applies key events per fixed update rather than per frame, which
+    /// cuts input latency on slow frames.
+    #[wasm_bindgen(setter)]
+    pub fn set_input_per_step(&mut self, input_per_step: bool) {
+        self.input_per_step = input_per_step;
+    }
+
+    /// Keeps the game's aspect ratio inside a canvas the page sizes freely,
+    /// e.g. to fill the window, with black bars around the play area.
+    #[wasm_bindgen(setter)]
+    pub fn set_letterbox(&mut self, letterbox: bool) {
+        self.letterbox = letterbox;
+    }
+
+    /// Forces auto-run on or off, overriding the player's setting.
+    #[wasm_bindgen(setter)]
+    pub fn set_auto_run(&mut self, auto_run: bool) {
+        self.auto_run = Some(auto_run);
+    }
+
+    /// Posts anonymous gameplay metrics as JSON to this URL. Nothing is
+    /// recorded without it.
+    #[wasm_bindgen(setter)]
+    pub fn set_metrics_url(&mut self, metrics_url: String) {
+        self.metrics_url = Some(metrics_url);
+    }
+
+    /// Called with the canvas once assets have loaded, just before the
+    /// first frame. A good place to hide a loading screen.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_ready(&mut self, on_ready: js_sys::Function) {
+        self.on_ready = Some(on_ready);
+    }
+}
+
+impl StartOptions {
+    fn resolve_canvas(&mut self) -> anyhow::Result<HtmlCanvasElement> {
+        match (self.canvas.take(), &self.canvas_id) {
+            (Some(canvas), _) => Ok(canvas),
+            (None, Some(id)) => browser::canvas_by_id(id),
+            (None, None) => browser::canvas_by_id(DEFAULT_CANVAS_ID),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn start(options: Option<StartOptions>) -> Result<GameHandle, JsValue> {
+    let mut options = options.unwrap_or_default();
+    let canvas = options
+        .resolve_canvas()
+        .map_err(|err| JsValue::from(err.to_string()))?;
+    let debug_options = browser::query_params()
+        .map(|params| DebugOptions::from_params(&params))
+        .unwrap_or_default();
+    let loop_config = GameLoopConfig {
+        input_target: options.input_target,
+        debug_mode: debug_options.debug,
+        input_per_step: options.input_per_step,
+        letterbox: options.letterbox,
+    };
+
+    let commands: SharedCommandQueue = Rc::new(RefCell::new(VecDeque::new()));
+    let loop_commands = Rc::clone(&commands);
+    let recording: SharedRecording = Rc::new(RefCell::new(Default::default()));
+    let loop_recording = Rc::clone(&recording);
+    let screen: SharedScreenTransform = Rc::new(Cell::new(ScreenTransform::measure(&canvas)));
+    let loop_screen = Rc::clone(&screen);
+    let callbacks: SharedCallbacks = Rc::new(RefCell::new(Default::default()));
+    callbacks
+        .borrow_mut()
+        .set_event_target(canvas.clone().into());
+    let handle_canvas = canvas.clone();
+    let error_callbacks = Rc::clone(&callbacks);
+    let on_ready = options.on_ready.take().map(|callback| {
+        let canvas = canvas.clone();
+        Box::new(move |_: &mut dyn engine::Game, _: &engine::Renderer| {
+            browser::call_function(&callback, &canvas);
+        }) as ReadyCallback
     });
+    let game = WalkTheDogBuilder::new()
+        .players(if options.two_players { 2 } else { 1 })
+        .auto_run(options.auto_run)
+        .metrics_url(options.metrics_url.take())
+        .debug_options(&debug_options)
+        .build(Rc::clone(&callbacks));
 
-    Ok(())
+    browser::spawn_local(async move {
+        if let Err(err) = GameLoop::start(
+            game,
+            canvas,
+            loop_config,
+            loop_commands,
+            loop_recording,
+            loop_screen,
+            on_ready,
+        )
+        .await
+        {
+            log!("Could not start game loop {:#?}", err);
+            error_callbacks.borrow().error(&err.to_string());
+        }
+    });
+
+    Ok(GameHandle {
+        commands,
+        callbacks,
+        canvas: handle_canvas,
+        recording,
+        screen,
+    })
 }
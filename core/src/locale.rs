@@ -0,0 +1,246 @@
+//! On-screen text looked up by key. Translations load from
+//! `assets/locale/{lang}.json`, a flat object of key to text, and anything
+//! missing falls back to the English compiled in below.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::browser;
+
+/// Every key the game draws, with its English text. `{}` marks where
+/// `Strings::format` puts each argument, in order.
+const ENGLISH: &[(&str, &str)] = &[
+    ("title.name", "Walk the Dog"),
+    ("title.settings", "Settings"),
+    ("title.loading_level", "Loading level…"),
+    ("title.hint", "↑/↓ to choose, Enter or tap to start"),
+    ("title.load_failed", "Could not load that level"),
+    ("mode.endless", "Endless"),
+    ("mode.daily_challenge", "Daily Challenge"),
+    ("mode.level_one", "Level 1"),
+    ("level_complete.title", "Level {} complete!"),
+    ("level_complete.summary", "score {}  distance {}m  coins {}"),
+    ("level_complete.loading", "Loading..."),
+    ("level_complete.next", "Press Enter for the next level"),
+    (
+        "level_complete.last",
+        "That was the last level! Press Enter",
+    ),
+    (
+        "level_complete.load_failed",
+        "Could not load the next level",
+    ),
+    ("medal.bronze", "Bronze medal"),
+    ("medal.silver", "Silver medal"),
+    ("medal.gold", "Gold medal!"),
+    ("pause.title", "Paused"),
+    ("pause.resume", "Resume"),
+    ("pause.restart", "Restart"),
+    ("pause.quit", "Quit to menu"),
+    ("pause.assist", "Assist mode: {}"),
+    ("settings.title", "Settings"),
+    ("settings.on", "on"),
+    ("settings.off", "off"),
+    ("settings.music_volume", "Music volume"),
+    ("settings.sfx_volume", "Effects volume"),
+    ("settings.screen_shake", "Screen shake: {}"),
+    ("settings.high_contrast", "High-contrast debug boxes: {}"),
+    ("settings.auto_run", "Auto-run: {}"),
+    ("settings.assist", "Assist mode: {} ({}% slower)"),
+    ("settings.show_overlaps", "Debug collision overlaps: {}"),
+    ("settings.rebind_keys", "Rebind keys ({} / {} / {})"),
+    ("settings.reset", "Reset to defaults"),
+    ("settings.back", "Back"),
+    (
+        "settings.hint",
+        "↑/↓ to choose, ←/→ to adjust, Enter to toggle, Escape to go back",
+    ),
+    ("settings.press_key", "Press a key for {} (Escape cancels)"),
+    ("settings.already_bound", "{} is already bound to {}"),
+    ("settings.keys_saved", "Keys saved"),
+    ("settings.rebind_cancelled", "Rebinding cancelled"),
+    ("settings.was_reset", "Settings reset to defaults"),
+    ("action.run", "run"),
+    ("action.jump", "jump"),
+    ("action.slide", "slide"),
+    (
+        "hud.resume",
+        "Press R to resume your {}m run, or → for a new run",
+    ),
+    ("hud.game_over", "Press T to go back to the title"),
+    ("hud.swept_away", "Swept away!"),
+    ("hud.assist_run", "Assist mode run (assist best {})"),
+    ("hud.score", "Score {}"),
+    ("hud.magnet", "MAGNET"),
+    ("hud.jetpack", "JETPACK"),
+    ("toast.milestone", "{}m!"),
+    ("toast.high_score", "New high score!"),
+    ("toast.checkpoint", "Checkpoint!"),
+    (
+        "toast.fullscreen_denied",
+        "Fullscreen is not available here",
+    ),
+    ("intro.wait", "Wait for me!"),
+    ("hint.jump", "Jump over stones!"),
+    ("hint.slide", "Slide under low platforms!"),
+];
+
+pub struct Strings {
+    lang: String,
+    table: HashMap<String, String>,
+    /// Unknown keys already logged, so each is reported once.
+    unknown: RefCell<HashSet<String>>,
+}
+
+impl Strings {
+    /// Loads `lang` (a BCP 47 tag such as `pt-BR`), then its base language,
+    /// settling for English when neither has a file.
+    pub async fn load(lang: &str) -> Strings {
+        let base = lang.split('-').next().unwrap_or(lang);
+        if base != "en" {
+            for candidate in [lang, base] {
+                match Strings::fetch(candidate).await {
+                    Ok(table) => return Strings::new(lang, table),
+                    Err(err) => {
+                        log!("No {} locale: {:#?}", candidate, err);
+                    }
+                }
+            }
+        }
+        Strings::new(lang, HashMap::new())
+    }
+
+    fn new(lang: &str, table: HashMap<String, String>) -> Strings {
+        Strings {
+            lang: lang.to_string(),
+            table,
+            unknown: RefCell::new(HashSet::new()),
+        }
+    }
+
+    async fn fetch(lang: &str) -> anyhow::Result<HashMap<String, String>> {
+        let json = browser::fetch_json(&format!("assets/locale/{}.json", lang)).await?;
+        serde_wasm_bindgen::from_value(json)
+            .map_err(|err| anyhow::anyhow!("Could not parse locale {} {:#?}", lang, err))
+    }
+
+    /// The text for `key`, in English when the locale lacks it, or `None`
+    /// for a key that isn't in `ENGLISH` either.
+    pub fn lookup(&self, key: &str) -> Option<&str> {
+        self.table.get(key).map(String::as_str).or_else(|| {
+            ENGLISH
+                .iter()
+                .find(|(english, _)| *english == key)
+                .map(|(_, text)| *text)
+        })
+    }
+
+    /// `lookup(key)`, for keys the game itself draws. An unknown key is
+    /// logged the first time and comes back as itself, so it is easy to
+    /// spot on screen.
+    pub fn t<'a>(&'a self, key: &'a str) -> &'a str {
+        self.lookup(key).unwrap_or_else(|| {
+            if self.unknown.borrow_mut().insert(key.to_string()) {
+                log!("Missing locale key {}", key);
+            }
+            key
+        })
+    }
+
+    /// `t(key)` with each `{}` replaced by the next of `args`.
+    pub fn format(&self, key: &str, args: &[&dyn Display]) -> String {
+        let mut args = args.iter();
+        let mut pieces = self.t(key).split("{}");
+        let mut text = pieces.next().unwrap_or_default().to_string();
+        for piece in pieces {
+            if let Some(arg) = args.next() {
+                text.push_str(&arg.to_string());
+            }
+            text.push_str(piece);
+        }
+        text
+    }
+
+    /// `number` with the locale's digit grouping, such as `12,345`.
+    pub fn number(&self, number: impl Into<f64>) -> String {
+        js_sys::Number::from(number.into())
+            .to_locale_string(&self.lang)
+            .into()
+    }
+
+    pub fn on_off(&self, on: bool) -> &str {
+        self.t(if on { "settings.on" } else { "settings.off" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    /// Every string literal in `source` shaped like a key in one of the
+    /// sections `ENGLISH` uses, such as `"hud.magnet"`.
+    fn keys_in(source: &str) -> Vec<&str> {
+        let sections: HashSet<&str> = ENGLISH
+            .iter()
+            .filter_map(|(key, _)| key.split_once('.'))
+            .map(|(section, _)| section)
+            .collect();
+        source
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .filter(|literal| {
+                literal.split_once('.').is_some_and(|(section, name)| {
+                    sections.contains(section)
+                        && !name.is_empty()
+                        && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_key_the_game_uses_has_english_text() {
+        let strings = Strings::new("en", HashMap::new());
+        let keys = keys_in(include_str!("game.rs"));
+        assert!(keys.contains(&"hud.magnet"));
+
+        for key in keys {
+            assert!(strings.lookup(key).is_some(), "{} is not in ENGLISH", key);
+        }
+    }
+
+    #[test]
+    fn a_locale_falls_back_to_english_for_missing_keys() {
+        let table = HashMap::from([("pause.title".to_string(), "Pausa".to_string())]);
+        let strings = Strings::new("es", table);
+
+        assert_eq!(strings.t("pause.title"), "Pausa");
+        assert_eq!(strings.t("pause.resume"), "Resume");
+    }
+
+    #[test]
+    fn unknown_keys_show_as_themselves_and_are_reported_once() {
+        let strings = Strings::new("en", HashMap::new());
+
+        assert_eq!(strings.lookup("hud.nonexistent"), None);
+        assert_eq!(strings.t("hud.nonexistent"), "hud.nonexistent");
+        assert_eq!(strings.t("hud.nonexistent"), "hud.nonexistent");
+        assert_eq!(strings.unknown.borrow().len(), 1);
+    }
+
+    #[test]
+    fn format_fills_placeholders_in_order() {
+        let strings = Strings::new("en", HashMap::new());
+
+        assert_eq!(
+            strings.format("settings.already_bound", &[&"Space", &"jump"]),
+            "Space is already bound to jump"
+        );
+    }
+}
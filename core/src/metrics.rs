@@ -0,0 +1,192 @@
+//! Opt-in gameplay metrics for hosts: how many runs start, how long they
+//! last, what ends them and how far they get. Batches are posted as JSON to
+//! the configured endpoint; without one nothing is recorded or sent.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::browser::{self, EventListenerGuard};
+
+/// Width of each bucket in the distance histogram.
+const BUCKET_METERS: i32 = 100;
+/// Runs past the last bucket are counted in it.
+const BUCKETS: usize = 20;
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct MetricsBatch {
+    runs_started: u32,
+    runs: Vec<RunMetric>,
+    /// Finished runs per `BUCKET_METERS` of distance.
+    distance_histogram: Vec<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunMetric {
+    duration_ms: f32,
+    meters: i32,
+    cause: &'static str,
+}
+
+impl MetricsBatch {
+    fn is_empty(&self) -> bool {
+        self.runs_started == 0 && self.runs.is_empty()
+    }
+
+    fn record_run(&mut self, run: RunMetric) {
+        if self.distance_histogram.is_empty() {
+            self.distance_histogram = vec![0; BUCKETS];
+        }
+        let bucket = (run.meters.max(0) / BUCKET_METERS) as usize;
+        self.distance_histogram[bucket.min(BUCKETS - 1)] += 1;
+        self.runs.push(run);
+    }
+}
+
+pub struct Metrics {
+    endpoint: Option<String>,
+    batch: Rc<RefCell<MetricsBatch>>,
+    /// Sends the last batch when the page is hidden.
+    _on_hidden: Option<EventListenerGuard>,
+}
+
+impl Metrics {
+    pub fn new(endpoint: Option<String>) -> Result<Self> {
+        let batch = Rc::new(RefCell::new(MetricsBatch::default()));
+        let on_hidden = match &endpoint {
+            Some(endpoint) => {
+                let endpoint = endpoint.clone();
+                let batch = Rc::clone(&batch);
+                let on_visibility_change = browser::closure_wrap(Box::new(move || {
+                    if browser::is_page_hidden() {
+                        send(&endpoint, &batch, true);
+                    }
+                })
+                    as Box<dyn FnMut()>);
+                Some(browser::add_document_listener(
+                    "visibilitychange",
+                    on_visibility_change,
+                )?)
+            }
+            None => None,
+        };
+
+        Ok(Metrics {
+            endpoint,
+            batch,
+            _on_hidden: on_hidden,
+        })
+    }
+
+    pub fn run_started(&self) {
+        if self.endpoint.is_some() {
+            self.batch.borrow_mut().runs_started += 1;
+        }
+    }
+
+    pub fn run_ended(&self, duration_ms: f32, meters: i32, cause: &'static str) {
+        if self.endpoint.is_some() {
+            self.batch.borrow_mut().record_run(RunMetric {
+                duration_ms,
+                meters,
+                cause,
+            });
+        }
+    }
+
+    /// Sends everything recorded since the last batch.
+    pub fn flush(&self) {
+        if let Some(endpoint) = &self.endpoint {
+            send(endpoint, &self.batch, false);
+        }
+    }
+}
+
+/// Posts the batch and starts a new one. Failures are only logged. While
+/// the page is hiding a beacon is used when the browser has one, since a
+/// fetch can be cut off.
+fn send(endpoint: &str, batch: &RefCell<MetricsBatch>, hiding: bool) {
+    if batch.borrow().is_empty() {
+        return;
+    }
+    let batch = std::mem::take(&mut *batch.borrow_mut());
+    let body = match serde_json::to_string(&batch) {
+        Ok(body) => body,
+        Err(err) => {
+            log!("Could not serialize metrics {:#?}", err);
+            return;
+        }
+    };
+    if hiding && browser::send_beacon(endpoint, &body) {
+        return;
+    }
+
+    let endpoint = endpoint.to_string();
+    browser::spawn_local(async move {
+        if let Err(err) = browser::post_json(&endpoint, &body).await {
+            log!("Could not send metrics {:#?}", err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as test;
+
+    fn run(meters: i32) -> RunMetric {
+        RunMetric {
+            duration_ms: 1500.0,
+            meters,
+            cause: "stone",
+        }
+    }
+
+    #[test]
+    fn runs_are_bucketed_by_distance() {
+        let mut batch = MetricsBatch::default();
+        for meters in [-5, 0, 99, 100, 250, 1999, 2000, 50_000] {
+            batch.record_run(run(meters));
+        }
+
+        let mut expected = vec![0; BUCKETS];
+        expected[0] = 3;
+        expected[1] = 1;
+        expected[2] = 1;
+        expected[BUCKETS - 1] = 3;
+        assert_eq!(batch.distance_histogram, expected);
+        assert_eq!(batch.runs.len(), 8);
+    }
+
+    #[test]
+    fn a_batch_serializes_to_the_documented_shape() {
+        let mut batch = MetricsBatch {
+            runs_started: 2,
+            ..MetricsBatch::default()
+        };
+        batch.record_run(run(120));
+
+        let mut histogram = vec![0; BUCKETS];
+        histogram[1] = 1;
+        assert_eq!(
+            serde_json::to_value(&batch).unwrap(),
+            serde_json::json!({
+                "runsStarted": 2,
+                "runs": [{ "durationMs": 1500.0, "meters": 120, "cause": "stone" }],
+                "distanceHistogram": histogram,
+            })
+        );
+    }
+
+    #[test]
+    fn only_an_untouched_batch_is_empty() {
+        let mut batch = MetricsBatch::default();
+        assert!(batch.is_empty());
+
+        batch.runs_started += 1;
+        assert!(!batch.is_empty());
+    }
+}
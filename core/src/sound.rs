@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+use js_sys::ArrayBuffer;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, GainNode};
+
+pub fn create_audio_context() -> Result<AudioContext> {
+    AudioContext::new().map_err(|err| anyhow!("Could not create audio context: {:#?}", err))
+}
+
+pub fn create_gain(ctx: &AudioContext) -> Result<GainNode> {
+    let gain = ctx
+        .create_gain()
+        .map_err(|err| anyhow!("Error creating gain node {:#?}", err))?;
+    gain.connect_with_audio_node(&ctx.destination())
+        .map_err(|err| anyhow!("Error connecting gain node {:#?}", err))?;
+    Ok(gain)
+}
+
+fn create_buffer_source(ctx: &AudioContext) -> Result<AudioBufferSourceNode> {
+    ctx.create_buffer_source()
+        .map_err(|err| anyhow!("Error creating buffer source {:#?}", err))
+}
+
+fn connect_with_audio_node(
+    buffer_source: &AudioBufferSourceNode,
+    destination: &AudioNode,
+) -> Result<AudioNode> {
+    buffer_source
+        .connect_with_audio_node(destination)
+        .map_err(|err| anyhow!("Error connecting audio source to destination {:#?}", err))
+}
+
+/// Starts `buffer` on a new one-shot source, so overlapping plays of the
+/// same buffer don't cut each other off.
+pub fn play_sound(
+    ctx: &AudioContext,
+    buffer: &AudioBuffer,
+    destination: &AudioNode,
+) -> Result<AudioBufferSourceNode> {
+    let track_source = create_buffer_source(ctx)?;
+    track_source.set_buffer(Some(buffer));
+    connect_with_audio_node(&track_source, destination)?;
+    track_source
+        .start()
+        .map_err(|err| anyhow!("Could not start sound! {:#?}", err))?;
+    Ok(track_source)
+}
+
+pub fn stop_sound(source: &AudioBufferSourceNode) -> Result<()> {
+    source
+        .stop()
+        .map_err(|err| anyhow!("Could not stop sound! {:#?}", err))
+}
+
+/// Stops the context's clock; scheduled sounds wait until it resumes.
+pub fn suspend(ctx: &AudioContext) -> Result<()> {
+    ctx.suspend()
+        .map(|_| ())
+        .map_err(|err| anyhow!("Could not suspend audio {:#?}", err))
+}
+
+pub fn resume(ctx: &AudioContext) -> Result<()> {
+    ctx.resume()
+        .map(|_| ())
+        .map_err(|err| anyhow!("Could not resume audio {:#?}", err))
+}
+
+/// Moves `gain` from `from` to `to` over `seconds` without clicks.
+pub fn ramp_gain(
+    ctx: &AudioContext,
+    gain: &GainNode,
+    from: f32,
+    to: f32,
+    seconds: f64,
+) -> Result<()> {
+    let now = ctx.current_time();
+    let param = gain.gain();
+    param
+        .cancel_scheduled_values(now)
+        .and_then(|param| param.set_value_at_time(from, now))
+        .and_then(|param| param.linear_ramp_to_value_at_time(to, now + seconds))
+        .map(|_| ())
+        .map_err(|err| anyhow!("Could not ramp gain {:#?}", err))
+}
+
+pub async fn decode_audio_data(
+    ctx: &AudioContext,
+    array_buffer: &ArrayBuffer,
+) -> Result<AudioBuffer> {
+    JsFuture::from(
+        ctx.decode_audio_data(array_buffer)
+            .map_err(|err| anyhow!("Could not decode audio from array buffer {:#?}", err))?,
+    )
+    .await
+    .map_err(|err| anyhow!("Could not convert promise to future {:#?}", err))?
+    .dyn_into()
+    .map_err(|err| anyhow!("Could not cast into AudioBuffer {:#?}", err))
+}